@@ -0,0 +1,35 @@
+// A generated program where a handful of variables are used thousands of times, the shape of
+// program that identifier interning is for. Compiling dominates since the input is empty.
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 20;
+
+fn program(statements: usize) -> String {
+    let mut program = String::from("BEGIN {\n");
+    for idx in 0..statements {
+        program.push_str(&format!(
+            "total = total + count * {}; count = count + 1; last = total\n",
+            idx
+        ));
+    }
+    program.push_str("print total, count, last\n}\n");
+    program
+}
+
+fn main() {
+    for statements in [100, 1_000, 5_000] {
+        let program = program(statements);
+        let mut elapsed = Duration::ZERO;
+        for _ in 0..RUNS {
+            let start = Instant::now();
+            jawk::run_capture(&program, "").unwrap();
+            elapsed += start.elapsed();
+        }
+        println!(
+            "{} statements, {} variable uses: {:?} per run",
+            statements,
+            statements * 7,
+            elapsed / RUNS
+        );
+    }
+}
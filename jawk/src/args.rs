@@ -1,3 +1,4 @@
+use crate::parser::Dialect;
 use std::path::PathBuf;
 
 // TODO: Find a small library to do this
@@ -5,48 +6,51 @@ use std::path::PathBuf;
 #[derive(Debug, PartialEq)]
 pub struct AwkArgs {
     pub debug: bool,
+    // Consume the first record as a header so fields can be read by name through F
+    pub header: bool,
     pub program: ProgramType,
     pub files: Vec<String>,
     pub save_executable: Option<PathBuf>,
+    // gawk's extensions are turned off by --posix and --traditional
+    pub dialect: Dialect,
+    // -v name=value, made before BEGIN runs
+    pub assignments: Vec<String>,
+    // -F fs, FS before BEGIN runs
+    pub field_sep: Option<String>,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum ProgramType {
     CLI(String),
-    File(String),
-}
-
-impl ProgramType {
-    pub fn load(&self) -> Result<String, String> {
-        match self {
-            ProgramType::CLI(s) => Ok(s.clone()),
-            ProgramType::File(s) => match std::fs::read_to_string(s) {
-                Ok(s) => Ok(s),
-                Err(e) => Err(format!(
-                    "Unable to load source program '{}'\nGot error: {}",
-                    s, e
-                )),
-            },
-        }
-    }
+    // Each -f in order, the program is all of them joined
+    Files(Vec<String>),
 }
 
 fn print_help() {
     eprintln!(
         "\
-Usage: llawk [--debug] [--save path] -f progfile file ...
-Usage: llawk [--debug] [--save path] 'program' file ...
+Usage: llawk [--debug] [--header] [--posix | --traditional] [--save path] [-F fs] [-v var=value] -f progfile [-f progfile ...] file ...
+Usage: llawk [--debug] [--header] [--posix | --traditional] [--save path] [-F fs] [-v var=value] 'program' file ...
 --dump: Dump the AST after parsing
---save file_path: Save the executable to the given path"
+--header: Treat the first record as a header, F[\"name\"] is the field under that name
+--posix: Reject gawk extensions such as typeof, isarray and patsplit
+--traditional: Like --posix, for programs written for the one true awk (bwk awk)
+--save file_path: Save the executable to the given path
+-F fs: Split records into fields on fs, escapes in fs are decoded like in a string
+-v var=value: Assign var before BEGIN, escapes in value are decoded like in a string"
     );
 }
 
 impl AwkArgs {
     pub fn new(args: Vec<String>) -> Result<Self, ()> {
         let mut debug = false;
+        let mut header = false;
         let mut program: Option<ProgramType> = None;
         let mut files: Vec<String> = vec![];
         let mut save_executable: Option<PathBuf> = None;
+        let mut dialect = Dialect::Gawk;
+        let mut assignments: Vec<String> = vec![];
+        let mut field_sep: Option<String> = None;
 
         let mut i = 1;
         while i < args.len() {
@@ -55,6 +59,18 @@ impl AwkArgs {
                     debug = true;
                     i += 1;
                 }
+                "--header" => {
+                    header = true;
+                    i += 1;
+                }
+                "--posix" => {
+                    dialect = Dialect::Posix;
+                    i += 1;
+                }
+                "--traditional" => {
+                    dialect = Dialect::Bwk;
+                    i += 1;
+                }
                 "--save" => {
                     if let Some(next) = args.get(i + 1) {
                         save_executable = Some(PathBuf::from(next));
@@ -64,8 +80,38 @@ impl AwkArgs {
                     }
                     i += 2;
                 }
+                "-v" => {
+                    if let Some(next) = args.get(i + 1) {
+                        assignments.push(next.clone());
+                    } else {
+                        print_help();
+                        eprintln!("-v must be followed by var=value");
+                        return Err(());
+                    }
+                    i += 2;
+                }
+                arg if arg.starts_with("-v") => {
+                    // -vvar=value
+                    assignments.push(arg[2..].to_string());
+                    i += 1;
+                }
+                "-F" => {
+                    if let Some(next) = args.get(i + 1) {
+                        field_sep = Some(next.clone());
+                    } else {
+                        print_help();
+                        eprintln!("-F must be followed by a field separator");
+                        return Err(());
+                    }
+                    i += 2;
+                }
+                arg if arg.starts_with("-F") => {
+                    // -F, or -F'\t'
+                    field_sep = Some(arg[2..].to_string());
+                    i += 1;
+                }
                 "-f" => {
-                    if program != None {
+                    if let Some(ProgramType::CLI(_)) = program {
                         print_help();
                         eprintln!("Cannot specify multiple programs!");
                         return Err(());
@@ -78,7 +124,10 @@ impl AwkArgs {
                         }
                         Some(path) => path,
                     };
-                    program = Some(ProgramType::File(next.to_string()));
+                    match &mut program {
+                        Some(ProgramType::Files(paths)) => paths.push(next.to_string()),
+                        _ => program = Some(ProgramType::Files(vec![next.to_string()])),
+                    }
                     i += 2;
                 }
                 _ => {
@@ -101,9 +150,13 @@ impl AwkArgs {
         };
         Ok(AwkArgs {
             debug,
+            header,
             program,
             files,
             save_executable,
+            dialect,
+            assignments,
+            field_sep,
         })
     }
 }
@@ -0,0 +1,189 @@
+use crate::codgen::{CodeGen, ValueT, SUBSEP};
+use crate::parser::{AwkT, Stmt, TypedExpr};
+use crate::runtime::Runtime;
+use crate::Expr;
+use gnu_libjit::{Label, Value};
+
+impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
+    pub(super) fn compile_array_index(
+        &mut self,
+        name: &str,
+        path: &[Vec<TypedExpr>],
+        indices: &[TypedExpr],
+    ) -> ValueT {
+        let array = self.array_ref(name, path);
+        let key = self.array_key(indices);
+        let out = self.runtime.array_get(&mut self.function, array, key);
+        self.load_value_out(&out)
+    }
+
+    // The runtime keeps a copy of the value and the caller gets the original back
+    pub(super) fn compile_array_assign(
+        &mut self,
+        name: &str,
+        path: &[Vec<TypedExpr>],
+        indices: &[TypedExpr],
+        value: &TypedExpr,
+    ) -> ValueT {
+        let array = self.array_ref(name, path);
+        let key = self.array_key(indices);
+        let result = self.compile_expr(value);
+        let copy = self.copy_if_string(result.clone(), value.typ);
+        self.runtime.array_assign(
+            &mut self.function,
+            array,
+            key,
+            copy.tag,
+            copy.float,
+            copy.pointer,
+        );
+        result
+    }
+
+    // Store a value we own, which the runtime takes
+    pub(super) fn array_store(
+        &mut self,
+        name: &str,
+        path: &[Vec<TypedExpr>],
+        indices: &[TypedExpr],
+        value: ValueT,
+    ) {
+        let array = self.array_ref(name, path);
+        let key = self.array_key(indices);
+        self.runtime.array_assign(
+            &mut self.function,
+            array,
+            key,
+            value.tag,
+            value.float,
+            value.pointer,
+        );
+    }
+
+    pub(super) fn compile_array_type_of(
+        &mut self,
+        name: &str,
+        path: &[Vec<TypedExpr>],
+        indices: &[TypedExpr],
+    ) -> ValueT {
+        let array = self.array_ref(name, path);
+        let key = self.array_key(indices);
+        let typ = self.runtime.array_type_of(&mut self.function, array, key);
+        ValueT::new(self.string_tag(), self.zero_f(), typ)
+    }
+
+    pub(super) fn compile_in_array(
+        &mut self,
+        indices: &[TypedExpr],
+        name: &str,
+        path: &[Vec<TypedExpr>],
+    ) -> ValueT {
+        let array = self.array_ref(name, path);
+        let key = self.array_key(indices);
+        let found = self.runtime.array_contains(&mut self.function, array, key);
+        ValueT::new(self.float_tag(), found, self.zero_ptr.clone())
+    }
+
+    // delete a[k], or delete a without indices
+    pub(super) fn compile_delete(
+        &mut self,
+        name: &str,
+        path: &[Vec<TypedExpr>],
+        indices: &[TypedExpr],
+    ) {
+        let array = self.array_ref(name, path);
+        if indices.is_empty() {
+            self.runtime.array_clear(&mut self.function, array);
+        } else {
+            let key = self.array_key(indices);
+            self.runtime.array_delete(&mut self.function, array, key);
+        }
+    }
+
+    // isarray(a) is known while compiling, isarray(a[k]) depends on what a[k] holds
+    pub(super) fn compile_is_array(&mut self, expr: &TypedExpr) -> ValueT {
+        let is_array = match &expr.expr {
+            Expr::Variable(name) => {
+                let is_array = self.arrays.contains(&**name) as i32 as f64;
+                self.function.create_float64_constant(is_array)
+            }
+            Expr::ArrayIndex {
+                name,
+                path,
+                indices,
+            } => {
+                let array = self.array_ref(name, path);
+                let key = self.array_key(indices);
+                self.runtime.array_is_array(&mut self.function, array, key)
+            }
+            _ => {
+                let value = self.compile_expr(expr);
+                self.drop_if_str(&value, expr.typ);
+                self.zero_f()
+            }
+        };
+        ValueT::new(self.float_tag(), is_array, self.zero_ptr.clone())
+    }
+
+    // for (var in a) body. Keys are a snapshot taken before the first iteration.
+    pub(super) fn compile_for_in(
+        &mut self,
+        var: &str,
+        name: &str,
+        path: &[Vec<TypedExpr>],
+        body: &Stmt,
+    ) {
+        let array = self.array_ref(name, path);
+        let iterator = self.function.create_value_int();
+        let started = self.runtime.array_iterate(&mut self.function, array);
+        self.function.insn_store(&iterator, &started);
+
+        let mut next_label = Label::new();
+        let mut done_label = Label::new();
+        self.function.insn_label(&mut next_label);
+        let out = self
+            .runtime
+            .array_next_key(&mut self.function, iterator.clone());
+        let out = self.load_value_out(&out);
+        let zero = self.function.create_float64_constant(0.0);
+        let done = self.function.insn_eq(&out.float, &zero);
+        self.function.insn_branch_if(&done, &mut done_label);
+        let var_ptrs = self.scopes.get(var).clone();
+        let old_value = self.load(&var_ptrs);
+        self.drop_if_str(&old_value, AwkT::Variable);
+        let key = ValueT::new(self.string_tag(), self.zero_f(), out.pointer);
+        self.store(&var_ptrs, &key);
+        let mut lp = self.compile_loop_body(body, next_label, done_label, Some(iterator));
+        self.function.insn_branch(&mut lp.continue_label);
+        self.function.insn_label(&mut lp.break_label);
+    }
+
+    // The id of a, or for a[1][2] the sub-array stored at a[1][2]
+    pub(super) fn array_ref(&mut self, name: &str, path: &[Vec<TypedExpr>]) -> Value {
+        let id = self.runtime.define_array(name);
+        let mut array = self.function.create_int_constant(id);
+        for indices in path {
+            let key = self.array_key(indices);
+            array = self.runtime.subarray(&mut self.function, array, key);
+        }
+        array
+    }
+
+    // a[1, 2] is a["1" SUBSEP "2"]
+    pub(super) fn array_key(&mut self, indices: &[TypedExpr]) -> Value {
+        if indices.len() == 1 {
+            let index = self.compile_expr(&indices[0]);
+            return self.to_string(&index, indices[0].typ);
+        }
+        let subsep = TypedExpr::new_var(Expr::Variable(SUBSEP.into()));
+        let mut parts = vec![];
+        for (idx, index) in indices.iter().enumerate() {
+            if idx != 0 {
+                parts.push(subsep.clone());
+            }
+            parts.push(index.clone());
+        }
+        let compiled = self.compile_exprs_to_string(&parts);
+        self.concat_values(&compiled).pointer
+    }
+}
@@ -0,0 +1,273 @@
+use crate::codgen::{record_var, CodeGen, ValueT, RT};
+use crate::columns::DEFAULT_FPAT;
+use crate::parser::{AwkT, TypedExpr};
+use crate::runtime::Runtime;
+use crate::Expr;
+use gnu_libjit::{Label, Value};
+use std::os::raw::c_char;
+
+impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
+    // Arity has already been checked by the parser
+    pub(super) fn compile_builtin(&mut self, target: &str, args: &[TypedExpr]) -> ValueT {
+        match target {
+            "typeof" => {
+                // Inspect the variable in place rather than compiling a read of it, so asking
+                // for the type never changes it.
+                let arg = &args[0];
+                match &arg.expr {
+                    Expr::Variable(var) if self.arrays.contains(&**var) => {
+                        return self
+                            .compile_expr(&TypedExpr::new_str(Expr::String("array".to_string())));
+                    }
+                    // Only typeof or isarray see it so it was never defined
+                    Expr::Variable(var)
+                        if record_var(var).is_none()
+                            && *var != RT
+                            && !self.scopes.contains(var) =>
+                    {
+                        return self.compile_expr(&TypedExpr::new_str(Expr::String(
+                            "untyped".to_string(),
+                        )));
+                    }
+                    Expr::ArrayIndex {
+                        name,
+                        path,
+                        indices,
+                    } => return self.compile_array_type_of(name, path, indices),
+                    _ => {}
+                }
+                let is_field = matches!(arg.expr, Expr::Column(_)) as c_char;
+                let is_field = self.function.create_sbyte_constant(is_field);
+                let (value, owned) = match &arg.expr {
+                    Expr::Variable(var) if record_var(var).is_none() && *var != RT => {
+                        let var_ptr = self.scopes.get(var).clone();
+                        (self.load(&var_ptr), false)
+                    }
+                    _ => (self.compile_expr(arg), true),
+                };
+                let typ = self.runtime.type_of(
+                    &mut self.function,
+                    value.tag.clone(),
+                    value.pointer.clone(),
+                    is_field,
+                );
+                if owned {
+                    self.drop_if_str(&value, arg.typ);
+                }
+                ValueT::new(self.string_tag(), self.zero_f(), typ)
+            }
+            "sprintf" => {
+                let fmt = &args[0];
+                let fmt_value = self.compile_expr(fmt);
+                let fmt_str = self.to_string(&fmt_value, fmt.typ);
+                self.push_format_args(&args[1..]);
+                let result = self.runtime.sprintf(&mut self.function, fmt_str.clone());
+                self.runtime.free_string(&mut self.function, fmt_str);
+                ValueT::new(self.string_tag(), self.zero_f(), result)
+            }
+            "close" => {
+                let name = &args[0];
+                let name_value = self.compile_expr(name);
+                let name_str = self.to_string(&name_value, name.typ);
+                let how = match args.get(1) {
+                    Some(how) => {
+                        let how_value = self.compile_expr(how);
+                        self.to_string(&how_value, how.typ)
+                    }
+                    None => self.runtime.empty_string(&mut self.function),
+                };
+                let result = self.runtime.close(&mut self.function, name_str, how);
+                ValueT::new(self.float_tag(), result, self.zero_ptr.clone())
+            }
+            "system" => {
+                let command = &args[0];
+                let command_value = self.compile_expr(command);
+                let command_str = self.to_string(&command_value, command.typ);
+                let result = self.runtime.system(&mut self.function, command_str);
+                ValueT::new(self.float_tag(), result, self.zero_ptr.clone())
+            }
+            "toupper" | "tolower" => {
+                let arg = &args[0];
+                let value = self.compile_expr(arg);
+                let string = self.to_string(&value, arg.typ);
+                let upper = self
+                    .function
+                    .create_sbyte_constant((target == "toupper") as c_char);
+                let result = self.runtime.change_case(&mut self.function, string, upper);
+                ValueT::new(self.string_tag(), self.zero_f(), result)
+            }
+            "rand" => {
+                let result = self.runtime.rand(&mut self.function);
+                ValueT::new(self.float_tag(), result, self.zero_ptr.clone())
+            }
+            "match" => {
+                let string = self.compile_expr(&args[0]);
+                let string = self.to_string(&string, args[0].typ);
+                let pattern = self.pattern_arg(&args[1]);
+                let index = self
+                    .runtime
+                    .match_index(&mut self.function, string, pattern);
+                ValueT::new(self.float_tag(), index, self.zero_ptr.clone())
+            }
+            "srand" => {
+                let (seed, has_seed) = match args.first() {
+                    Some(arg) => {
+                        let value = self.compile_expr(arg);
+                        let seed = self.to_float(&value, arg.typ);
+                        self.drop_if_str(&value, arg.typ);
+                        (seed, 1)
+                    }
+                    None => (self.zero_f(), 0),
+                };
+                let has_seed = self.function.create_sbyte_constant(has_seed);
+                let previous = self.runtime.srand(&mut self.function, seed, has_seed);
+                ValueT::new(self.float_tag(), previous, self.zero_ptr.clone())
+            }
+            "isarray" => self.compile_is_array(&args[0]),
+            "patsplit" => self.compile_patsplit(args),
+            "sub" => self.compile_substitute(args, false),
+            "gsub" => self.compile_substitute(args, true),
+            _ => panic!("Parser bug, unknown builtin {}", target),
+        }
+    }
+
+    // patsplit(s, arr [, fpat [, seps]]), the parser made sure arr and seps are names
+    fn compile_patsplit(&mut self, args: &[TypedExpr]) -> ValueT {
+        let string = self.compile_expr(&args[0]);
+        let string = self.to_string(&string, args[0].typ);
+        let array = self.patsplit_array(&args[1]);
+        let fpat = match args.get(2) {
+            Some(fpat) => self.pattern_arg(fpat),
+            None => self.pattern_arg(&TypedExpr::new_str(Expr::String(DEFAULT_FPAT.to_string()))),
+        };
+        let seps = match args.get(3) {
+            Some(seps) => self.patsplit_array(seps),
+            None => self.function.create_int_constant(-1),
+        };
+        let count = self
+            .runtime
+            .patsplit(&mut self.function, string, array, fpat, seps);
+        ValueT::new(self.float_tag(), count, self.zero_ptr.clone())
+    }
+
+    // sub(re, repl [, target]) and gsub. The target, $0 without one, is only assigned when
+    // something was replaced. Its field index or subscripts are evaluated once.
+    fn compile_substitute(&mut self, args: &[TypedExpr], global: bool) -> ValueT {
+        let pattern = self.pattern_arg(&args[0]);
+        let replacement = self.compile_to_string(&args[1]);
+        let record = TypedExpr::new_str(Expr::Column(Box::new(TypedExpr::new_num(
+            Expr::NumberF64(0.0),
+        ))));
+        let target = args.get(2).unwrap_or(&record);
+        let mut done_lbl = Label::new();
+        let count = match &target.expr {
+            Expr::Variable(var) => {
+                let text = self.compile_to_string(target);
+                let out = self.substitute(pattern, replacement, text, global, &mut done_lbl);
+                let result = ValueT::new(self.string_tag(), self.zero_f(), out.pointer);
+                let var_ptrs = self.scopes.get(var).clone();
+                let old_value = self.load(&var_ptrs);
+                self.drop_if_str(&old_value, AwkT::Variable);
+                self.store(&var_ptrs, &result);
+                self.assigned_special_var(var, &result, AwkT::String);
+                out.float
+            }
+            Expr::Column(index) => {
+                let index_value = self.compile_expr(index);
+                let idx = self.to_float(&index_value, index.typ);
+                self.drop_if_str(&index_value, index.typ);
+                let (float_tag, zero_ptr) = (self.float_tag(), self.zero_ptr.clone());
+                let text =
+                    self.runtime
+                        .column(&mut self.function, float_tag, idx.clone(), zero_ptr);
+                let out = self.substitute(pattern, replacement, text, global, &mut done_lbl);
+                self.runtime
+                    .set_column(&mut self.function, idx, out.pointer);
+                out.float
+            }
+            Expr::ArrayIndex {
+                name,
+                path,
+                indices,
+            } => {
+                let array = self.array_ref(name, path);
+                let key = self.array_key(indices);
+                let key_copy = self.runtime.copy_string(&mut self.function, key.clone());
+                let value = self
+                    .runtime
+                    .array_get(&mut self.function, array.clone(), key_copy);
+                let value = self.load_value_out(&value);
+                let text = self.to_string(&value, AwkT::Variable);
+                let mut kept_lbl = Label::new();
+                let out = self.substitute(pattern, replacement, text, global, &mut kept_lbl);
+                let (string_tag, zero) = (self.string_tag(), self.zero_f());
+                self.runtime.array_assign(
+                    &mut self.function,
+                    array,
+                    key.clone(),
+                    string_tag,
+                    zero,
+                    out.pointer,
+                );
+                self.function.insn_branch(&mut done_lbl);
+                self.function.insn_label(&mut kept_lbl);
+                self.drop(&key);
+                out.float
+            }
+            _ => panic!("Parser bug, the target of sub isn't a variable, field or array element"),
+        };
+        self.function.insn_label(&mut done_lbl);
+        ValueT::new(self.float_tag(), count, self.zero_ptr.clone())
+    }
+
+    // The runtime's substitute. Branches to kept_lbl when nothing was replaced, otherwise falls
+    // through with the new text in the pointer. The float is how many were replaced.
+    fn substitute(
+        &mut self,
+        pattern: Value,
+        replacement: Value,
+        text: Value,
+        global: bool,
+        kept_lbl: &mut Label,
+    ) -> ValueT {
+        let global = self.function.create_sbyte_constant(global as c_char);
+        let out = self
+            .runtime
+            .substitute(&mut self.function, pattern, replacement, text, global);
+        let out = self.load_value_out(&out);
+        let zero = self.zero_f();
+        let replaced = self.function.insn_gt(&out.float, &zero);
+        self.function.insn_branch_if_not(&replaced, kept_lbl);
+        out
+    }
+
+    // The pattern a builtin takes as a string. A /re/ argument is the pattern itself, not $0 ~ /re/.
+    fn pattern_arg(&mut self, arg: &TypedExpr) -> Value {
+        let pattern = match &arg.expr {
+            Expr::Regex(regex) => TypedExpr::new_str(Expr::String(regex.clone())),
+            _ => arg.clone(),
+        };
+        let value = self.compile_expr(&pattern);
+        self.to_string(&value, pattern.typ)
+    }
+
+    fn patsplit_array(&mut self, arg: &TypedExpr) -> Value {
+        match &arg.expr {
+            Expr::Variable(name) => {
+                let id = self.runtime.define_array(name);
+                self.function.create_int_constant(id)
+            }
+            _ => panic!("Parser bug, patsplit array argument isn't a name"),
+        }
+    }
+
+    // Arguments for the next printf/sprintf call. The runtime takes ownership of each value so
+    // there is nothing to drop.
+    pub(super) fn push_format_args(&mut self, args: &[TypedExpr]) {
+        for arg in args {
+            let value = self.compile_expr(arg);
+            self.runtime
+                .push_format_arg(&mut self.function, value.tag, value.float, value.pointer);
+        }
+    }
+}
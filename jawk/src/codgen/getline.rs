@@ -0,0 +1,63 @@
+use crate::codgen::{CodeGen, ValueT};
+use crate::parser::{AwkT, GetlineSource, Lvalue, TypedExpr};
+use crate::runtime::{
+    Runtime, GETLINE_COMMAND, GETLINE_COPROCESS, GETLINE_FILE, GETLINE_MAIN_INPUT,
+};
+use gnu_libjit::{Label, Value};
+use std::os::raw::c_char;
+
+impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
+    // getline [var], getline [var] < file, cmd | getline [var] and cmd |& getline [var]. Without
+    // a var the runtime replaces $0 itself, with one we store the line in the var but only if one
+    // was read.
+    // The runtime also takes care of which of NR, FNR and NF change.
+    pub(super) fn compile_getline(
+        &mut self,
+        into: &Option<Lvalue>,
+        source: &GetlineSource,
+    ) -> ValueT {
+        let (source_str, source_kind) = match source {
+            GetlineSource::MainInput => (self.zero_ptr.clone(), GETLINE_MAIN_INPUT),
+            GetlineSource::File(file) => (self.compile_to_string(file), GETLINE_FILE),
+            GetlineSource::Command(cmd) => (self.compile_to_string(cmd), GETLINE_COMMAND),
+            GetlineSource::Coprocess(cmd) => (self.compile_to_string(cmd), GETLINE_COPROCESS),
+        };
+        let source_kind = self.function.create_sbyte_constant(source_kind as c_char);
+        let into_var = self
+            .function
+            .create_sbyte_constant(into.is_some() as c_char);
+        let out = self
+            .runtime
+            .getline(&mut self.function, source_str, source_kind, into_var);
+        let out = self.load_value_out(&out);
+
+        if let Some(into) = into {
+            let mut done_lbl = Label::new();
+            let zero = self.function.create_float64_constant(0.0);
+            let got_line = self.function.insn_gt(&out.float, &zero);
+            self.function.insn_branch_if_not(&got_line, &mut done_lbl);
+            let line = ValueT::new(self.string_tag(), self.zero_f(), out.pointer);
+            match into {
+                Lvalue::Variable(var) => {
+                    let var_ptrs = self.scopes.get(var).clone();
+                    let old_value = self.load(&var_ptrs);
+                    self.drop_if_str(&old_value, AwkT::Variable);
+                    self.store(&var_ptrs, &line);
+                }
+                Lvalue::ArrayIndex {
+                    name,
+                    path,
+                    indices,
+                } => self.array_store(name, path, indices, line),
+                Lvalue::Column(index) => self.store_column(index, line.pointer),
+            }
+            self.function.insn_label(&mut done_lbl);
+        }
+        ValueT::new(self.float_tag(), out.float, self.zero_ptr.clone())
+    }
+
+    pub(super) fn compile_to_string(&mut self, expr: &TypedExpr) -> Value {
+        let value = self.compile_expr(expr);
+        self.to_string(&value, expr.typ)
+    }
+}
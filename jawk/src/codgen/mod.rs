@@ -5,15 +5,21 @@ pub mod variable_extract;
 
 pub use value::{ValuePtrT, ValueT};
 
+mod arrays;
+mod builtins;
+mod getline;
 mod subroutines;
 mod value;
 
 use crate::codgen::scopes::Scopes;
 use crate::codgen::subroutines::Subroutines;
 use crate::lexer::{BinOp, LogicalOp, MathOp};
-use crate::parser::{AwkT, Stmt, TypedExpr};
+use crate::parser::{AwkT, Output, Stmt, TypedExpr, SPECIAL_VARS};
 use crate::printable_error::PrintableError;
-use crate::runtime::{LiveRuntime, Runtime, TestRuntime};
+use crate::runtime::{
+    LiveRuntime, Runtime, TestRuntime, ValueOut, FNR_VAR, FS_SEP, NF_VAR, NR_VAR, OFS_SEP,
+    OUTPUT_APPEND, OUTPUT_COPROCESS, OUTPUT_FILE, OUTPUT_PIPE, RS_SEP,
+};
 use crate::Expr;
 use gnu_libjit::{Abi, Context, Function, Label, Value};
 use std::collections::HashSet;
@@ -31,25 +37,86 @@ use std::rc::Rc;
 
 pub const FLOAT_TAG: u8 = 0;
 pub const STRING_TAG: u8 = 1;
+// A variable that has never been assigned. Its pointer is a valid empty string so anything that
+// isn't FLOAT_TAG can be treated as a string, but typeof() can still tell it apart.
+pub const UNINIT_TAG: u8 = 2;
+// A string from outside the program, like a -v value, that looks numeric. The pointer is the
+// text it prints as and the float is its value, which comparisons use when the other side is a
+// number or strnum too.
+pub const STRNUM_TAG: u8 = 3;
 
-// Entry point to run a program
-pub fn compile_and_run(prog: Stmt, files: &[String]) -> Result<(), PrintableError> {
-    let mut runtime = LiveRuntime::new(files.to_vec());
+// Special variables that are always defined, and their initial values
+fn initialized_vars() -> impl Iterator<Item = (&'static str, &'static str)> {
+    SPECIAL_VARS
+        .iter()
+        .filter_map(|(name, value)| value.map(|value| (*name, value)))
+}
+
+const FS: &str = "FS";
+const OFS: &str = "OFS";
+const RS: &str = "RS";
+const ORS: &str = "ORS";
+const SUBSEP: &str = "SUBSEP";
+const OFMT: &str = "OFMT";
+const CONVFMT: &str = "CONVFMT";
+const NR: &str = "NR";
+const FNR: &str = "FNR";
+const NF: &str = "NF";
+pub const RT: &str = "RT";
+
+// NR, FNR and NF are kept by the runtime as it reads records. Reading one asks the runtime
+// instead of loading a variable so they are always numbers.
+pub fn record_var(name: &str) -> Option<u8> {
+    match name {
+        NR => Some(NR_VAR),
+        FNR => Some(FNR_VAR),
+        NF => Some(NF_VAR),
+        _ => None,
+    }
+}
+
+// Entry point to run a program. Returns the program's exit status.
+pub fn compile_and_run(prog: Stmt, files: &[String], header: bool) -> Result<i32, PrintableError> {
+    let mut runtime = LiveRuntime::new(files.to_vec(), header);
+    let mut codegen = CodeGen::new(&mut runtime);
+    codegen.compile(prog, false)?;
+    let status = codegen.run();
+    runtime.close_handles();
+    Ok(status)
+}
+
+// Entry point to run a program over input held in memory and collect what it prints
+pub fn compile_and_collect(prog: Stmt, input: String) -> Result<Vec<u8>, PrintableError> {
+    let mut runtime = LiveRuntime::capturing(input);
     let mut codegen = CodeGen::new(&mut runtime);
     codegen.compile(prog, false)?;
     codegen.run();
-    Ok(())
+    runtime.close_handles();
+    Ok(runtime.take_output())
 }
 
 // Entry point to run and debug/test a program. Use the test runtime.
-pub fn compile_and_capture(prog: Stmt, files: &[String]) -> Result<TestRuntime, PrintableError> {
-    let mut test_runtime = TestRuntime::new(files.to_vec());
+pub fn compile_and_capture(
+    prog: Stmt,
+    files: &[String],
+    header: bool,
+) -> Result<TestRuntime, PrintableError> {
+    let mut test_runtime = TestRuntime::new(files.to_vec(), header);
     let mut codegen = CodeGen::new(&mut test_runtime);
     codegen.compile(prog, true)?;
     codegen.run();
+    test_runtime.close_handles();
     Ok(test_runtime)
 }
 
+// Where break and continue jump to
+struct Loop {
+    break_label: Label,
+    continue_label: Label,
+    // The iterator of a for (k in a) loop, which has to be dropped when leaving early
+    iterator: Option<Value>,
+}
+
 struct CodeGen<'a, RuntimeT: Runtime> {
     // Core stuff
     pub(crate) function: Function,
@@ -61,6 +128,17 @@ struct CodeGen<'a, RuntimeT: Runtime> {
 
     // Used for commonly reused snippets like string-truthyness etc.
     subroutines: Subroutines,
+    // Every name used as an array anywhere in the program
+    arrays: HashSet<String>,
+    // Where `next` jumps to, the start of the loop over records
+    next_record: Option<Label>,
+    // Where `exit` jumps to. The start of END while exiting from BEGIN or the main loop, then the
+    // end of the program once in END. The exit code is kept unless the later exit sets it again.
+    exit_target: Option<Label>,
+    // Float the program returns as its exit status
+    exit_code: Value,
+    // The loops around the statement being compiled, innermost last
+    loops: Vec<Loop>,
 
     // These are effectively stack variables that we use as scratch space.
     binop_scratch: ValuePtrT,
@@ -96,6 +174,8 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
             function.create_value_float64(),
             function.create_value_void_ptr(),
         );
+        let exit_code = function.create_value_float64();
+        function.insn_store(&exit_code, &zero_f);
         let subroutines = Subroutines::new(&mut context, runtime);
         let codegen = CodeGen {
             function,
@@ -103,6 +183,11 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
             context,
             runtime,
             subroutines,
+            arrays: HashSet::new(),
+            next_record: None,
+            exit_target: None,
+            exit_code,
+            loops: vec![],
             binop_scratch,
             binop_scratch_int,
             zero_ptr,
@@ -113,15 +198,17 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
         codegen
     }
 
-    fn run(&mut self) {
-        let function: extern "C" fn() = self.function.to_closure();
-        function();
+    fn run(&mut self) -> i32 {
+        let function: extern "C" fn() -> f64 = self.function.to_closure();
+        function() as i32
     }
 
     fn compile(&mut self, prog: Stmt, dump: bool) -> Result<(), PrintableError> {
-        let zero = self.function.create_float64_constant(0.0);
         let vars = self.define_all_vars(&prog)?;
+        self.exit_target = Some(Label::new());
         self.compile_stmt(&prog);
+        let mut done = self.exit_target.take().unwrap();
+        self.function.insn_label(&mut done);
 
         // This is just so # strings allocated == # of strings freed which makes testing easier
         for var in vars {
@@ -129,7 +216,8 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
             self.drop_if_string_ptr(&var_ptrs, AwkT::Variable);
         }
 
-        self.function.insn_return(&zero);
+        let exit_code = self.exit_code.clone();
+        self.function.insn_return(&exit_code);
         self.context.build_end();
         if dump {
             println!("{}", self.function.dump().unwrap());
@@ -150,44 +238,84 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
     }
 
     fn define_all_vars(&mut self, prog: &Stmt) -> Result<HashSet<String>, PrintableError> {
-        // All variables are init'ed to the empty string.
-        let (vars, string_constants) = variable_extract::extract(prog);
-        for var in &vars {
+        let (mut vars, mut string_constants, arrays) = variable_extract::extract(prog);
+        self.arrays = arrays;
+        for (name, value) in initialized_vars() {
+            vars.insert(name.to_string());
+            string_constants.insert(value.to_string());
+        }
+
+        // All string constants like a in `print "a"`; are stored in a variable
+        // the name of the variable is " a". Just a space in front to prevent collisions.
+        for str_const in string_constants {
+            let space_in_front = format!(" {}", str_const);
             let tag = self.function.create_value_int();
             self.function.insn_store(&tag, &self.string_tag);
 
+            let ptr = Rc::into_raw(Rc::new(str_const)) as *mut c_void;
+            let ptr = self.function.create_void_ptr_constant(ptr);
             let ptr_value = self.function.create_value_void_ptr();
-            let ptr = self.runtime.empty_string(&mut self.function);
             self.function.insn_store(&ptr_value, &ptr);
 
             let float_value = self.function.create_value_float64();
             self.function.insn_store(&float_value, &self.zero_f);
 
             let val = ValueT::new(tag, float_value, ptr);
-            self.scopes.insert(var.clone(), val)?;
+            self.scopes.insert(space_in_front, val)?;
         }
 
-        // All string constants like a in `print "a"`; are stored in a variable
-        // the name of the variable is " a". Just a space in front to prevent collisions.
-        for str_const in string_constants {
-            let space_in_front = format!(" {}", str_const);
+        // All variables are init'ed to the empty string except special variables which start
+        // as a copy of their initial value.
+        let uninit_tag = self.function.create_sbyte_constant(UNINIT_TAG as c_char);
+        for var in &vars {
             let tag = self.function.create_value_int();
-            self.function.insn_store(&tag, &self.string_tag);
-
-            let ptr = Rc::into_raw(Rc::new(str_const)) as *mut c_void;
-            let ptr = self.function.create_void_ptr_constant(ptr);
             let ptr_value = self.function.create_value_void_ptr();
+            let ptr = match initialized_vars().find(|(name, _)| name == var) {
+                Some((_, value)) => {
+                    self.function.insn_store(&tag, &self.string_tag);
+                    let init = self.scopes.get(&format!(" {}", value)).pointer.clone();
+                    self.runtime.copy_string(&mut self.function, init)
+                }
+                None => {
+                    self.function.insn_store(&tag, &uninit_tag);
+                    self.runtime.empty_string(&mut self.function)
+                }
+            };
             self.function.insn_store(&ptr_value, &ptr);
 
             let float_value = self.function.create_value_float64();
             self.function.insn_store(&float_value, &self.zero_f);
 
             let val = ValueT::new(tag, float_value, ptr);
-            self.scopes.insert(space_in_front, val)?;
+            self.scopes.insert(var.clone(), val)?;
         }
         Ok(vars)
     }
 
+    // Turn an int condition (what insn_eq etc. and truthy_ret_integer give) into the float 1.0
+    // or 0.0. Everything boolean goes through here so no operator produces some other truthy value.
+    fn mkbool(&mut self, condition: &Value) -> Value {
+        let one = self.function.create_float64_constant(1.0);
+        let zero = self.function.create_float64_constant(0.0);
+        let mut true_lbl = Label::new();
+        let mut done_lbl = Label::new();
+        self.function.insn_branch_if(condition, &mut true_lbl);
+        self.function.insn_store(&self.binop_scratch.float, &zero);
+        self.function.insn_branch(&mut done_lbl);
+        self.function.insn_label(&mut true_lbl);
+        self.function.insn_store(&self.binop_scratch.float, &one);
+        self.function.insn_label(&mut done_lbl);
+        self.function.insn_load(&self.binop_scratch.float)
+    }
+
+    // An int 1 if the tag is a number or a strnum, whose float is valid to read
+    fn is_numeric(&mut self, tag: &Value) -> Value {
+        let strnum_tag = self.function.create_sbyte_constant(STRNUM_TAG as c_char);
+        let is_float = self.function.insn_eq(tag, &self.float_tag);
+        let is_strnum = self.function.insn_eq(tag, &strnum_tag);
+        self.function.insn_or(&is_float, &is_strnum)
+    }
+
     fn float_is_truthy_ret_int(&mut self, value: &Value) -> Value {
         let zero_f = self.function.create_float64_constant(0.0);
         self.function.insn_ne(&value, &zero_f)
@@ -211,8 +339,8 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
         let mut done_lbl = Label::new();
         self.function
             .insn_store(&self.binop_scratch.pointer, &value.pointer);
-        let is_string = self.function.insn_eq(&value.tag, &self.string_tag);
-        self.function.insn_branch_if(&is_string, &mut done_lbl);
+        let is_float = self.function.insn_eq(&value.tag, &self.float_tag);
+        self.function.insn_branch_if_not(&is_float, &mut done_lbl);
 
         let new_string = self
             .runtime
@@ -241,10 +369,10 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
                 self.drop(&value.pointer);
             }
             AwkT::Variable => {
-                let str_tag = self.string_tag();
+                let float_tag = self.float_tag();
                 let mut done_lbl = Label::new();
-                let is_string = self.function.insn_eq(&str_tag, &value.tag);
-                self.function.insn_branch_if_not(&is_string, &mut done_lbl);
+                let is_float = self.function.insn_eq(&float_tag, &value.tag);
+                self.function.insn_branch_if(&is_float, &mut done_lbl);
                 self.drop(&value.pointer);
                 self.function.insn_label(&mut done_lbl);
             }
@@ -275,9 +403,9 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
                 let mut string_lbl = Label::new();
                 let mut done_lbl = Label::new();
 
-                let one_tag = self.string_tag();
-                let tag_is_one = self.function.insn_eq(&value.tag, &one_tag);
-                self.function.insn_branch_if(&tag_is_one, &mut string_lbl);
+                // A strnum is true when its number isn't 0
+                let is_float = self.is_numeric(&value.tag);
+                self.function.insn_branch_if_not(&is_float, &mut string_lbl);
 
                 // is float code
                 let is_truthy_f = self.float_is_truthy_ret_int(&value.float);
@@ -313,10 +441,11 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
             AwkT::Variable => {
                 // If type unknown, check tag and call runtime if it's a string
                 let mut done = Label::new();
-                let is_string = self.function.insn_eq(&str_tag, &value.tag);
+                let float_tag = self.float_tag();
+                let is_float = self.function.insn_eq(&float_tag, &value.tag);
                 self.function
                     .insn_store(&self.binop_scratch.pointer, &self.zero_ptr);
-                self.function.insn_branch_if_not(&is_string, &mut done);
+                self.function.insn_branch_if(&is_float, &mut done);
                 let ptr = self.runtime.copy_string(&mut self.function, value.pointer);
                 self.function.insn_store(&self.binop_scratch.pointer, &ptr);
                 self.function.insn_label(&mut done);
@@ -332,26 +461,49 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
                 let res = self.compile_expr(expr);
                 self.drop_if_str(&res, expr.typ);
             }
-            Stmt::Print(expr) => {
-                let val = self.compile_expr(expr);
-                // Optimize print based on static knowledge of type
-                match expr.typ {
-                    AwkT::String => {
-                        self.runtime
-                            .print_string(&mut self.function, val.pointer.clone());
+            Stmt::Print {
+                args,
+                output: Some(output),
+            } => {
+                // The whole line a OFS b ORS is built first then written in one go
+                let ofs = TypedExpr::new_var(Expr::Variable(OFS.into()));
+                let ors = TypedExpr::new_var(Expr::Variable(ORS.into()));
+                let mut strings = vec![];
+                for (idx, arg) in args.iter().enumerate() {
+                    if idx != 0 {
+                        strings.push(self.compile_to_string(&ofs));
+                    }
+                    strings.push(self.compile_to_string(arg));
+                }
+                strings.push(self.compile_to_string(&ors));
+                let text = self.concat_values(&strings).pointer;
+                self.print_output(output, text);
+            }
+            Stmt::Print { args, output: None } => {
+                // print a, b prints a OFS b ORS
+                for (idx, arg) in args.iter().enumerate() {
+                    if idx != 0 {
+                        self.print_var(OFS);
                     }
-                    AwkT::Float => {
-                        self.runtime.print_float(&mut self.function, val.float);
-                        return;
+                    self.print_expr(arg);
+                }
+                self.print_var(ORS);
+            }
+            Stmt::Printf { fmt, args, output } => {
+                let fmt_value = self.compile_expr(fmt);
+                let fmt_str = self.to_string(&fmt_value, fmt.typ);
+                self.push_format_args(args);
+                match output {
+                    Some(output) => {
+                        let text = self.runtime.sprintf(&mut self.function, fmt_str.clone());
+                        self.runtime.free_string(&mut self.function, fmt_str);
+                        self.print_output(output, text);
                     }
-                    AwkT::Variable => {
-                        let str = self.to_string(&val, expr.typ);
-                        self.runtime.print_string(&mut self.function, str.clone());
-                        self.runtime.free_string(&mut self.function, str);
-                        return;
+                    None => {
+                        self.runtime.printf(&mut self.function, fmt_str.clone());
+                        self.runtime.free_string(&mut self.function, fmt_str);
                     }
                 }
-                self.drop_if_str(&val, expr.typ);
             }
             Stmt::Group(group) => {
                 for group in group {
@@ -382,6 +534,41 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
                 }
             }
             Stmt::While(test, body) => {
+                let mut test_label = Label::new();
+                let mut done_label = Label::new();
+                self.function.insn_label(&mut test_label);
+                if let Expr::NextLine = test.expr {
+                    let mut next_record = Label::new();
+                    self.function.insn_label(&mut next_record);
+                    self.next_record = Some(next_record);
+                }
+                let test_value = self.compile_expr(test);
+                let bool_value = self.truthy_ret_integer(&test_value, test.typ);
+                self.drop_if_str(&test_value, test.typ);
+                self.function
+                    .insn_branch_if_not(&bool_value, &mut done_label);
+                let mut lp = self.compile_loop_body(body, test_label, done_label, None);
+                self.function.insn_branch(&mut lp.continue_label);
+                self.function.insn_label(&mut lp.break_label);
+            }
+            Stmt::DoWhile(body, test) => {
+                let mut body_label = Label::new();
+                self.function.insn_label(&mut body_label);
+                let mut lp = self.compile_loop_body(body, Label::new(), Label::new(), None);
+                self.function.insn_label(&mut lp.continue_label);
+                let test_value = self.compile_expr(test);
+                let bool_value = self.truthy_ret_integer(&test_value, test.typ);
+                self.drop_if_str(&test_value, test.typ);
+                self.function.insn_branch_if(&bool_value, &mut body_label);
+                self.function.insn_label(&mut lp.break_label);
+            }
+            Stmt::For {
+                init,
+                test,
+                incr,
+                body,
+            } => {
+                self.compile_stmt(init);
                 let mut test_label = Label::new();
                 let mut done_label = Label::new();
                 self.function.insn_label(&mut test_label);
@@ -390,11 +577,128 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
                 self.drop_if_str(&test_value, test.typ);
                 self.function
                     .insn_branch_if_not(&bool_value, &mut done_label);
-                self.compile_stmt(body);
+                let mut lp = self.compile_loop_body(body, Label::new(), done_label, None);
+                self.function.insn_label(&mut lp.continue_label);
+                self.compile_stmt(incr);
                 self.function.insn_branch(&mut test_label);
-                self.function.insn_label(&mut done_label);
+                self.function.insn_label(&mut lp.break_label);
+            }
+            Stmt::ForIn {
+                var,
+                name,
+                path,
+                body,
+            } => self.compile_for_in(var, name, path, body),
+            Stmt::Delete {
+                name,
+                path,
+                indices,
+            } => self.compile_delete(name, path, indices),
+            Stmt::Break => {
+                let lp = self
+                    .loops
+                    .last_mut()
+                    .expect("break is only parsed in a loop");
+                if let Some(iterator) = &lp.iterator {
+                    self.runtime
+                        .array_stop_iterating(&mut self.function, iterator.clone());
+                }
+                self.function.insn_branch(&mut lp.break_label);
+            }
+            Stmt::Continue => {
+                let lp = self
+                    .loops
+                    .last_mut()
+                    .expect("continue is only parsed in a loop");
+                self.function.insn_branch(&mut lp.continue_label);
+            }
+            Stmt::Next => {
+                self.stop_iterating();
+                let next_record = self
+                    .next_record
+                    .as_mut()
+                    .expect("next is only parsed outside of BEGIN and END");
+                self.function.insn_branch(next_record);
+            }
+            Stmt::Exit(status) => {
+                if let Some(status) = status {
+                    let value = self.compile_expr(status);
+                    let code = self.to_float(&value, status.typ);
+                    self.drop_if_str(&value, status.typ);
+                    self.function.insn_store(&self.exit_code, &code);
+                }
+                self.stop_iterating();
+                let exit_target = self
+                    .exit_target
+                    .as_mut()
+                    .expect("exit target is set for the whole program");
+                self.function.insn_branch(exit_target);
             }
+            Stmt::Main(body) => {
+                // An exit before END jumps to END, an exit in END ends the program
+                self.exit_target = Some(Label::new());
+                self.compile_stmt(body);
+                let mut end_actions = self.exit_target.replace(Label::new()).unwrap();
+                self.function.insn_label(&mut end_actions);
+            }
+        }
+    }
+
+    // Compiles a loop's body with break and continue jumping to these labels. Returns them for
+    // the caller to place.
+    fn compile_loop_body(
+        &mut self,
+        body: &Stmt,
+        continue_label: Label,
+        break_label: Label,
+        iterator: Option<Value>,
+    ) -> Loop {
+        self.loops.push(Loop {
+            break_label,
+            continue_label,
+            iterator,
+        });
+        self.compile_stmt(body);
+        self.loops.pop().unwrap()
+    }
+
+    // next and exit leave every loop. Dropping the outermost for (k in a) iterator drops those
+    // inside it as well.
+    fn stop_iterating(&mut self) {
+        if let Some(iterator) = self.loops.iter().find_map(|lp| lp.iterator.clone()) {
+            self.runtime
+                .array_stop_iterating(&mut self.function, iterator);
+        }
+    }
+
+    fn print_expr(&mut self, expr: &TypedExpr) {
+        let val = self.compile_expr(expr);
+        // Optimize print based on static knowledge of type
+        if let AwkT::Float = expr.typ {
+            self.runtime.print_float(&mut self.function, val.float);
+            return;
         }
+        let str = self.to_string(&val, expr.typ);
+        self.runtime.print_string(&mut self.function, str.clone());
+        self.runtime.free_string(&mut self.function, str);
+    }
+
+    // Writes text, which is consumed, to where a print or printf is redirected
+    fn print_output(&mut self, output: &Output, text: Value) {
+        let (target, kind) = match output {
+            Output::File(target) => (target, OUTPUT_FILE),
+            Output::Append(target) => (target, OUTPUT_APPEND),
+            Output::Pipe(target) => (target, OUTPUT_PIPE),
+            Output::Coprocess(target) => (target, OUTPUT_COPROCESS),
+        };
+        let target = self.compile_to_string(target);
+        let kind = self.function.create_sbyte_constant(kind as c_char);
+        self.runtime
+            .print_output(&mut self.function, kind, target, text);
+    }
+
+    fn print_var(&mut self, name: &str) {
+        self.print_expr(&TypedExpr::new_var(Expr::Variable(name.into())));
     }
 
     // When compile_expr returns a string the caller is responsible for freeing it
@@ -417,6 +721,7 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
                     self.drop_if_str(&old_value, AwkT::Variable);
                     let new_value = self.concat_values(&strings_to_concat);
                     self.store(&var_ptrs, &new_value);
+                    self.assigned_special_var(var, &new_value, AwkT::String);
                     return self.copy_if_string(new_value, AwkT::Variable);
                 }
                 let new_value = self.compile_expr(value);
@@ -424,6 +729,7 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
                 let old_value = self.load(&var_ptrs);
                 self.drop_if_str(&old_value, AwkT::Variable);
                 self.store(&var_ptrs, &new_value);
+                self.assigned_special_var(var, &new_value, value.typ);
                 self.copy_if_string(new_value, value.typ)
             }
             Expr::NumberF64(num) => ValueT::new(
@@ -431,6 +737,18 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
                 self.function.create_float64_constant(*num),
                 self.zero_ptr.clone(),
             ),
+            Expr::Regex(regex) => {
+                // A bare /re/ is short for $0 ~ /re/
+                let record = TypedExpr::new_str(Expr::Column(Box::new(TypedExpr::new_num(
+                    Expr::NumberF64(0.0),
+                ))));
+                let pattern = TypedExpr::new_str(Expr::String(regex.clone()));
+                self.compile_expr(&TypedExpr::new_num(Expr::BinOp(
+                    Box::new(record),
+                    BinOp::MatchedBy,
+                    Box::new(pattern),
+                )))
+            }
             Expr::String(str) => {
                 // Every string constant is stored in a variable with the name " name"
                 // the space ensures we don't collide with normal variable names;
@@ -441,6 +759,14 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
                 let new_ptr = self.runtime.copy_string(&mut self.function, var.pointer);
                 ValueT::new(string_tag, zero, new_ptr)
             }
+            Expr::StrNum(str, num) => {
+                let var_ptr = self.scopes.get(&format!(" {}", str)).clone();
+                let var = self.load(&var_ptr);
+                let tag = self.function.create_sbyte_constant(STRNUM_TAG as c_char);
+                let num = self.function.create_float64_constant(*num);
+                let new_ptr = self.runtime.copy_string(&mut self.function, var.pointer);
+                ValueT::new(tag, num, new_ptr)
+            }
             Expr::MathOp(left_expr, op, right_expr) => {
                 // Convert left and right to floats if needed and perform the MathOp
                 let mut left = self.compile_expr(left_expr);
@@ -475,6 +801,28 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
                 };
                 ValueT::new(zero, result, self.zero_ptr.clone())
             }
+            Expr::BinOp(left_expr, op @ (BinOp::MatchedBy | BinOp::NotMatchedBy), right_expr) => {
+                // Matching is always done on strings, there's no float fast path. A /re/ on the
+                // right is the pattern itself rather than $0 ~ /re/.
+                let left = self.compile_expr(left_expr);
+                let left = self.to_string(&left, left_expr.typ);
+                let right = match &right_expr.expr {
+                    Expr::Regex(regex) => {
+                        let pattern = TypedExpr::new_str(Expr::String(regex.clone()));
+                        self.compile_expr(&pattern).pointer
+                    }
+                    _ => {
+                        let right = self.compile_expr(right_expr);
+                        self.to_string(&right, right_expr.typ)
+                    }
+                };
+                let res = self
+                    .runtime
+                    .binop(&mut self.function, left.clone(), right.clone(), *op);
+                self.drop(&left);
+                self.drop(&right);
+                ValueT::new(self.float_tag(), res, self.zero_ptr.clone())
+            }
             Expr::BinOp(left_expr, op, right_expr) => {
                 let left = self.compile_expr(left_expr);
                 let right = self.compile_expr(right_expr);
@@ -492,8 +840,9 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
                     _ => {}
                 }
 
-                let left_is_float = self.function.insn_eq(&tag, &left.tag);
-                let right_is_float = self.function.insn_eq(&tag, &right.tag);
+                // A strnum compares as a number when the other side is one too
+                let left_is_float = self.is_numeric(&left.tag);
+                let right_is_float = self.is_numeric(&right.tag);
                 let mut both_float_lbl = Label::new();
                 let mut done_lbl = Label::new();
                 let both_float = self.function.insn_and(&left_is_float, &right_is_float);
@@ -526,58 +875,83 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
                 self.load(&self.binop_scratch.clone())
             }
             Expr::LogicalOp(left, op, right) => {
-                let float_1 = self.function.create_float64_constant(1.0);
-                let float_0 = self.function.create_float64_constant(0.0);
-                // Short circuiting and and or operators.
+                let int_1 = self.function.create_int_constant(1);
+                let int_0 = self.function.create_int_constant(0);
+                // Short circuiting and and or operators. The outcome is stored in binop_scratch_int
+                // and turned into 1.0/0.0 by mkbool.
                 // Gotta be careful to free values appropriately and only when they are actually created.
-                let res = match op {
-                    LogicalOp::And => {
-                        let mut ret_false = Label::new();
-                        let mut done = Label::new();
-                        let left_val = self.compile_expr(left);
-                        let l = self.truthy_ret_integer(&left_val, left.typ);
-                        self.drop_if_str(&left_val, left.typ);
-                        self.function.insn_branch_if_not(&l, &mut ret_false);
-                        let right_val = self.compile_expr(right);
-                        let r = self.truthy_ret_integer(&right_val, right.typ);
-                        self.drop_if_str(&right_val, right.typ);
-                        self.function.insn_branch_if_not(&r, &mut ret_false);
-                        self.function
-                            .insn_store(&self.binop_scratch.float, &float_1);
-                        self.function.insn_branch(&mut done);
-                        self.function.insn_label(&mut ret_false);
-                        self.function
-                            .insn_store(&self.binop_scratch.float, &float_0);
-                        self.function.insn_branch(&mut done);
-                        self.function.insn_label(&mut done);
-                        let tag = self.float_tag();
-                        let result_f = self.function.insn_load(&self.binop_scratch.float);
-                        ValueT::new(tag, result_f, self.zero_ptr.clone())
-                    }
-                    LogicalOp::Or => {
-                        let mut done = Label::new();
-                        let mut return_true = Label::new();
-                        let left_val = self.compile_expr(left);
-                        let l = self.truthy_ret_integer(&left_val, left.typ);
-                        self.drop_if_str(&left_val, left.typ);
-                        self.function.insn_branch_if(&l, &mut return_true);
-                        let right_val = self.compile_expr(right);
-                        let r = self.truthy_ret_integer(&right_val, right.typ);
-                        self.drop_if_str(&right_val, right.typ);
-                        self.function.insn_branch_if(&r, &mut return_true);
-                        self.function
-                            .insn_store(&self.binop_scratch.float, &float_0);
-                        self.function.insn_branch(&mut done);
-                        self.function.insn_label(&mut return_true);
-                        self.function
-                            .insn_store(&self.binop_scratch.float, &float_1);
-                        self.function.insn_label(&mut done);
-                        let tag = self.float_tag();
-                        let result_f = self.function.insn_load(&self.binop_scratch.float);
-                        ValueT::new(tag, result_f, self.zero_ptr.clone())
-                    }
+                let mut done = Label::new();
+                let mut short_circuit = Label::new();
+                let (short_circuit_result, other_result) = match op {
+                    LogicalOp::And => (&int_0, &int_1),
+                    LogicalOp::Or => (&int_1, &int_0),
                 };
-                res
+                for side in [left, right] {
+                    let side_val = self.compile_expr(side);
+                    let truthy = self.truthy_ret_integer(&side_val, side.typ);
+                    self.drop_if_str(&side_val, side.typ);
+                    match op {
+                        LogicalOp::And => self
+                            .function
+                            .insn_branch_if_not(&truthy, &mut short_circuit),
+                        LogicalOp::Or => self.function.insn_branch_if(&truthy, &mut short_circuit),
+                    }
+                }
+                self.function
+                    .insn_store(&self.binop_scratch_int, other_result);
+                self.function.insn_branch(&mut done);
+                self.function.insn_label(&mut short_circuit);
+                self.function
+                    .insn_store(&self.binop_scratch_int, short_circuit_result);
+                self.function.insn_label(&mut done);
+                let result = self.function.insn_load(&self.binop_scratch_int);
+                let tag = self.float_tag();
+                let result_f = self.mkbool(&result);
+                ValueT::new(tag, result_f, self.zero_ptr.clone())
+            }
+            Expr::Ternary(test, if_so, if_not) => {
+                // Only the branch taken is compiled into the path run, it leaves its value in
+                // result. The branches' types can differ, the tag says which one it was.
+                let result = ValueT::new(
+                    self.function.create_value_int(),
+                    self.function.create_value_float64(),
+                    self.function.create_value_void_ptr(),
+                );
+                let mut if_not_lbl = Label::new();
+                let mut done_lbl = Label::new();
+                let test_value = self.compile_expr(test);
+                let truthy = self.truthy_ret_integer(&test_value, test.typ);
+                self.drop_if_str(&test_value, test.typ);
+                self.function.insn_branch_if_not(&truthy, &mut if_not_lbl);
+                let value = self.compile_expr(if_so);
+                self.store(&result, &value);
+                self.function.insn_branch(&mut done_lbl);
+                self.function.insn_label(&mut if_not_lbl);
+                let value = self.compile_expr(if_not);
+                self.store(&result, &value);
+                self.function.insn_label(&mut done_lbl);
+                self.load(&result)
+            }
+            Expr::Not(value) => {
+                let compiled = self.compile_expr(value);
+                let truthy = self.truthy_ret_integer(&compiled, value.typ);
+                self.drop_if_str(&compiled, value.typ);
+                let int_0 = self.function.create_int_constant(0);
+                let falsy = self.function.insn_eq(&truthy, &int_0);
+                let tag = self.float_tag();
+                let result_f = self.mkbool(&falsy);
+                ValueT::new(tag, result_f, self.zero_ptr.clone())
+            }
+            Expr::Variable(var) if record_var(var).is_some() => {
+                let id = record_var(var).unwrap() as c_char;
+                let id = self.function.create_sbyte_constant(id);
+                let value = self.runtime.record_var(&mut self.function, id);
+                ValueT::new(self.float_tag(), value, self.zero_ptr.clone())
+            }
+            // Like the record vars RT is kept by the runtime, it is always a string
+            Expr::Variable(var) if *var == RT => {
+                let value = self.runtime.record_terminator(&mut self.function);
+                ValueT::new(self.string_tag(), self.zero_f.clone(), value)
             }
             Expr::Variable(var) => {
                 // compile_expr returns a string/float that is 'owned' by the caller.
@@ -598,11 +972,12 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
                         // otherwise store zero self.binop_scratch.pointer. After this load self.binop_scratch.pointer
                         // and make a new value with the old tag/float + new string pointer.
                         let var = self.load(&var_ptr);
-                        let is_not_str = self.function.insn_eq(&string_tag, &var.tag);
+                        let float_tag = self.float_tag();
+                        let is_float = self.function.insn_eq(&float_tag, &var.tag);
                         let mut done_lbl = Label::new();
                         let mut is_not_str_lbl = Label::new();
                         self.function
-                            .insn_branch_if_not(&is_not_str, &mut is_not_str_lbl);
+                            .insn_branch_if(&is_float, &mut is_not_str_lbl);
                         let new_ptr = self.runtime.copy_string(&mut self.function, var.pointer);
                         self.function
                             .insn_store(&self.binop_scratch.pointer, &new_ptr);
@@ -631,7 +1006,41 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
                 self.drop_if_str(&column, col.typ);
                 ValueT::new(tag, self.function.create_float64_constant(0.0), val)
             }
-            Expr::Call => {
+            Expr::ColumnAssign { index, value } => {
+                let new_value = self.compile_expr(value);
+                let copy = self.copy_if_string(new_value.clone(), value.typ);
+                let field = self.to_string(&copy, value.typ);
+                self.store_column(index, field);
+                new_value
+            }
+            Expr::Call { target, args } => self.compile_builtin(target, args),
+            Expr::ArrayIndex {
+                name,
+                path,
+                indices,
+            } => self.compile_array_index(name, path, indices),
+            Expr::ArrayAssign {
+                name,
+                path,
+                indices,
+                value,
+            } => self.compile_array_assign(name, path, indices, value),
+            Expr::InArray {
+                indices,
+                name,
+                path,
+            } => self.compile_in_array(indices, name, path),
+            Expr::Getline { into, source } => self.compile_getline(into, source),
+            Expr::NfAssign(value) => {
+                let new_value = self.compile_expr(value);
+                let nf = self.to_float(&new_value, value.typ);
+                self.drop_if_str(&new_value, value.typ);
+                let id = self.function.create_sbyte_constant(NF_VAR as c_char);
+                self.runtime
+                    .set_record_var(&mut self.function, id, nf.clone());
+                ValueT::new(self.float_tag(), nf, self.zero_ptr.clone())
+            }
+            Expr::NextLine => {
                 // Ask runtime if there is a next line. Returns a float 0 or 1
                 let one = self.float_tag();
                 let next_line_exists = self.runtime.call_next_line(&mut self.function);
@@ -645,6 +1054,45 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
         }
     }
 
+    // $index = field, the runtime takes the string
+    fn store_column(&mut self, index: &TypedExpr, field: Value) {
+        let index_value = self.compile_expr(index);
+        let idx = self.to_float(&index_value, index.typ);
+        self.drop_if_str(&index_value, index.typ);
+        self.runtime.set_column(&mut self.function, idx, field);
+    }
+
+    // The runtime keeps its own copy of the special variables it uses itself
+    fn assigned_special_var(&mut self, var: &str, value: &ValueT, typ: AwkT) {
+        if var == NR || var == FNR {
+            let id = record_var(var).unwrap() as c_char;
+            let id = self.function.create_sbyte_constant(id);
+            let value = self.to_float(value, typ);
+            self.runtime.set_record_var(&mut self.function, id, value);
+            return;
+        }
+        if var == OFS || var == FS || var == RS {
+            let copy = self.copy_if_string(value.clone(), typ);
+            let sep = self.to_string(&copy, typ);
+            let id = match var {
+                FS => FS_SEP,
+                RS => RS_SEP,
+                _ => OFS_SEP,
+            };
+            let id = self.function.create_sbyte_constant(id as c_char);
+            self.runtime.set_separator(&mut self.function, id, sep);
+            return;
+        }
+        if var != OFMT && var != CONVFMT {
+            return;
+        }
+        let copy = self.copy_if_string(value.clone(), typ);
+        let fmt = self.to_string(&copy, typ);
+        let is_ofmt = self.function.create_sbyte_constant((var == OFMT) as c_char);
+        self.runtime
+            .set_number_format(&mut self.function, is_ofmt, fmt);
+    }
+
     fn float_binop(&mut self, a: &Value, b: &Value, op: BinOp) -> Value {
         let bool = match op {
             BinOp::Greater => self.function.insn_gt(a, b),
@@ -656,18 +1104,7 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
             BinOp::MatchedBy => todo!("regex for float??"),
             BinOp::NotMatchedBy => todo!("regex for float??"),
         };
-        let one = self.function.create_float64_constant(1.0);
-        let zero = self.function.create_float64_constant(0.0);
-        let mut true_lbl = Label::new();
-        let mut done_lbl = Label::new();
-        self.function.insn_branch_if(&bool, &mut true_lbl);
-        self.function.insn_store(&self.binop_scratch.float, &zero);
-        self.function.insn_branch(&mut done_lbl);
-        self.function.insn_label(&mut true_lbl);
-        self.function.insn_store(&self.binop_scratch.float, &one);
-
-        self.function.insn_label(&mut done_lbl);
-        self.function.insn_load(&self.binop_scratch.float)
+        self.mkbool(&bool)
     }
 
     fn compile_exprs_to_string(&mut self, exprs: &Vec<TypedExpr>) -> Vec<Value> {
@@ -702,6 +1139,26 @@ impl<'a, RuntimeT: Runtime> CodeGen<'a, RuntimeT> {
         ValueT::new(tag, val, ptr)
     }
 
+    // Load the value a runtime call returned through a ValueOut
+    fn load_value_out(&mut self, out: &Value) -> ValueT {
+        let tag = self.function.insn_load_relative(
+            out,
+            ValueOut::TAG_OFFSET as c_long,
+            &Context::sbyte_type(),
+        );
+        let float = self.function.insn_load_relative(
+            out,
+            ValueOut::FLOAT_OFFSET as c_long,
+            &Context::float64_type(),
+        );
+        let pointer = self.function.insn_load_relative(
+            out,
+            ValueOut::POINTER_OFFSET as c_long,
+            &Context::void_ptr_type(),
+        );
+        ValueT::new(tag, float, pointer)
+    }
+
     fn store(&mut self, ptr: &ValuePtrT, value: &ValueT) {
         self.function.insn_store(&ptr.tag, &value.tag);
         self.function.insn_store(&ptr.float, &value.float);
@@ -26,4 +26,8 @@ impl Scopes {
     pub fn get(&self, name: &str) -> &ValuePtrT {
         self.scopes.get(name).unwrap()
     }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.scopes.contains_key(name)
+    }
 }
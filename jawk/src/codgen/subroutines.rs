@@ -1,7 +1,7 @@
-use crate::codgen::ValueT;
+use crate::codgen::{ValueT, FLOAT_TAG, STRNUM_TAG};
 use crate::runtime::Runtime;
 use gnu_libjit::{Abi, Context, Function, JitType, Label};
-use std::os::raw::c_long;
+use std::os::raw::{c_char, c_long};
 
 #[allow(dead_code)]
 // Lazily constructs subroutines for use by the codegen module.
@@ -98,7 +98,8 @@ impl Subroutines {
         let mut func = ctx
             .function(Abi::Cdecl, Context::float64_type(), Subroutines::value())
             .expect("unable to create a new sub routine");
-        let zero = func.create_sbyte_constant(0);
+        let float_tag = func.create_sbyte_constant(FLOAT_TAG as c_char);
+        let strnum_tag = func.create_sbyte_constant(STRNUM_TAG as c_char);
         let result = func.create_value_float64();
         let mut done_lbl = Label::new();
         let arg0 = ValueT::new(
@@ -107,8 +108,11 @@ impl Subroutines {
             func.arg(2).unwrap(),
         );
         func.insn_store(&result, &arg0.float);
-        let is_float = func.insn_eq(&arg0.tag, &zero);
-        func.insn_branch_if(&is_float, &mut done_lbl);
+        // A strnum already has its number
+        let is_float = func.insn_eq(&arg0.tag, &float_tag);
+        let is_strnum = func.insn_eq(&arg0.tag, &strnum_tag);
+        let is_numeric = func.insn_or(&is_float, &is_strnum);
+        func.insn_branch_if(&is_numeric, &mut done_lbl);
 
         let res = runtime.string_to_number(&mut func, arg0.pointer.clone());
         func.insn_store(&result, &res);
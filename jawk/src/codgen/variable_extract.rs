@@ -1,69 +1,115 @@
-use crate::parser::{Stmt, TypedExpr};
+use crate::columns::DEFAULT_FPAT;
+use crate::parser::{walk_lvalue, Lvalue, Stmt, TypedExpr, Visitor};
 use crate::Expr;
 use std::collections::HashSet;
 
-// Returns 0. the list of all variables  1. All string constants
-pub fn extract(prog: &Stmt) -> (HashSet<String>, HashSet<String>) {
-    let mut vars = HashSet::new();
-    let mut string_constants = HashSet::new();
-    extract_stmt(prog, &mut vars, &mut string_constants);
-    (vars, string_constants)
+// Returns 0. the list of all variables  1. All string constants  2. Every name used as an array
+pub fn extract(prog: &Stmt) -> (HashSet<String>, HashSet<String>, HashSet<String>) {
+    let mut extract = Extract {
+        vars: HashSet::new(),
+        consts: HashSet::new(),
+        arrays: HashSet::new(),
+    };
+    extract.visit_stmt(prog);
+    (extract.vars, extract.consts, extract.arrays)
 }
 
-fn extract_stmt(stmt: &Stmt, vars: &mut HashSet<String>, consts: &mut HashSet<String>) {
-    match stmt {
-        Stmt::Expr(expr) => extract_expr(expr, vars, consts),
-        Stmt::Print(expr) => extract_expr(expr, vars, consts),
-        Stmt::Group(group) => {
-            for elem in group {
-                extract_stmt(elem, vars, consts);
+struct Extract {
+    vars: HashSet<String>,
+    consts: HashSet<String>,
+    arrays: HashSet<String>,
+}
+
+impl Visitor for Extract {
+    fn visit_for_in(&mut self, var: &str, name: &str, path: &[Vec<TypedExpr>], body: &Stmt) {
+        self.vars.insert(var.to_string());
+        self.arrays.insert(name.to_string());
+        self.visit_path(path, &[]);
+        self.visit_stmt(body);
+    }
+
+    fn visit_delete(&mut self, name: &str, path: &[Vec<TypedExpr>], indices: &[TypedExpr]) {
+        self.arrays.insert(name.to_string());
+        self.visit_path(path, indices);
+    }
+
+    fn visit_lvalue(&mut self, lvalue: &Lvalue) {
+        match lvalue {
+            Lvalue::Variable(var) => {
+                self.vars.insert(var.to_string());
             }
-        }
-        Stmt::If(test, if_block, else_block) => {
-            extract_expr(test, vars, consts);
-            extract_stmt(if_block, vars, consts);
-            if let Some(else_block) = else_block {
-                extract_stmt(else_block, vars, consts);
+            Lvalue::ArrayIndex { name, .. } => {
+                self.arrays.insert(name.clone());
             }
+            Lvalue::Column(_) => {}
         }
-        Stmt::While(test, body) => {
-            extract_expr(test, vars, consts);
-            extract_stmt(body, vars, consts);
-        }
+        walk_lvalue(self, lvalue);
     }
-}
 
-fn extract_expr(expr: &TypedExpr, vars: &mut HashSet<String>, consts: &mut HashSet<String>) {
-    match &expr.expr {
-        Expr::Variable(var) => {
-            vars.insert(var.clone());
-        }
-        Expr::String(str) => {
-            consts.insert(str.to_string());
-        }
-        Expr::NumberF64(_n) => {}
-        Expr::BinOp(left, _op, right) => {
-            extract_expr(left, vars, consts);
-            extract_expr(right, vars, consts);
-        }
-        Expr::MathOp(left, _op, right) => {
-            extract_expr(left, vars, consts);
-            extract_expr(right, vars, consts);
-        }
-        Expr::LogicalOp(left, _op, right) => {
-            extract_expr(left, vars, consts);
-            extract_expr(right, vars, consts);
+    fn visit_assign(&mut self, var: &str, value: &TypedExpr) {
+        self.vars.insert(var.to_string());
+        self.visit_expr(value);
+    }
+
+    fn visit_string(&mut self, str: &str) {
+        self.consts.insert(str.to_string());
+    }
+
+    // Passed to the runtime as the source string of the pattern
+    fn visit_regex(&mut self, regex: &str) {
+        self.consts.insert(regex.to_string());
+    }
+
+    fn visit_variable(&mut self, name: &str) {
+        self.vars.insert(name.to_string());
+    }
+
+    // Arrays live in the runtime, only their indices can reference variables
+    fn visit_array_index(&mut self, name: &str, path: &[Vec<TypedExpr>], indices: &[TypedExpr]) {
+        self.arrays.insert(name.to_string());
+        self.visit_path(path, indices);
+    }
+
+    fn visit_in_array(&mut self, indices: &[TypedExpr], name: &str, path: &[Vec<TypedExpr>]) {
+        self.arrays.insert(name.to_string());
+        self.visit_path(path, indices);
+    }
+
+    fn visit_array_assign(
+        &mut self,
+        name: &str,
+        path: &[Vec<TypedExpr>],
+        indices: &[TypedExpr],
+        value: &TypedExpr,
+    ) {
+        self.arrays.insert(name.to_string());
+        self.visit_path(path, indices);
+        self.visit_expr(value);
+    }
+
+    fn visit_call(&mut self, target: &str, args: &[TypedExpr]) {
+        if target == "typeof" {
+            // Answers typeof gives without asking the runtime
+            self.consts.insert("array".to_string());
+            self.consts.insert("untyped".to_string());
         }
-        Expr::Column(col) => extract_expr(col, vars, consts),
-        Expr::Call => {}
-        Expr::Assign(var, value) => {
-            vars.insert(var.clone());
-            extract_expr(value, vars, consts);
+        if target == "patsplit" {
+            self.consts.insert(DEFAULT_FPAT.to_string());
         }
-        Expr::Concatenation(vals) => {
-            for val in vals {
-                extract_expr(val, vars, consts);
+        for (idx, arg) in args.iter().enumerate() {
+            // patsplit's array arguments are filled in, not read
+            if target == "patsplit" && (idx == 1 || idx == 3) {
+                if let Expr::Variable(name) = &arg.expr {
+                    self.arrays.insert(name.to_string());
+                    continue;
+                }
+            }
+            // typeof(x) and isarray(x) don't use x. If nothing else does it stays untyped.
+            let type_query = target == "typeof" || target == "isarray";
+            if type_query && matches!(arg.expr, Expr::Variable(_)) {
+                continue;
             }
+            self.visit_expr(arg);
         }
     }
 }
@@ -1,4 +1,6 @@
-use std::collections::HashMap;
+use crate::regex::Regex;
+use crate::runtime::is_stdin;
+use std::collections::{HashMap, VecDeque};
 use std::path::PathBuf;
 
 type Line = HashMap<usize, String>;
@@ -6,140 +8,488 @@ type Line = HashMap<usize, String>;
 pub struct Columns {
     rs: String,
     fs: String,
+    ofs: String,
+    records: Records,
+    // The current record split into fields, 0 is the whole record
+    line: Line,
+    // With the header option the first record names the fields. None until it has been read.
+    use_header: bool,
+    header: Option<HashMap<String, usize>>,
+}
+
+// The records of the input files. The main loop, plain getline and getline var all take the
+// next record from here so each record is read exactly once, and NR/FNR count them in one place.
+struct Records {
+    // Files still to open, the next one last
     files: Vec<String>,
-    current_path: Option<String>,
-    lines: HashMap<usize, Line>,
-    line_number: Option<usize>,
+    // Input handed over directly rather than through a file, read before any file
+    text: Option<String>,
+    // Records of the current file not read yet, each with the separator that ended it
+    pending: VecDeque<(String, String)>,
+    // The separator that ended the last record read, which is RT
+    rt: String,
+    // Records read so far in total and in the current file
+    nr: usize,
+    fnr: usize,
+}
+
+impl Records {
+    fn new(files: Vec<String>) -> Self {
+        Records {
+            files: files.into_iter().rev().collect(),
+            text: None,
+            pending: VecDeque::new(),
+            rt: String::new(),
+            nr: 0,
+            fnr: 0,
+        }
+    }
+
+    // The next record, moving on through the files as each runs out
+    fn next(&mut self, rs: &str) -> Option<String> {
+        loop {
+            if let Some((record, rt)) = self.pending.pop_front() {
+                self.nr += 1;
+                self.fnr += 1;
+                self.rt = rt;
+                return Some(record);
+            }
+            let contents = match self.text.take() {
+                Some(text) => text,
+                None => {
+                    let next_file = self.files.pop()?;
+                    let contents = if is_stdin(&next_file) {
+                        std::io::read_to_string(std::io::stdin())
+                    } else {
+                        std::fs::read_to_string(PathBuf::from(next_file.clone()))
+                    };
+                    match contents {
+                        Ok(s) => s,
+                        Err(err) => {
+                            eprintln!("Unable to load file @ `{}`\nErr: {}", next_file, err);
+                            std::process::exit(-1);
+                        }
+                    }
+                }
+            };
+            self.fnr = 0;
+            self.split(rs, &contents);
+        }
+    }
+
+    // RS changed, the records not read yet are joined back up with their separators and split
+    // again so the new RS applies from the next record on
+    fn resplit(&mut self, rs: &str) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let rest = self
+            .pending
+            .drain(..)
+            .map(|(record, rt)| record + &rt)
+            .collect::<String>();
+        self.split(rs, &rest);
+    }
+
+    fn split(&mut self, rs: &str, contents: &str) {
+        self.pending = match split_records(rs, contents) {
+            Ok(records) => records,
+            Err(err) => {
+                eprintln!("RS is not a valid regex: {}", err);
+                std::process::exit(-1);
+            }
+        };
+    }
+}
+
+// Every record of a file with the separator that ended it. A single character RS separates
+// records as is, a longer one is a regex like in gawk. RS = "" is paragraph mode where records
+// are separated by one or more blank lines, and newlines at the start and end of the file are
+// ignored.
+fn split_records(rs: &str, contents: &str) -> Result<VecDeque<(String, String)>, String> {
+    if rs.is_empty() {
+        let contents = contents.trim_start_matches('\n');
+        let blank_lines = Regex::new("\n\n+").unwrap().find_all(contents);
+        let mut records = separated(contents, &blank_lines);
+        // The newlines ending the file end the last record rather than being part of it
+        if let Some((record, rt)) = records.back_mut() {
+            let len = record.trim_end_matches('\n').len();
+            *rt = record.split_off(len) + rt;
+        }
+        return Ok(records);
+    }
+    let separators = if rs.chars().count() == 1 {
+        contents
+            .match_indices(rs)
+            .map(|(idx, sep)| (idx, idx + sep.len()))
+            .collect()
+    } else {
+        Regex::new(rs)?.find_all(contents)
+    };
+    Ok(separated(contents, &separators))
+}
+
+// The fields of a record split on fs. Like RS a single char is used as is and a longer FS is a
+// regex.
+fn split_fields<'a>(fs: &str, line: &'a str) -> Result<Vec<&'a str>, String> {
+    if fs.chars().count() == 1 {
+        return Ok(line.split(fs).collect());
+    }
+    let mut fields = vec![];
+    let mut start = 0;
+    for (sep_start, sep_end) in Regex::new(fs)?.find_all(line) {
+        fields.push(&line[start..sep_start]);
+        start = sep_end;
+    }
+    fields.push(&line[start..]);
+    Ok(fields)
+}
+
+// The text between the separators, which are byte ranges of contents. An empty line is an empty
+// record, only the separator ending the last record doesn't start another one.
+fn separated(contents: &str, separators: &[(usize, usize)]) -> VecDeque<(String, String)> {
+    let mut records = VecDeque::new();
+    let mut start = 0;
+    for (sep_start, sep_end) in separators {
+        let record = contents[start..*sep_start].to_string();
+        records.push_back((record, contents[*sep_start..*sep_end].to_string()));
+        start = *sep_end;
+    }
+    if start < contents.len() {
+        records.push_back((contents[start..].to_string(), String::new()));
+    }
+    records
 }
 
 impl Columns {
     pub fn new(files: Vec<String>) -> Self {
-        let c = Columns {
+        Columns {
             rs: String::from("\n"),
             fs: String::from(" "),
-            files: files.into_iter().rev().collect(),
-            line_number: None,
-            lines: HashMap::new(),
-            current_path: None,
-        };
-        c
+            ofs: String::from(" "),
+            records: Records::new(files),
+            line: HashMap::new(),
+            use_header: false,
+            header: None,
+        }
     }
 
-    fn get_line_number(&self) -> usize {
-        if let Some(line) = self.line_number {
-            line
-        } else {
-            0
-        }
+    // Records come from the given text instead of files
+    pub fn from_text(text: String) -> Self {
+        let mut columns = Columns::new(vec![]);
+        columns.records.text = Some(text);
+        columns
     }
 
     pub fn get(&mut self, column: usize) -> String {
-        if let Some(line) = self.lines.get(&self.get_line_number()) {
-            if let Some(field) = line.get(&column) {
-                return field.to_string();
-            }
+        match self.line.get(&column) {
+            Some(field) => field.to_string(),
+            None => "".to_string(),
         }
-        "".to_string()
     }
 
-    fn parse_input_file(fs: &str, rs: &str, contents: String) -> HashMap<usize, Line> {
-        let mut lines = HashMap::new();
-        for (line_idx, line) in contents.split(rs).enumerate() {
-            if line.len() == 0 {
-                continue;
-            }
-
-            let mut map = HashMap::new();
-            map.insert(0, line.to_string());
-            for (field_idx, field) in line.split(fs).enumerate() {
-                map.insert(field_idx + 1, field.to_string());
-            }
-            lines.insert(line_idx, map);
+    // Assigning to $0 re-splits the record into fields. Assigning to any other field
+    // rebuilds $0 from the fields joined by OFS, padding with empty fields if needed.
+    // This is the coupling sub/gsub (and field assignment) rely on.
+    pub fn set(&mut self, column: usize, value: String) {
+        let line = &mut self.line;
+        if column == 0 {
+            *line = Columns::split_line(&self.fs, self.rs.is_empty(), value);
+            return;
         }
-        lines
+        let nf = Columns::nf_of(line).max(column);
+        line.insert(column, value);
+        self.rebuild_record(nf);
+    }
+
+    // NF = nf, fields past nf are dropped and missing ones up to it are empty
+    pub fn set_nf(&mut self, nf: usize) {
+        self.line.retain(|idx, _| *idx <= nf);
+        self.rebuild_record(nf);
     }
 
-    fn advance_file(&mut self) -> bool {
-        if let Some(next_file) = self.files.pop() {
-            let contents = match std::fs::read_to_string(PathBuf::from(next_file.clone())) {
-                Ok(s) => s,
+    // $0 is fields 1 to nf joined with OFS
+    fn rebuild_record(&mut self, nf: usize) {
+        let line = &mut self.line;
+        let fields = (1..=nf)
+            .map(|idx| line.entry(idx).or_default().clone())
+            .collect::<Vec<String>>();
+        line.insert(0, fields.join(&self.ofs));
+    }
+
+    pub fn nf(&self) -> usize {
+        Columns::nf_of(&self.line)
+    }
+
+    fn nf_of(line: &Line) -> usize {
+        line.len().saturating_sub(1)
+    }
+
+    // An empty record has no fields. The default FS of a single space splits on runs of blanks,
+    // tabs and newlines and ignores them at the start and end, so a record of only spaces has
+    // no fields either. In paragraph mode a newline separates fields whatever FS is.
+    fn split_line(fs: &str, paragraphs: bool, line: String) -> Line {
+        let mut map = HashMap::new();
+        let fields: Vec<&str> = if fs == " " {
+            line.split([' ', '\t', '\n'])
+                .filter(|field| !field.is_empty())
+                .collect()
+        } else if line.is_empty() {
+            vec![]
+        } else {
+            let fields = if paragraphs {
+                line.split('\n')
+                    .map(|part| split_fields(fs, part))
+                    .collect::<Result<Vec<Vec<&str>>, String>>()
+                    .map(|parts| parts.concat())
+            } else {
+                split_fields(fs, &line)
+            };
+            match fields {
+                Ok(fields) => fields,
                 Err(err) => {
-                    eprintln!("Unable to load file @ `{}`\nErr: {}", next_file, err);
+                    eprintln!("FS is not a valid regex: {}", err);
                     std::process::exit(-1);
                 }
-            };
-            self.current_path = Some(next_file);
-            self.lines = Columns::parse_input_file(&self.fs, &self.rs, contents);
-            true
-        } else {
-            false
+            }
+        };
+        for (field_idx, field) in fields.into_iter().enumerate() {
+            map.insert(field_idx + 1, field.to_string());
         }
+        map.insert(0, line);
+        map
     }
 
-    pub fn next_line(&mut self) -> bool {
-        if self.current_path.is_none() && !self.advance_file() {
-            return false;
+    // The next record of the main input, after the header if there is one
+    fn next_record(&mut self) -> Option<String> {
+        if self.use_header && self.header.is_none() {
+            let header = self.records.next(&self.rs)?;
+            // The header isn't a record as far as NR and FNR go
+            self.records.nr -= 1;
+            self.records.fnr -= 1;
+            self.read_header(header);
         }
-        loop {
-            let line_number = if let Some(line_num) = self.line_number {
-                line_num
-            } else {
-                self.line_number = Some(0);
-                return self.lines.get(&0).is_some();
-            };
-            if let Some(_next_line) = self.lines.get(&(line_number + 1)) {
-                self.line_number = Some(line_number + 1);
-                return true;
-            }
-            if self.advance_file() {
-                self.line_number = None;
-            } else {
-                return false;
+        self.records.next(&self.rs)
+    }
+
+    // Advance to the next record, used by both the main loop and plain getline
+    pub fn next_line(&mut self) -> bool {
+        match self.next_record() {
+            Some(record) => {
+                self.line = Columns::split_line(&self.fs, self.rs.is_empty(), record);
+                true
             }
+            None => false,
         }
     }
 
-    #[allow(dead_code)]
+    // getline var. The next record goes into the var and counts towards NR and FNR, but $0 and
+    // NF keep the current record.
+    pub fn next_line_for_var(&mut self) -> Option<String> {
+        self.next_record()
+    }
+
+    // A record read by cmd | getline counts towards NR but not FNR
+    pub fn count_record(&mut self) {
+        self.records.nr += 1;
+    }
+
+    pub fn nr(&self) -> usize {
+        self.records.nr
+    }
+
+    pub fn fnr(&self) -> usize {
+        self.records.fnr
+    }
+
+    pub fn rt(&self) -> &str {
+        &self.records.rt
+    }
+
+    pub fn set_nr(&mut self, nr: usize) {
+        self.records.nr = nr;
+    }
+
+    pub fn set_fnr(&mut self, fnr: usize) {
+        self.records.fnr = fnr;
+    }
+
+    // Consume the first record as the header. It is not counted in NR/FNR.
+    pub fn use_header(&mut self) {
+        self.use_header = true;
+    }
+
+    fn read_header(&mut self, header: String) {
+        let header = Columns::split_line(&self.fs, self.rs.is_empty(), header);
+        let header = (1..=Columns::nf_of(&header))
+            .map(|idx| (header[&idx].clone(), idx))
+            .collect::<HashMap<String, usize>>();
+        self.header = Some(header);
+    }
+
+    pub fn header_index(&self, name: &str) -> Option<usize> {
+        self.header.as_ref()?.get(name).cloned()
+    }
+
+    // Used from the next record on, the current one is already split off
     pub fn set_record_sep(&mut self, value: String) {
-        if self.current_path.is_some() {
-            panic!("must set fs/rs before reading lines")
-        }
         self.rs = value;
+        self.records.resplit(&self.rs);
     }
 
-    #[allow(dead_code)]
+    // Used from the next record on, the current one keeps its fields
     pub fn set_field_sep(&mut self, value: String) {
-        if self.current_path.is_some() {
-            panic!("must set fs/rs before reading lines")
-        }
         self.fs = value;
     }
+
+    pub fn set_output_field_sep(&mut self, value: String) {
+        self.ofs = value;
+    }
+}
+
+// gawk's default FPAT, fields are runs of non-space
+pub const DEFAULT_FPAT: &str = "[^[:space:]]+";
+
+// gawk's FPAT splitting, fields are what the pattern matches rather than what it separates.
+// Returns the fields and the text around them: seps[0] is before the first field, seps[i]
+// between field i and i + 1 and the last is whatever follows the last field. Like gawk an
+// empty match right after a field doesn't count as another field.
+pub fn split_by_pattern(fpat: &Regex, line: &str) -> (Vec<String>, Vec<String>) {
+    let mut fields = vec![];
+    let mut seps = vec![];
+    let mut field_end = 0;
+    let mut search_from = 0;
+    let mut after_field = false;
+    while search_from <= line.len() {
+        let (start, end) = match fpat.find_at(line, search_from) {
+            Some(found) => found,
+            None => break,
+        };
+        if start == end && after_field && start == field_end {
+            after_field = false;
+            search_from = next_char(line, start);
+            continue;
+        }
+        seps.push(line[field_end..start].to_string());
+        fields.push(line[start..end].to_string());
+        field_end = end;
+        after_field = start != end;
+        search_from = if start == end {
+            next_char(line, end)
+        } else {
+            end
+        };
+    }
+    seps.push(line[field_end..].to_string());
+    (fields, seps)
+}
+
+// Byte offset of the char after the one at idx, past the end of line if there is none
+fn next_char(line: &str, idx: usize) -> usize {
+    match line[idx..].chars().next() {
+        Some(c) => idx + c.len_utf8(),
+        None => idx + 1,
+    }
 }
 
 #[test]
-fn test_parse_input_file() {
-    let actual = Columns::parse_input_file(" ", "\n", "a b c\nd e f\ng h i".to_string());
-    let mut map: HashMap<usize, Line> = HashMap::new();
-    let mut line1: Line = HashMap::new();
-    line1.insert(0, "a b c".to_string());
-    line1.insert(1, "a".to_string());
-    line1.insert(2, "b".to_string());
-    line1.insert(3, "c".to_string());
-    let mut line2: Line = HashMap::new();
-    line2.insert(0, "d e f".to_string());
-    line2.insert(1, "d".to_string());
-    line2.insert(2, "e".to_string());
-    line2.insert(3, "f".to_string());
-    let mut line3: Line = HashMap::new();
-    line3.insert(0, "g h i".to_string());
-    line3.insert(1, "g".to_string());
-    line3.insert(2, "h".to_string());
-    line3.insert(3, "i".to_string());
+fn test_split_records() {
+    let records = |rs: &str, contents: &str| {
+        split_records(rs, contents)
+            .unwrap()
+            .into_iter()
+            .map(|(record, _)| record)
+            .collect::<Vec<String>>()
+    };
+    assert_eq!(
+        records("\n", "a b c\nd e f\ng h i"),
+        vec!["a b c", "d e f", "g h i"]
+    );
+    assert_eq!(records("\n", "a\n\nb\n"), vec!["a", "", "b"]);
+    assert_eq!(records("\n", "\n"), vec![""]);
+    assert!(records("\n", "").is_empty());
+    assert_eq!(records("", "\n\na\nb\n\n\n\nc d\n\n"), vec!["a\nb", "c d"]);
+    assert!(records("", "\n\n").is_empty());
+}
+
+#[test]
+fn test_regex_record_sep() {
+    let split = |rs: &str, contents: &str| {
+        split_records(rs, contents)
+            .unwrap()
+            .into_iter()
+            .collect::<Vec<(String, String)>>()
+    };
+    let pair = |record: &str, rt: &str| (record.to_string(), rt.to_string());
+    assert_eq!(
+        split("[0-9]+|;;", "a12b;;c;d3"),
+        vec![pair("a", "12"), pair("b", ";;"), pair("c;d", "3")]
+    );
+    assert_eq!(split("x", "axbx"), vec![pair("a", "x"), pair("b", "x")]);
+    assert_eq!(
+        split("", "a\nb\n\n\nc\n"),
+        vec![pair("a\nb", "\n\n\n"), pair("c", "\n")]
+    );
+    assert!(split_records("a(", "a").is_err());
+
+    let mut cols = Columns::from_text("one, two,three".to_string());
+    cols.set_record_sep(", ?".to_string());
+    assert!(cols.next_line());
+    assert_eq!((cols.get(0).as_str(), cols.rt()), ("one", ", "));
+    assert!(cols.next_line());
+    assert_eq!((cols.get(0).as_str(), cols.rt()), ("two", ","));
+    assert!(cols.next_line());
+    assert_eq!((cols.get(0).as_str(), cols.rt()), ("three", ""));
+    assert!(!cols.next_line());
+}
 
-    map.insert(0, line1);
-    map.insert(1, line2);
-    map.insert(2, line3);
-    assert_eq!(actual, map)
+#[test]
+fn test_set_record_sep_while_reading() {
+    let mut cols = Columns::from_text("a;b\nc;d;e\n".to_string());
+    assert!(cols.next_line());
+    assert_eq!(cols.get(0), "a;b");
+    cols.set_record_sep(";".to_string());
+    assert!(cols.next_line());
+    assert_eq!((cols.get(0).as_str(), cols.rt()), ("c", ";"));
+    assert!(cols.next_line());
+    assert_eq!(cols.get(0), "d");
+    cols.set_record_sep("\n".to_string());
+    assert!(cols.next_line());
+    assert_eq!((cols.get(0).as_str(), cols.rt()), ("e", "\n"));
+    assert!(!cols.next_line());
+    assert_eq!(cols.nr(), 4);
+}
+
+#[test]
+fn test_paragraph_fields() {
+    let mut cols = Columns::from_text("\nname:ann\nage:3\n\nname:bo\n".to_string());
+    cols.set_record_sep("".to_string());
+    cols.set_field_sep(":".to_string());
+    assert!(cols.next_line());
+    assert_eq!(cols.get(0), "name:ann\nage:3");
+    assert_eq!(cols.nf(), 4);
+    assert_eq!(cols.get(2), "ann");
+    assert_eq!(cols.get(3), "age");
+    assert!(cols.next_line());
+    assert_eq!(cols.get(2), "bo");
+    assert_eq!(cols.nf(), 2);
+    assert!(!cols.next_line());
+}
+
+#[test]
+fn test_regex_field_sep() {
+    let mut cols = Columns::from_text("a, b,c\n1x22y3\n".to_string());
+    cols.set_field_sep(", *".to_string());
+    assert!(cols.next_line());
+    assert_eq!(cols.get(2), "b");
+    assert_eq!(cols.get(3), "c");
+    assert_eq!(cols.nf(), 3);
+    cols.set_field_sep("[a-z]+".to_string());
+    assert!(cols.next_line());
+    assert_eq!(cols.get(2), "22");
+    assert_eq!(cols.nf(), 3);
+    assert_eq!(split_fields("[0-9]", "a1b").unwrap(), vec!["a", "b"]);
+    assert!(split_fields("a(", "a").is_err());
 }
 
 #[test]
@@ -190,3 +540,235 @@ fn test_files() {
     assert_eq!(cols.next_line(), false);
     assert_eq!(cols.next_line(), false);
 }
+
+#[test]
+fn test_record_counts() {
+    use tempfile::tempdir;
+
+    let temp_dir = tempdir().unwrap();
+    let file_path_1 = temp_dir.path().join("file1.txt");
+    let file_path_2 = temp_dir.path().join("file2.txt");
+    std::fs::write(file_path_1.clone(), "a\nb\n").unwrap();
+    std::fs::write(file_path_2.clone(), "c\n").unwrap();
+
+    let mut cols = Columns::new(vec![
+        file_path_1.to_str().unwrap().to_string(),
+        file_path_2.to_str().unwrap().to_string(),
+    ]);
+    assert_eq!((cols.nr(), cols.fnr()), (0, 0));
+    assert!(cols.next_line());
+    assert!(cols.next_line());
+    assert_eq!((cols.nr(), cols.fnr()), (2, 2));
+    assert!(cols.next_line());
+    assert_eq!(cols.get(0), "c");
+    assert_eq!((cols.nr(), cols.fnr()), (3, 1));
+    assert!(!cols.next_line());
+    assert_eq!((cols.nr(), cols.fnr()), (3, 1));
+}
+
+#[test]
+fn test_set_record_counts() {
+    use tempfile::tempdir;
+
+    let temp_dir = tempdir().unwrap();
+    let file_path_1 = temp_dir.path().join("file1.txt");
+    let file_path_2 = temp_dir.path().join("file2.txt");
+    std::fs::write(file_path_1.clone(), "a\nb\n").unwrap();
+    std::fs::write(file_path_2.clone(), "c\n").unwrap();
+
+    let mut cols = Columns::new(vec![
+        file_path_1.to_str().unwrap().to_string(),
+        file_path_2.to_str().unwrap().to_string(),
+    ]);
+    assert!(cols.next_line());
+    cols.set_nr(10);
+    cols.set_fnr(5);
+    assert!(cols.next_line());
+    assert_eq!((cols.nr(), cols.fnr()), (11, 6));
+    // A new file still restarts FNR
+    assert!(cols.next_line());
+    assert_eq!((cols.nr(), cols.fnr()), (12, 1));
+}
+
+#[test]
+fn test_next_line_for_var() {
+    use tempfile::tempdir;
+
+    let temp_dir = tempdir().unwrap();
+    let file_path_1 = temp_dir.path().join("file1.txt");
+    let file_path_2 = temp_dir.path().join("file2.txt");
+    std::fs::write(file_path_1.clone(), "a b\n").unwrap();
+    std::fs::write(file_path_2.clone(), "c\nd e f\n").unwrap();
+
+    let mut cols = Columns::new(vec![
+        file_path_1.to_str().unwrap().to_string(),
+        file_path_2.to_str().unwrap().to_string(),
+    ]);
+    // Before any record $0 stays empty
+    assert_eq!(cols.next_line_for_var(), Some("a b".to_string()));
+    assert_eq!((cols.get(0), cols.nf()), ("".to_string(), 0));
+    assert_eq!((cols.nr(), cols.fnr()), (1, 1));
+    assert!(cols.next_line());
+    assert_eq!(cols.get(0), "c");
+    // Crossing into the next file keeps the record too
+    assert_eq!(cols.next_line_for_var(), Some("d e f".to_string()));
+    assert_eq!((cols.get(0), cols.nf()), ("c".to_string(), 1));
+    assert_eq!((cols.nr(), cols.fnr()), (3, 2));
+    assert_eq!(cols.next_line_for_var(), None);
+    assert!(!cols.next_line());
+    assert_eq!((cols.nr(), cols.fnr()), (3, 2));
+
+    cols.count_record();
+    assert_eq!((cols.nr(), cols.fnr()), (4, 2));
+}
+
+#[test]
+fn test_records_read_once() {
+    use tempfile::tempdir;
+
+    let temp_dir = tempdir().unwrap();
+    let file_path = temp_dir.path().join("file.txt");
+    std::fs::write(file_path.clone(), "a\nb\n\nd\n").unwrap();
+
+    // The main loop, plain getline and getline var all share the records
+    let mut cols = Columns::new(vec![file_path.to_str().unwrap().to_string()]);
+    assert!(cols.next_line());
+    assert_eq!(cols.get(0), "a");
+    assert!(cols.next_line());
+    assert_eq!(cols.get(0), "b");
+    assert_eq!(cols.next_line_for_var(), Some("".to_string()));
+    assert!(cols.next_line());
+    assert_eq!(cols.get(0), "d");
+    assert_eq!((cols.nr(), cols.fnr()), (4, 4));
+    assert!(!cols.next_line());
+    assert_eq!(cols.next_line_for_var(), None);
+}
+
+#[test]
+fn test_record_terminator() {
+    use tempfile::tempdir;
+
+    let temp_dir = tempdir().unwrap();
+    let file_path_1 = temp_dir.path().join("file1.txt");
+    let file_path_2 = temp_dir.path().join("file2.txt");
+    std::fs::write(file_path_1.clone(), "a\nb").unwrap();
+    std::fs::write(file_path_2.clone(), "c\n").unwrap();
+
+    let mut cols = Columns::new(vec![
+        file_path_1.to_str().unwrap().to_string(),
+        file_path_2.to_str().unwrap().to_string(),
+    ]);
+    assert_eq!(cols.rt(), "");
+    assert!(cols.next_line());
+    assert_eq!((cols.get(0).as_str(), cols.rt()), ("a", "\n"));
+    assert!(cols.next_line());
+    assert_eq!((cols.get(0).as_str(), cols.rt()), ("b", ""));
+    assert!(cols.next_line());
+    assert_eq!((cols.get(0).as_str(), cols.rt()), ("c", "\n"));
+    assert!(!cols.next_line());
+}
+
+#[test]
+fn test_records_from_text() {
+    let mut cols = Columns::from_text("a b\nc\n".to_string());
+    assert!(cols.next_line());
+    assert_eq!(cols.get(2), "b");
+    assert!(cols.next_line());
+    assert_eq!(cols.get(0), "c");
+    assert_eq!((cols.nr(), cols.fnr()), (2, 2));
+    assert!(!cols.next_line());
+}
+
+#[test]
+fn test_split_empty_and_blank() {
+    let mut cols = Columns::from_text("\n  \n \ta  b \n".to_string());
+    assert!(cols.next_line());
+    assert_eq!(cols.get(0), "");
+    assert_eq!(cols.get(1), "");
+    assert_eq!(cols.nf(), 0);
+    assert!(cols.next_line());
+    assert_eq!(cols.get(0), "  ");
+    assert_eq!(cols.get(1), "");
+    assert_eq!(cols.nf(), 0);
+    assert!(cols.next_line());
+    assert_eq!(cols.get(1), "a");
+    assert_eq!(cols.get(2), "b");
+    assert_eq!(cols.get(3), "");
+    assert_eq!(cols.nf(), 2);
+
+    let mut cols = Columns::new(vec![]);
+    cols.set_field_sep(",".to_string());
+    cols.set(0, ",a,".to_string());
+    assert_eq!(cols.get(1), "");
+    assert_eq!(cols.get(2), "a");
+    assert_eq!(cols.nf(), 3);
+    cols.set(0, "".to_string());
+    assert_eq!(cols.nf(), 0);
+}
+
+#[test]
+fn test_set_nf() {
+    let mut cols = Columns::new(vec![]);
+    cols.line = Columns::split_line(" ", false, "a b c d".to_string());
+    cols.set_nf(2);
+    assert_eq!(cols.get(0), "a b");
+    assert_eq!(cols.get(3), "");
+    assert_eq!(cols.nf(), 2);
+    cols.set_output_field_sep("-".to_string());
+    cols.set_nf(4);
+    assert_eq!(cols.get(0), "a-b--");
+    assert_eq!(cols.nf(), 4);
+    cols.set_nf(0);
+    assert_eq!(cols.get(0), "");
+    assert_eq!(cols.nf(), 0);
+}
+
+#[test]
+fn test_header() {
+    use tempfile::tempdir;
+
+    let temp_dir = tempdir().unwrap();
+    let file_path = temp_dir.path().join("people.csv");
+    std::fs::write(file_path.clone(), "name,age\nbob,30\namy,41\n").unwrap();
+
+    let mut cols = Columns::new(vec![file_path.to_str().unwrap().to_string()]);
+    cols.set_field_sep(",".to_string());
+    cols.use_header();
+    assert_eq!(cols.header_index("age"), None);
+    assert!(cols.next_line());
+    assert_eq!(cols.header_index("name"), Some(1));
+    assert_eq!(cols.header_index("age"), Some(2));
+    assert_eq!(cols.header_index("height"), None);
+    assert_eq!(cols.get(0), "bob,30");
+    assert_eq!((cols.nr(), cols.fnr()), (1, 1));
+    assert!(cols.next_line());
+    assert_eq!(cols.get(2), "41");
+    assert!(!cols.next_line());
+}
+
+#[test]
+fn test_split_by_pattern() {
+    let split = |fpat: &str, line: &str| split_by_pattern(&Regex::new(fpat).unwrap(), line);
+    assert_eq!(
+        split("[a-z]", "a1b2c"),
+        (
+            vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            vec![
+                "".to_string(),
+                "1".to_string(),
+                "2".to_string(),
+                "".to_string()
+            ]
+        )
+    );
+    assert_eq!(
+        split("[^[:space:]]+", "  ab cd "),
+        (
+            vec!["ab".to_string(), "cd".to_string()],
+            vec!["  ".to_string(), " ".to_string(), " ".to_string()]
+        )
+    );
+    assert_eq!(split("[0-9]+", "abc"), (vec![], vec!["abc".to_string()]));
+    let (fields, _seps) = split("b*", "abbc");
+    assert_eq!(fields, vec!["", "bb", ""]);
+}
@@ -0,0 +1,124 @@
+use crate::lexer::MathOp;
+use crate::parser::{walk_expr_mut, walk_program_mut, Program, TypedExpr, VisitorMut};
+use crate::printf::{number_to_string, NUMBER_FORMAT};
+use crate::Expr;
+
+// Replace math, ! and concatenation of constants with the constant they compute, so
+// x = 60 * 60 * 24 becomes x = 86400. Anything reading a variable, field or call is left alone.
+pub fn fold_constants(program: &mut Program) {
+    walk_program_mut(&mut Folder {}, program);
+}
+
+struct Folder {}
+
+impl VisitorMut for Folder {
+    // Children first so constants fold bottom up
+    fn visit_expr(&mut self, expr: &mut TypedExpr) {
+        walk_expr_mut(self, expr);
+        let folded = match &expr.expr {
+            Expr::MathOp(left, op, right) => match (&left.expr, &right.expr) {
+                (Expr::NumberF64(left), Expr::NumberF64(right)) => {
+                    fold_math(*left, *op, *right).map(Expr::NumberF64)
+                }
+                _ => None,
+            },
+            Expr::Not(value) => match &value.expr {
+                Expr::NumberF64(num) => Some(Expr::NumberF64((*num == 0.0) as i32 as f64)),
+                Expr::String(str) => Some(Expr::NumberF64(str.is_empty() as i32 as f64)),
+                _ => None,
+            },
+            Expr::Concatenation(vals) => vals
+                .iter()
+                .map(|val| constant_string(&val.expr))
+                .collect::<Option<String>>()
+                .map(Expr::String),
+            _ => None,
+        };
+        // Typed just like the parser types a literal
+        if let Some(folded) = folded {
+            *expr = folded.into();
+        }
+    }
+}
+
+// Division by zero is left for the runtime to report
+fn fold_math(left: f64, op: MathOp, right: f64) -> Option<f64> {
+    Some(match op {
+        MathOp::Minus => left - right,
+        MathOp::Plus => left + right,
+        MathOp::Star => left * right,
+        MathOp::Slash | MathOp::Modulus if right == 0.0 => return None,
+        MathOp::Slash => left / right,
+        MathOp::Modulus => left % right,
+        MathOp::Exponent => left.powf(right),
+    })
+}
+
+// A program can change CONVFMT while running so only numbers it never applies to, integers,
+// are folded into strings
+fn constant_string(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::String(str) => Some(str.clone()),
+        Expr::NumberF64(num) if num.fract() == 0.0 && num.abs() < 1e16 => {
+            Some(number_to_string(*num, NUMBER_FORMAT))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+fn parsed(program: &str) -> Program {
+    use crate::lex;
+    use crate::parser::parse;
+    parse(lex(program).unwrap()).unwrap()
+}
+
+#[cfg(test)]
+fn folded(program: &str) -> Program {
+    let mut program = parsed(program);
+    fold_constants(&mut program);
+    program
+}
+
+#[test]
+fn test_fold_math() {
+    assert_eq!(
+        folded("BEGIN { x = 60 * 60 * 24; print 2 ^ 10 - 1 }"),
+        parsed("BEGIN { x = 86400; print 1023 }")
+    );
+}
+
+#[test]
+fn test_fold_concatenation() {
+    assert_eq!(
+        folded("{ print \"a\" 1 + 2 \"b\" (4 / 2) }"),
+        parsed("{ print \"a3b2\" }")
+    );
+}
+
+#[test]
+fn test_fold_not() {
+    assert_eq!(
+        folded("!(1 - 1) { print !\"\", !\"0\" }"),
+        parsed("1 { print 1, 0 }")
+    );
+}
+
+#[test]
+fn test_fold_inside_unfoldable() {
+    assert_eq!(
+        folded("{ print $(1 + 1), a[2 * 3], length(\"a\" \"b\") }"),
+        parsed("{ print $2, a[6], length(\"ab\") }")
+    );
+}
+
+#[test]
+fn test_fold_leaves_unfoldable() {
+    for program in [
+        "{ print x * 2, 2 * $1, 1 + length($0), \"a\" x }",
+        "{ print 1 / 0, 1 % 0, \"a\" 0.5 }",
+        "BEGIN { print \"3\" + 4, !x }",
+    ] {
+        assert_eq!(folded(program), parsed(program));
+    }
+}
@@ -1,32 +1,231 @@
+mod symbol;
 mod types;
 
-pub use types::{BinOp, LogicalOp, MathOp, Token, TokenType};
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use symbol::Interner;
+pub use symbol::Symbol;
+pub use types::{BinOp, LogicalOp, MathOp, Token, TokenKind, TokenType};
 
+// All the tokens at once, ending with EOF
 pub fn lex(str: &str) -> LexerResult {
-    let mut lexer = Lexer::new(str);
-    lexer.scan_tokens()?;
-    Ok(lexer.tokens)
+    Lexer::new(str).collect()
 }
 
-struct Lexer {
+// Decodes the escape sequences of a string literal in text that has no quotes around it, like
+// the value of a -v assignment
+pub fn unescape(text: &str) -> String {
+    let mut lexer = Lexer::new(text);
+    let mut str = String::new();
+    while !lexer.is_at_end() {
+        let c = lexer.advance();
+        if c == '\\' && !lexer.is_at_end() {
+            lexer.escape(&mut str);
+        } else {
+            str.push(c);
+        }
+    }
+    str
+}
+
+// Same as lex with only these keywords active
+pub fn lex_with_keywords(str: &str, keywords: Keywords) -> LexerResult {
+    Lexer::with_keywords(str, keywords).collect()
+}
+
+// Every token with where it is in the source, comments included, eg. for syntax highlighting.
+// Ends with EOF like lex.
+pub fn lex_spanned(str: &str) -> Result<Vec<SpannedToken>, LexError> {
+    // The byte offset of each char and of the end of the source
+    let offsets: Vec<usize> = str
+        .char_indices()
+        .map(|(idx, _)| idx)
+        .chain(std::iter::once(str.len()))
+        .collect();
+    let mut lexer = Lexer::new(str).with_comments();
+    let mut tokens = vec![];
+    while let Some(token) = lexer.next_token() {
+        let (start, end) = lexer.span;
+        tokens.push(SpannedToken {
+            token: token?,
+            start: offsets[start],
+            end: offsets[end],
+        });
+    }
+    Ok(tokens)
+}
+
+/// A token of a program and the byte range of the source it was lexed from, `&src[start..end]`
+/// is its text
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub(crate) token: Token,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl SpannedToken {
+    pub fn kind(&self) -> TokenKind {
+        self.token.kind()
+    }
+}
+
+// Every keyword by its lowercase name
+const KEYWORDS: &[(&str, Token)] = &[
+    ("true", Token::True),
+    ("false", Token::False),
+    ("return", Token::Ret),
+    ("if", Token::If),
+    ("else", Token::Else),
+    ("begin", Token::Begin),
+    ("for", Token::For),
+    ("in", Token::In),
+    ("while", Token::While),
+    ("do", Token::Do),
+    ("end", Token::End),
+    ("print", Token::Print),
+    ("printf", Token::Printf),
+    ("getline", Token::Getline),
+    ("next", Token::Next),
+    ("exit", Token::Exit),
+    ("break", Token::Break),
+    ("continue", Token::Continue),
+    ("delete", Token::Delete),
+];
+
+/// The keywords the lexer recognizes, every other word is a name. All of awk's by default, a
+/// restricted dialect can turn some off so eg. print is just a variable.
+#[derive(Debug, Clone)]
+pub struct Keywords {
+    active: HashSet<&'static str>,
+}
+
+impl Default for Keywords {
+    fn default() -> Self {
+        Keywords {
+            active: KEYWORDS.iter().map(|(name, _)| *name).collect(),
+        }
+    }
+}
+
+impl Keywords {
+    /// Turns off the keyword name, given in lowercase
+    pub fn without(mut self, name: &str) -> Self {
+        self.active.remove(name);
+        self
+    }
+
+    fn get(&self, name: &str) -> Option<Token> {
+        if !self.active.contains(name) {
+            return None;
+        }
+        KEYWORDS
+            .iter()
+            .find(|(keyword, _)| *keyword == name)
+            .map(|(_, token)| token.clone())
+    }
+}
+
+// Hands out one token at a time, see next_token
+pub struct Lexer {
     src: Vec<char>,
     start: usize,
     current: usize,
     line: usize,
-    tokens: Vec<Token>,
+    // How many ( and [ we are inside of. Newlines in them never end a statement.
+    nesting: usize,
+    // The token scan_token just found, if it found one
+    scanned: Option<Token>,
+    // The token handed out before it, which decides what a newline or a '/' means
+    last: Option<Token>,
+    // The last ++ or -- came right after a value, so it is postfix like in i++
+    postfix: bool,
+    // EOF or an error was handed out
+    finished: bool,
+    keywords: Keywords,
+    // The names lexed so far, each later use of one shares its Symbol
+    interner: Interner,
+    // Hand out comments as Token::Comment rather than skipping them
+    comments: bool,
+    // The chars of src the token handed out last was lexed from
+    span: (usize, usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+    pub message: String,
+    pub line: usize,
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
 }
 
-type LexerResult = Result<Vec<Token>, (String, usize)>;
+type LexerResult = Result<Vec<Token>, LexError>;
+
+impl Iterator for Lexer {
+    type Item = Result<Token, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_token()
+    }
+}
 
 impl Lexer {
-    fn new(src: &str) -> Lexer {
+    pub fn new(src: &str) -> Lexer {
+        Lexer::with_keywords(src, Keywords::default())
+    }
+
+    pub fn with_keywords(src: &str, keywords: Keywords) -> Lexer {
         Lexer {
             src: src.chars().collect(),
             start: 0,
             current: 0,
             line: 0,
-            tokens: vec![],
+            nesting: 0,
+            scanned: None,
+            last: None,
+            postfix: false,
+            finished: false,
+            keywords,
+            interner: Interner::default(),
+            comments: false,
+            span: (0, 0),
+        }
+    }
+
+    pub fn with_comments(mut self) -> Self {
+        self.comments = true;
+        self
+    }
+
+    // Only scans as far as the next token. EOF comes exactly once at the end, after it or an
+    // error there are no more tokens.
+    pub fn next_token(&mut self) -> Option<Result<Token, LexError>> {
+        if self.finished {
+            return None;
+        }
+        while !self.is_at_end() {
+            if let Err(message) = self.scan_token() {
+                self.finished = true;
+                let line = self.line;
+                return Some(Err(LexError { message, line }));
+            }
+            self.span = (self.start, self.current);
+            self.start = self.current;
+            if let Some(token) = self.scanned.take() {
+                // A comment is invisible to what comes after it
+                if !matches!(token, Token::Comment(_)) {
+                    self.last = Some(token.clone());
+                }
+                return Some(Ok(token));
+            }
         }
+        self.finished = true;
+        self.span = (self.current, self.current);
+        Some(Ok(Token::EOF))
     }
     fn is_at_end(&self) -> bool {
         self.current >= self.src.len()
@@ -37,46 +236,221 @@ impl Lexer {
         x
     }
     fn add_token(&mut self, tt: Token) {
-        self.tokens.push(tt);
+        self.scanned = Some(tt);
+    }
+    // A newline ends a statement like ';' unless the statement obviously continues, after an
+    // operator, a ',' or an opening brace, or inside parens or brackets. Blank lines and newlines
+    // after something already terminated are dropped so the parser sees at most one.
+    fn newline(&mut self) {
+        self.line += 1;
+        if self.nesting > 0 {
+            return;
+        }
+        let continues = match &self.last {
+            None => true,
+            Some(token) => matches!(
+                token,
+                Token::Newline
+                    | Token::Semicolon
+                    | Token::Comma
+                    | Token::LeftBrace
+                    | Token::RightBrace
+                    | Token::Eq
+                    | Token::InplaceEq(_)
+                    | Token::BinOp(_)
+                    | Token::MathOp(_)
+                    | Token::LogicalOp(_)
+                    | Token::Bang
+                    | Token::Pipe
+                    | Token::TwoWayPipe
+                    | Token::Else
+                    | Token::Do
+                    | Token::Question
+                    | Token::Colon
+            ),
+        };
+        if !continues {
+            self.add_token(Token::Newline);
+        }
     }
+    // After the opening '"'. Escape sequences are decoded here so the token holds the real string.
     fn string(&mut self) -> Result<(), String> {
+        let mut str = String::new();
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
+            let c = self.advance();
+            if c == '\n' {
                 self.line += 1;
             }
-            self.advance();
+            if c == '\\' && !self.is_at_end() {
+                self.escape(&mut str);
+            } else {
+                str.push(c);
+            }
         }
         if self.is_at_end() {
-            let string: String = self.src[self.start..self.src.len() - self.start]
-                .iter()
-                .collect();
+            let string: String = self.src[self.start..].iter().collect();
             return Err(format!("Unterminated String: {}", string));
         }
         self.advance();
-        let str = self
-            .src
-            .iter()
-            .skip(self.start + 1)
-            .take(self.current - self.start - 2)
-            .collect::<String>();
         self.add_token(Token::String(str));
         return Ok(());
     }
+    // After a '\' in a string. Unknown escapes keep the backslash like POSIX says.
+    fn escape(&mut self, str: &mut String) {
+        let decoded = match self.peek() {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '\\' => '\\',
+            '"' => '"',
+            '/' => '/',
+            'a' => '\x07',
+            'b' => '\x08',
+            'f' => '\x0c',
+            'v' => '\x0b',
+            '0'..='7' => {
+                // \ddd, one to three octal digits
+                let mut value: u32 = 0;
+                for _ in 0..3 {
+                    match self.peek().to_digit(8) {
+                        Some(digit) => value = value * 8 + digit,
+                        None => break,
+                    }
+                    self.advance();
+                }
+                str.push(char::from(value as u8));
+                return;
+            }
+            _ => {
+                str.push('\\');
+                return;
+            }
+        };
+        self.advance();
+        str.push(decoded);
+    }
+    // Whether the token handed out last ends something that has a value. A postfix ++ or --
+    // does, the value is the old one.
+    fn after_value(&self) -> bool {
+        match self.last {
+            Some(Token::Incr | Token::Decr) => self.postfix,
+            Some(
+                Token::Ident(_)
+                | Token::NumberF64(_)
+                | Token::True
+                | Token::False
+                | Token::String(_)
+                | Token::Regex(_)
+                | Token::RightParen
+                | Token::RightBracket,
+            ) => true,
+            _ => false,
+        }
+    }
+    // After the # or // that starts a comment, which runs to the end of the line
+    fn comment(&mut self) {
+        let text_start = self.current;
+        while self.peek() != '\n' && !self.is_at_end() {
+            self.advance();
+        }
+        if self.comments {
+            let text = self.src[text_start..self.current].iter().collect();
+            self.add_token(Token::Comment(text));
+        }
+    }
+
+    // A '/' right after something that has a value divides it, anywhere else it starts a regex
+    fn regex_allowed(&self) -> bool {
+        !self.after_value()
+    }
+    // After the opening '/'. \/ is a literal slash, other escapes are left for the regex engine.
+    // A '/' in a bracket expression like [/] doesn't end the regex either. A regex can't span
+    // lines so without a closing '/' on this one nothing is consumed and false is returned, the
+    // '/' is then a division.
+    fn regex(&mut self) -> bool {
+        let mut regex = String::new();
+        // Where the bracket expression we are in starts after its [ or [^, a ] right there is
+        // part of it rather than closing it
+        let mut class_start = None;
+        loop {
+            if self.is_at_end() || self.peek() == '\n' {
+                self.current = self.start + 1;
+                return false;
+            }
+            let c = self.advance();
+            match c {
+                '/' if class_start.is_none() => break,
+                '\\' if self.peek() == '/' => regex.push(self.advance()),
+                '\\' if !self.is_at_end() && self.peek() != '\n' => {
+                    regex.push(c);
+                    regex.push(self.advance());
+                }
+                '[' if class_start.is_none() => {
+                    regex.push(c);
+                    if self.peek() == '^' {
+                        regex.push(self.advance());
+                    }
+                    class_start = Some(regex.len());
+                }
+                // [:alpha:] has a ] of its own
+                '[' if self.peek() == ':' => {
+                    regex.push(c);
+                    regex.push(self.advance());
+                    while !self.is_at_end() && self.peek() != '\n' && !regex.ends_with(":]") {
+                        regex.push(self.advance());
+                    }
+                }
+                ']' if class_start.is_some_and(|start| start < regex.len()) => {
+                    regex.push(c);
+                    class_start = None;
+                }
+                _ => regex.push(c),
+            }
+        }
+        self.add_token(Token::Regex(regex));
+        true
+    }
+    // 12, 1.5, .5, 1e10, 1.5E-3 and hex like 0x1F
     fn number(&mut self) -> Result<Token, String> {
+        if self.src[self.start] == '0'
+            && matches!(self.peek(), 'x' | 'X')
+            && self.peek_next().is_ascii_hexdigit()
+        {
+            self.advance();
+            let mut num = 0.0;
+            while let Some(digit) = self.peek().to_digit(16) {
+                num = num * 16.0 + digit as f64;
+                self.advance();
+            }
+            return Ok(Token::NumberF64(num));
+        }
         while self.peek().is_digit(10) {
             self.advance();
         }
-        if self.peek() == '.' && self.peek_next().is_digit(10) {
+        // .5 already started with its '.'
+        if self.src[self.start] != '.' && self.peek() == '.' && self.peek_next().is_ascii_digit() {
             self.advance();
         }
         while self.peek().is_digit(10) {
             self.advance();
         }
+        // Only an exponent if digits follow, otherwise the e starts whatever comes next
+        if matches!(self.peek(), 'e' | 'E') {
+            let digits_at = match self.peek_next() {
+                '+' | '-' => self.current + 2,
+                _ => self.current + 1,
+            };
+            if matches!(self.src.get(digits_at), Some(c) if c.is_ascii_digit()) {
+                self.current = digits_at;
+                while self.peek().is_digit(10) {
+                    self.advance();
+                }
+            }
+        }
 
         let num = self.src[self.start..self.current]
             .iter()
             .collect::<String>();
-        // TODO: scientific notation
         match num.parse::<f64>() {
             Ok(float) => Ok(Token::NumberF64(float)),
             Err(_) => {
@@ -90,31 +464,13 @@ impl Lexer {
             self.advance();
         }
         let src: String = self.src[self.start..self.current].iter().collect();
-        let src = src.to_ascii_lowercase();
-        if src == "true" {
-            self.add_token(Token::True);
-        } else if src == "false" {
-            self.add_token(Token::False);
-        } else if src == "return" {
-            self.add_token(Token::Ret);
-        } else if src == "if" {
-            self.add_token(Token::If);
-        } else if src == "else" {
-            self.add_token(Token::Else);
-        } else if src == "begin" {
-            self.add_token(Token::Begin);
-        } else if src == "for" {
-            self.add_token(Token::For);
-        } else if src == "while" {
-            self.add_token(Token::While);
-        } else if src == "do" {
-            self.add_token(Token::Do);
-        } else if src == "end" {
-            self.add_token(Token::End);
-        } else if src == "print" {
-            self.add_token(Token::Print);
-        } else {
-            self.add_token(Token::Ident(src));
+        // Keywords are matched whatever their case but names keep theirs, x and X are different
+        match self.keywords.get(&src.to_ascii_lowercase()) {
+            Some(keyword) => self.add_token(keyword),
+            None => {
+                let name = self.interner.intern(&src);
+                self.add_token(Token::Ident(name))
+            }
         }
         Ok(())
     }
@@ -135,14 +491,20 @@ impl Lexer {
         match c {
             '$' => self.add_token(Token::Column),
             '-' => {
-                if self.matches('=') {
+                if self.matches('-') {
+                    self.postfix = self.after_value();
+                    self.add_token(Token::Decr); // --
+                } else if self.matches('=') {
                     self.add_token(Token::InplaceEq(MathOp::Minus)); // -=
                 } else {
                     self.add_token(Token::MathOp(MathOp::Minus)) // -
                 }
             }
             '+' => {
-                if self.matches('=') {
+                if self.matches('+') {
+                    self.postfix = self.after_value();
+                    self.add_token(Token::Incr);
+                } else if self.matches('=') {
                     self.add_token(Token::InplaceEq(MathOp::Plus));
                 } else {
                     self.add_token(Token::MathOp(MathOp::Plus))
@@ -150,23 +512,39 @@ impl Lexer {
             }
             // ';' => self.add_token(Token::Semicolon),
             '*' => {
-                if self.matches('=') {
+                // ** and **= are gawk's spelling of ^ and ^=
+                if self.matches('*') {
+                    if self.matches('=') {
+                        self.add_token(Token::InplaceEq(MathOp::Exponent));
+                    } else {
+                        self.add_token(Token::MathOp(MathOp::Exponent));
+                    }
+                } else if self.matches('=') {
                     self.add_token(Token::InplaceEq(MathOp::Star));
                 } else {
                     self.add_token(Token::MathOp(MathOp::Star))
                 }
             }
             '!' => {
-                let tt = match self.matches('=') {
-                    true => Token::BinOp(BinOp::BangEq),
-                    false => Token::Bang,
+                let tt = if self.matches('=') {
+                    Token::BinOp(BinOp::BangEq)
+                } else if self.matches('~') {
+                    Token::BinOp(BinOp::NotMatchedBy)
+                } else {
+                    Token::Bang
                 };
                 self.add_token(tt);
             }
+            '~' => self.add_token(Token::BinOp(BinOp::MatchedBy)),
+            '?' => self.add_token(Token::Question),
+            ':' => self.add_token(Token::Colon),
             '|' => {
-                let tt = match self.matches('|') {
-                    true => Token::LogicalOp(LogicalOp::Or),
-                    false => return Err("| must be followed by ||".to_string()),
+                let tt = if self.matches('|') {
+                    Token::LogicalOp(LogicalOp::Or)
+                } else if self.matches('&') {
+                    Token::TwoWayPipe
+                } else {
+                    Token::Pipe
                 };
                 self.add_token(tt);
             }
@@ -192,9 +570,12 @@ impl Lexer {
                 self.add_token(tt)
             }
             '>' => {
-                let tt = match self.matches('=') {
-                    true => Token::BinOp(BinOp::GreaterEq),
-                    false => Token::BinOp(BinOp::Greater),
+                let tt = if self.matches('=') {
+                    Token::BinOp(BinOp::GreaterEq)
+                } else if self.matches('>') {
+                    Token::Append
+                } else {
+                    Token::BinOp(BinOp::Greater)
                 };
                 self.add_token(tt)
             }
@@ -212,29 +593,47 @@ impl Lexer {
                     self.add_token(Token::MathOp(MathOp::Exponent));
                 }
             }
+            '#' => self.comment(),
             '/' => {
-                if self.matches('=') {
+                if self.matches('/') {
+                    self.comment();
+                } else if self.regex_allowed() && self.regex() {
+                    // Token::Regex was added
+                } else if self.matches('=') {
                     self.add_token(Token::InplaceEq(MathOp::Slash));
-                } else if self.matches('/') {
-                    while self.peek() != '\n' && !self.is_at_end() {
-                        self.advance();
-                    }
                 } else {
                     self.add_token(Token::MathOp(MathOp::Slash));
                 }
             }
             '{' => self.add_token(Token::LeftBrace),
             '}' => self.add_token(Token::RightBrace),
-            '(' => self.add_token(Token::LeftParen),
-            ')' => self.add_token(Token::RightParen),
+            '(' => {
+                self.nesting += 1;
+                self.add_token(Token::LeftParen)
+            }
+            ')' => {
+                self.nesting = self.nesting.saturating_sub(1);
+                self.add_token(Token::RightParen)
+            }
+            '[' => {
+                self.nesting += 1;
+                self.add_token(Token::LeftBracket)
+            }
+            ']' => {
+                self.nesting = self.nesting.saturating_sub(1);
+                self.add_token(Token::RightBracket)
+            }
             ';' => self.add_token(Token::Semicolon),
+            ',' => self.add_token(Token::Comma),
             '"' => self.string()?,
             '\r' => (),
             '\t' => (),
             ' ' => (),
-            '\n' => self.line += 1,
+            // An escaped newline is whitespace, the statement carries on on the next line
+            '\\' if self.matches('\n') => self.line += 1,
+            '\n' => self.newline(),
             _ => {
-                if c.is_digit(10) || (c == '-' && self.peek_next().is_digit(10)) {
+                if c.is_digit(10) || (c == '.' && self.peek().is_ascii_digit()) {
                     let num = self.number()?;
                     self.add_token(num);
                 } else if c.is_alphabetic() {
@@ -262,24 +661,41 @@ impl Lexer {
             }
         }
     }
+}
 
-    fn scan_tokens(&mut self) -> LexerResult {
-        while !self.is_at_end() {
-            if let Err(x) = self.scan_token() {
-                return Err((x, self.line));
-            }
-            self.start = self.current;
-        }
-        self.tokens.push(Token::EOF);
-        // self.tokens.push(Token::new_src(
-        //     Token::EOF,
-        //     self.current,
-        //     self.current - self.start,
-        //     self.line,
-        //     self.source.clone(),
-        // ));
-        Ok(self.tokens.clone())
-    }
+#[test]
+fn test_next_token() {
+    let mut lexer = Lexer::new("{ print 1 }\n");
+    assert_eq!(lexer.next_token(), Some(Ok(Token::LeftBrace)));
+    assert_eq!(lexer.next_token(), Some(Ok(Token::Print)));
+    assert_eq!(lexer.next_token(), Some(Ok(Token::NumberF64(1.0))));
+    assert_eq!(lexer.next_token(), Some(Ok(Token::RightBrace)));
+    assert_eq!(lexer.next_token(), Some(Ok(Token::EOF)));
+    assert_eq!(lexer.next_token(), None);
+    assert_eq!(lexer.next_token(), None);
+
+    let mut empty = Lexer::new("  \n");
+    assert_eq!(empty.next_token(), Some(Ok(Token::EOF)));
+    assert_eq!(empty.next_token(), None);
+}
+
+#[test]
+fn test_lexer_stops_at_error() {
+    let tokens: Vec<_> = Lexer::new("a\nb @ c").collect();
+    let error = LexError {
+        message: "Unexpected token: `@`".to_string(),
+        line: 1,
+    };
+    assert_eq!(
+        tokens,
+        vec![
+            Ok(Token::Ident("a".into())),
+            Ok(Token::Newline),
+            Ok(Token::Ident("b".into())),
+            Err(error.clone()),
+        ]
+    );
+    assert_eq!(lex("a\nb @ c"), Err(error));
 }
 
 #[test]
@@ -312,6 +728,43 @@ fn test_eq_eq() {
     )
 }
 
+#[test]
+fn test_lex_number_forms() {
+    let number = |str: &str| match &lex(str).unwrap()[..] {
+        [Token::NumberF64(num), Token::EOF] => *num,
+        tokens => panic!("{} lexed to {:?}", str, tokens),
+    };
+    assert_eq!(number("12"), 12.0);
+    assert_eq!(number("1.25"), 1.25);
+    assert_eq!(number(".5"), 0.5);
+    assert_eq!(number("1e10"), 1e10);
+    assert_eq!(number("1.5E-3"), 1.5e-3);
+    assert_eq!(number("2e+2"), 200.0);
+    assert_eq!(number(".5e1"), 5.0);
+    assert_eq!(number("0x1F"), 31.0);
+    assert_eq!(number("0XfF"), 255.0);
+}
+
+#[test]
+fn test_lex_number_not_exponent_or_hex() {
+    assert_eq!(
+        lex("1e 2ex 0x 0xg 1.5.5").unwrap(),
+        vec![
+            Token::NumberF64(1.0),
+            Token::Ident("e".into()),
+            Token::NumberF64(2.0),
+            Token::Ident("ex".into()),
+            Token::NumberF64(0.0),
+            Token::Ident("x".into()),
+            Token::NumberF64(0.0),
+            Token::Ident("xg".into()),
+            Token::NumberF64(1.5),
+            Token::NumberF64(0.5),
+            Token::EOF
+        ]
+    );
+}
+
 #[test]
 fn test_column_simple() {
     let str = "$1";
@@ -416,7 +869,7 @@ fn test_lex_assignment() {
     assert_eq!(
         lex(str).unwrap(),
         vec![
-            Token::Ident("abc".to_string()),
+            Token::Ident("abc".into()),
             Token::Eq,
             Token::NumberF64(4.0),
             Token::EOF
@@ -433,7 +886,7 @@ fn test_ret() {
             Token::Ret,
             Token::NumberF64(1.0),
             Token::Ret,
-            Token::Ident(format!("abc")),
+            Token::Ident("abc".into()),
             Token::EOF
         ]
     );
@@ -494,6 +947,20 @@ fn test_begin_end() {
     );
 }
 
+#[test]
+fn test_ident_keeps_case() {
+    let str = "NF nf Begin";
+    assert_eq!(
+        lex(str).unwrap(),
+        vec![
+            Token::Ident("NF".into()),
+            Token::Ident("nf".into()),
+            Token::Begin,
+            Token::EOF
+        ]
+    );
+}
+
 #[test]
 fn test_ident() {
     let str = "{ x }";
@@ -501,7 +968,7 @@ fn test_ident() {
         lex(str).unwrap(),
         vec![
             Token::LeftBrace,
-            Token::Ident("x".to_string()),
+            Token::Ident("x".into()),
             Token::RightBrace,
             Token::EOF
         ]
@@ -544,7 +1011,7 @@ fn test_lex_while_l00p() {
         vec![
             Token::While,
             Token::LeftParen,
-            Token::Ident(format!("x")),
+            Token::Ident("x".into()),
             Token::RightParen,
             Token::LeftBrace,
             Token::RightBrace,
@@ -574,7 +1041,7 @@ fn test_lex_do_while_l00p() {
 #[test]
 fn test_lex_for_l00p() {
     let str = "for (a = 0;";
-    let a = Token::Ident(format!("a"));
+    let a = Token::Ident("a".into());
     assert_eq!(
         lex(str).unwrap(),
         vec![
@@ -604,6 +1071,67 @@ fn test_lt_gt_eq() {
     );
 }
 
+#[test]
+fn test_lex_operators() {
+    let str = "a[1,2] ? b ~ /x/ : c !~ d >> \"f\"";
+    assert_eq!(
+        lex(str).unwrap(),
+        vec![
+            Token::Ident("a".into()),
+            Token::LeftBracket,
+            Token::NumberF64(1.0),
+            Token::Comma,
+            Token::NumberF64(2.0),
+            Token::RightBracket,
+            Token::Question,
+            Token::Ident("b".into()),
+            Token::BinOp(BinOp::MatchedBy),
+            Token::Regex("x".to_string()),
+            Token::Colon,
+            Token::Ident("c".into()),
+            Token::BinOp(BinOp::NotMatchedBy),
+            Token::Ident("d".into()),
+            Token::Append,
+            Token::String("f".to_string()),
+            Token::EOF
+        ]
+    );
+}
+
+#[test]
+fn test_lex_star_star() {
+    assert_eq!(lex("2 ** 3").unwrap(), lex("2 ^ 3").unwrap());
+    assert_eq!(lex("x **= 2").unwrap(), lex("x ^= 2").unwrap());
+    assert_eq!(
+        lex("a * *b").unwrap(),
+        vec![
+            Token::Ident("a".into()),
+            Token::MathOp(MathOp::Star),
+            Token::MathOp(MathOp::Star),
+            Token::Ident("b".into()),
+            Token::EOF
+        ]
+    );
+}
+
+#[test]
+fn test_lex_incr_decr() {
+    assert_eq!(
+        lex("++a - --b+++c").unwrap(),
+        vec![
+            Token::Incr,
+            Token::Ident("a".into()),
+            Token::MathOp(MathOp::Minus),
+            Token::Decr,
+            Token::Ident("b".into()),
+            Token::Incr,
+            Token::MathOp(MathOp::Plus),
+            Token::Ident("c".into()),
+            Token::EOF
+        ]
+    );
+}
+
 #[test]
 fn test_op_eq() {
     let str = "^= %= *= /= += -=";
@@ -620,3 +1148,371 @@ fn test_op_eq() {
         ]
     );
 }
+
+#[test]
+fn test_lex_call() {
+    let str = "typeof(a, 1)";
+    assert_eq!(
+        lex(str).unwrap(),
+        vec![
+            Token::Ident("typeof".into()),
+            Token::LeftParen,
+            Token::Ident("a".into()),
+            Token::Comma,
+            Token::NumberF64(1.0),
+            Token::RightParen,
+            Token::EOF
+        ]
+    );
+}
+
+#[test]
+fn test_lex_printf() {
+    let str = "print printf getline";
+    assert_eq!(
+        lex(str).unwrap(),
+        vec![Token::Print, Token::Printf, Token::Getline, Token::EOF]
+    );
+}
+
+#[test]
+fn test_lex_array_index() {
+    let str = "a[\"x\"]";
+    assert_eq!(
+        lex(str).unwrap(),
+        vec![
+            Token::Ident("a".into()),
+            Token::LeftBracket,
+            Token::String("x".to_string()),
+            Token::RightBracket,
+            Token::EOF
+        ]
+    );
+}
+
+#[test]
+fn test_lex_regex() {
+    let str = "/[0-9]+/ a / 2; x = /a\\/b\\./; (1) / y";
+    assert_eq!(
+        lex(str).unwrap(),
+        vec![
+            Token::Regex("[0-9]+".to_string()),
+            Token::Ident("a".into()),
+            Token::MathOp(MathOp::Slash),
+            Token::NumberF64(2.0),
+            Token::Semicolon,
+            Token::Ident("x".into()),
+            Token::Eq,
+            Token::Regex("a/b\\.".to_string()),
+            Token::Semicolon,
+            Token::LeftParen,
+            Token::NumberF64(1.0),
+            Token::RightParen,
+            Token::MathOp(MathOp::Slash),
+            Token::Ident("y".into()),
+            Token::EOF
+        ]
+    );
+}
+
+#[test]
+fn test_lex_division_after_postfix_increment() {
+    assert_eq!(
+        lex("{ a = i++ / 2; b = c / 3 }").unwrap(),
+        vec![
+            Token::LeftBrace,
+            Token::Ident("a".into()),
+            Token::Eq,
+            Token::Ident("i".into()),
+            Token::Incr,
+            Token::MathOp(MathOp::Slash),
+            Token::NumberF64(2.0),
+            Token::Semicolon,
+            Token::Ident("b".into()),
+            Token::Eq,
+            Token::Ident("c".into()),
+            Token::MathOp(MathOp::Slash),
+            Token::NumberF64(3.0),
+            Token::RightBrace,
+            Token::EOF
+        ]
+    );
+    let slashes = |src: &str| {
+        lex(src)
+            .unwrap()
+            .into_iter()
+            .filter(|token| *token == Token::MathOp(MathOp::Slash))
+            .count()
+    };
+    assert_eq!(slashes("x = a[i]-- / 2 / y"), 2);
+    assert_eq!(slashes("x = $1++ / 2; y = 1 / 3"), 2);
+}
+
+#[test]
+fn test_lex_regex_delimiters() {
+    let regexes = |src: &str| {
+        lex(src)
+            .unwrap()
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::Regex(regex) => Some(regex),
+                _ => None,
+            })
+            .collect::<Vec<String>>()
+    };
+    assert_eq!(regexes("/a\\/b/"), vec!["a/b"]);
+    assert_eq!(regexes("/\\d+\\//"), vec!["\\d+/"]);
+    assert_eq!(regexes("/a[/]b/ || /[^/]+/"), vec!["a[/]b", "[^/]+"]);
+    assert_eq!(regexes("/[]/]/ || /[^]/]/"), vec!["[]/]", "[^]/]"]);
+    assert_eq!(regexes("/[[:alpha:]/]/"), vec!["[[:alpha:]/]"]);
+    assert_eq!(regexes("/[a\\]/]/"), vec!["[a\\]/]"]);
+}
+
+#[test]
+fn test_lex_unterminated_regex() {
+    assert_eq!(
+        lex("x = /abc").unwrap(),
+        vec![
+            Token::Ident("x".into()),
+            Token::Eq,
+            Token::MathOp(MathOp::Slash),
+            Token::Ident("abc".into()),
+            Token::EOF
+        ]
+    );
+}
+
+#[test]
+fn test_lex_string_escapes() {
+    assert_eq!(
+        lex("\"\\t\"").unwrap(),
+        vec![Token::String("\t".to_string()), Token::EOF]
+    );
+    let str = r#""a\tb\n \r\\ \" \/ \a\b\f\v \101\0619 \q""#;
+    assert_eq!(
+        lex(str).unwrap(),
+        vec![
+            Token::String("a\tb\n \r\\ \" / \x07\x08\x0c\x0b A19 \\q".to_string()),
+            Token::EOF
+        ]
+    );
+}
+
+#[test]
+fn test_lex_without_keyword() {
+    let keywords = Keywords::default().without("print");
+    assert_eq!(
+        lex_with_keywords("print PRINT printf", keywords).unwrap(),
+        vec![
+            Token::Ident("print".into()),
+            Token::Ident("PRINT".into()),
+            Token::Printf,
+            Token::EOF
+        ]
+    );
+}
+
+#[test]
+fn test_unescape() {
+    assert_eq!(unescape(r#"a\tb "c" \101 \q\"#), "a\tb \"c\" A \\q\\");
+    assert_eq!(unescape("plain"), "plain");
+}
+
+#[test]
+fn test_lex_pipe() {
+    let str = "\"cmd\" | getline || 1";
+    assert_eq!(
+        lex(str).unwrap(),
+        vec![
+            Token::String("cmd".to_string()),
+            Token::Pipe,
+            Token::Getline,
+            Token::LogicalOp(LogicalOp::Or),
+            Token::NumberF64(1.0),
+            Token::EOF
+        ]
+    );
+}
+
+#[test]
+fn test_lex_two_way_pipe() {
+    let str = "print x |& \"cmd\"; \"cmd\" |& getline | && ||";
+    assert_eq!(
+        lex(str).unwrap(),
+        vec![
+            Token::Print,
+            Token::Ident("x".into()),
+            Token::TwoWayPipe,
+            Token::String("cmd".to_string()),
+            Token::Semicolon,
+            Token::String("cmd".to_string()),
+            Token::TwoWayPipe,
+            Token::Getline,
+            Token::Pipe,
+            Token::LogicalOp(LogicalOp::And),
+            Token::LogicalOp(LogicalOp::Or),
+            Token::EOF
+        ]
+    );
+}
+
+#[test]
+fn test_lex_newlines() {
+    let str = "\n{ a = 1\n\n  b = (1 +\n 2) &&\n 3\n}\nc\n";
+    assert_eq!(
+        lex(str).unwrap(),
+        vec![
+            Token::LeftBrace,
+            Token::Ident("a".into()),
+            Token::Eq,
+            Token::NumberF64(1.0),
+            Token::Newline,
+            Token::Ident("b".into()),
+            Token::Eq,
+            Token::LeftParen,
+            Token::NumberF64(1.0),
+            Token::MathOp(MathOp::Plus),
+            Token::NumberF64(2.0),
+            Token::RightParen,
+            Token::LogicalOp(LogicalOp::And),
+            Token::NumberF64(3.0),
+            Token::Newline,
+            Token::RightBrace,
+            Token::Ident("c".into()),
+            Token::Newline,
+            Token::EOF
+        ]
+    );
+}
+
+#[test]
+fn test_lex_line_continuation() {
+    let tokens = lex("print a, \\\nb \\\n+ 1\nx").unwrap();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Print,
+            Token::Ident("a".into()),
+            Token::Comma,
+            Token::Ident("b".into()),
+            Token::MathOp(MathOp::Plus),
+            Token::NumberF64(1.0),
+            Token::Newline,
+            Token::Ident("x".into()),
+            Token::EOF
+        ]
+    );
+    let err = lex("a \\\n@").unwrap_err();
+    assert_eq!(err.line, 1);
+}
+
+#[test]
+fn test_lex_line_continuation_in_pattern_and_at_end() {
+    let tokens = lex("x > 1 \\\n&& y < 2 { print }\\\n").unwrap();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Ident("x".into()),
+            Token::BinOp(BinOp::Greater),
+            Token::NumberF64(1.0),
+            Token::LogicalOp(LogicalOp::And),
+            Token::Ident("y".into()),
+            Token::BinOp(BinOp::Less),
+            Token::NumberF64(2.0),
+            Token::LeftBrace,
+            Token::Print,
+            Token::RightBrace,
+            Token::EOF
+        ]
+    );
+    // A backslash that doesn't end the line is still an error
+    assert!(lex("a \\").is_err());
+    // In a string it is kept like any unknown escape
+    assert_eq!(
+        lex("\"a\\\nb\"").unwrap(),
+        vec![Token::String("a\\\nb".to_string()), Token::EOF]
+    );
+}
+
+#[test]
+fn test_lex_spanned() {
+    let src = "BEGIN { x = \"héllo\" } // the greeting\n$1 ~ /a\\/b/ { print x, 1.5 }\nz\ny";
+    let tokens = lex_spanned(src).unwrap();
+    let spans: Vec<(TokenKind, &str)> = tokens
+        .iter()
+        .map(|token| (token.kind(), &src[token.start..token.end]))
+        .collect();
+    assert_eq!(
+        spans,
+        vec![
+            (TokenKind::Keyword, "BEGIN"),
+            (TokenKind::Punctuation, "{"),
+            (TokenKind::Identifier, "x"),
+            (TokenKind::Operator, "="),
+            (TokenKind::String, "\"héllo\""),
+            (TokenKind::Punctuation, "}"),
+            (TokenKind::Comment, "// the greeting"),
+            (TokenKind::Operator, "$"),
+            (TokenKind::Number, "1"),
+            (TokenKind::Operator, "~"),
+            (TokenKind::Regex, "/a\\/b/"),
+            (TokenKind::Punctuation, "{"),
+            (TokenKind::Keyword, "print"),
+            (TokenKind::Identifier, "x"),
+            (TokenKind::Punctuation, ","),
+            (TokenKind::Number, "1.5"),
+            (TokenKind::Punctuation, "}"),
+            (TokenKind::Identifier, "z"),
+            (TokenKind::Punctuation, "\n"),
+            (TokenKind::Identifier, "y"),
+            (TokenKind::Punctuation, ""),
+        ]
+    );
+    assert_eq!(tokens[6].token, Token::Comment(" the greeting".to_string()));
+    assert_eq!(tokens.last().unwrap().start, src.len());
+
+    // Without asking for them comments are skipped
+    assert_eq!(
+        lex("a // b").unwrap(),
+        vec![Token::Ident("a".into()), Token::EOF]
+    );
+}
+
+#[test]
+fn test_lex_hash_comment() {
+    let src = "# whole line\nx = 1 # the rest\ny = \"#\" ~ /#/";
+    let tokens = lex_spanned(src).unwrap();
+    let spans: Vec<(TokenKind, &str)> = tokens
+        .iter()
+        .map(|token| (token.kind(), &src[token.start..token.end]))
+        .collect();
+    assert_eq!(
+        spans,
+        vec![
+            // Nothing before it for the newline to end
+            (TokenKind::Comment, "# whole line"),
+            (TokenKind::Identifier, "x"),
+            (TokenKind::Operator, "="),
+            (TokenKind::Number, "1"),
+            (TokenKind::Comment, "# the rest"),
+            (TokenKind::Punctuation, "\n"),
+            (TokenKind::Identifier, "y"),
+            (TokenKind::Operator, "="),
+            (TokenKind::String, "\"#\""),
+            (TokenKind::Operator, "~"),
+            (TokenKind::Regex, "/#/"),
+            (TokenKind::Punctuation, ""),
+        ]
+    );
+    assert_eq!(tokens[4].token, Token::Comment(" the rest".to_string()));
+    // The newline ending a comment still ends the statement
+    assert_eq!(
+        lex("a # b\nc").unwrap(),
+        vec![
+            Token::Ident("a".into()),
+            Token::Newline,
+            Token::Ident("c".into()),
+            Token::EOF
+        ]
+    );
+}
@@ -0,0 +1,135 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::Arc;
+
+// An identifier. The lexer interns them, every use of a name in a program shares the one
+// allocation so cloning a Symbol only bumps a count and comparing two of the same name is a
+// pointer check. Symbols made from the same name elsewhere are still equal, just not shared.
+#[derive(Clone)]
+pub struct Symbol(Arc<str>);
+
+// The names one lexer has seen. It goes away with the lexer, a name is freed once the last
+// Symbol for it is dropped.
+#[derive(Default)]
+pub struct Interner {
+    names: HashSet<Arc<str>>,
+}
+
+impl Interner {
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(name) = self.names.get(name) {
+            return Symbol(name.clone());
+        }
+        let name: Arc<str> = Arc::from(name);
+        self.names.insert(name.clone());
+        Symbol(name)
+    }
+}
+
+impl Symbol {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol(Arc::from(name))
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Self {
+        Symbol(Arc::from(name))
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0) || self.0 == other.0
+    }
+}
+
+impl Eq for Symbol {}
+
+// By name like eq, so a symbol hashes the same whether or not it was interned
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+// By name rather than by id so sorting symbols doesn't depend on the order they were seen in
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Like the String it replaces, quoted
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+#[test]
+fn test_intern() {
+    let mut interner = Interner::default();
+    let a = interner.intern("symboltest");
+    let name = String::from("symboltest");
+    let b = interner.intern(&name);
+    let c = interner.intern("symboltestother");
+    assert!(Arc::ptr_eq(&a.0, &b.0));
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+    assert_eq!(a, Symbol::from("symboltest"));
+    assert_eq!(a, "symboltest");
+    assert_eq!(&*c, "symboltestother");
+    assert_eq!(format!("{} {:?}", a, a), "symboltest \"symboltest\"");
+    assert!(a < c);
+}
+
+#[test]
+fn test_symbol_is_send() {
+    let symbol = Interner::default().intern("symbolthread");
+    let name = std::thread::spawn(move || symbol.to_string())
+        .join()
+        .unwrap();
+    assert_eq!(name, "symbolthread");
+}
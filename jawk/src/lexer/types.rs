@@ -1,3 +1,4 @@
+use crate::lexer::Symbol;
 use std::fmt::{Display, Formatter};
 
 #[derive(Debug, Clone, PartialOrd, PartialEq, Copy)]
@@ -70,13 +71,18 @@ impl Display for LogicalOp {
 pub enum Token {
     Eq,
     Semicolon,
+    // Only emitted where a newline can end a statement
+    Newline,
+    Comma,
     Column,
     BinOp(BinOp),         // < <= >= >
     MathOp(MathOp),       // + - ^ %
     LogicalOp(LogicalOp), // && ||
     Bang,
     String(String),
-    Ident(String),
+    // /re/ without the slashes
+    Regex(String),
+    Ident(Symbol),
     NumberF64(f64),
     False,
     True,
@@ -85,7 +91,18 @@ pub enum Token {
     RightBrace,
     LeftParen,
     RightParen,
+    LeftBracket,
+    RightBracket,
+    Pipe,
+    // |& as in print |& cmd, gawk's two-way pipe
+    TwoWayPipe,
+    // >> as in print >> "file"
+    Append,
+    Question,
+    Colon,
     Print,
+    Printf,
+    Getline,
     Ret,
     If,
     Begin,
@@ -93,8 +110,34 @@ pub enum Token {
     Else,
     While,
     For,
+    In,
     Do,
+    Next,
+    Exit,
+    Break,
+    Continue,
+    Delete,
     InplaceEq(MathOp),
+    // ++ and --
+    Incr,
+    Decr,
+    // A # or // comment without the # or slashes. Only a lexer asked for comments hands these
+    // out, the parser never sees one.
+    Comment(String),
+}
+
+/// What a token is, for syntax highlighting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Keyword,
+    Operator,
+    Number,
+    String,
+    Regex,
+    Comment,
+    Identifier,
+    /// Braces, parens, brackets, `;`, `,`, a newline ending a statement and the end
+    Punctuation,
 }
 
 #[derive(Debug, Clone, PartialOrd, PartialEq, Hash, Eq, Ord)]
@@ -114,6 +157,7 @@ pub enum TokenType {
     Less,
     LessEq,
     String,
+    Regex,
     NumberF64,
     And,
     Or,
@@ -125,8 +169,19 @@ pub enum TokenType {
     RightBrace,
     LeftParen,
     RightParen,
+    LeftBracket,
+    RightBracket,
+    Pipe,
+    TwoWayPipe,
+    Append,
+    Question,
+    Colon,
     Print,
+    Printf,
+    Getline,
     Semicolon,
+    Newline,
+    Comma,
     Eq,
     Ret,
     If,
@@ -134,11 +189,20 @@ pub enum TokenType {
     End,
     Else,
     For,
+    In,
     While,
     Do,
+    Next,
+    Exit,
+    Break,
+    Continue,
+    Delete,
     MatchedBy,
     NotMatchedBy,
     InplaceAssign,
+    Incr,
+    Decr,
+    Comment,
 }
 
 impl Token {
@@ -170,6 +234,7 @@ impl Token {
             },
             Token::Bang => TokenType::Bang,
             Token::String(_) => TokenType::String,
+            Token::Regex(_) => TokenType::Regex,
             Token::NumberF64(_) => TokenType::NumberF64,
             Token::False => TokenType::False,
             Token::True => TokenType::True,
@@ -179,8 +244,19 @@ impl Token {
             Token::RightBrace => TokenType::RightBrace,
             Token::LeftParen => TokenType::LeftParen,
             Token::RightParen => TokenType::RightParen,
+            Token::LeftBracket => TokenType::LeftBracket,
+            Token::RightBracket => TokenType::RightBracket,
+            Token::Pipe => TokenType::Pipe,
+            Token::TwoWayPipe => TokenType::TwoWayPipe,
+            Token::Append => TokenType::Append,
+            Token::Question => TokenType::Question,
+            Token::Colon => TokenType::Colon,
             Token::Print => TokenType::Print,
+            Token::Printf => TokenType::Printf,
+            Token::Getline => TokenType::Getline,
             Token::Semicolon => TokenType::Semicolon,
+            Token::Newline => TokenType::Newline,
+            Token::Comma => TokenType::Comma,
             Token::Eq => TokenType::Eq,
             Token::Ret => TokenType::Ret,
             Token::If => TokenType::If,
@@ -190,7 +266,69 @@ impl Token {
             Token::Ident(_) => TokenType::Ident,
             Token::While => TokenType::While,
             Token::For => TokenType::For,
+            Token::In => TokenType::In,
             Token::Do => TokenType::Do,
+            Token::Next => TokenType::Next,
+            Token::Exit => TokenType::Exit,
+            Token::Break => TokenType::Break,
+            Token::Continue => TokenType::Continue,
+            Token::Delete => TokenType::Delete,
+            Token::Incr => TokenType::Incr,
+            Token::Decr => TokenType::Decr,
+            Token::Comment(_) => TokenType::Comment,
+        }
+    }
+
+    pub fn kind(&self) -> TokenKind {
+        match self {
+            Token::Print
+            | Token::Printf
+            | Token::Getline
+            | Token::Ret
+            | Token::If
+            | Token::Else
+            | Token::Begin
+            | Token::End
+            | Token::While
+            | Token::For
+            | Token::In
+            | Token::Do
+            | Token::Next
+            | Token::Exit
+            | Token::Break
+            | Token::Continue
+            | Token::Delete
+            | Token::True
+            | Token::False => TokenKind::Keyword,
+            Token::Eq
+            | Token::Column
+            | Token::BinOp(_)
+            | Token::MathOp(_)
+            | Token::LogicalOp(_)
+            | Token::InplaceEq(_)
+            | Token::Bang
+            | Token::Pipe
+            | Token::TwoWayPipe
+            | Token::Append
+            | Token::Question
+            | Token::Colon
+            | Token::Incr
+            | Token::Decr => TokenKind::Operator,
+            Token::NumberF64(_) => TokenKind::Number,
+            Token::String(_) => TokenKind::String,
+            Token::Regex(_) => TokenKind::Regex,
+            Token::Comment(_) => TokenKind::Comment,
+            Token::Ident(_) => TokenKind::Identifier,
+            Token::Semicolon
+            | Token::Newline
+            | Token::Comma
+            | Token::EOF
+            | Token::LeftBrace
+            | Token::RightBrace
+            | Token::LeftParen
+            | Token::RightParen
+            | Token::LeftBracket
+            | Token::RightBracket => TokenKind::Punctuation,
         }
     }
 }
@@ -210,6 +348,7 @@ impl TokenType {
             TokenType::Less => "Less",
             TokenType::LessEq => "LessEq",
             TokenType::String => "String",
+            TokenType::Regex => "Regex",
             TokenType::NumberF64 => "NumberF64",
             TokenType::And => "And",
             TokenType::Or => "Or",
@@ -222,8 +361,19 @@ impl TokenType {
             TokenType::RightBrace => "RightBrace",
             TokenType::LeftParen => "LeftParen",
             TokenType::RightParen => "RightParen",
+            TokenType::LeftBracket => "LeftBracket",
+            TokenType::RightBracket => "RightBracket",
+            TokenType::Pipe => "Pipe",
+            TokenType::TwoWayPipe => "TwoWayPipe",
+            TokenType::Append => "Append",
+            TokenType::Question => "Question",
+            TokenType::Colon => "Colon",
             TokenType::Print => "Print",
+            TokenType::Printf => "Printf",
+            TokenType::Getline => "Getline",
             TokenType::Semicolon => "Semicolon",
+            TokenType::Newline => "Newline",
+            TokenType::Comma => "Comma",
             TokenType::Eq => "Eq",
             TokenType::Ret => "Ret",
             TokenType::If => "If",
@@ -232,12 +382,21 @@ impl TokenType {
             TokenType::End => "End",
             TokenType::Ident => "Ident",
             TokenType::For => "For",
+            TokenType::In => "In",
             TokenType::Do => "Do",
+            TokenType::Next => "Next",
+            TokenType::Exit => "Exit",
+            TokenType::Break => "Break",
+            TokenType::Continue => "Continue",
+            TokenType::Delete => "Delete",
             TokenType::MatchedBy => "~",
-            TokenType::NotMatchedBy => "~!",
+            TokenType::NotMatchedBy => "!~",
             TokenType::Modulo => "%",
             TokenType::Exponent => "^",
             TokenType::InplaceAssign => "?=",
+            TokenType::Incr => "++",
+            TokenType::Decr => "--",
+            TokenType::Comment => "Comment",
         }
     }
 }
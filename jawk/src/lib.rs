@@ -0,0 +1,381 @@
+//! jawk is an awk that compiles programs to machine code with libjit before running them.
+//!
+//! The `jawk` binary is a thin wrapper around [`run_cli`]. Programs embedding jawk, and tests,
+//! can use [`run_capture`] to run a program over some input and get back what it prints.
+//! Editors can use [`tokens`] to highlight a program and [`diagnostics`] to list its errors.
+//! Other tools working on awk source can [`parse`] it into its [`ast`].
+
+use crate::args::{AwkArgs, ProgramType};
+use crate::folding::fold_constants;
+use crate::lexer::{lex, lex_spanned, lex_with_keywords, unescape, Token};
+use crate::parser::{parse_with_dialect, Dialect, Expr, ParseError, Program, Stmt, TypedExpr};
+use crate::runtime::strnum;
+use crate::transformer::transform;
+use crate::typing::analyze;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+mod args;
+mod codgen;
+mod columns;
+mod folding;
+mod lexer;
+mod parser;
+mod printable_error;
+mod printf;
+mod regex;
+mod runtime;
+#[allow(dead_code)]
+mod test;
+mod transformer;
+mod typing;
+
+pub use lexer::{Keywords, SpannedToken, TokenKind};
+
+/// The syntax tree [`parse`] returns and visitors to walk it. Each `Visitor` method visits the
+/// node's children by default, a tool overrides the nodes it cares about and calls the matching
+/// `walk_` function to keep going below them.
+pub mod ast {
+    pub use crate::lexer::{BinOp, LogicalOp, MathOp, Symbol};
+    pub use crate::parser::{
+        is_special_var, walk_expr, walk_expr_mut, walk_lvalue, walk_lvalue_mut, walk_program,
+        walk_program_mut, walk_stmt, walk_stmt_mut, AwkT, Expr, GetlineSource, Lvalue, Output,
+        Pattern, PatternAction, Program, Stmt, TypedExpr, Visitor, VisitorMut,
+    };
+    pub use crate::typing::infer_types;
+}
+
+/// Why a program couldn't be run
+#[derive(Debug)]
+pub enum RunError {
+    /// The program couldn't be split into tokens. The message and the line it happened on.
+    Lex(String, usize),
+    /// The program couldn't be compiled. Syntax errors are all listed, one per line.
+    Compile(String),
+    /// A program file couldn't be read. The file and why.
+    Load(String, std::io::Error),
+    /// The error is in this program file, a line in it counts from the start of the file
+    InFile(String, Box<RunError>),
+}
+
+impl Display for RunError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunError::Lex(msg, line) => write!(f, "line {}: {}", line, msg),
+            RunError::Compile(msg) => f.write_str(msg),
+            RunError::Load(file, err) => {
+                write!(f, "Unable to load source program '{}': {}", file, err)
+            }
+            RunError::InFile(file, err) => write!(f, "{}: {}", file, err),
+        }
+    }
+}
+
+impl std::error::Error for RunError {}
+
+/// Runs the program over `input` and returns the bytes it prints. Nothing is written to stdout.
+///
+/// The output is returned as bytes rather than a `String` since printf in byte mode may print
+/// bytes that aren't valid UTF-8.
+///
+/// ```
+/// let output = jawk::run_capture("{ print $2, NR }", "a b\nc d\n").unwrap();
+/// assert_eq!(output, b"b 1\nd 2\n");
+/// ```
+pub fn run_capture(program: &str, input: &str) -> Result<Vec<u8>, RunError> {
+    run_capture_with_vars(program, &[], input)
+}
+
+/// [`run_capture`] with `name=value` assignments made before BEGIN, like `-v` on the command
+/// line. Escapes in the value are decoded as in a string literal and a value that is a number
+/// compares as one.
+///
+/// ```
+/// let output = jawk::run_capture_with_vars("BEGIN { print x < 10, y }", &["x=3", "y=a\\tb"], "")
+///     .unwrap();
+/// assert_eq!(output, b"1 a\tb\n");
+/// ```
+pub fn run_capture_with_vars(
+    program: &str,
+    assignments: &[&str],
+    input: &str,
+) -> Result<Vec<u8>, RunError> {
+    let ast = compile(program, Dialect::Gawk, assignments)?;
+    codgen::compile_and_collect(ast, input.to_string())
+        .map_err(|err| RunError::Compile(err.to_string()))
+}
+
+/// [`run_capture`] with the program read from `paths`, joined in order like repeating `-f` on
+/// the command line. A lex error names the file it is in.
+///
+/// ```
+/// let dir = std::env::temp_dir();
+/// let (first, second) = (dir.join("jawk-doc-first.awk"), dir.join("jawk-doc-second.awk"));
+/// std::fs::write(&first, "BEGIN { x = 2 }").unwrap();
+/// std::fs::write(&second, "{ print $1 * x }").unwrap();
+/// let output = jawk::run_capture_files(&[first, second], "3\n4\n").unwrap();
+/// assert_eq!(output, b"6\n8\n");
+/// ```
+pub fn run_capture_files<P: AsRef<Path>>(paths: &[P], input: &str) -> Result<Vec<u8>, RunError> {
+    let ast = compile_files(paths, Dialect::Gawk, &[])?;
+    codgen::compile_and_collect(ast, input.to_string())
+        .map_err(|err| RunError::Compile(err.to_string()))
+}
+
+/// [`run_capture`] with only these keywords recognized, a word that was turned off is a name
+/// like any other.
+///
+/// ```
+/// let keywords = jawk::Keywords::default().without("delete");
+/// let output = jawk::run_capture_with_keywords("BEGIN { delete = 2; print delete }", keywords, "")
+///     .unwrap();
+/// assert_eq!(output, b"2\n");
+/// ```
+pub fn run_capture_with_keywords(
+    program: &str,
+    keywords: Keywords,
+    input: &str,
+) -> Result<Vec<u8>, RunError> {
+    let tokens =
+        lex_with_keywords(program, keywords).map_err(|err| RunError::Lex(err.message, err.line))?;
+    let ast = compile_tokens(tokens, Dialect::Gawk, &[])?;
+    codgen::compile_and_collect(ast, input.to_string())
+        .map_err(|err| RunError::Compile(err.to_string()))
+}
+
+/// The tokens of a program with their kind and where they are in it, comments included.
+/// The last is the end of the program, an empty token at its length.
+///
+/// ```
+/// use jawk::TokenKind;
+///
+/// let program = "/a/ { print \"é\" } # done";
+/// let tokens = jawk::tokens(program).unwrap();
+/// let (first, comment) = (&tokens[0], &tokens[tokens.len() - 2]);
+/// assert_eq!((first.kind(), &program[first.start..first.end]), (TokenKind::Regex, "/a/"));
+/// assert_eq!(comment.kind(), TokenKind::Comment);
+/// assert_eq!(&program[comment.start..comment.end], "# done");
+/// ```
+pub fn tokens(program: &str) -> Result<Vec<SpannedToken>, RunError> {
+    lex_spanned(program).map_err(|err| RunError::Lex(err.message, err.line))
+}
+
+/// Every error in a program, for an editor to show all at once. Parsing skips past each error to
+/// the end of the statement or action it is in and carries on. A program that can't be split into
+/// tokens has just the one lex error. Empty when the program is fine.
+///
+/// ```
+/// let errors = jawk::diagnostics("BEGIN { x = ; print 1 }\nEND { break }");
+/// let messages: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
+/// assert_eq!(messages.len(), 2);
+/// assert_eq!(messages[1], "break must be inside a loop");
+/// assert!(jawk::diagnostics("{ print $1 }").is_empty());
+/// ```
+pub fn diagnostics(program: &str) -> Vec<RunError> {
+    let tokens = match lex(program) {
+        Ok(tokens) => tokens,
+        Err(err) => return vec![RunError::Lex(err.message, err.line)],
+    };
+    match parse_with_dialect(tokens, Dialect::Gawk) {
+        Ok(_) => vec![],
+        Err(errors) => errors
+            .into_iter()
+            .map(|err| RunError::Compile(err.message))
+            .collect(),
+    }
+}
+
+/// Parses a program into its syntax tree without compiling it. The tree is as written, BEGIN and
+/// END actions and patterns are still separate and every expression's type is `Variable` until
+/// [`ast::infer_types`] fills in the ones that follow from the expression alone.
+/// [`ast::Program::to_source`] prints a tree back out as awk, formatted the one way.
+///
+/// ```
+/// use jawk::ast::{walk_program, TypedExpr, Visitor};
+///
+/// struct Columns(usize);
+///
+/// impl Visitor for Columns {
+///     fn visit_column(&mut self, index: &TypedExpr) {
+///         self.0 += 1;
+///         self.visit_expr(index);
+///     }
+/// }
+///
+/// let program = jawk::parse("$1 > 2 { print $2, $($3) }").unwrap();
+/// let mut columns = Columns(0);
+/// walk_program(&mut columns, &program);
+/// assert_eq!(columns.0, 4);
+///
+/// let mut program = jawk::parse("BEGIN { print 1 + x, x \"s\", x }").unwrap();
+/// jawk::ast::infer_types(&mut program);
+/// let print = program.begins[0].to_string();
+/// assert_eq!(print.trim_end(), "print (f (f 1)+(v x)), (s (v x) (s \"s\")), (v x)");
+///
+/// let program = jawk::parse("$1>2{print $2,$($3)}").unwrap();
+/// assert_eq!(program.to_source(), "$1 > 2 {\n    print $2, $($3)\n}\n");
+/// ```
+pub fn parse(program: &str) -> Result<Program, RunError> {
+    let tokens = lex(program).map_err(|err| RunError::Lex(err.message, err.line))?;
+    parse_tokens(tokens, Dialect::Gawk)
+}
+
+/// Runs jawk as the command line tool with these arguments, the first being the program name.
+/// Returns the exit status.
+pub fn run_cli(args: Vec<String>) -> i32 {
+    let args = match AwkArgs::new(args) {
+        Ok(args) => args,
+        Err(_) => return 0,
+    };
+    // -F is FS=fs made before any -v
+    let field_sep = args.field_sep.map(|fs| format!("FS={}", fs));
+    let assignments: Vec<&str> = field_sep
+        .iter()
+        .chain(args.assignments.iter())
+        .map(String::as_str)
+        .collect();
+    let compiled = match &args.program {
+        ProgramType::CLI(program) => compile(program, args.dialect, &assignments),
+        ProgramType::Files(paths) => compile_files(paths, args.dialect, &assignments),
+    };
+    let ast = match compiled {
+        Ok(ast) => ast,
+        Err(err) => {
+            eprintln!("{}", err);
+            return 2;
+        }
+    };
+
+    if args.debug {
+        println!("{:?}", ast);
+        println!("{}", ast);
+    }
+
+    // Without any files the input is stdin
+    let files = if args.files.is_empty() {
+        vec!["-".to_string()]
+    } else {
+        args.files
+    };
+
+    // 6, see compile for the rest
+    if args.debug {
+        if let Err(err) = codgen::compile_and_capture(ast, &files, args.header) {
+            eprintln!("{}", err);
+        }
+        0
+    } else {
+        match codgen::compile_and_run(ast, &files, args.header) {
+            Ok(status) => status,
+            Err(err) => {
+                eprintln!("{}", err);
+                0
+            }
+        }
+    }
+}
+
+fn compile(program: &str, dialect: Dialect, assignments: &[&str]) -> Result<Stmt, RunError> {
+    // 1. Lex into token
+    // 2. Parse into tree
+    // 3. Fold constant expressions
+    // 4. Transform the program with its patterns and actions into a singular Stmt
+    // 5. Type checking pass
+    // 6. Run it, done by the caller
+
+    // 1
+    let tokens = lex(program).map_err(|err| RunError::Lex(err.message, err.line))?;
+    compile_tokens(tokens, dialect, assignments)
+}
+
+// compile once the program is lexed
+fn compile_tokens(
+    tokens: Vec<Token>,
+    dialect: Dialect,
+    assignments: &[&str],
+) -> Result<Stmt, RunError> {
+    // 2
+    let mut program = parse_tokens(tokens, dialect)?;
+    for (idx, assignment) in assignments.iter().enumerate() {
+        program
+            .begins
+            .insert(idx, command_line_assignment(assignment)?);
+    }
+
+    // 3
+    fold_constants(&mut program);
+
+    // 4
+    let mut ast = transform(program);
+
+    // 5
+    analyze(&mut ast);
+    Ok(ast)
+}
+
+// Every syntax error is listed, one per line
+fn parse_tokens(tokens: Vec<Token>, dialect: Dialect) -> Result<Program, RunError> {
+    parse_with_dialect(tokens, dialect).map_err(|errors| {
+        let messages: Vec<String> = errors.iter().map(ParseError::to_string).collect();
+        RunError::Compile(messages.join("\n"))
+    })
+}
+
+// The files are joined with a newline after each, a lex error's line in the joined program is
+// turned back into the file and the line in it
+fn compile_files<P: AsRef<Path>>(
+    paths: &[P],
+    dialect: Dialect,
+    assignments: &[&str],
+) -> Result<Stmt, RunError> {
+    let mut program = String::new();
+    // Each file and the line of the joined program it starts on
+    let mut starts = vec![];
+    let mut line = 0;
+    for path in paths {
+        let path = path.as_ref().display().to_string();
+        let source =
+            std::fs::read_to_string(&path).map_err(|err| RunError::Load(path.clone(), err))?;
+        starts.push((path, line));
+        line += source.matches('\n').count() + 1;
+        program.push_str(&source);
+        program.push('\n');
+    }
+    compile(&program, dialect, assignments).map_err(|err| match err {
+        RunError::Lex(msg, line) => {
+            let (path, start) = starts
+                .iter()
+                .rev()
+                .find(|(_, start)| *start <= line)
+                .unwrap();
+            RunError::InFile(path.clone(), Box::new(RunError::Lex(msg, line - start)))
+        }
+        err => err,
+    })
+}
+
+// -v name=value as the statement name = value. A value that looks numeric is a strnum, so 03
+// compares as 3 but still prints as 03. Anything else is assigned as a string.
+fn command_line_assignment(assignment: &str) -> Result<Stmt, RunError> {
+    let invalid = || {
+        RunError::Compile(format!(
+            "-v assignment `{}` must be var=value with a variable name",
+            assignment
+        ))
+    };
+    let (name, value) = assignment.split_once('=').ok_or_else(invalid)?;
+    match lex(name).as_deref() {
+        Ok([Token::Ident(ident), Token::EOF]) if ident == name => {}
+        _ => return Err(invalid()),
+    }
+    let value = unescape(value);
+    let value = match strnum(&value) {
+        Some(num) => TypedExpr::new_var(Expr::StrNum(value, num)),
+        None => TypedExpr::new_str(Expr::String(value)),
+    };
+    let assign = match name {
+        "NF" => Expr::NfAssign(Box::new(value)),
+        _ => Expr::Assign(name.into(), Box::new(value)),
+    };
+    Ok(Stmt::Expr(assign.into()))
+}
@@ -0,0 +1,94 @@
+use crate::printf::NUMBER_FORMAT;
+use std::ops::RangeInclusive;
+
+// Which awk the program is written for. gawk's extensions are only available under Gawk.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dialect {
+    Posix,
+    Gawk,
+    // The one true awk, Brian Kernighan's
+    Bwk,
+}
+
+impl Dialect {
+    // The command line option that picks this dialect. Gawk is the default and has none.
+    pub fn option(&self) -> &'static str {
+        match self {
+            Dialect::Posix => "--posix",
+            Dialect::Gawk => "",
+            Dialect::Bwk => "--traditional",
+        }
+    }
+}
+
+// Number of arguments each builtin function accepts. None if `name` isn't a builtin.
+pub fn builtin_arity(name: &str) -> Option<RangeInclusive<usize>> {
+    let arity = match name {
+        "typeof" => 1..=1,
+        "isarray" => 1..=1,
+        "sprintf" => 1..=usize::MAX,
+        // close(name [, how]), how is "to" or "from" for one end of a coprocess
+        "close" => 1..=2,
+        "system" => 1..=1,
+        "toupper" => 1..=1,
+        "tolower" => 1..=1,
+        // match(s, re), the pattern is a /re/ literal or any string. Returns where re first
+        // matches s counting from 1 or 0, RSTART and RLENGTH aren't set yet.
+        "match" => 2..=2,
+        // sub(re, repl [, target]) and gsub, target is a variable, field or array element and
+        // $0 without one
+        "sub" => 2..=3,
+        "gsub" => 2..=3,
+        "rand" => 0..=0,
+        // srand() without a seed uses the time of day
+        "srand" => 0..=1,
+        // patsplit(s, arr [, fpat [, seps]])
+        "patsplit" => 2..=4,
+        _ => return None,
+    };
+    Some(arity)
+}
+
+// Variables awk gives a meaning, as opposed to the program's own, and the value the ones that
+// are plain variables start as. NR, FNR, NF and RT are kept by the runtime as it reads records.
+pub const SPECIAL_VARS: &[(&str, Option<&str>)] = &[
+    ("ARGC", None),
+    ("ARGV", None),
+    ("CONVFMT", Some(NUMBER_FORMAT)),
+    ("ENVIRON", None),
+    ("FILENAME", None),
+    ("FNR", None),
+    ("FS", Some(" ")),
+    ("NF", None),
+    ("NR", None),
+    ("OFMT", Some(NUMBER_FORMAT)),
+    ("OFS", Some(" ")),
+    ("ORS", Some("\n")),
+    ("RLENGTH", None),
+    ("RS", Some("\n")),
+    ("RSTART", None),
+    ("RT", None),
+    ("SUBSEP", Some("\u{1c}")),
+];
+
+// Whether name is one of awk's special variables. Names are case sensitive, nf is just a variable.
+pub fn is_special_var(name: &str) -> bool {
+    SPECIAL_VARS.iter().any(|(var, _)| *var == name)
+}
+
+// Builtins only gawk has
+pub fn gawk_only(name: &str) -> bool {
+    matches!(name, "typeof" | "isarray" | "patsplit")
+}
+
+#[test]
+fn test_is_special_var() {
+    assert!(is_special_var("NF"));
+    assert!(is_special_var("FS"));
+    assert!(is_special_var("ENVIRON"));
+    assert!(is_special_var("ARGV"));
+    assert!(is_special_var("ARGC"));
+    assert!(is_special_var("RT"));
+    assert!(!is_special_var("nf"));
+    assert!(!is_special_var("x"));
+}
@@ -1,8 +1,19 @@
+mod builtins;
+mod source;
 mod types;
+mod visitor;
 
 use crate::lexer::{BinOp, LogicalOp, MathOp, Token, TokenType};
+use crate::parser::builtins::{builtin_arity, gawk_only};
+pub use crate::parser::builtins::{is_special_var, Dialect, SPECIAL_VARS};
+pub use crate::parser::types::Output;
 pub use crate::parser::types::PatternAction;
-pub use types::{AwkT, Expr, Program, Stmt, TypedExpr};
+use std::fmt::{Display, Formatter};
+pub use types::{AwkT, Expr, GetlineSource, Lvalue, Pattern, Program, Stmt, TypedExpr};
+pub use visitor::{
+    walk_expr, walk_expr_mut, walk_lvalue, walk_lvalue_mut, walk_program, walk_program_mut,
+    walk_stmt, walk_stmt_mut, Visitor, VisitorMut,
+};
 
 // Pattern Action Type
 // Normal eg: $1 == "a" { doSomething() }
@@ -14,31 +25,151 @@ enum PAType {
     End(Stmt),
 }
 
-pub fn parse(tokens: Vec<Token>) -> Program {
-    let mut parser = Parser { tokens, current: 0 };
-    parser.parse()
+pub fn parse(tokens: Vec<Token>) -> Result<Program, Vec<ParseError>> {
+    parse_with_dialect(tokens, Dialect::Gawk)
+}
+
+// Parses as much of the program as it can, skipping past each error to the end of the statement
+// or action it is in and carrying on. The program is only returned if there were no errors,
+// otherwise every error is.
+pub fn parse_with_dialect(
+    tokens: Vec<Token>,
+    dialect: Dialect,
+) -> Result<Program, Vec<ParseError>> {
+    let mut parser = Parser::new(tokens, dialect);
+    let program = parser.parse();
+    if parser.errors.is_empty() {
+        Ok(program)
+    } else {
+        Err(parser.errors)
+    }
+}
+
+// Something wrong with the program being parsed, as opposed to a bug in the parser
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
 }
 
 struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    // In unparenthesized print arguments `>` is output redirection, not a comparison
+    in_print: bool,
+    // There is no next record to skip to from BEGIN or END
+    in_begin_or_end: bool,
+    // How many loops the current statement is inside of, break and continue need one
+    loops: usize,
+    dialect: Dialect,
+    // Every error so far, the parse skips past each one and keeps going
+    errors: Vec<ParseError>,
 }
 
 impl Parser {
+    fn new(tokens: Vec<Token>, dialect: Dialect) -> Parser {
+        Parser {
+            tokens,
+            current: 0,
+            in_print: false,
+            in_begin_or_end: false,
+            loops: 0,
+            dialect,
+            errors: vec![],
+        }
+    }
+
     fn parse(&mut self) -> Program {
         let mut begin = vec![];
         let mut end = vec![];
         let mut generic = vec![];
+        self.skip_newlines();
         while !self.is_at_end() {
-            match self.pattern_action() {
-                PAType::Normal(pa) => generic.push(pa),
-                PAType::Begin(pa) => begin.push(pa),
-                PAType::End(pa) => end.push(pa),
+            match self.recovering(|p| p.pattern_action(), true) {
+                Some(PAType::Normal(pa)) => generic.push(pa),
+                Some(PAType::Begin(pa)) => begin.push(pa),
+                Some(PAType::End(pa)) => end.push(pa),
+                None => {}
             }
+            self.skip_newlines();
         }
         Program::new(begin, end, generic)
     }
 
+    fn error<T, S: Into<String>>(&self, message: S) -> Result<T, ParseError> {
+        Err(ParseError {
+            message: message.into(),
+        })
+    }
+
+    // feature is one of gawk's extensions, which the other dialects don't have
+    fn require_gawk(&self, feature: &str) -> Result<(), ParseError> {
+        if self.dialect != Dialect::Gawk {
+            return self.error(format!(
+                "{} is a gawk extension, not available with {}",
+                feature,
+                self.dialect.option()
+            ));
+        }
+        Ok(())
+    }
+
+    // Runs parse, and if it errors records the error and skips to where parsing can pick up
+    // again: past the end of the statement, or of the pattern action at the top level.
+    fn recovering<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+        top_level: bool,
+    ) -> Option<T> {
+        let loops = self.loops;
+        let in_begin_or_end = self.in_begin_or_end;
+        let err = match parse(self) {
+            Ok(result) => return Some(result),
+            Err(err) => err,
+        };
+        self.errors.push(err);
+        self.loops = loops;
+        self.in_begin_or_end = in_begin_or_end;
+        self.in_print = false;
+        self.synchronize(top_level);
+        None
+    }
+
+    // Skips to the next ; or newline outside of braces. A } that closes a block opened before
+    // the error is left for the block, at the top level it ends the pattern action and is skipped.
+    fn synchronize(&mut self, top_level: bool) {
+        let mut depth = 0;
+        while !self.is_at_end() {
+            match self.peek().ttype() {
+                TokenType::LeftBrace => depth += 1,
+                TokenType::RightBrace if depth == 0 => {
+                    if top_level {
+                        self.advance();
+                    }
+                    return;
+                }
+                TokenType::RightBrace => {
+                    depth -= 1;
+                    if depth == 0 && top_level {
+                        self.advance();
+                        return;
+                    }
+                }
+                TokenType::Semicolon | TokenType::Newline if depth == 0 => {
+                    self.advance();
+                    return;
+                }
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
     fn check(&mut self, typ: TokenType) -> bool {
         if self.is_at_end() {
             false
@@ -47,17 +178,17 @@ impl Parser {
         }
     }
 
-    fn consume(&mut self, typ: TokenType, message: &str) -> Token {
+    fn consume(&mut self, typ: TokenType, message: &str) -> Result<Token, ParseError> {
         if self.check(typ.clone()) {
-            return self.advance();
+            return Ok(self.advance());
         }
-        panic!(
+        self.error(format!(
             "{} - didn't find a {} as expected. Found a {} {:?}",
             message,
             TokenType::name(typ),
             TokenType::name(self.peek().ttype()),
             self.peek()
-        );
+        ))
     }
 
     fn matches(&mut self, tokens: Vec<TokenType>) -> bool {
@@ -100,30 +231,50 @@ impl Parser {
         self.previous().unwrap()
     }
 
-    fn pattern_action(&mut self) -> PAType {
+    fn pattern_action(&mut self) -> Result<PAType, ParseError> {
         let b = if self.matches(vec![TokenType::LeftBrace]) {
             // { print 1; }
             let pa = PAType::Normal(PatternAction::new_action_only(self.stmts()));
-            self.consume(TokenType::RightBrace, "Expected '}' after action block");
+            self.consume(TokenType::RightBrace, "Expected '}' after action block")?;
             pa
         } else if self.matches(vec![TokenType::Begin]) {
             // BEGIN { print 1; }
-            self.consume(TokenType::LeftBrace, "Expected a '{' after a begin");
+            self.consume(TokenType::LeftBrace, "Expected a '{' after a begin")?;
+            self.in_begin_or_end = true;
             let pa = PAType::Begin(self.stmts());
-            self.consume(TokenType::RightBrace, "Begin action should end with '}'");
+            self.in_begin_or_end = false;
+            self.consume(TokenType::RightBrace, "Begin action should end with '}'")?;
             pa
         } else if self.matches(vec![TokenType::End]) {
             // END { print 1; }
-            self.consume(TokenType::LeftBrace, "Expected a {' after a end");
+            self.consume(TokenType::LeftBrace, "Expected a {' after a end")?;
+            self.in_begin_or_end = true;
             let pa = PAType::End(self.stmts());
-            self.consume(TokenType::RightBrace, "End action should end with '}'");
+            self.in_begin_or_end = false;
+            self.consume(TokenType::RightBrace, "End action should end with '}'")?;
             pa
+        } else if let Some(keyword) = self.statement_keyword() {
+            // Not a pattern, a statement that was meant to be in braces
+            return self.error(format!(
+                "{} must be inside an action, eg. {{ {} ... }}",
+                keyword, keyword
+            ));
         } else {
-            let test = self.expression();
-            if self.matches(vec![TokenType::LeftBrace]) {
+            let test = self.expression()?;
+            if self.matches(vec![TokenType::Comma]) {
+                // start, end { print 1; }
+                let end = self.expression()?;
+                if self.matches(vec![TokenType::LeftBrace]) {
+                    let pa = PAType::Normal(PatternAction::new_range(test, end, self.stmts()));
+                    self.consume(TokenType::RightBrace, "Patern action should end with '}'")?;
+                    pa
+                } else {
+                    PAType::Normal(PatternAction::new_range_only(test, end))
+                }
+            } else if self.matches(vec![TokenType::LeftBrace]) {
                 // test { print 1; }
                 let pa = PAType::Normal(PatternAction::new(Some(test), self.stmts()));
-                self.consume(TokenType::RightBrace, "Patern action should end with '}'");
+                self.consume(TokenType::RightBrace, "Patern action should end with '}'")?;
                 pa
             } else {
                 // test
@@ -131,182 +282,490 @@ impl Parser {
                 PAType::Normal(PatternAction::new_pattern_only(test))
             }
         };
-        b
+        Ok(b)
     }
-    fn group(&mut self) -> Stmt {
-        self.consume(TokenType::LeftBrace, "Expected a '}'");
-        let s = self.stmts();
-        self.consume(TokenType::RightBrace, "Expected a '}'");
-        s
+
+    // The keyword if the next token starts a statement that can't also be an expression
+    fn statement_keyword(&self) -> Option<&'static str> {
+        let keyword = match self.peek() {
+            Token::Print => "print",
+            Token::Printf => "printf",
+            Token::If => "if",
+            Token::While => "while",
+            Token::Do => "do",
+            Token::For => "for",
+            Token::Next => "next",
+            Token::Exit => "exit",
+            Token::Break => "break",
+            Token::Continue => "continue",
+            Token::Delete => "delete",
+            _ => return None,
+        };
+        Some(keyword)
     }
 
-    fn stmt_and_optional_semicolon(&mut self) -> Stmt {
-        let stmt = self.stmt();
-        if self.peek().ttype() == TokenType::Semicolon {
-            self.consume(TokenType::Semicolon, "not possible");
-        }
-        stmt
+    // A newline terminates a statement just like a semicolon
+    fn stmt_and_optional_semicolon(&mut self) -> Result<Stmt, ParseError> {
+        let stmt = self.stmt()?;
+        while self.matches(vec![TokenType::Semicolon, TokenType::Newline]) {}
+        Ok(stmt)
     }
 
-    fn stmt(&mut self) -> Stmt {
+    // Newlines are allowed but mean nothing here, eg. between `if (a)` and its `{`
+    fn skip_newlines(&mut self) {
+        while self.matches(vec![TokenType::Newline]) {}
+    }
+
+    fn stmt(&mut self) -> Result<Stmt, ParseError> {
         let stmt = if self.matches(vec![TokenType::Print]) {
-            Stmt::Print(self.expression())
+            let mut args = self.print_args()?;
+            if args.is_empty() {
+                // Bare print is print $0
+                args.push(TypedExpr::new_str(Expr::Column(Box::new(
+                    TypedExpr::new_num(Expr::NumberF64(0.0)),
+                ))));
+            }
+            let output = self.output_redirect()?;
+            Stmt::Print { args, output }
         } else if self.matches(vec![TokenType::For]) {
-            self.consume(TokenType::LeftParen, "Expected a '(' after the for keyword");
-            let init = self.stmt();
+            self.consume(TokenType::LeftParen, "Expected a '(' after the for keyword")?;
+            if self.check(TokenType::Ident) && self.peek_next().ttype() == TokenType::In {
+                return self.for_in();
+            }
+            // Any part can be left out, for (;;) loops until a break
+            let init = self.optional_stmt(TokenType::Semicolon)?;
             self.consume(
                 TokenType::Semicolon,
                 "Expected a ';' after for loop init statement",
-            );
-            let test = self.expression();
+            )?;
+            let test = if self.check(TokenType::Semicolon) {
+                TypedExpr::new_num(Expr::NumberF64(1.0))
+            } else {
+                self.expression()?
+            };
             self.consume(
                 TokenType::Semicolon,
                 "Expected a ';' after for loop test statement",
-            );
-            let incr = self.stmt();
-            self.consume(TokenType::RightParen, "Expected a ')' to end for loop");
-            self.consume(
-                TokenType::LeftBrace,
-                "Expected a '{' to begin for loop body",
-            );
-            let body = self.stmts();
-            self.consume(TokenType::RightBrace, "Expected a '}' after for loop body");
-            Stmt::Group(vec![
-                init,
-                Stmt::While(test, Box::new(Stmt::Group(vec![body, incr]))),
-            ])
-        } else if self.peek_next().ttype() == TokenType::Eq {
-            let str = if let Token::Ident(str) =
-                self.consume(TokenType::Ident, "Expected identifier before '='")
-            {
-                str
-            } else {
-                panic!("Expected identifier before '='")
-            };
-            self.consume(TokenType::Eq, "Expected '=' after identifier");
-            Stmt::Expr(TypedExpr::new_var(Expr::Assign(
-                str,
-                Box::new(self.expression()),
-            )))
+            )?;
+            let incr = self.optional_stmt(TokenType::RightParen)?;
+            let body = self.for_body()?;
+            Stmt::For {
+                init: Box::new(init),
+                test,
+                incr: Box::new(incr),
+                body: Box::new(body),
+            }
             // } else if self.matches(vec![TokenType::Ret]) {
             //     self.return_stmt()
         } else if self.matches(vec![TokenType::While]) {
-            self.consume(TokenType::LeftParen, "Must have paren after while");
-            let expr = self.expression();
+            self.consume(TokenType::LeftParen, "Must have paren after while")?;
+            let expr = self.expression()?;
+            self.consume(
+                TokenType::RightParen,
+                "Must have right parent after while statement test expression",
+            )?;
+            self.skip_newlines();
+            Stmt::While(expr, Box::new(self.loop_body()?))
+        } else if self.matches(vec![TokenType::Do]) {
+            self.skip_newlines();
+            let body = self.loop_body()?;
+            // do print x; while (x) has a ; before the while like if's else
+            while self.matches(vec![TokenType::Semicolon, TokenType::Newline]) {}
+            self.consume(TokenType::While, "Expected 'while' after the body of do")?;
+            self.consume(TokenType::LeftParen, "Must have paren after while")?;
+            let test = self.expression()?;
             self.consume(
                 TokenType::RightParen,
                 "Must have right parent after while statement test expression",
-            );
-            self.consume(TokenType::LeftBrace, "Must have brace after `while (expr)`");
-            let stmts = self.stmts();
-            self.consume(TokenType::RightBrace, "While loop must be followed by '}'");
-            Stmt::While(expr, Box::new(stmts))
-        } else if self.matches(vec![TokenType::Print]) {
-            let expr = self.expression();
-            Stmt::Print(expr)
+            )?;
+            Stmt::DoWhile(Box::new(body), test)
+        } else if self.matches(vec![TokenType::Printf]) {
+            let mut args = self.print_args()?;
+            if args.is_empty() {
+                return self.error("printf requires a format string");
+            }
+            let fmt = args.remove(0);
+            let output = self.output_redirect()?;
+            Stmt::Printf { fmt, args, output }
+        } else if self.matches(vec![TokenType::Delete]) {
+            self.delete()?
+        } else if self.matches(vec![TokenType::Next]) {
+            if self.in_begin_or_end {
+                return self.error("next cannot be used in a BEGIN or END action");
+            }
+            Stmt::Next
+        } else if self.matches(vec![TokenType::Break]) {
+            if self.loops == 0 {
+                return self.error("break must be inside a loop");
+            }
+            Stmt::Break
+        } else if self.matches(vec![TokenType::Continue]) {
+            if self.loops == 0 {
+                return self.error("continue must be inside a loop");
+            }
+            Stmt::Continue
+        } else if self.matches(vec![TokenType::Exit]) {
+            if self.at_stmt_end() {
+                Stmt::Exit(None)
+            } else {
+                Stmt::Exit(Some(self.expression()?))
+            }
         } else if self.matches(vec![TokenType::If]) {
-            self.if_stmt()
+            self.if_stmt()?
+        } else if self.matches(vec![TokenType::Semicolon]) {
+            // A lone ; is an empty statement, eg. the body of while ((getline line) > 0) ;
+            Stmt::Group(vec![])
         } else if self.matches(vec![TokenType::LeftBrace]) {
             let s = self.stmts();
             self.consume(
                 TokenType::RightBrace,
                 "Expected a right brace after a group",
-            );
+            )?;
             s
         } else {
-            Stmt::Expr(self.expression())
+            Stmt::Expr(self.expression()?)
         };
-        stmt
+        Ok(stmt)
+    }
+
+    // A statement, or an empty group if there is none before `end`
+    fn optional_stmt(&mut self, end: TokenType) -> Result<Stmt, ParseError> {
+        if self.check(end) {
+            Ok(Stmt::Group(vec![]))
+        } else {
+            self.stmt()
+        }
     }
 
     fn stmts(&mut self) -> Stmt {
         let mut stmts = vec![];
-        while self.peek().ttype() != TokenType::RightBrace {
-            let stmt = self.stmt_and_optional_semicolon();
-            stmts.push(stmt);
+        while self.peek().ttype() != TokenType::RightBrace && !self.is_at_end() {
+            // A lone ; is an empty statement
+            if self.matches(vec![TokenType::Semicolon, TokenType::Newline]) {
+                continue;
+            }
+            if let Some(stmt) = self.recovering(|p| p.stmt_and_optional_semicolon(), false) {
+                stmts.push(stmt);
+            }
+        }
+        // { } is an empty group. It does nothing, after a pattern that keeps the record from
+        // being printed.
+        match stmts.len() {
+            1 => stmts.pop().unwrap(),
+            _ => Stmt::Group(stmts),
+        }
+    }
+
+    // The arguments to print/printf, either `print a, b` or `print(a, b)`
+    fn print_args(&mut self) -> Result<Vec<TypedExpr>, ParseError> {
+        if self.check(TokenType::LeftParen) {
+            let start = self.current;
+            self.advance();
+            let args = self.expression_list()?;
+            if self.matches(vec![TokenType::RightParen])
+                && (self.at_stmt_end() || self.at_redirect())
+            {
+                return Ok(args);
+            }
+            // The parens only grouped the first expression eg. printf ("%s") "\n"
+            self.current = start;
         }
-        if stmts.len() == 1 {
-            return stmts.pop().unwrap();
+        if self.at_stmt_end() || self.at_redirect() {
+            return Ok(vec![]);
+        }
+        self.in_print = true;
+        let args = self.expression_list()?;
+        self.in_print = false;
+        Ok(args)
+    }
+
+    fn at_redirect(&mut self) -> bool {
+        self.check(TokenType::Greater)
+            || self.check(TokenType::Append)
+            || self.check(TokenType::Pipe)
+            || self.check(TokenType::TwoWayPipe)
+    }
+
+    fn output_redirect(&mut self) -> Result<Option<Output>, ParseError> {
+        let output = if self.matches(vec![TokenType::Greater]) {
+            Some(Output::File(self.string_concat()?))
+        } else if self.matches(vec![TokenType::Append]) {
+            Some(Output::Append(self.string_concat()?))
+        } else if self.matches(vec![TokenType::Pipe]) {
+            Some(Output::Pipe(self.string_concat()?))
+        } else if self.matches(vec![TokenType::TwoWayPipe]) {
+            self.require_gawk("|&")?;
+            Some(Output::Coprocess(self.string_concat()?))
+        } else {
+            None
+        };
+        Ok(output)
+    }
+
+    // Parse something nested in brackets where `>` is a comparison again, eg. print (a > b)
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        let in_print = std::mem::replace(&mut self.in_print, false);
+        let result = parse(self);
+        self.in_print = in_print;
+        result
+    }
+
+    fn expression_list(&mut self) -> Result<Vec<TypedExpr>, ParseError> {
+        let mut exprs = vec![self.expression()?];
+        while self.matches(vec![TokenType::Comma]) {
+            exprs.push(self.expression()?);
         }
-        Stmt::Group(stmts)
+        Ok(exprs)
     }
 
-    fn if_stmt(&mut self) -> Stmt {
-        self.consume(TokenType::LeftParen, "Expected '(' after if");
-        let predicate = self.expression();
-        self.consume(TokenType::RightParen, "Expected ')' after if predicate");
-        let then_blk = self.group();
+    fn at_stmt_end(&self) -> bool {
+        self.is_at_end()
+            || matches!(
+                self.peek().ttype(),
+                TokenType::Semicolon | TokenType::Newline | TokenType::RightBrace
+            )
+    }
+
+    fn if_stmt(&mut self) -> Result<Stmt, ParseError> {
+        self.consume(TokenType::LeftParen, "Expected '(' after if")?;
+        let predicate = self.expression()?;
+        self.consume(TokenType::RightParen, "Expected ')' after if predicate")?;
+        self.skip_newlines();
+        // Either body may be a single statement without braces
+        let then_blk = self.stmt()?;
+        // In `if (a) print 1; else print 2` the ; ends the print, not the if
+        let before_else = self.current;
+        while self.matches(vec![TokenType::Semicolon, TokenType::Newline]) {}
         let else_blk = if self.matches(vec![TokenType::Else]) {
-            Some(Box::new(self.group()))
+            Some(Box::new(self.stmt()?))
         } else {
+            self.current = before_else;
             None
         };
-        Stmt::If(predicate, Box::new(then_blk), else_blk)
-    }
-
-    fn expression(&mut self) -> TypedExpr {
-        self.assignment().into()
-    }
-
-    fn assignment(&mut self) -> TypedExpr {
-        let lhs = self.logical_or();
-        if let Expr::Variable(var) = &lhs.expr {
-            let var = var.clone();
-            if self.matches(vec![TokenType::Eq]) {
-                return TypedExpr::new_var(Expr::Assign(var, Box::new(self.assignment())));
-            } else if self.matches(vec![TokenType::InplaceAssign]) {
-                if let Token::InplaceEq(math_op) = self.previous().unwrap() {
-                    let expr = Expr::MathOp(
-                        Box::new(TypedExpr::new_var(Expr::Variable(var.to_string()))),
-                        math_op,
-                        Box::new(self.assignment()),
-                    );
-                    return TypedExpr::new_var(Expr::Assign(
-                        var.to_string(),
-                        Box::new(TypedExpr::new_var(expr)),
-                    ));
-                } else {
-                    panic!("not possible")
-                }
+        Ok(Stmt::If(predicate, Box::new(then_blk), else_blk))
+    }
+
+    // After the '(' of for (k in a)
+    fn for_in(&mut self) -> Result<Stmt, ParseError> {
+        let var =
+            if let Token::Ident(var) = self.consume(TokenType::Ident, "Expected a variable")? {
+                var
+            } else {
+                return self.error("Expected a variable before 'in'");
+            };
+        self.consume(TokenType::In, "Expected 'in' after the loop variable")?;
+        let name = if let Token::Ident(name) =
+            self.consume(TokenType::Ident, "Expected an array after 'in'")?
+        {
+            name
+        } else {
+            return self.error("Expected an array after 'in'");
+        };
+        let path = if self.check(TokenType::LeftBracket) {
+            self.subscripts()?
+        } else {
+            vec![]
+        };
+        let body = self.for_body()?;
+        Ok(Stmt::ForIn {
+            var: var.to_string(),
+            name: name.to_string(),
+            path,
+            body: Box::new(body),
+        })
+    }
+
+    // After delete, either an element a[k] or a whole array a
+    fn delete(&mut self) -> Result<Stmt, ParseError> {
+        let name = if let Token::Ident(name) =
+            self.consume(TokenType::Ident, "Expected an array after delete")?
+        {
+            name
+        } else {
+            return self.error("Expected an array after delete");
+        };
+        let mut path = self.subscripts()?;
+        let indices = path.pop().unwrap_or_default();
+        Ok(Stmt::Delete {
+            name: name.to_string(),
+            path,
+            indices,
+        })
+    }
+
+    // The closing ')' of a for loop and then its body
+    fn for_body(&mut self) -> Result<Stmt, ParseError> {
+        self.consume(TokenType::RightParen, "Expected a ')' to end for loop")?;
+        self.skip_newlines();
+        self.loop_body()
+    }
+
+    // The body may be a single statement without braces
+    fn loop_body(&mut self) -> Result<Stmt, ParseError> {
+        self.loops += 1;
+        let body = self.stmt()?;
+        self.loops -= 1;
+        Ok(body)
+    }
+
+    // One or more [a, b] after an array name, gawk's a[1][2] indexes the array in a[1]
+    fn subscripts(&mut self) -> Result<Vec<Vec<TypedExpr>>, ParseError> {
+        let mut groups = vec![];
+        while self.matches(vec![TokenType::LeftBracket]) {
+            groups.push(self.nested(|p| p.expression_list())?);
+            self.consume(TokenType::RightBracket, "Expected ']' after array index")?;
+        }
+        Ok(groups)
+    }
+
+    fn expression(&mut self) -> Result<TypedExpr, ParseError> {
+        self.assignment()
+    }
+
+    fn assignment(&mut self) -> Result<TypedExpr, ParseError> {
+        let lhs = self.ternary()?;
+        if !Parser::is_lvalue(&lhs) {
+            return Ok(lhs);
+        }
+        let value = if self.matches(vec![TokenType::Eq]) {
+            self.assignment()?
+        } else if self.matches(vec![TokenType::InplaceAssign]) {
+            // a op= b is a = a op b for every kind of lvalue
+            let math_op = match self.previous().unwrap() {
+                Token::InplaceEq(math_op) => math_op,
+                _ => panic!("not possible"),
+            };
+            TypedExpr::new_var(Expr::MathOp(
+                Box::new(lhs.clone()),
+                math_op,
+                Box::new(self.assignment()?),
+            ))
+        } else {
+            return Ok(lhs);
+        };
+        Ok(Parser::assign_to(lhs, value))
+    }
+
+    fn is_lvalue(expr: &TypedExpr) -> bool {
+        matches!(
+            expr.expr,
+            Expr::Variable(_) | Expr::ArrayIndex { .. } | Expr::Column(_)
+        )
+    }
+
+    // lhs must be a variable, array element or field. NF isn't just a variable, setting it
+    // changes the record.
+    fn assign_to(lhs: TypedExpr, value: TypedExpr) -> TypedExpr {
+        let value = Box::new(value);
+        match lhs.expr {
+            Expr::Variable(var) if var == "NF" => Expr::NfAssign(value).into(),
+            Expr::Variable(var) => Expr::Assign(var, value).into(),
+            Expr::ArrayIndex {
+                name,
+                path,
+                indices,
+            } => Expr::ArrayAssign {
+                name,
+                path,
+                indices,
+                value,
             }
+            .into(),
+            Expr::Column(index) => Expr::ColumnAssign { index, value }.into(),
+            _ => unreachable!(),
+        }
+    }
+
+    // test ? if_so : if_not. The if_not side is another ternary so a ? b : c ? d : e is
+    // a ? b : (c ? d : e). The if_so side ends at the ':' so it can be any expression.
+    fn ternary(&mut self) -> Result<TypedExpr, ParseError> {
+        let test = self.logical_or()?;
+        if !self.matches(vec![TokenType::Question]) {
+            return Ok(test);
         }
-        lhs
+        let if_so = self.nested(|p| p.expression())?;
+        self.consume(
+            TokenType::Colon,
+            "Expected ':' after the first branch of '?'",
+        )?;
+        let if_not = self.ternary()?;
+        Ok(TypedExpr::new_var(Expr::Ternary(
+            Box::new(test),
+            Box::new(if_so),
+            Box::new(if_not),
+        )))
     }
 
-    fn logical_or(&mut self) -> TypedExpr {
-        let mut expr = self.logical_and();
+    fn logical_or(&mut self) -> Result<TypedExpr, ParseError> {
+        let mut expr = self.logical_and()?;
         while self.matches(vec![TokenType::Or]) {
             expr = TypedExpr::new_var(Expr::LogicalOp(
                 Box::new(expr),
                 LogicalOp::Or,
-                Box::new(self.logical_and()),
+                Box::new(self.logical_and()?),
             ))
         }
-        expr
+        Ok(expr)
     }
 
-    fn logical_and(&mut self) -> TypedExpr {
-        let mut expr = self.compare();
+    fn logical_and(&mut self) -> Result<TypedExpr, ParseError> {
+        let mut expr = self.in_array()?;
         while self.matches(vec![TokenType::And]) {
             expr = TypedExpr::new_var(Expr::LogicalOp(
                 Box::new(expr),
                 LogicalOp::And,
-                Box::new(self.compare()),
+                Box::new(self.in_array()?),
             ))
         }
-        expr
+        Ok(expr)
+    }
+
+    // k in a. Binds looser than the comparisons and tighter than &&.
+    fn in_array(&mut self) -> Result<TypedExpr, ParseError> {
+        let mut expr = self.compare()?;
+        while self.matches(vec![TokenType::In]) {
+            expr = self.in_array_rest(vec![expr])?;
+        }
+        Ok(expr)
+    }
+
+    // The array after the 'in' of (indices) in a, which may be a sub-array like a[1]
+    fn in_array_rest(&mut self, indices: Vec<TypedExpr>) -> Result<TypedExpr, ParseError> {
+        let name = match self.consume(TokenType::Ident, "Expected an array after 'in'")? {
+            Token::Ident(name) => name.to_string(),
+            _ => return self.error("Expected an array after 'in'"),
+        };
+        let path = if self.check(TokenType::LeftBracket) {
+            self.subscripts()?
+        } else {
+            vec![]
+        };
+        Ok(TypedExpr::new_num(Expr::InArray {
+            indices,
+            name,
+            path,
+        }))
     }
 
-    fn compare(&mut self) -> TypedExpr {
-        let mut expr = self.string_concat();
-        while self.matches(vec![
+    // The comparisons and the regex matches ~ and !~
+    fn compare(&mut self) -> Result<TypedExpr, ParseError> {
+        let mut expr = self.pipe_getline()?;
+        let mut ops = vec![
             TokenType::GreaterEq,
-            TokenType::Greater,
             TokenType::Less,
             TokenType::LessEq,
             TokenType::EqEq,
             TokenType::BangEq,
-        ]) {
+            TokenType::MatchedBy,
+            TokenType::NotMatchedBy,
+        ];
+        if !self.in_print {
+            ops.push(TokenType::Greater);
+        }
+        while self.matches(ops.clone()) {
             let op = match self.previous().unwrap() {
                 Token::BinOp(BinOp::Less) => BinOp::Less,
                 Token::BinOp(BinOp::LessEq) => BinOp::LessEq,
@@ -314,15 +773,41 @@ impl Parser {
                 Token::BinOp(BinOp::GreaterEq) => BinOp::GreaterEq,
                 Token::BinOp(BinOp::BangEq) => BinOp::BangEq,
                 Token::BinOp(BinOp::EqEq) => BinOp::EqEq,
+                Token::BinOp(BinOp::MatchedBy) => BinOp::MatchedBy,
+                Token::BinOp(BinOp::NotMatchedBy) => BinOp::NotMatchedBy,
                 _ => panic!("Parser bug in compare matches function"),
             };
-            expr = Expr::BinOp(Box::new(expr), op, Box::new(self.string_concat())).into()
+            expr = Expr::BinOp(Box::new(expr), op, Box::new(self.pipe_getline()?)).into()
+        }
+        Ok(expr)
+    }
+
+    // cmd | getline [var] or cmd |& getline [var]. Binds looser than concatenation so
+    // "a" "b" | getline runs "ab" and tighter than comparison so "cmd" | getline > 0 compares
+    // the result.
+    fn pipe_getline(&mut self) -> Result<TypedExpr, ParseError> {
+        let mut expr = self.string_concat()?;
+        while (self.check(TokenType::Pipe) || self.check(TokenType::TwoWayPipe))
+            && self.peek_next().ttype() == TokenType::Getline
+        {
+            let two_way = self.advance() == Token::TwoWayPipe;
+            self.advance();
+            if two_way {
+                self.require_gawk("|&")?;
+            }
+            let into = self.getline_lvalue()?;
+            let source = if two_way {
+                GetlineSource::Coprocess(Box::new(expr))
+            } else {
+                GetlineSource::Command(Box::new(expr))
+            };
+            expr = TypedExpr::new_num(Expr::Getline { into, source });
         }
-        expr
+        Ok(expr)
     }
 
-    fn string_concat(&mut self) -> TypedExpr {
-        let mut expr = self.comparison();
+    fn string_concat(&mut self) -> Result<TypedExpr, ParseError> {
+        let mut expr = self.comparison()?;
         let not_these = vec![
             TokenType::InplaceAssign,
             TokenType::Less,
@@ -331,57 +816,50 @@ impl Parser {
             TokenType::EqEq,
             TokenType::Greater,
             TokenType::GreaterEq,
+            TokenType::MatchedBy,
+            TokenType::NotMatchedBy,
+            TokenType::In,
             TokenType::And,
             TokenType::Or,
             TokenType::Eq,
             TokenType::Semicolon,
+            TokenType::Newline,
             TokenType::RightBrace,
             TokenType::RightParen,
             TokenType::LeftBrace,
+            TokenType::Comma,
+            TokenType::RightBracket,
+            TokenType::Pipe,
+            TokenType::TwoWayPipe,
+            TokenType::Append,
+            TokenType::Question,
+            TokenType::Colon,
         ];
         while !self.is_at_end() && !not_these.contains(&self.peek().ttype()) {
             if let Expr::Concatenation(vals) = &mut expr.expr {
-                vals.push(self.comparison());
+                vals.push(self.comparison()?);
             } else {
-                expr = TypedExpr::new_var(Expr::Concatenation(vec![expr, self.comparison()]));
+                expr = TypedExpr::new_var(Expr::Concatenation(vec![expr, self.comparison()?]));
             }
         }
-        expr
+        Ok(expr)
     }
 
-    fn comparison(&mut self) -> TypedExpr {
-        let mut expr = self.term();
+    fn comparison(&mut self) -> Result<TypedExpr, ParseError> {
+        let mut expr = self.term()?;
         while self.matches(vec![TokenType::Plus, TokenType::Minus]) {
             let op = match self.previous().unwrap() {
                 Token::MathOp(MathOp::Minus) => MathOp::Minus,
                 Token::MathOp(MathOp::Plus) => MathOp::Plus,
                 _ => panic!("Parser bug in comparison function"),
             };
-            if let Expr::Variable(name) = expr.expr.clone() {
-                if op == MathOp::Plus && self.peek().ttype() == TokenType::Plus {
-                    self.advance();
-                    // a + 1 
-                    let increment = Expr::MathOp(Box::new(expr), op, Box::new(Expr::NumberF64(1.0).into())).into();
-                    let assign = Expr::Assign(name, Box::new(increment)).into(); // a = a + 1
-                    expr = Expr::MathOp(Box::new(assign), MathOp::Minus, Box::new(Expr::NumberF64(1.0).into())).into(); // a - 1 
-                }
-                else if op == MathOp::Minus && self.peek().ttype() == TokenType::Minus {
-                    self.advance();
-                    let decrement = Expr::MathOp(Box::new(expr), op, Box::new(Expr::NumberF64(1.0).into())).into();
-                    let assign = Expr::Assign(name, Box::new(decrement)).into();
-                    expr = Expr::MathOp(Box::new(assign), MathOp::Plus, Box::new(Expr::NumberF64(1.0).into())).into(); 
-                } else {
-                    expr = Expr::MathOp(Box::new(expr), op, Box::new(self.comparison())).into();
-                }
-            } else {
-                expr = Expr::MathOp(Box::new(expr), op, Box::new(self.comparison())).into();
-            }
+            expr = Expr::MathOp(Box::new(expr), op, Box::new(self.term()?)).into();
         }
-        expr
+        Ok(expr)
     }
     //1 * 3
-    fn term(&mut self) -> TypedExpr {
-        let mut expr = self.exp();
+    fn term(&mut self) -> Result<TypedExpr, ParseError> {
+        let mut expr = self.unary()?;
         while self.matches(vec![TokenType::Star, TokenType::Slash, TokenType::Modulo]) {
             let op = match self.previous().unwrap() {
                 Token::MathOp(MathOp::Star) => MathOp::Star,
@@ -389,59 +867,279 @@ impl Parser {
                 Token::MathOp(MathOp::Modulus) => MathOp::Modulus,
                 _ => panic!("Parser bug in comparison function"),
             };
-            expr = Expr::MathOp(Box::new(expr), op, Box::new(self.exp())).into()
+            expr = Expr::MathOp(Box::new(expr), op, Box::new(self.unary()?)).into()
+        }
+        Ok(expr)
+    }
+
+    // !a, -a and +a bind tighter than * but looser than ^ so !2^0 is !(2^0)
+    // !x is 1 if x is false by awk's rules and 0 otherwise. It binds tighter than comparisons and
+    // `in`, so !k in a is (!k) in a and testing for a missing key needs !(k in a).
+    // There is no negation in the ast, -x is 0 - x and +x is 0 + x which also makes x a number.
+    fn unary(&mut self) -> Result<TypedExpr, ParseError> {
+        if self.matches(vec![TokenType::Bang]) {
+            return Ok(TypedExpr::new_num(Expr::Not(Box::new(self.unary()?))));
+        }
+        if self.matches(vec![TokenType::Minus, TokenType::Plus]) {
+            let op = match self.previous().unwrap() {
+                Token::MathOp(op) => op,
+                _ => panic!("Parser bug in unary function"),
+            };
+            let zero = Box::new(Expr::NumberF64(0.0).into());
+            return Ok(Expr::MathOp(zero, op, Box::new(self.unary()?)).into());
+        }
+        self.exp()
+    }
+
+    // ^ is right associative, 2 ^ 3 ^ 2 is 2 ^ (3 ^ 2). It binds tighter than the unary
+    // operators above it so !2 ^ 2 is !(2 ^ 2), and a unary minus has to keep -2 ^ 2 as -(2 ^ 2).
+    // The power can still start with one, 2 ^ -1 is 2 ^ (-1).
+    fn exp(&mut self) -> Result<TypedExpr, ParseError> {
+        let base = self.pre_increment()?;
+        if !self.matches(vec![TokenType::Exponent]) {
+            return Ok(base);
+        }
+        let power = self.unary()?;
+        Ok(Expr::MathOp(Box::new(base), MathOp::Exponent, Box::new(power)).into())
+    }
+
+    // ++lv and --lv are lv += 1 and lv -= 1, so the result is the new value. They bind tighter
+    // than ^ so ++x ^ 2 is (++x) ^ 2.
+    fn pre_increment(&mut self) -> Result<TypedExpr, ParseError> {
+        if !self.matches(vec![TokenType::Incr, TokenType::Decr]) {
+            return self.post_increment();
+        }
+        let op = self.increment_op();
+        let lhs = self.post_increment()?;
+        if !Parser::is_lvalue(&lhs) {
+            return self.error(format!(
+                "++ and -- need a variable, array element or field, not `{}`",
+                lhs.expr
+            ));
+        }
+        Ok(Parser::increment(lhs, op))
+    }
+
+    // lv++ is (lv += 1) - 1 and lv-- is (lv -= 1) + 1, the old value as a number. $i++ is ($i)++.
+    fn post_increment(&mut self) -> Result<TypedExpr, ParseError> {
+        let lhs = self.column()?;
+        if !Parser::is_lvalue(&lhs) || !self.matches(vec![TokenType::Incr, TokenType::Decr]) {
+            return Ok(lhs);
         }
-        expr
+        let op = self.increment_op();
+        let undo = match op {
+            MathOp::Plus => MathOp::Minus,
+            _ => MathOp::Plus,
+        };
+        let one = Box::new(Expr::NumberF64(1.0).into());
+        Ok(Expr::MathOp(Box::new(Parser::increment(lhs, op)), undo, one).into())
     }
 
-    fn exp(&mut self) -> TypedExpr {
-        let mut expr = self.column();
-        while self.matches(vec![TokenType::Exponent]) {
-            let op = MathOp::Exponent;
-            expr = Expr::MathOp(Box::new(expr), op, Box::new(self.column())).into()
+    // Plus for the ++ just matched, Minus for --
+    fn increment_op(&self) -> MathOp {
+        match self.previous().unwrap() {
+            Token::Incr => MathOp::Plus,
+            _ => MathOp::Minus,
         }
-        expr
     }
 
-    fn column(&mut self) -> TypedExpr {
+    // lhs = lhs op 1
+    fn increment(lhs: TypedExpr, op: MathOp) -> TypedExpr {
+        let one = Box::new(Expr::NumberF64(1.0).into());
+        let value = TypedExpr::new_var(Expr::MathOp(Box::new(lhs.clone()), op, one));
+        Parser::assign_to(lhs, value)
+    }
+
+    fn column(&mut self) -> Result<TypedExpr, ParseError> {
         let mut num_cols: usize = 0;
         while self.matches(vec![TokenType::Column]) {
             num_cols += 1;
         }
-        let mut expr = self.primary();
+        let mut expr = self.primary()?;
         for _ in 0..num_cols {
             // If this isn't a col we loop 0 times and just return primary
             expr = TypedExpr::new_var(Expr::Column(Box::new(expr)));
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn primary(&mut self) -> TypedExpr {
+    fn primary(&mut self) -> Result<TypedExpr, ParseError> {
         if self.is_at_end() {
-            panic!("Primary and at end")
+            return self.error("Primary and at end");
         }
-        match self.tokens.get(self.current).unwrap().clone() {
+        let expr = match self.tokens.get(self.current).unwrap().clone() {
             Token::NumberF64(num) => {
                 self.advance();
                 Expr::NumberF64(num).into()
             }
             Token::LeftParen => {
-                self.consume(TokenType::LeftParen, "Expected to parse a left paren here");
-                let expr = self.expression();
-                self.consume(TokenType::RightParen, "Missing closing ')' after group");
-                expr.into()
+                self.consume(TokenType::LeftParen, "Expected to parse a left paren here")?;
+                let expr = self.nested(|p| p.expression())?;
+                if self.check(TokenType::Comma) {
+                    // (i, j) in a is the only place a parenthesized list can be
+                    let mut indices = vec![expr];
+                    while self.matches(vec![TokenType::Comma]) {
+                        indices.push(self.nested(|p| p.expression())?);
+                    }
+                    self.consume(TokenType::RightParen, "Missing closing ')' after group")?;
+                    self.consume(TokenType::In, "Expected 'in' after a parenthesized list")?;
+                    return self.in_array_rest(indices);
+                }
+                self.consume(TokenType::RightParen, "Missing closing ')' after group")?;
+                expr
             }
             Token::Ident(name) => {
-                self.consume(TokenType::Ident, "Expected to parse an ident here");
+                self.consume(TokenType::Ident, "Expected to parse an ident here")?;
+                if builtin_arity(&name).is_some() && self.check(TokenType::LeftParen) {
+                    return self.call(name.to_string());
+                }
+                if self.check(TokenType::LeftBracket) {
+                    let mut path = self.subscripts()?;
+                    let indices = path.pop().unwrap();
+                    return Ok(Expr::ArrayIndex {
+                        name: name.to_string(),
+                        path,
+                        indices,
+                    }
+                    .into());
+                }
                 Expr::Variable(name).into()
             }
             Token::String(string) => {
-                self.consume(TokenType::String, "Expected to parse a string here");
+                self.consume(TokenType::String, "Expected to parse a string here")?;
                 Expr::String(string).into()
             }
-            t => panic!("Unexpected token {:?} {}", t, TokenType::name(t.ttype())),
+            Token::Regex(regex) => {
+                self.consume(TokenType::Regex, "Expected to parse a regex here")?;
+                Expr::Regex(regex).into()
+            }
+            Token::Getline => {
+                self.consume(TokenType::Getline, "Expected to parse getline here")?;
+                let into = self.getline_lvalue()?;
+                // Without a file getline [var] reads the next record of the main input
+                let source = if self.matches(vec![TokenType::Less]) {
+                    GetlineSource::File(Box::new(self.getline_file()?))
+                } else {
+                    GetlineSource::MainInput
+                };
+                TypedExpr::new_num(Expr::Getline { into, source })
+            }
+            t => {
+                return self.error(format!(
+                    "Unexpected token {:?} {}",
+                    t,
+                    TokenType::name(t.ttype())
+                ))
+            }
+        };
+        Ok(expr)
+    }
+
+    // The file after getline [var] <. Like gawk only a $ expression or something simpler is taken
+    // so getline line < "f" > 0 is (getline line < "f") > 0 and the usual read loop works
+    // without parens. A concatenation right after the file is an error rather than silently
+    // becoming (getline < "a") "b".
+    fn getline_file(&mut self) -> Result<TypedExpr, ParseError> {
+        let file = self.column()?;
+        let concatenated = matches!(
+            self.peek().ttype(),
+            TokenType::String
+                | TokenType::Ident
+                | TokenType::NumberF64
+                | TokenType::Column
+                | TokenType::LeftParen
+                | TokenType::Regex
+        );
+        if concatenated {
+            return self.error(format!(
+                "getline < {} is followed by {:?}, which concatenates with getline's result \
+                 and not the file name. Use getline < (a b) to read from a concatenated name \
+                 or (getline < a) b to concatenate the result.",
+                file,
+                self.peek()
+            ));
+        }
+        Ok(file)
+    }
+
+    // The optional var, a[k] or $n in getline var
+    fn getline_lvalue(&mut self) -> Result<Option<Lvalue>, ParseError> {
+        let lvalue = match self.peek() {
+            Token::Ident(name) => {
+                self.advance();
+                if !self.check(TokenType::LeftBracket) {
+                    return Ok(Some(Lvalue::Variable(name)));
+                }
+                let mut path = self.subscripts()?;
+                let indices = path.pop().unwrap();
+                Some(Lvalue::ArrayIndex {
+                    name: name.to_string(),
+                    path,
+                    indices,
+                })
+            }
+            Token::Column => {
+                self.advance();
+                Some(Lvalue::Column(Box::new(self.column()?)))
+            }
+            _ => None,
+        };
+        Ok(lvalue)
+    }
+
+    fn call(&mut self, target: String) -> Result<TypedExpr, ParseError> {
+        self.consume(TokenType::LeftParen, "Expected '(' after function name")?;
+        let mut args = vec![];
+        if !self.matches(vec![TokenType::RightParen]) {
+            loop {
+                args.push(self.nested(|p| p.expression())?);
+                if self.matches(vec![TokenType::RightParen]) {
+                    break;
+                }
+                self.consume(TokenType::Comma, "Expected ',' between function arguments")?;
+            }
+        }
+        let arity = builtin_arity(&target).expect("only builtins can be called");
+        if gawk_only(&target) {
+            self.require_gawk(&target)?;
+        }
+        if target == "patsplit" {
+            for idx in [1, 3] {
+                if !matches!(
+                    args.get(idx).map(|arg| &arg.expr),
+                    None | Some(Expr::Variable(_))
+                ) {
+                    return self.error(format!(
+                        "patsplit argument {} must be an array name",
+                        idx + 1
+                    ));
+                }
+            }
+        }
+        if target == "sub" || target == "gsub" {
+            if let Some(arg) = args.get(2) {
+                if !matches!(
+                    arg.expr,
+                    Expr::Variable(_) | Expr::Column(_) | Expr::ArrayIndex { .. }
+                ) {
+                    return self.error(format!(
+                        "{} argument 3 must be a variable, field or array element",
+                        target
+                    ));
+                }
+            }
         }
+        if !arity.contains(&args.len()) {
+            return self.error(format!(
+                "{} accepts {} to {} arguments but was called with {}",
+                target,
+                arity.start(),
+                arity.end(),
+                args.len()
+            ));
+        }
+        Ok(Expr::Call { target, args }.into())
     }
 }
 
@@ -473,6 +1171,16 @@ macro_rules! texpr {
     };
 }
 
+#[cfg(test)]
+macro_rules! print_stmt {
+    ($($arg:expr),*) => {
+        Stmt::Print {
+            args: vec![$($arg),*],
+            output: None,
+        }
+    };
+}
+
 #[cfg(test)]
 macro_rules! mathop {
     ($a:expr, $op:expr, $b:expr) => {
@@ -498,7 +1206,7 @@ macro_rules! sprogram {
 macro_rules! actual {
     ($name:ident, $body:expr) => {
         use crate::lexer::lex;
-        let $name = parse(lex($body).unwrap());
+        let $name = parse(lex($body).unwrap()).unwrap();
     };
 }
 
@@ -507,7 +1215,7 @@ fn test_ast_number() {
     use crate::lexer::lex;
 
     assert_eq!(
-        parse(lex("{1 + 2;}").unwrap()),
+        parse(lex("{1 + 2;}").unwrap()).unwrap(),
         Program::new(
             vec![],
             vec![],
@@ -527,7 +1235,7 @@ fn test_ast_oop() {
     let right = Box::new(mathop!(bnum!(3.0), MathOp::Star, bnum!(2.0)));
     let mult = Stmt::Expr(mathop!(left, MathOp::Plus, right));
     assert_eq!(
-        parse(lex("{1 + 3 * 2;}").unwrap()),
+        parse(lex("{1 + 3 * 2;}").unwrap()).unwrap(),
         Program::new_action_only(mult)
     );
 }
@@ -543,17 +1251,29 @@ fn test_ast_oop_2() {
     )));
     let mult = Stmt::Expr(texpr!(Expr::MathOp(right, MathOp::Plus, left)));
     assert_eq!(
-        parse(lex("{1 * 3 + 2;}").unwrap()),
+        parse(lex("{1 * 3 + 2;}").unwrap()).unwrap(),
         Program::new_action_only(mult)
     );
 }
 
+#[test]
+fn test_plus_minus_are_left_associative() {
+    actual!(actual, "{ a - b - c; a - b + c }");
+    let a = || btexpr!(Expr::Variable("a".into()));
+    let b = || btexpr!(Expr::Variable("b".into()));
+    let c = || btexpr!(Expr::Variable("c".into()));
+    let a_minus_b = || btexpr!(Expr::MathOp(a(), MathOp::Minus, b()));
+    let minus = Stmt::Expr(mathop!(a_minus_b(), MathOp::Minus, c()));
+    let plus = Stmt::Expr(mathop!(a_minus_b(), MathOp::Plus, c()));
+    assert_eq!(actual, sprogram!(Stmt::Group(vec![minus, plus])));
+}
+
 #[test]
 fn test_ast_assign() {
     use crate::lexer::lex;
-    let stmt = Stmt::Expr(texpr!(Expr::Assign(format!("abc"), bnum!(2.0))));
+    let stmt = Stmt::Expr(texpr!(Expr::Assign("abc".into(), bnum!(2.0))));
     assert_eq!(
-        parse(lex("{abc = 2.0; }").unwrap()),
+        parse(lex("{abc = 2.0; }").unwrap()).unwrap(),
         Program::new_action_only(stmt)
     );
 }
@@ -563,7 +1283,7 @@ fn test_mathop_exponent() {
     use crate::lexer::lex;
 
     assert_eq!(
-        parse(lex("{2 ^ 2;}").unwrap()),
+        parse(lex("{2 ^ 2;}").unwrap()).unwrap(),
         Program::new(
             vec![],
             vec![],
@@ -576,6 +1296,42 @@ fn test_mathop_exponent() {
     );
 }
 
+#[test]
+fn test_exponent_is_right_associative() {
+    actual!(actual, "{ 2 ^ 3 ^ 2; !2 ^ 2 }");
+    let power = btexpr!(Expr::MathOp(bnum!(3.0), MathOp::Exponent, bnum!(2.0)));
+    let nested = Stmt::Expr(mathop!(bnum!(2.0), MathOp::Exponent, power));
+    let squared = btexpr!(Expr::MathOp(bnum!(2.0), MathOp::Exponent, bnum!(2.0)));
+    let not = Stmt::Expr(TypedExpr::new_num(Expr::Not(squared)));
+    assert_eq!(actual, sprogram!(Stmt::Group(vec![nested, not])));
+}
+
+#[test]
+fn test_unary_in_exponent() {
+    actual!(actual, "{ 2 ^ -1; -2 ^ 2; 2 ^ !x }");
+    let minus_one = btexpr!(Expr::MathOp(bnum!(0.0), MathOp::Minus, bnum!(1.0)));
+    let inverse = Stmt::Expr(mathop!(bnum!(2.0), MathOp::Exponent, minus_one));
+    let squared = btexpr!(Expr::MathOp(bnum!(2.0), MathOp::Exponent, bnum!(2.0)));
+    let negated = Stmt::Expr(mathop!(bnum!(0.0), MathOp::Minus, squared));
+    let not = Box::new(TypedExpr::new_num(Expr::Not(btexpr!(Expr::Variable(
+        "x".into()
+    )))));
+    let not_power = Stmt::Expr(mathop!(bnum!(2.0), MathOp::Exponent, not));
+    assert_eq!(
+        actual,
+        sprogram!(Stmt::Group(vec![inverse, negated, not_power]))
+    );
+}
+
+#[test]
+fn test_star_star_is_exponent() {
+    use crate::lexer::lex;
+    assert_eq!(
+        parse(lex("{ 2 ** 3 ** 2 }").unwrap()).unwrap(),
+        parse(lex("{ 2 ^ 3 ^ 2 }").unwrap()).unwrap()
+    );
+}
+
 #[test]
 fn test_mathop_exponent_2() {
     use crate::lexer::lex;
@@ -588,7 +1344,7 @@ fn test_mathop_exponent_2() {
     let expo = Stmt::Expr(texpr!(Expr::MathOp(left, MathOp::Star, right)));
 
     assert_eq!(
-        parse(lex("{2 ^ 2 * 3;}").unwrap()),
+        parse(lex("{2 ^ 2 * 3;}").unwrap()).unwrap(),
         Program::new_action_only(expo)
     );
 }
@@ -597,13 +1353,13 @@ fn test_mathop_exponent_2() {
 fn test_if_else() {
     use crate::lexer::lex;
     let str = "{ if (1) { print 2; } else { print 3; }}";
-    let actual = parse(lex(str).unwrap());
+    let actual = parse(lex(str).unwrap()).unwrap();
     assert_eq!(
         actual,
         Program::new_action_only(Stmt::If(
             num!(1.0),
-            Box::new(Stmt::Print(num!(2.0))),
-            Some(Box::new(Stmt::Print(num!(3.0))))
+            Box::new(print_stmt!(num!(2.0))),
+            Some(Box::new(print_stmt!(num!(3.0))))
         ))
     );
 }
@@ -613,8 +1369,8 @@ fn test_if_only() {
     use crate::lexer::lex;
     let str = "{if (1) { print 2; }}";
     assert_eq!(
-        parse(lex(str).unwrap()),
-        Program::new_action_only(Stmt::If(num!(1.0), Box::new(Stmt::Print(num!(2.0))), None))
+        parse(lex(str).unwrap()).unwrap(),
+        Program::new_action_only(Stmt::If(num!(1.0), Box::new(print_stmt!(num!(2.0))), None))
     );
 }
 
@@ -623,8 +1379,8 @@ fn test_print() {
     use crate::lexer::lex;
     let str = "{print 1;}";
     assert_eq!(
-        parse(lex(str).unwrap()),
-        Program::new_action_only(Stmt::Print(num!(1.0)))
+        parse(lex(str).unwrap()).unwrap(),
+        Program::new_action_only(print_stmt!(num!(1.0)))
     );
 }
 
@@ -633,85 +1389,241 @@ fn test_group() {
     use crate::lexer::lex;
     let str = "{{print 1; print 2;}}";
     assert_eq!(
-        parse(lex(str).unwrap()),
+        parse(lex(str).unwrap()).unwrap(),
         Program::new_action_only(Stmt::Group(vec![
-            Stmt::Print(num!(1.0)),
-            Stmt::Print(num!(2.0))
+            print_stmt!(num!(1.0)),
+            print_stmt!(num!(2.0))
         ]))
     );
 }
 
+#[test]
+fn test_assign_as_if_test() {
+    actual!(actual, "{ if (a = 1) print a }");
+    let test = texpr!(Expr::Assign("a".into(), bnum!(1.0)));
+    let print = print_stmt!(texpr!(Expr::Variable("a".into())));
+    assert_eq!(actual, sprogram!(Stmt::If(test, Box::new(print), None)));
+}
+
+#[test]
+fn test_assign_as_value() {
+    actual!(
+        actual,
+        "{ while ((n = n - 1) > 0) x = toupper(s = \"abc\") }"
+    );
+    let n = btexpr!(Expr::Variable("n".into()));
+    let n_minus_1 = btexpr!(Expr::MathOp(n, MathOp::Minus, bnum!(1.0)));
+    let assign_n = btexpr!(Expr::Assign("n".into(), n_minus_1));
+    let test = binop!(assign_n, BinOp::Greater, bnum!(0.0));
+    let abc = btexpr!(Expr::String("abc".to_string()));
+    let upper = btexpr!(Expr::Call {
+        target: "toupper".to_string(),
+        args: vec![texpr!(Expr::Assign("s".into(), abc))],
+    });
+    let body = Stmt::Expr(texpr!(Expr::Assign("x".into(), upper)));
+    assert_eq!(actual, sprogram!(Stmt::While(test, Box::new(body))));
+}
+
 #[test]
 fn test_if_else_continues() {
     use crate::lexer::lex;
     let str = "{if (1) { print 2; } else { print 3; } 4.0;}";
-    let actual = parse(lex(str).unwrap());
+    let actual = parse(lex(str).unwrap()).unwrap();
     assert_eq!(
         actual,
         Program::new_action_only(Stmt::Group(vec![
             Stmt::If(
                 num!(1.0),
-                Box::new(Stmt::Print(num!(2.0))),
-                Some(Box::new(Stmt::Print(num!(3.0))))
+                Box::new(print_stmt!(num!(2.0))),
+                Some(Box::new(print_stmt!(num!(3.0))))
             ),
             Stmt::Expr(num!(4.0))
         ]))
     );
 }
 
+#[test]
+fn test_if_without_braces() {
+    use crate::lexer::lex;
+    let str = "{ if (1) print 2; else if (3) print 4\n else print 5; if (6) print 7; 8 }";
+    let actual = parse(lex(str).unwrap()).unwrap();
+    let else_if = Stmt::If(
+        num!(3.0),
+        Box::new(print_stmt!(num!(4.0))),
+        Some(Box::new(print_stmt!(num!(5.0)))),
+    );
+    assert_eq!(
+        actual,
+        Program::new_action_only(Stmt::Group(vec![
+            Stmt::If(
+                num!(1.0),
+                Box::new(print_stmt!(num!(2.0))),
+                Some(Box::new(else_if))
+            ),
+            Stmt::If(num!(6.0), Box::new(print_stmt!(num!(7.0))), None),
+            Stmt::Expr(num!(8.0))
+        ]))
+    );
+}
+
 #[test]
 fn test_paser_begin_end() {
     use crate::lexer::lex;
     let str =
         "a { print 5; } BEGIN { print 1; } begin { print 2; } END { print 3; } end { print 4; }";
-    let actual = parse(lex(str).unwrap());
-    let begins = vec![Stmt::Print(num!(1.0)), Stmt::Print(num!(2.0))];
-    let ends = vec![Stmt::Print(num!(3.0)), Stmt::Print(num!(4.0))];
+    let actual = parse(lex(str).unwrap()).unwrap();
+    let begins = vec![print_stmt!(num!(1.0)), print_stmt!(num!(2.0))];
+    let ends = vec![print_stmt!(num!(3.0)), print_stmt!(num!(4.0))];
     let generic = PatternAction::new(
-        Some(texpr!(Expr::Variable("a".to_string()))),
-        Stmt::Print(num!(5.0)),
+        Some(texpr!(Expr::Variable("a".into()))),
+        print_stmt!(num!(5.0)),
     );
     assert_eq!(actual, Program::new(begins, ends, vec![generic]));
 }
 
+#[test]
+fn test_paser_begin_end_interleaved() {
+    use crate::lexer::lex;
+    let str = "END { print 3; } a { print 5; } BEGIN { print 1; } b { print 6; } BEGIN { print 2; } END { print 4; }";
+    let actual = parse(lex(str).unwrap()).unwrap();
+    let begins = vec![print_stmt!(num!(1.0)), print_stmt!(num!(2.0))];
+    let ends = vec![print_stmt!(num!(3.0)), print_stmt!(num!(4.0))];
+    let generic = vec![
+        PatternAction::new(
+            Some(texpr!(Expr::Variable("a".into()))),
+            print_stmt!(num!(5.0)),
+        ),
+        PatternAction::new(
+            Some(texpr!(Expr::Variable("b".into()))),
+            print_stmt!(num!(6.0)),
+        ),
+    ];
+    assert_eq!(actual, Program::new(begins, ends, generic));
+}
+
 #[test]
 fn test_pattern_only() {
     use crate::lexer::lex;
     let str = "test";
-    let actual = parse(lex(str).unwrap());
+    let actual = parse(lex(str).unwrap()).unwrap();
     assert_eq!(
         actual,
         Program::new(
             vec![],
             vec![],
             vec![PatternAction::new_pattern_only(texpr!(Expr::Variable(
-                "test".to_string()
+                "test".into()
             )))]
         )
     );
 }
 
 #[test]
-fn test_print_no_semicolon() {
+fn test_range_pattern() {
     use crate::lexer::lex;
-    let str = "{ print 1 }";
-    let actual = parse(lex(str).unwrap());
-    assert_eq!(
-        actual,
-        Program::new(
-            vec![],
-            vec![],
-            vec![PatternAction::new_action_only(Stmt::Print(num!(1.0)))]
-        )
-    );
+    let str = "NR==2, NR==4 { print }";
+    let actual = parse(lex(str).unwrap()).unwrap();
+    let nr = || btexpr!(Expr::Variable("NR".into()));
+    let start = texpr!(Expr::BinOp(nr(), BinOp::EqEq, bnum!(2.0)));
+    let end = texpr!(Expr::BinOp(nr(), BinOp::EqEq, bnum!(4.0)));
+    let body = Stmt::Print {
+        args: vec![TypedExpr::new_str(Expr::Column(Box::new(
+            TypedExpr::new_num(Expr::NumberF64(0.0)),
+        )))],
+        output: None,
+    };
+    let pa = PatternAction::new_range(start.clone(), end.clone(), body);
+    assert_eq!(actual, Program::new(vec![], vec![], vec![pa]));
+
+    let actual = parse(lex("NR==2, NR==4").unwrap()).unwrap();
+    let pa = PatternAction::new_range_only(start, end);
+    assert_eq!(actual, Program::new(vec![], vec![], vec![pa]));
+}
+
+#[test]
+fn test_getline_pattern() {
+    use crate::lexer::lex;
+    let getline = |var: &str, file: &str| {
+        Box::new(TypedExpr::new_num(Expr::Getline {
+            into: Some(Lvalue::Variable(var.into())),
+            source: GetlineSource::File(btexpr!(Expr::String(file.to_string()))),
+        }))
+    };
+    let print = |var: &str| print_stmt!(texpr!(Expr::Variable(var.into())));
+    let str = "(getline line < \"f\") > 0 { print line }\ngetline x < \"g\" > 0 { print x }";
+    let actual = parse(lex(str).unwrap()).unwrap();
+    let test = |var: &str, file: &str| {
+        Some(texpr!(Expr::BinOp(
+            getline(var, file),
+            BinOp::Greater,
+            bnum!(0.0)
+        )))
+    };
+    let pas = vec![
+        PatternAction::new(test("line", "f"), print("line")),
+        PatternAction::new(test("x", "g"), print("x")),
+    ];
+    assert_eq!(actual, Program::new(vec![], vec![], pas));
+}
+
+#[test]
+fn test_newline_terminated_stmts() {
+    actual!(
+        actual,
+        "{\n    a = 1\n    print a,\n        2\n\n    if (a)\n    {\n        print (a +\n 1)\n    }\n}\n"
+    );
+    let a = || texpr!(Expr::Variable("a".into()));
+    let expected = Stmt::Group(vec![
+        Stmt::Expr(texpr!(Expr::Assign("a".into(), bnum!(1.0)))),
+        print_stmt!(a(), num!(2.0)),
+        Stmt::If(
+            a(),
+            Box::new(print_stmt!(texpr!(Expr::MathOp(
+                Box::new(a()),
+                MathOp::Plus,
+                bnum!(1.0)
+            )))),
+            None,
+        ),
+    ]);
+    assert_eq!(actual, sprogram!(expected));
+}
+
+#[test]
+fn test_newline_ends_pattern() {
+    actual!(actual, "a\n{ print 1 }\n");
+    let expected = Program::new(
+        vec![],
+        vec![],
+        vec![
+            PatternAction::new_pattern_only(texpr!(Expr::Variable("a".into()))),
+            PatternAction::new_action_only(print_stmt!(num!(1.0))),
+        ],
+    );
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn test_print_no_semicolon() {
+    use crate::lexer::lex;
+    let str = "{ print 1 }";
+    let actual = parse(lex(str).unwrap()).unwrap();
+    assert_eq!(
+        actual,
+        Program::new(
+            vec![],
+            vec![],
+            vec![PatternAction::new_action_only(print_stmt!(num!(1.0)))]
+        )
+    );
 }
 
 #[test]
 fn test_column() {
     use crate::lexer::lex;
     let str = "$0+2 { print a; }";
-    let actual = parse(lex(str).unwrap());
-    let body = Stmt::Print(texpr!(Expr::Variable("a".to_string())));
+    let actual = parse(lex(str).unwrap()).unwrap();
+    let body = print_stmt!(texpr!(Expr::Variable("a".into())));
 
     let col = Expr::Column(bnum!(0.0));
     let binop = texpr!(Expr::MathOp(btexpr!(col), MathOp::Plus, bnum!(2.0)));
@@ -724,8 +1636,8 @@ fn test_column() {
 fn test_nested_column() {
     use crate::lexer::lex;
     let str = "$$0 { print a; }";
-    let actual = parse(lex(str).unwrap());
-    let body = Stmt::Print(texpr!(Expr::Variable("a".to_string())));
+    let actual = parse(lex(str).unwrap()).unwrap();
+    let body = print_stmt!(texpr!(Expr::Variable("a".into())));
 
     let col = Expr::Column(bnum!(0.0));
     let col = Expr::Column(btexpr!(col));
@@ -738,8 +1650,8 @@ fn test_nested_column() {
 fn test_while_l00p() {
     use crate::lexer::lex;
     let str = "{ while (123) { print 1; } }";
-    let actual = parse(lex(str).unwrap());
-    let body = Stmt::While(num!(123.0), Box::new(Stmt::Print(num!(1.0))));
+    let actual = parse(lex(str).unwrap()).unwrap();
+    let body = Stmt::While(num!(123.0), Box::new(print_stmt!(num!(1.0))));
     assert_eq!(
         actual,
         Program::new(vec![], vec![], vec![PatternAction::new_action_only(body)])
@@ -818,14 +1730,14 @@ fn test_cmp_oop2() {
 
     let left = texpr!(Expr::MathOp(bnum!(1.0), MathOp::Star, bnum!(3.0)));
     let body = btexpr!(Expr::BinOp(Box::new(left), BinOp::EqEq, bnum!(4.0)));
-    let stmt = Stmt::Expr(texpr!(Expr::Assign(format!("a"), body)));
+    let stmt = Stmt::Expr(texpr!(Expr::Assign("a".into(), body)));
     assert_eq!(actual, sprogram!(stmt));
 }
 
 #[test]
 fn test_for_loop() {
     actual!(actual, "{ for (a = 0; a < 1000; a = a + 1) { print a; } }");
-    let a = format!("a");
+    let a = crate::lexer::Symbol::from("a");
     let init = texpr!(Expr::Assign(a.clone(), btexpr!(Expr::NumberF64(0.0))));
     let test = texpr!(Expr::BinOp(
         btexpr!(Expr::Variable(a.clone())),
@@ -840,10 +1752,49 @@ fn test_for_loop() {
             btexpr!(Expr::NumberF64(1.0))
         ))
     ));
-    let body = Stmt::Print(texpr!(Expr::Variable(a.clone())));
+    let body = print_stmt!(texpr!(Expr::Variable(a)));
+    let expected = Stmt::For {
+        init: Box::new(Stmt::Expr(init)),
+        test,
+        incr: Box::new(Stmt::Expr(incr)),
+        body: Box::new(body),
+    };
+    assert_eq!(actual, sprogram!(expected))
+}
+
+#[test]
+fn test_loops_without_braces() {
+    actual!(
+        actual,
+        "{ for (i = 1; i <= NF; i = i + 1) print $i; while (x) x = 0 }"
+    );
+    let i = || btexpr!(Expr::Variable("i".into()));
+    let init = texpr!(Expr::Assign("i".into(), bnum!(1.0)));
+    let test = texpr!(Expr::BinOp(
+        i(),
+        BinOp::LessEq,
+        btexpr!(Expr::Variable("NF".into()))
+    ));
+    let incr = texpr!(Expr::Assign(
+        "i".into(),
+        btexpr!(Expr::MathOp(i(), MathOp::Plus, bnum!(1.0)))
+    ));
+    let body = Stmt::Print {
+        args: vec![texpr!(Expr::Column(i()))],
+        output: None,
+    };
+    let x = || texpr!(Expr::Variable("x".into()));
     let expected = Stmt::Group(vec![
-        Stmt::Expr(init),
-        Stmt::While(test, Box::new(Stmt::Group(vec![body, Stmt::Expr(incr)]))),
+        Stmt::For {
+            init: Box::new(Stmt::Expr(init)),
+            test,
+            incr: Box::new(Stmt::Expr(incr)),
+            body: Box::new(body),
+        },
+        Stmt::While(
+            x(),
+            Box::new(Stmt::Expr(texpr!(Expr::Assign("x".into(), bnum!(0.0))))),
+        ),
     ]);
     assert_eq!(actual, sprogram!(expected))
 }
@@ -851,9 +1802,9 @@ fn test_for_loop() {
 #[test]
 fn test_logical_and() {
     actual!(actual, "{ a && b && c }");
-    let a = btexpr!(Expr::Variable("a".to_string()));
-    let b = btexpr!(Expr::Variable("b".to_string()));
-    let c = btexpr!(Expr::Variable("c".to_string()));
+    let a = btexpr!(Expr::Variable("a".into()));
+    let b = btexpr!(Expr::Variable("b".into()));
+    let c = btexpr!(Expr::Variable("c".into()));
     let a_and_b = btexpr!(Expr::LogicalOp(a, LogicalOp::And, b));
     let expected = Stmt::Expr(texpr!(Expr::LogicalOp(a_and_b, LogicalOp::And, c)));
     assert_eq!(actual, sprogram!(expected))
@@ -862,20 +1813,119 @@ fn test_logical_and() {
 #[test]
 fn test_logical_or() {
     actual!(actual, "{ a || b || c }");
-    let a = btexpr!(Expr::Variable("a".to_string()));
-    let b = btexpr!(Expr::Variable("b".to_string()));
-    let c = btexpr!(Expr::Variable("c".to_string()));
+    let a = btexpr!(Expr::Variable("a".into()));
+    let b = btexpr!(Expr::Variable("b".into()));
+    let c = btexpr!(Expr::Variable("c".into()));
     let a_and_b = btexpr!(Expr::LogicalOp(a, LogicalOp::Or, b));
     let expected = Stmt::Expr(texpr!(Expr::LogicalOp(a_and_b, LogicalOp::Or, c)));
     assert_eq!(actual, sprogram!(expected))
 }
 
+#[test]
+fn test_not() {
+    actual!(actual, "{ !a * 2 }");
+    let a = btexpr!(Expr::Variable("a".into()));
+    let not_a = Box::new(TypedExpr::new_num(Expr::Not(a)));
+    let expected = Stmt::Expr(texpr!(Expr::MathOp(not_a, MathOp::Star, bnum!(2.0))));
+    assert_eq!(actual, sprogram!(expected))
+}
+
+#[test]
+fn test_double_not() {
+    actual!(actual, "{ !!a == 1 }");
+    let a = btexpr!(Expr::Variable("a".into()));
+    let not_a = Box::new(TypedExpr::new_num(Expr::Not(a)));
+    let not_not_a = Box::new(TypedExpr::new_num(Expr::Not(not_a)));
+    let expected = Stmt::Expr(binop!(not_not_a, BinOp::EqEq, bnum!(1.0)));
+    assert_eq!(actual, sprogram!(expected))
+}
+
+#[test]
+fn test_concatenation_binds_tighter_than_comparison() {
+    actual!(actual, "$1 $2 == \"ab\"");
+    let column = |idx: f64| texpr!(Expr::Column(bnum!(idx)));
+    let concat = btexpr!(Expr::Concatenation(vec![column(1.0), column(2.0)]));
+    let ab = btexpr!(Expr::String("ab".to_string()));
+    let test = binop!(concat, BinOp::EqEq, ab);
+    assert_eq!(
+        actual,
+        Program::new(vec![], vec![], vec![PatternAction::new_pattern_only(test)])
+    );
+}
+
+#[test]
+fn test_negated_regex_pattern() {
+    actual!(actual, "!/b/");
+    let regex = btexpr!(Expr::Regex("b".to_string()));
+    let test = TypedExpr::new_num(Expr::Not(regex));
+    assert_eq!(
+        actual,
+        Program::new(vec![], vec![], vec![PatternAction::new_pattern_only(test)])
+    );
+}
+
+#[test]
+fn test_match_operators() {
+    actual!(actual, "$1 ~ /a/ { print } x !~ \"b\" c { print }");
+    let matched = binop!(
+        btexpr!(Expr::Column(bnum!(1.0))),
+        BinOp::MatchedBy,
+        btexpr!(Expr::Regex("a".to_string()))
+    );
+    // Concatenation binds tighter, the pattern is x !~ ("b" c)
+    let b_c = btexpr!(Expr::Concatenation(vec![
+        texpr!(Expr::String("b".to_string())),
+        texpr!(Expr::Variable("c".into())),
+    ]));
+    let not_matched = binop!(
+        btexpr!(Expr::Variable("x".into())),
+        BinOp::NotMatchedBy,
+        b_c
+    );
+    assert_eq!(
+        actual,
+        Program::new(
+            vec![],
+            vec![],
+            vec![
+                PatternAction::new_pattern_only(matched),
+                PatternAction::new_pattern_only(not_matched)
+            ]
+        )
+    );
+}
+
+#[test]
+fn test_in_array() {
+    actual!(actual, "{ print k in a, !(k in a), (1, k) in b[2] && x }");
+    let k = || texpr!(Expr::Variable("k".into()));
+    let k_in_a = || {
+        TypedExpr::new_num(Expr::InArray {
+            indices: vec![k()],
+            name: "a".to_string(),
+            path: vec![],
+        })
+    };
+    let pair_in_b = TypedExpr::new_num(Expr::InArray {
+        indices: vec![num!(1.0), k()],
+        name: "b".to_string(),
+        path: vec![vec![num!(2.0)]],
+    });
+    let and_x = texpr!(Expr::LogicalOp(
+        Box::new(pair_in_b),
+        LogicalOp::And,
+        btexpr!(Expr::Variable("x".into()))
+    ));
+    let not_in = TypedExpr::new_num(Expr::Not(Box::new(k_in_a())));
+    assert_eq!(actual, sprogram!(print_stmt!(k_in_a(), not_in, and_x)));
+}
+
 #[test]
 fn string_concat() {
     actual!(actual, "{ print (a b) } ");
-    let a = texpr!(Expr::Variable("a".to_string()));
-    let b = texpr!(Expr::Variable("b".to_string()));
-    let print = Stmt::Print(texpr!(Expr::Concatenation(vec![a, b])));
+    let a = texpr!(Expr::Variable("a".into()));
+    let b = texpr!(Expr::Variable("b".into()));
+    let print = print_stmt!(texpr!(Expr::Concatenation(vec![a, b])));
     assert_eq!(actual, sprogram!(print));
 }
 
@@ -884,51 +1934,51 @@ fn string_concat2() {
     actual!(actual, "{ print (\"a\" \"b\") } ");
     let a = texpr!(Expr::String("a".to_string()));
     let b = texpr!(Expr::String("b".to_string()));
-    let print = Stmt::Print(texpr!(Expr::Concatenation(vec![a, b])));
+    let print = print_stmt!(texpr!(Expr::Concatenation(vec![a, b])));
     assert_eq!(actual, sprogram!(print));
 }
 
 #[test]
 fn string_concat_ooo() {
     actual!(actual, "{ print (a b - c) } ");
-    let a = texpr!(Expr::Variable("a".to_string()));
-    let b = btexpr!(Expr::Variable("b".to_string()));
-    let c = btexpr!(Expr::Variable("c".to_string()));
+    let a = texpr!(Expr::Variable("a".into()));
+    let b = btexpr!(Expr::Variable("b".into()));
+    let c = btexpr!(Expr::Variable("c".into()));
     let b_minus_c = texpr!(Expr::MathOp(b, MathOp::Minus, c));
-    let expected = Stmt::Print(texpr!(Expr::Concatenation(vec![a, b_minus_c])));
+    let expected = print_stmt!(texpr!(Expr::Concatenation(vec![a, b_minus_c])));
     assert_eq!(actual, sprogram!(expected));
 }
 
 #[test]
 fn string_concat_ooo_2() {
     actual!(actual, "{ print (a - c b ) } ");
-    let a = btexpr!(Expr::Variable("a".to_string()));
-    let b = texpr!(Expr::Variable("b".to_string()));
-    let c = btexpr!(Expr::Variable("c".to_string()));
+    let a = btexpr!(Expr::Variable("a".into()));
+    let b = texpr!(Expr::Variable("b".into()));
+    let c = btexpr!(Expr::Variable("c".into()));
     let a_minus_c = texpr!(Expr::MathOp(a, MathOp::Minus, c));
-    let expected = Stmt::Print(texpr!(Expr::Concatenation(vec![a_minus_c, b])));
+    let expected = print_stmt!(texpr!(Expr::Concatenation(vec![a_minus_c, b])));
     assert_eq!(actual, sprogram!(expected));
 }
 
 #[test]
 fn string_concat_ooo_3() {
     actual!(actual, "{ print (a < b c ) } ");
-    let a = btexpr!(Expr::Variable("a".to_string()));
-    let b = texpr!(Expr::Variable("b".to_string()));
-    let c = texpr!(Expr::Variable("c".to_string()));
+    let a = btexpr!(Expr::Variable("a".into()));
+    let b = texpr!(Expr::Variable("b".into()));
+    let c = texpr!(Expr::Variable("c".into()));
     let b_concat_c = btexpr!(Expr::Concatenation(vec![b, c]));
-    let expected = Stmt::Print(texpr!(Expr::BinOp(a, BinOp::Less, b_concat_c)));
+    let expected = print_stmt!(texpr!(Expr::BinOp(a, BinOp::Less, b_concat_c)));
     assert_eq!(actual, sprogram!(expected));
 }
 
 #[test]
 fn string_concat_ooo_4() {
     actual!(actual, "{ print (a b < c ) } ");
-    let a = texpr!(Expr::Variable("a".to_string()));
-    let b = texpr!(Expr::Variable("b".to_string()));
-    let c = btexpr!(Expr::Variable("c".to_string()));
+    let a = texpr!(Expr::Variable("a".into()));
+    let b = texpr!(Expr::Variable("b".into()));
+    let c = btexpr!(Expr::Variable("c".into()));
     let a_concat_b = btexpr!(Expr::Concatenation(vec![a, b]));
-    let expected = Stmt::Print(texpr!(Expr::BinOp(a_concat_b, BinOp::Less, c)));
+    let expected = print_stmt!(texpr!(Expr::BinOp(a_concat_b, BinOp::Less, c)));
     assert_eq!(actual, sprogram!(expected));
 }
 
@@ -938,7 +1988,1276 @@ fn string_concat_two_cols() {
     let one = texpr!(Expr::Column(bnum!(1.0)));
     let two = texpr!(Expr::Column(bnum!(2.0)));
     let concat = texpr!(Expr::Concatenation(vec![one, two]));
-    let print = Stmt::Print(concat);
+    let print = print_stmt!(concat);
     println!("{}", actual.pattern_actions[0].action);
     assert_eq!(actual, sprogram!(print));
 }
+
+#[test]
+fn string_concat_assign() {
+    actual!(actual, "{ a = b c } ");
+    let b = texpr!(Expr::Variable("b".into()));
+    let c = texpr!(Expr::Variable("c".into()));
+    let b_concat_c = btexpr!(Expr::Concatenation(vec![b, c]));
+    let expected = Stmt::Expr(texpr!(Expr::Assign("a".into(), b_concat_c)));
+    assert_eq!(actual, sprogram!(expected));
+}
+
+#[test]
+fn string_concat_assign_3() {
+    actual!(actual, "{ a = b c d } ");
+    let b = texpr!(Expr::Variable("b".into()));
+    let c = texpr!(Expr::Variable("c".into()));
+    let d = texpr!(Expr::Variable("d".into()));
+    let concat = btexpr!(Expr::Concatenation(vec![b, c, d]));
+    let expected = Stmt::Expr(texpr!(Expr::Assign("a".into(), concat)));
+    assert_eq!(actual, sprogram!(expected));
+}
+
+#[test]
+fn string_concat_assign_ooo() {
+    actual!(actual, "{ a = b - c d } ");
+    let b = btexpr!(Expr::Variable("b".into()));
+    let c = btexpr!(Expr::Variable("c".into()));
+    let d = texpr!(Expr::Variable("d".into()));
+    let b_minus_c = texpr!(Expr::MathOp(b, MathOp::Minus, c));
+    let concat = btexpr!(Expr::Concatenation(vec![b_minus_c, d]));
+    let expected = Stmt::Expr(texpr!(Expr::Assign("a".into(), concat)));
+    assert_eq!(actual, sprogram!(expected));
+}
+
+#[test]
+fn test_chained_assign() {
+    actual!(actual, "{ a = b = 5; x[1] = $2 = c = \"s\" } ");
+    let b_is_5 = btexpr!(Expr::Assign("b".into(), bnum!(5.0)));
+    let a = Stmt::Expr(texpr!(Expr::Assign("a".into(), b_is_5)));
+    let c_is_s = btexpr!(Expr::Assign(
+        "c".into(),
+        btexpr!(Expr::String("s".to_string()))
+    ));
+    let x = Stmt::Expr(texpr!(Expr::ArrayAssign {
+        name: "x".to_string(),
+        path: vec![],
+        indices: vec![num!(1.0)],
+        value: btexpr!(Expr::ColumnAssign {
+            index: bnum!(2.0),
+            value: c_is_s,
+        }),
+    }));
+    assert_eq!(actual, sprogram!(Stmt::Group(vec![a, x])));
+}
+
+#[test]
+fn test_chained_assign_is_right_associative() {
+    actual!(actual, "{ a = b = c = 5 }");
+    let c_is_5 = btexpr!(Expr::Assign("c".into(), bnum!(5.0)));
+    let b_is_c = btexpr!(Expr::Assign("b".into(), c_is_5));
+    let expected = Stmt::Expr(texpr!(Expr::Assign("a".into(), b_is_c)));
+    assert_eq!(actual, sprogram!(expected));
+}
+
+#[test]
+fn test_nf_assign() {
+    actual!(actual, "{ NF = 2; x = 2; nf = 2; NF -= 1 }");
+    let nf = texpr!(Expr::Variable("NF".into()));
+    let nf_minus_1 = btexpr!(Expr::MathOp(Box::new(nf), MathOp::Minus, bnum!(1.0)));
+    let expected = sprogram!(Stmt::Group(vec![
+        Stmt::Expr(texpr!(Expr::NfAssign(bnum!(2.0)))),
+        Stmt::Expr(texpr!(Expr::Assign("x".into(), bnum!(2.0)))),
+        Stmt::Expr(texpr!(Expr::Assign("nf".into(), bnum!(2.0)))),
+        Stmt::Expr(texpr!(Expr::NfAssign(nf_minus_1))),
+    ]));
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn test_disabled_keyword_is_a_variable() {
+    use crate::lexer::{lex_with_keywords, Keywords};
+    let keywords = Keywords::default().without("print");
+    let actual =
+        parse(lex_with_keywords("{ print = 5; printf print }", keywords).unwrap()).unwrap();
+    let print_var = texpr!(Expr::Variable("print".into()));
+    let expected = sprogram!(Stmt::Group(vec![
+        Stmt::Expr(texpr!(Expr::Assign("print".into(), bnum!(5.0)))),
+        Stmt::Printf {
+            fmt: print_var,
+            args: vec![],
+            output: None,
+        },
+    ]));
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn string_concat_assign_in_expr() {
+    actual!(actual, "{ print (a = b c) } ");
+    let b = texpr!(Expr::Variable("b".into()));
+    let c = texpr!(Expr::Variable("c".into()));
+    let b_concat_c = btexpr!(Expr::Concatenation(vec![b, c]));
+    let expected = print_stmt!(texpr!(Expr::Assign("a".into(), b_concat_c)));
+    assert_eq!(actual, sprogram!(expected));
+}
+
+#[test]
+fn test_call_typeof() {
+    actual!(actual, "{ typeof(x) }");
+    let x = texpr!(Expr::Variable("x".into()));
+    let call = Stmt::Expr(texpr!(Expr::Call {
+        target: "typeof".to_string(),
+        args: vec![x]
+    }));
+    assert_eq!(actual, sprogram!(call));
+}
+
+#[test]
+#[should_panic(expected = "patsplit is a gawk extension, not available with --posix")]
+fn test_gawk_builtin_in_posix() {
+    use crate::lexer::lex;
+    parse_with_dialect(lex("{ patsplit($0, a) }").unwrap(), Dialect::Posix).unwrap();
+}
+
+#[test]
+#[should_panic(expected = "typeof is a gawk extension, not available with --traditional")]
+fn test_gawk_builtin_in_bwk() {
+    use crate::lexer::lex;
+    parse_with_dialect(lex("{ print typeof(x) }").unwrap(), Dialect::Bwk).unwrap();
+}
+
+#[test]
+fn test_builtins_by_dialect() {
+    use crate::lexer::lex;
+    let program = "{ print typeof(x), isarray(a), toupper(x) }";
+    let gawk = parse_with_dialect(lex(program).unwrap(), Dialect::Gawk).unwrap();
+    assert_eq!(gawk, parse(lex(program).unwrap()).unwrap());
+    // Builtins every awk has are there in every dialect
+    let shared = "{ print toupper(x), sprintf(\"%d\", rand()) }";
+    for dialect in [Dialect::Posix, Dialect::Bwk] {
+        let parsed = parse_with_dialect(lex(shared).unwrap(), dialect).unwrap();
+        assert_eq!(parsed, parse(lex(shared).unwrap()).unwrap());
+    }
+}
+
+#[test]
+#[should_panic(expected = "gsub argument 3 must be a variable, field or array element")]
+fn test_gsub_needs_lvalue() {
+    actual!(_actual, "{ gsub(/a/, \"b\", $1 \"c\") }");
+}
+
+#[test]
+#[should_panic(expected = "patsplit argument 2 must be an array name")]
+fn test_patsplit_needs_array() {
+    actual!(_actual, "{ patsplit($0, $1) }");
+}
+
+#[test]
+fn test_call_match() {
+    actual!(actual, "{ match($0, /[0-9]+/); match(a, \"b\" c) }");
+    let call = |args: Vec<TypedExpr>| {
+        Stmt::Expr(texpr!(Expr::Call {
+            target: "match".to_string(),
+            args,
+        }))
+    };
+    let expected = Stmt::Group(vec![
+        call(vec![
+            texpr!(Expr::Column(bnum!(0.0))),
+            texpr!(Expr::Regex("[0-9]+".to_string())),
+        ]),
+        call(vec![
+            texpr!(Expr::Variable("a".into())),
+            texpr!(Expr::Concatenation(vec![
+                texpr!(Expr::String("b".to_string())),
+                texpr!(Expr::Variable("c".into())),
+            ])),
+        ]),
+    ]);
+    assert_eq!(actual, sprogram!(expected));
+}
+
+#[test]
+#[should_panic]
+fn test_call_match_one_arg() {
+    actual!(_actual, "{ match($0) }");
+}
+
+#[test]
+#[should_panic]
+fn test_call_match_three_args() {
+    actual!(_actual, "{ match($0, /a/, arr) }");
+}
+
+#[test]
+fn test_call_isarray_column() {
+    actual!(actual, "{ print isarray($1) }");
+    let col = texpr!(Expr::Column(bnum!(1.0)));
+    let call = print_stmt!(texpr!(Expr::Call {
+        target: "isarray".to_string(),
+        args: vec![col]
+    }));
+    assert_eq!(actual, sprogram!(call));
+}
+
+#[test]
+#[should_panic]
+fn test_call_typeof_arity() {
+    actual!(_actual, "{ typeof(x, y) }");
+}
+
+#[test]
+fn test_printf() {
+    actual!(actual, "{ printf \"%d %s\", 1, a }");
+    assert_eq!(
+        actual,
+        sprogram!(Stmt::Printf {
+            fmt: texpr!(Expr::String("%d %s".to_string())),
+            args: vec![num!(1.0), texpr!(Expr::Variable("a".into()))],
+            output: None,
+        })
+    );
+}
+
+#[test]
+fn test_printf_parens() {
+    actual!(actual, "{ printf(\"%c\", 200); }");
+    assert_eq!(
+        actual,
+        sprogram!(Stmt::Printf {
+            fmt: texpr!(Expr::String("%c".to_string())),
+            args: vec![num!(200.0)],
+            output: None,
+        })
+    );
+}
+
+#[test]
+fn test_printf_grouped_format() {
+    actual!(actual, "{ printf (\"a\") \"b\" }");
+    assert_eq!(
+        actual,
+        sprogram!(Stmt::Printf {
+            fmt: texpr!(Expr::Concatenation(vec![
+                texpr!(Expr::String("a".to_string())),
+                texpr!(Expr::String("b".to_string())),
+            ])),
+            args: vec![],
+            output: None,
+        })
+    );
+}
+
+#[test]
+fn test_call_sprintf() {
+    actual!(actual, "{ x = sprintf(\"%d-%s\", n, s) }");
+    let call = texpr!(Expr::Call {
+        target: "sprintf".to_string(),
+        args: vec![
+            texpr!(Expr::String("%d-%s".to_string())),
+            texpr!(Expr::Variable("n".into())),
+            texpr!(Expr::Variable("s".into())),
+        ]
+    });
+    let assign = Stmt::Expr(texpr!(Expr::Assign("x".into(), Box::new(call))));
+    assert_eq!(actual, sprogram!(assign));
+}
+
+#[test]
+fn test_plain_getline() {
+    actual!(actual, "{ getline; r = getline }");
+    let getline = TypedExpr::new_num(Expr::Getline {
+        into: None,
+        source: GetlineSource::MainInput,
+    });
+    assert_eq!(
+        actual,
+        sprogram!(Stmt::Group(vec![
+            Stmt::Expr(getline.clone()),
+            Stmt::Expr(texpr!(Expr::Assign("r".into(), Box::new(getline)))),
+        ]))
+    );
+}
+
+#[test]
+fn test_print_two_args() {
+    actual!(actual, "{ print a, 1 }");
+    let a = texpr!(Expr::Variable("a".into()));
+    assert_eq!(actual, sprogram!(print_stmt!(a, num!(1.0))));
+}
+
+#[test]
+fn test_print_three_args() {
+    actual!(actual, "{ print a b, $1, \"c\" }");
+    let a = texpr!(Expr::Variable("a".into()));
+    let b = texpr!(Expr::Variable("b".into()));
+    let ab = texpr!(Expr::Concatenation(vec![a, b]));
+    let col = texpr!(Expr::Column(bnum!(1.0)));
+    let c = texpr!(Expr::String("c".to_string()));
+    assert_eq!(actual, sprogram!(print_stmt!(ab, col, c)));
+}
+
+#[test]
+fn test_print_parenthesized_list() {
+    actual!(actual, "{ print (a, 1); print (a) 1 }");
+    let a = texpr!(Expr::Variable("a".into()));
+    let concat = texpr!(Expr::Concatenation(vec![a.clone(), num!(1.0)]));
+    assert_eq!(
+        actual,
+        sprogram!(Stmt::Group(vec![
+            print_stmt!(a, num!(1.0)),
+            print_stmt!(concat)
+        ]))
+    );
+}
+
+#[test]
+fn test_array_index() {
+    actual!(actual, "{ print F[\"age\"], a[1, b] }");
+    let age = texpr!(Expr::ArrayIndex {
+        name: "F".to_string(),
+        path: vec![],
+        indices: vec![texpr!(Expr::String("age".to_string()))],
+    });
+    let a = texpr!(Expr::ArrayIndex {
+        name: "a".to_string(),
+        path: vec![],
+        indices: vec![num!(1.0), texpr!(Expr::Variable("b".into()))],
+    });
+    assert_eq!(actual, sprogram!(print_stmt!(age, a)));
+}
+
+#[test]
+fn test_predefined_arrays() {
+    actual!(actual, "{ print ENVIRON[\"PATH\"], ARGV[ARGC - 1] }");
+    let path = texpr!(Expr::ArrayIndex {
+        name: "ENVIRON".to_string(),
+        path: vec![],
+        indices: vec![texpr!(Expr::String("PATH".to_string()))],
+    });
+    let argc = btexpr!(Expr::Variable("ARGC".into()));
+    let last_arg = texpr!(Expr::ArrayIndex {
+        name: "ARGV".to_string(),
+        path: vec![],
+        indices: vec![mathop!(argc, MathOp::Minus, bnum!(1.0))],
+    });
+    assert_eq!(actual, sprogram!(print_stmt!(path, last_arg)));
+}
+
+#[test]
+fn test_array_assign() {
+    actual!(actual, "{ a[\"x\"] = b = 1 }");
+    let assign = texpr!(Expr::ArrayAssign {
+        name: "a".to_string(),
+        path: vec![],
+        indices: vec![texpr!(Expr::String("x".to_string()))],
+        value: Box::new(texpr!(Expr::Assign("b".into(), bnum!(1.0)))),
+    });
+    assert_eq!(actual, sprogram!(Stmt::Expr(assign)));
+}
+
+#[test]
+fn test_delete() {
+    actual!(actual, "{ delete a[1, b]; delete a[1][2]; delete a }");
+    let element = Stmt::Delete {
+        name: "a".to_string(),
+        path: vec![],
+        indices: vec![num!(1.0), texpr!(Expr::Variable("b".into()))],
+    };
+    let nested = Stmt::Delete {
+        name: "a".to_string(),
+        path: vec![vec![num!(1.0)]],
+        indices: vec![num!(2.0)],
+    };
+    let whole = Stmt::Delete {
+        name: "a".to_string(),
+        path: vec![],
+        indices: vec![],
+    };
+    assert_eq!(actual, sprogram!(Stmt::Group(vec![element, nested, whole])));
+    let errors = parse(lex("{ delete 1 }").unwrap()).unwrap_err();
+    assert!(errors[0]
+        .message
+        .starts_with("Expected an array after delete"));
+    let errors = parse(lex("delete a").unwrap()).unwrap_err();
+    assert_eq!(
+        errors[0].message,
+        "delete must be inside an action, eg. { delete ... }"
+    );
+}
+
+#[test]
+fn test_column_assign() {
+    actual!(actual, "{ $1 = \"hello\"; $(NF + 1) = $2 = \"y\" }");
+    let first = texpr!(Expr::ColumnAssign {
+        index: bnum!(1.0),
+        value: btexpr!(Expr::String("hello".to_string())),
+    });
+    let nf_plus_one = texpr!(Expr::MathOp(
+        btexpr!(Expr::Variable("NF".into())),
+        MathOp::Plus,
+        bnum!(1.0),
+    ));
+    let last = texpr!(Expr::ColumnAssign {
+        index: Box::new(nf_plus_one),
+        value: btexpr!(Expr::ColumnAssign {
+            index: bnum!(2.0),
+            value: btexpr!(Expr::String("y".to_string())),
+        }),
+    });
+    assert_eq!(
+        actual,
+        sprogram!(Stmt::Group(vec![Stmt::Expr(first), Stmt::Expr(last)]))
+    );
+}
+
+#[test]
+fn test_pre_increment() {
+    actual!(actual, "{ ++x; --a[1]; ++$2 ^ 2 }");
+    let one = || bnum!(1.0);
+    let x = btexpr!(Expr::Variable("x".into()));
+    let incr_x = texpr!(Expr::Assign(
+        "x".into(),
+        btexpr!(Expr::MathOp(x, MathOp::Plus, one()))
+    ));
+    let a_1 = btexpr!(Expr::ArrayIndex {
+        name: "a".to_string(),
+        path: vec![],
+        indices: vec![num!(1.0)],
+    });
+    let decr_a_1 = texpr!(Expr::ArrayAssign {
+        name: "a".to_string(),
+        path: vec![],
+        indices: vec![num!(1.0)],
+        value: btexpr!(Expr::MathOp(a_1, MathOp::Minus, one())),
+    });
+    let col_2 = btexpr!(Expr::Column(bnum!(2.0)));
+    let incr_col_2 = btexpr!(Expr::ColumnAssign {
+        index: bnum!(2.0),
+        value: btexpr!(Expr::MathOp(col_2, MathOp::Plus, one())),
+    });
+    let squared = texpr!(Expr::MathOp(incr_col_2, MathOp::Exponent, bnum!(2.0)));
+    assert_eq!(
+        actual,
+        sprogram!(Stmt::Group(vec![
+            Stmt::Expr(incr_x),
+            Stmt::Expr(decr_a_1),
+            Stmt::Expr(squared)
+        ]))
+    );
+}
+
+#[test]
+fn test_increment_spacing() {
+    use crate::lexer::lex;
+    let var = |name: &str| btexpr!(Expr::Variable(name.into()));
+    let incr = |name: &str| {
+        btexpr!(Expr::Assign(
+            name.into(),
+            btexpr!(Expr::MathOp(var(name), MathOp::Plus, bnum!(1.0)))
+        ))
+    };
+    let stmt = |expr: TypedExpr| sprogram!(Stmt::Expr(expr));
+
+    // a++ + b is (a++) + b, and a++ is the old value
+    let a_post_incr = btexpr!(Expr::MathOp(incr("a"), MathOp::Minus, bnum!(1.0)));
+    assert_eq!(
+        parse(lex("{ a++ + b }").unwrap()).unwrap(),
+        stmt(mathop!(a_post_incr, MathOp::Plus, var("b")))
+    );
+    // a+ ++b is a + (++b)
+    assert_eq!(
+        parse(lex("{ a+ ++b }").unwrap()).unwrap(),
+        stmt(mathop!(var("a"), MathOp::Plus, incr("b")))
+    );
+    // a + +b and a - -b have a unary plus and minus and leave a alone
+    let unary = |op: MathOp| btexpr!(Expr::MathOp(bnum!(0.0), op, var("b")));
+    assert_eq!(
+        parse(lex("{ a + +b }").unwrap()).unwrap(),
+        stmt(mathop!(var("a"), MathOp::Plus, unary(MathOp::Plus)))
+    );
+    assert_eq!(
+        parse(lex("{ a - -b }").unwrap()).unwrap(),
+        stmt(mathop!(var("a"), MathOp::Minus, unary(MathOp::Minus)))
+    );
+}
+
+#[test]
+fn test_post_increment_lvalues() {
+    actual!(actual, "{ $i++; a[1]-- }");
+    let i = btexpr!(Expr::Variable("i".into()));
+    let col_i = btexpr!(Expr::Column(i.clone()));
+    let incr = texpr!(Expr::MathOp(
+        btexpr!(Expr::ColumnAssign {
+            index: i,
+            value: btexpr!(Expr::MathOp(col_i, MathOp::Plus, bnum!(1.0))),
+        }),
+        MathOp::Minus,
+        bnum!(1.0)
+    ));
+    let a_1 = btexpr!(Expr::ArrayIndex {
+        name: "a".to_string(),
+        path: vec![],
+        indices: vec![num!(1.0)],
+    });
+    let decr = texpr!(Expr::MathOp(
+        btexpr!(Expr::ArrayAssign {
+            name: "a".to_string(),
+            path: vec![],
+            indices: vec![num!(1.0)],
+            value: btexpr!(Expr::MathOp(a_1, MathOp::Minus, bnum!(1.0))),
+        }),
+        MathOp::Plus,
+        bnum!(1.0)
+    ));
+    assert_eq!(
+        actual,
+        sprogram!(Stmt::Group(vec![Stmt::Expr(incr), Stmt::Expr(decr)]))
+    );
+}
+
+#[test]
+fn test_unary_minus_precedence() {
+    actual!(actual, "{ -2 ^ 2 * 3 }");
+    let two_squared = btexpr!(Expr::MathOp(bnum!(2.0), MathOp::Exponent, bnum!(2.0)));
+    let negated = btexpr!(Expr::MathOp(bnum!(0.0), MathOp::Minus, two_squared));
+    let expected = Stmt::Expr(mathop!(negated, MathOp::Star, bnum!(3.0)));
+    assert_eq!(actual, sprogram!(expected));
+}
+
+#[test]
+#[should_panic(expected = "++ and -- need a variable, array element or field, not `1`")]
+fn test_pre_increment_needs_lvalue() {
+    actual!(_actual, "{ ++1 }");
+}
+
+#[test]
+fn test_inplace_assign() {
+    let ops = [
+        ("+=", MathOp::Plus),
+        ("-=", MathOp::Minus),
+        ("*=", MathOp::Star),
+        ("/=", MathOp::Slash),
+        ("%=", MathOp::Modulus),
+        ("^=", MathOp::Exponent),
+    ];
+    for (token, op) in ops {
+        let program = format!("{{ x {} 2; a[i] {} 1; $2 {} 3 }}", token, token, token);
+        actual!(actual, &program);
+
+        let x = btexpr!(Expr::Variable("x".into()));
+        let var = texpr!(Expr::Assign(
+            "x".into(),
+            btexpr!(Expr::MathOp(x, op, bnum!(2.0)))
+        ));
+
+        let indices = vec![texpr!(Expr::Variable("i".into()))];
+        let elem = btexpr!(Expr::ArrayIndex {
+            name: "a".to_string(),
+            path: vec![],
+            indices: indices.clone(),
+        });
+        let array = texpr!(Expr::ArrayAssign {
+            name: "a".to_string(),
+            path: vec![],
+            indices,
+            value: btexpr!(Expr::MathOp(elem, op, bnum!(1.0))),
+        });
+
+        let field = btexpr!(Expr::Column(bnum!(2.0)));
+        let column = texpr!(Expr::ColumnAssign {
+            index: bnum!(2.0),
+            value: btexpr!(Expr::MathOp(field, op, bnum!(3.0))),
+        });
+
+        assert_eq!(
+            actual,
+            sprogram!(Stmt::Group(vec![
+                Stmt::Expr(var),
+                Stmt::Expr(array),
+                Stmt::Expr(column)
+            ])),
+            "{}",
+            token
+        );
+    }
+}
+
+#[test]
+fn test_nested_array_index() {
+    actual!(actual, "{ a[\"x\"][1, 2][b] = a[\"x\"][1, 2][b] }");
+    let path = vec![
+        vec![texpr!(Expr::String("x".to_string()))],
+        vec![num!(1.0), num!(2.0)],
+    ];
+    let indices = vec![texpr!(Expr::Variable("b".into()))];
+    let read = texpr!(Expr::ArrayIndex {
+        name: "a".to_string(),
+        path: path.clone(),
+        indices: indices.clone(),
+    });
+    let assign = texpr!(Expr::ArrayAssign {
+        name: "a".to_string(),
+        path,
+        indices,
+        value: Box::new(read),
+    });
+    assert_eq!(actual, sprogram!(Stmt::Expr(assign)));
+}
+
+#[test]
+fn test_for_in() {
+    actual!(actual, "{ for (k in a[1]) { print k } for (k in b) { } }");
+    let print = print_stmt!(texpr!(Expr::Variable("k".into())));
+    let over_a = Stmt::ForIn {
+        var: "k".to_string(),
+        name: "a".to_string(),
+        path: vec![vec![num!(1.0)]],
+        body: Box::new(print),
+    };
+    let over_b = Stmt::ForIn {
+        var: "k".to_string(),
+        name: "b".to_string(),
+        path: vec![],
+        body: Box::new(Stmt::Group(vec![])),
+    };
+    assert_eq!(actual, sprogram!(Stmt::Group(vec![over_a, over_b])));
+}
+
+#[test]
+fn test_next() {
+    actual!(actual, "$1 == \"#\" { next; print \"skipped\" } { print }");
+    assert_eq!(
+        actual.pattern_actions[0].action,
+        Stmt::Group(vec![
+            Stmt::Next,
+            print_stmt!(texpr!(Expr::String("skipped".to_string())))
+        ])
+    );
+}
+
+#[test]
+fn test_exit() {
+    actual!(actual, "{ exit } END { exit 1 + 2 }");
+    assert_eq!(actual.pattern_actions[0].action, Stmt::Exit(None));
+    assert_eq!(
+        actual.ends,
+        vec![Stmt::Exit(Some(texpr!(Expr::MathOp(
+            bnum!(1.0),
+            MathOp::Plus,
+            bnum!(2.0)
+        ))))]
+    );
+}
+
+#[test]
+#[should_panic(expected = "next cannot be used in a BEGIN or END action")]
+fn test_next_in_begin() {
+    actual!(_actual, "BEGIN { if (1) { next } }");
+}
+
+#[test]
+#[should_panic(expected = "next cannot be used in a BEGIN or END action")]
+fn test_next_in_end() {
+    actual!(_actual, "END { next }");
+}
+
+#[test]
+fn test_break_continue() {
+    actual!(
+        actual,
+        "{ while (1) { if (x) break; for (k in a) continue } }"
+    );
+    let for_in = Stmt::ForIn {
+        var: "k".to_string(),
+        name: "a".to_string(),
+        path: vec![],
+        body: Box::new(Stmt::Continue),
+    };
+    let body = Stmt::Group(vec![
+        Stmt::If(
+            texpr!(Expr::Variable("x".into())),
+            Box::new(Stmt::Break),
+            None,
+        ),
+        for_in,
+    ]);
+    assert_eq!(actual, sprogram!(Stmt::While(num!(1.0), Box::new(body))));
+}
+
+#[test]
+fn test_do_while() {
+    actual!(actual, "{ do x = 1; while (y) do { break }\nwhile (z) }");
+    let var = |name: &str| texpr!(Expr::Variable(name.into()));
+    let assign = Stmt::Expr(texpr!(Expr::Assign("x".into(), bnum!(1.0))));
+    let expected = Stmt::Group(vec![
+        Stmt::DoWhile(Box::new(assign), var("y")),
+        Stmt::DoWhile(Box::new(Stmt::Break), var("z")),
+    ]);
+    assert_eq!(actual, sprogram!(expected));
+}
+
+#[test]
+#[should_panic(expected = "break must be inside a loop")]
+fn test_break_outside_loop() {
+    actual!(_actual, "{ while (x) { x = 0 } if (1) { break } }");
+}
+
+#[test]
+#[should_panic(expected = "continue must be inside a loop")]
+fn test_continue_outside_loop() {
+    actual!(_actual, "BEGIN { continue }");
+}
+
+#[test]
+fn test_bare_print() {
+    actual!(actual, "{ print }");
+    let record = TypedExpr::new_str(Expr::Column(Box::new(TypedExpr::new_num(Expr::NumberF64(
+        0.0,
+    )))));
+    assert_eq!(actual, sprogram!(print_stmt!(record)));
+}
+
+#[test]
+fn test_bare_print_redirect() {
+    actual!(actual, "{ print > \"f\" }");
+    let record = TypedExpr::new_str(Expr::Column(Box::new(TypedExpr::new_num(Expr::NumberF64(
+        0.0,
+    )))));
+    let print = Stmt::Print {
+        args: vec![record],
+        output: Some(Output::File(texpr!(Expr::String("f".to_string())))),
+    };
+    assert_eq!(actual, sprogram!(print));
+}
+
+#[test]
+fn test_print_append() {
+    actual!(actual, "{ print $1 >> \"f\"; print >> \"g\" }");
+    let record = TypedExpr::new_str(Expr::Column(Box::new(TypedExpr::new_num(Expr::NumberF64(
+        0.0,
+    )))));
+    let first = Stmt::Print {
+        args: vec![texpr!(Expr::Column(bnum!(1.0)))],
+        output: Some(Output::Append(texpr!(Expr::String("f".to_string())))),
+    };
+    let bare = Stmt::Print {
+        args: vec![record],
+        output: Some(Output::Append(texpr!(Expr::String("g".to_string())))),
+    };
+    assert_eq!(actual, sprogram!(Stmt::Group(vec![first, bare])));
+}
+
+#[test]
+fn test_print_redirect_is_not_comparison() {
+    actual!(actual, "{ print $1, (2 > 1) > \"f\" }");
+    let print = Stmt::Print {
+        args: vec![
+            texpr!(Expr::Column(bnum!(1.0))),
+            binop!(bnum!(2.0), BinOp::Greater, bnum!(1.0)),
+        ],
+        output: Some(Output::File(texpr!(Expr::String("f".to_string())))),
+    };
+    assert_eq!(actual, sprogram!(print));
+}
+
+#[test]
+fn test_printf_redirect() {
+    actual!(
+        actual,
+        "{ printf \"%d\\n\", a > b; printf(\"%d\\n\", a > b); printf(\"%d\\n\", n) | \"sort -n\" }"
+    );
+    let fmt = || texpr!(Expr::String("%d\n".to_string()));
+    let var = |name: &str| texpr!(Expr::Variable(name.into()));
+    let redirected = Stmt::Printf {
+        fmt: fmt(),
+        args: vec![var("a")],
+        output: Some(Output::File(var("b"))),
+    };
+    let comparison = Stmt::Printf {
+        fmt: fmt(),
+        args: vec![binop!(
+            Box::new(var("a")),
+            BinOp::Greater,
+            Box::new(var("b"))
+        )],
+        output: None,
+    };
+    let piped = Stmt::Printf {
+        fmt: fmt(),
+        args: vec![var("n")],
+        output: Some(Output::Pipe(texpr!(Expr::String("sort -n".to_string())))),
+    };
+    assert_eq!(
+        actual,
+        sprogram!(Stmt::Group(vec![redirected, comparison, piped]))
+    );
+}
+
+#[test]
+fn test_print_pipe() {
+    actual!(actual, "{ print $1, $2 | \"sort\" \" -r\" }");
+    let command = texpr!(Expr::Concatenation(vec![
+        texpr!(Expr::String("sort".to_string())),
+        texpr!(Expr::String(" -r".to_string())),
+    ]));
+    let print = Stmt::Print {
+        args: vec![
+            texpr!(Expr::Column(bnum!(1.0))),
+            texpr!(Expr::Column(bnum!(2.0))),
+        ],
+        output: Some(Output::Pipe(command)),
+    };
+    assert_eq!(actual, sprogram!(print));
+}
+
+#[test]
+fn test_coprocess() {
+    actual!(actual, "{ print $1 |& \"cat\"; \"cat\" |& getline x }");
+    let cat = || texpr!(Expr::String("cat".to_string()));
+    let print = Stmt::Print {
+        args: vec![texpr!(Expr::Column(bnum!(1.0)))],
+        output: Some(Output::Coprocess(cat())),
+    };
+    let getline = Stmt::Expr(TypedExpr::new_num(Expr::Getline {
+        into: Some(Lvalue::Variable("x".into())),
+        source: GetlineSource::Coprocess(Box::new(cat())),
+    }));
+    assert_eq!(actual, sprogram!(Stmt::Group(vec![print, getline])));
+}
+
+#[test]
+#[should_panic(expected = "|& is a gawk extension, not available with --posix")]
+fn test_coprocess_in_posix() {
+    use crate::lexer::lex;
+    parse_with_dialect(lex("{ \"cat\" |& getline }").unwrap(), Dialect::Posix).unwrap();
+}
+
+#[test]
+fn test_getline_file() {
+    actual!(actual, "BEGIN { getline < \"f\"; getline line < \"f\" }");
+    let file = || GetlineSource::File(btexpr!(Expr::String("f".to_string())));
+    let begins = vec![Stmt::Group(vec![
+        Stmt::Expr(TypedExpr::new_num(Expr::Getline {
+            into: None,
+            source: file(),
+        })),
+        Stmt::Expr(TypedExpr::new_num(Expr::Getline {
+            into: Some(Lvalue::Variable("line".into())),
+            source: file(),
+        })),
+    ])];
+    assert_eq!(actual, Program::new(begins, vec![], vec![]));
+}
+
+#[test]
+fn test_getline_var() {
+    actual!(actual, "{ getline; getline line }");
+    let expected = Stmt::Group(vec![
+        Stmt::Expr(TypedExpr::new_num(Expr::Getline {
+            into: None,
+            source: GetlineSource::MainInput,
+        })),
+        Stmt::Expr(TypedExpr::new_num(Expr::Getline {
+            into: Some(Lvalue::Variable("line".into())),
+            source: GetlineSource::MainInput,
+        })),
+    ]);
+    assert_eq!(actual, sprogram!(expected));
+}
+
+#[test]
+fn test_getline_lvalues() {
+    actual!(
+        actual,
+        "{ getline a[1]; getline b[1][2] < \"f\"; \"cmd\" | getline $2 }"
+    );
+    let expected = Stmt::Group(vec![
+        Stmt::Expr(TypedExpr::new_num(Expr::Getline {
+            into: Some(Lvalue::ArrayIndex {
+                name: "a".to_string(),
+                path: vec![],
+                indices: vec![num!(1.0)],
+            }),
+            source: GetlineSource::MainInput,
+        })),
+        Stmt::Expr(TypedExpr::new_num(Expr::Getline {
+            into: Some(Lvalue::ArrayIndex {
+                name: "b".to_string(),
+                path: vec![vec![num!(1.0)]],
+                indices: vec![num!(2.0)],
+            }),
+            source: GetlineSource::File(btexpr!(Expr::String("f".to_string()))),
+        })),
+        Stmt::Expr(TypedExpr::new_num(Expr::Getline {
+            into: Some(Lvalue::Column(bnum!(2.0))),
+            source: GetlineSource::Command(btexpr!(Expr::String("cmd".to_string()))),
+        })),
+    ]);
+    assert_eq!(actual, sprogram!(expected));
+}
+
+#[test]
+fn test_getline_file_loop() {
+    actual!(
+        actual,
+        "{ while ((getline line < \"f\") > 0) { print line } }"
+    );
+    let getline = TypedExpr::new_num(Expr::Getline {
+        into: Some(Lvalue::Variable("line".into())),
+        source: GetlineSource::File(btexpr!(Expr::String("f".to_string()))),
+    });
+    let test = binop!(Box::new(getline), BinOp::Greater, bnum!(0.0));
+    let body = print_stmt!(texpr!(Expr::Variable("line".into())));
+    assert_eq!(actual, sprogram!(Stmt::While(test, Box::new(body))));
+}
+
+#[test]
+fn test_getline_file_compare_precedence() {
+    let expected = |prog: &str| {
+        actual!(actual, prog);
+        actual
+    };
+    let getline = TypedExpr::new_num(Expr::Getline {
+        into: Some(Lvalue::Variable("line".into())),
+        source: GetlineSource::File(btexpr!(Expr::Column(bnum!(1.0)))),
+    });
+    let test = binop!(Box::new(getline), BinOp::Greater, bnum!(0.0));
+    let body = print_stmt!(texpr!(Expr::Variable("line".into())));
+    let ast = sprogram!(Stmt::While(test, Box::new(body)));
+    assert_eq!(
+        expected("{ while ((getline line < $1) > 0) { print line } }"),
+        ast
+    );
+    assert_eq!(
+        expected("{ while (getline line < $1 > 0) { print line } }"),
+        ast
+    );
+}
+
+#[test]
+fn test_getline_file_concatenated_name() {
+    actual!(actual, "{ getline < (\"a\" \"b\") }");
+    let name = texpr!(Expr::Concatenation(vec![
+        texpr!(Expr::String("a".to_string())),
+        texpr!(Expr::String("b".to_string())),
+    ]));
+    let expected = TypedExpr::new_num(Expr::Getline {
+        into: None,
+        source: GetlineSource::File(Box::new(name)),
+    });
+    assert_eq!(actual, sprogram!(Stmt::Expr(expected)));
+}
+
+#[test]
+#[should_panic(expected = "Use getline < (a b)")]
+fn test_getline_file_ambiguous_concat() {
+    actual!(_actual, "{ getline < \"a\" \"b\" }");
+}
+
+#[test]
+fn test_call_close() {
+    actual!(actual, "{ close(\"out.txt\") }");
+    let call = Stmt::Expr(texpr!(Expr::Call {
+        target: "close".to_string(),
+        args: vec![texpr!(Expr::String("out.txt".to_string()))],
+    }));
+    assert_eq!(actual, sprogram!(call));
+}
+
+#[test]
+fn test_call_system() {
+    actual!(actual, "{ system(\"ls\") }");
+    let call = Stmt::Expr(texpr!(Expr::Call {
+        target: "system".to_string(),
+        args: vec![texpr!(Expr::String("ls".to_string()))],
+    }));
+    assert_eq!(actual, sprogram!(call));
+}
+
+#[test]
+#[should_panic]
+fn test_call_system_no_args() {
+    actual!(_actual, "{ system() }");
+}
+
+#[test]
+fn test_call_rand_srand() {
+    actual!(actual, "{ rand(); srand(); srand(42) }");
+    let call = |target: &str, args: Vec<TypedExpr>| {
+        Stmt::Expr(texpr!(Expr::Call {
+            target: target.to_string(),
+            args,
+        }))
+    };
+    let expected = Stmt::Group(vec![
+        call("rand", vec![]),
+        call("srand", vec![]),
+        call("srand", vec![num!(42.0)]),
+    ]);
+    assert_eq!(actual, sprogram!(expected));
+}
+
+#[test]
+#[should_panic]
+fn test_call_rand_arity() {
+    actual!(_actual, "{ rand(1) }");
+}
+
+#[test]
+fn test_call_toupper_tolower() {
+    actual!(actual, "{ toupper($1); tolower(\"A\") }");
+    let call = |target: &str, arg: TypedExpr| {
+        Stmt::Expr(texpr!(Expr::Call {
+            target: target.to_string(),
+            args: vec![arg],
+        }))
+    };
+    let expected = Stmt::Group(vec![
+        call("toupper", texpr!(Expr::Column(bnum!(1.0)))),
+        call("tolower", texpr!(Expr::String("A".to_string()))),
+    ]);
+    assert_eq!(actual, sprogram!(expected));
+}
+
+#[test]
+#[should_panic]
+fn test_call_toupper_no_args() {
+    actual!(_actual, "{ toupper() }");
+}
+
+#[test]
+#[should_panic]
+fn test_call_tolower_two_args() {
+    actual!(_actual, "{ tolower(\"a\", \"b\") }");
+}
+
+#[test]
+fn test_getline_command() {
+    actual!(actual, "{ \"cmd\" | getline; \"a\" \"b\" | getline x }");
+    let cmd = texpr!(Expr::String("cmd".to_string()));
+    let a_concat_b = texpr!(Expr::Concatenation(vec![
+        texpr!(Expr::String("a".to_string())),
+        texpr!(Expr::String("b".to_string())),
+    ]));
+    let expected = Stmt::Group(vec![
+        Stmt::Expr(TypedExpr::new_num(Expr::Getline {
+            into: None,
+            source: GetlineSource::Command(Box::new(cmd)),
+        })),
+        Stmt::Expr(TypedExpr::new_num(Expr::Getline {
+            into: Some(Lvalue::Variable("x".into())),
+            source: GetlineSource::Command(Box::new(a_concat_b)),
+        })),
+    ]);
+    assert_eq!(actual, sprogram!(expected));
+}
+
+#[test]
+fn test_getline_command_compare() {
+    actual!(actual, "{ \"cmd\" | getline > 0 }");
+    let getline = TypedExpr::new_num(Expr::Getline {
+        into: None,
+        source: GetlineSource::Command(btexpr!(Expr::String("cmd".to_string()))),
+    });
+    let expected = binop!(Box::new(getline), BinOp::Greater, bnum!(0.0));
+    assert_eq!(actual, sprogram!(Stmt::Expr(expected)));
+}
+
+#[test]
+fn test_getline_command_compare_parenthesized() {
+    actual!(
+        actual,
+        "{ (\"cmd\" | getline line) > 0; \"cmd\" | getline line > 0 }"
+    );
+    let compare = || {
+        let getline = TypedExpr::new_num(Expr::Getline {
+            into: Some(Lvalue::Variable("line".into())),
+            source: GetlineSource::Command(btexpr!(Expr::String("cmd".to_string()))),
+        });
+        Stmt::Expr(binop!(Box::new(getline), BinOp::Greater, bnum!(0.0)))
+    };
+    assert_eq!(actual, sprogram!(Stmt::Group(vec![compare(), compare()])));
+}
+
+#[test]
+fn test_parse_reports_every_error() {
+    use crate::lexer::lex;
+    let errors = parse(
+        lex("BEGIN { x = ; print 1 }\n{ print (1 }\nEND { if (1) { break }; print 2 }").unwrap(),
+    )
+    .unwrap_err();
+    let messages: Vec<&str> = errors.iter().map(|err| err.message.as_str()).collect();
+    assert_eq!(messages.len(), 3, "{:?}", messages);
+    assert!(messages[0].starts_with("Unexpected token Semicolon"));
+    assert!(messages[1].contains("didn't find a RightParen"));
+    assert_eq!(messages[2], "break must be inside a loop");
+}
+
+#[test]
+fn test_parse_recovers_after_a_pattern() {
+    use crate::lexer::lex;
+    let errors =
+        parse(lex("$1 == ) { print 1 }\n{ print 2; x = * 3; print 4 }").unwrap()).unwrap_err();
+    let messages: Vec<&str> = errors.iter().map(|err| err.message.as_str()).collect();
+    assert_eq!(messages.len(), 2, "{:?}", messages);
+    assert!(messages[0].starts_with("Unexpected token RightParen"));
+    assert!(messages[1].starts_with("Unexpected token MathOp(Star)"));
+}
+
+#[test]
+fn test_parse_recovers_inside_blocks() {
+    use crate::lexer::lex;
+    let program = "{ while (1) { x = ; break }\nif (1) { print ( } }\n{ break }";
+    let errors = parse(lex(program).unwrap()).unwrap_err();
+    let messages: Vec<&str> = errors.iter().map(|err| err.message.as_str()).collect();
+    assert_eq!(messages.len(), 3, "{:?}", messages);
+    assert!(messages[0].starts_with("Unexpected token Semicolon"));
+    assert!(messages[1].starts_with("Unexpected token RightBrace"));
+    assert_eq!(messages[2], "break must be inside a loop");
+}
+
+#[test]
+fn test_parse_without_errors() {
+    use crate::lexer::lex;
+    let program = parse(lex("{ print 1 }").unwrap());
+    assert_eq!(program, Ok(sprogram!(print_stmt!(num!(1.0)))));
+}
+
+#[test]
+fn test_ternary() {
+    actual!(actual, "{ x = n == 1 ? \"item\" : \"items\" }");
+    let n = btexpr!(Expr::Variable("n".into()));
+    let test = btexpr!(Expr::BinOp(n, BinOp::EqEq, bnum!(1.0)));
+    let ternary = btexpr!(Expr::Ternary(
+        test,
+        btexpr!(Expr::String("item".to_string())),
+        btexpr!(Expr::String("items".to_string())),
+    ));
+    let assign = Stmt::Expr(texpr!(Expr::Assign("x".into(), ternary)));
+    assert_eq!(actual, sprogram!(assign));
+}
+
+#[test]
+fn test_nested_ternary_is_right_associative() {
+    actual!(actual, "{ print a ? b : c ? d : e }");
+    let var = |name: &str| btexpr!(Expr::Variable(name.into()));
+    let inner = btexpr!(Expr::Ternary(var("c"), var("d"), var("e")));
+    let outer = texpr!(Expr::Ternary(var("a"), var("b"), inner));
+    assert_eq!(actual, sprogram!(print_stmt!(outer)));
+}
+
+#[test]
+#[should_panic(expected = "print must be inside an action, eg. { print ... }")]
+fn test_print_outside_action() {
+    actual!(_actual, "BEGIN { x = 1 }\nprint 1");
+}
+
+#[test]
+fn test_statements_outside_actions() {
+    use crate::lexer::lex;
+    let errors =
+        parse(lex("if (x) { print }\nNR == 1\nwhile (1) { }\n{ print }").unwrap()).unwrap_err();
+    let messages: Vec<&str> = errors.iter().map(|err| err.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "if must be inside an action, eg. { if ... }",
+            "while must be inside an action, eg. { while ... }"
+        ]
+    );
+}
+
+#[test]
+fn test_empty_statements() {
+    actual!(actual, "{ ; ; print 1 ; ;; }");
+    assert_eq!(actual, sprogram!(print_stmt!(num!(1.0))));
+}
+
+#[test]
+fn test_empty_if_body() {
+    actual!(actual, "{ if (x) ; else print 1 }");
+    let expected = Stmt::If(
+        texpr!(Expr::Variable("x".into())),
+        Box::new(Stmt::Group(vec![])),
+        Some(Box::new(print_stmt!(num!(1.0)))),
+    );
+    assert_eq!(actual, sprogram!(expected));
+}
+
+#[test]
+fn test_empty_while_body() {
+    actual!(actual, "{ while ((getline line) > 0) ; }");
+    let getline = TypedExpr::new_num(Expr::Getline {
+        into: Some(Lvalue::Variable("line".into())),
+        source: GetlineSource::MainInput,
+    });
+    let expected = Stmt::While(
+        binop!(Box::new(getline), BinOp::Greater, bnum!(0.0)),
+        Box::new(Stmt::Group(vec![])),
+    );
+    assert_eq!(actual, sprogram!(expected));
+}
+
+#[test]
+fn test_empty_for_body() {
+    actual!(actual, "{ for (;;) ; }");
+    let expected = Stmt::For {
+        init: Box::new(Stmt::Group(vec![])),
+        test: TypedExpr::new_num(Expr::NumberF64(1.0)),
+        incr: Box::new(Stmt::Group(vec![])),
+        body: Box::new(Stmt::Group(vec![])),
+    };
+    assert_eq!(actual, sprogram!(expected));
+}
+
+#[test]
+fn test_for_without_clauses() {
+    actual!(actual, "{ for (;;) break }");
+    let expected = Stmt::For {
+        init: Box::new(Stmt::Group(vec![])),
+        test: TypedExpr::new_num(Expr::NumberF64(1.0)),
+        incr: Box::new(Stmt::Group(vec![])),
+        body: Box::new(Stmt::Break),
+    };
+    assert_eq!(actual, sprogram!(expected));
+}
+
+#[test]
+fn test_for_without_incr() {
+    actual!(actual, "{ for (i = 0; i < n;) i = i + 1 }");
+    let i = || btexpr!(Expr::Variable("i".into()));
+    let n = btexpr!(Expr::Variable("n".into()));
+    let i_plus_1 = btexpr!(Expr::MathOp(i(), MathOp::Plus, bnum!(1.0)));
+    let expected = Stmt::For {
+        init: Box::new(Stmt::Expr(texpr!(Expr::Assign("i".into(), bnum!(0.0))))),
+        test: binop!(i(), BinOp::Less, n),
+        incr: Box::new(Stmt::Group(vec![])),
+        body: Box::new(Stmt::Expr(texpr!(Expr::Assign("i".into(), i_plus_1)))),
+    };
+    assert_eq!(actual, sprogram!(expected));
+}
+
+#[test]
+fn test_empty_actions() {
+    use crate::lexer::lex;
+    for program in ["{ }", "{}", "{\n}", "{ ; }"] {
+        assert_eq!(
+            parse(lex(program).unwrap()).unwrap(),
+            sprogram!(Stmt::Group(vec![])),
+            "{}",
+            program
+        );
+    }
+    let test = binop!(
+        btexpr!(Expr::Variable("NR".into())),
+        BinOp::EqEq,
+        bnum!(1.0)
+    );
+    let expected = Program::new(
+        vec![Stmt::Group(vec![])],
+        vec![Stmt::Group(vec![])],
+        vec![PatternAction::new(Some(test), Stmt::Group(vec![]))],
+    );
+    assert_eq!(
+        parse(lex("BEGIN { } NR == 1 { } END {}").unwrap()).unwrap(),
+        expected
+    );
+}
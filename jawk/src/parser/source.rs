@@ -0,0 +1,387 @@
+use crate::parser::{Expr, GetlineSource, Lvalue, Output, Pattern, Program, Stmt, TypedExpr};
+
+// Prints a program back out as awk. Display shows the tree with its types for debugging, this is
+// canonical source instead: parsing it gives back the same tree, apart from the types.
+// Every compound expression is parenthesized so precedence never has to be worked out again.
+
+impl Program {
+    pub fn to_source(&self) -> String {
+        let mut source = Source {
+            out: String::new(),
+            indent: 0,
+        };
+        for begin in &self.begins {
+            source.out.push_str("BEGIN ");
+            source.block(begin);
+            source.out.push('\n');
+        }
+        for pattern_action in &self.pattern_actions {
+            match &pattern_action.pattern {
+                Some(Pattern::Expr(test)) => {
+                    source.out.push_str(&bare(test));
+                    source.out.push(' ');
+                }
+                Some(Pattern::Range(start, end)) => {
+                    source
+                        .out
+                        .push_str(&format!("{}, {} ", bare(start), bare(end)));
+                }
+                None => {}
+            }
+            source.block(&pattern_action.action);
+            source.out.push('\n');
+        }
+        for end in &self.ends {
+            source.out.push_str("END ");
+            source.block(end);
+            source.out.push('\n');
+        }
+        source.out
+    }
+}
+
+struct Source {
+    out: String,
+    indent: usize,
+}
+
+impl Source {
+    // { stmts } for the body of an action, if, or loop. The parser turns a body of one statement
+    // into that statement and anything else into a group so the group's braces are these.
+    fn block(&mut self, body: &Stmt) {
+        self.out.push_str("{\n");
+        self.indent += 1;
+        match body {
+            Stmt::Group(stmts) => {
+                for stmt in stmts {
+                    self.stmt(stmt);
+                }
+            }
+            stmt => self.stmt(stmt),
+        }
+        self.indent -= 1;
+        self.line_start();
+        self.out.push('}');
+    }
+
+    fn line_start(&mut self) {
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        self.line_start();
+        self.stmt_inline(stmt);
+        self.out.push('\n');
+    }
+
+    // The > file, >> file, | cmd or |& cmd after print or printf's arguments
+    fn redirect(&mut self, output: &Output) {
+        let (redirect, target) = match output {
+            Output::File(target) => (">", target),
+            Output::Append(target) => (">>", target),
+            Output::Pipe(target) => ("|", target),
+            Output::Coprocess(target) => ("|&", target),
+        };
+        self.out
+            .push_str(&format!(" {} {}", redirect, expr(target)));
+    }
+
+    // The init or incr of a for loop, which may have been left out
+    fn for_clause(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Group(stmts) if stmts.is_empty() => {}
+            stmt => self.stmt_inline(stmt),
+        }
+    }
+
+    // A statement from the current position without the line around it
+    fn stmt_inline(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(expr) => self.out.push_str(&bare(expr)),
+            Stmt::Print { args, output } => {
+                self.out.push_str("print");
+                // Parenthesized so > in an argument isn't taken for a redirect
+                if !is_record(args) {
+                    let args = args.iter().map(expr).collect::<Vec<String>>();
+                    self.out.push(' ');
+                    self.out.push_str(&args.join(", "));
+                }
+                if let Some(output) = output {
+                    self.redirect(output);
+                }
+            }
+            Stmt::Printf { fmt, args, output } => {
+                self.out.push_str("printf ");
+                self.out.push_str(&expr(fmt));
+                for arg in args {
+                    self.out.push_str(", ");
+                    self.out.push_str(&expr(arg));
+                }
+                if let Some(output) = output {
+                    self.redirect(output);
+                }
+            }
+            Stmt::Group(_) => self.block(stmt),
+            Stmt::If(test, if_so, if_not) => {
+                self.out.push_str(&format!("if ({}) ", bare(test)));
+                self.block(if_so);
+                match if_not.as_deref() {
+                    Some(else_if @ Stmt::If(..)) => {
+                        self.out.push_str(" else ");
+                        self.stmt_inline(else_if);
+                    }
+                    Some(if_not) => {
+                        self.out.push_str(" else ");
+                        self.block(if_not);
+                    }
+                    None => {}
+                }
+            }
+            Stmt::While(test, body) => {
+                self.out.push_str(&format!("while ({}) ", bare(test)));
+                self.block(body);
+            }
+            Stmt::DoWhile(body, test) => {
+                self.out.push_str("do ");
+                self.block(body);
+                self.out.push_str(&format!(" while ({})", bare(test)));
+            }
+            Stmt::For {
+                init,
+                test,
+                incr,
+                body,
+            } => {
+                self.out.push_str("for (");
+                self.for_clause(init);
+                self.out.push_str(&format!("; {}; ", bare(test)));
+                self.for_clause(incr);
+                self.out.push_str(") ");
+                self.block(body);
+            }
+            Stmt::ForIn {
+                var,
+                name,
+                path,
+                body,
+            } => {
+                self.out.push_str(&format!(
+                    "for ({} in {}) ",
+                    var,
+                    subscripts(name, path, &[])
+                ));
+                self.block(body);
+            }
+            Stmt::Delete {
+                name,
+                path,
+                indices,
+            } => self
+                .out
+                .push_str(&format!("delete {}", subscripts(name, path, indices))),
+            Stmt::Next => self.out.push_str("next"),
+            Stmt::Break => self.out.push_str("break"),
+            Stmt::Continue => self.out.push_str("continue"),
+            Stmt::Exit(status) => {
+                self.out.push_str("exit");
+                if let Some(status) = status {
+                    self.out.push(' ');
+                    self.out.push_str(&bare(status));
+                }
+            }
+            Stmt::Main(body) => self.stmt_inline(body),
+        }
+    }
+}
+
+// print with no arguments prints $0
+fn is_record(args: &[TypedExpr]) -> bool {
+    match args {
+        [arg] => match &arg.expr {
+            Expr::Column(index) => index.expr == Expr::NumberF64(0.0),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn list(exprs: &[TypedExpr]) -> String {
+    exprs.iter().map(bare).collect::<Vec<String>>().join(", ")
+}
+
+// An expression that can be used anywhere, parenthesized unless it is a single thing
+fn expr(expr: &TypedExpr) -> String {
+    let atomic = match &expr.expr {
+        Expr::NumberF64(num) => *num >= 0.0,
+        Expr::String(_)
+        | Expr::StrNum(..)
+        | Expr::Regex(_)
+        | Expr::Variable(_)
+        | Expr::ArrayIndex { .. }
+        | Expr::Call { .. }
+        | Expr::Column(_) => true,
+        _ => false,
+    };
+    if atomic {
+        bare(expr)
+    } else {
+        format!("({})", bare(expr))
+    }
+}
+
+// An expression without parens around it, for where nothing else competes for its operands
+fn bare(typed: &TypedExpr) -> String {
+    match &typed.expr {
+        // There is no unary minus
+        Expr::NumberF64(num) if *num < 0.0 => format!("0 - {}", -num),
+        Expr::NumberF64(num) => format!("{}", num),
+        // Awk source has no strnum literal
+        Expr::String(string) | Expr::StrNum(string, _) => string_literal(string),
+        Expr::Regex(regex) => format!("/{}/", regex.replace('/', "\\/")),
+        Expr::Concatenation(vals) => vals.iter().map(expr).collect::<Vec<String>>().join(" "),
+        Expr::BinOp(left, op, right) => format!("{} {} {}", expr(left), op, expr(right)),
+        Expr::MathOp(left, op, right) => format!("{} {} {}", expr(left), op, expr(right)),
+        Expr::LogicalOp(left, op, right) => format!("{} {} {}", expr(left), op, expr(right)),
+        Expr::Not(value) => format!("!{}", expr(value)),
+        Expr::Ternary(test, if_so, if_not) => {
+            format!("{} ? {} : {}", expr(test), expr(if_so), expr(if_not))
+        }
+        Expr::Assign(var, value) => format!("{} = {}", var, expr(value)),
+        Expr::Variable(var) => var.to_string(),
+        Expr::Column(index) => column(index),
+        Expr::ColumnAssign { index, value } => format!("{} = {}", column(index), expr(value)),
+        Expr::NfAssign(value) => format!("NF = {}", expr(value)),
+        Expr::NextLine => "getline".to_string(),
+        Expr::Getline { into, source } => {
+            let into = match into {
+                Some(Lvalue::Variable(var)) => format!(" {}", var),
+                Some(Lvalue::ArrayIndex {
+                    name,
+                    path,
+                    indices,
+                }) => format!(" {}", subscripts(name, path, indices)),
+                Some(Lvalue::Column(index)) => format!(" {}", column(index)),
+                None => String::new(),
+            };
+            match source {
+                GetlineSource::MainInput => format!("getline{}", into),
+                GetlineSource::File(file) => format!("getline{} < {}", into, expr(file)),
+                GetlineSource::Command(command) => format!("{} | getline{}", expr(command), into),
+                GetlineSource::Coprocess(command) => {
+                    format!("{} |& getline{}", expr(command), into)
+                }
+            }
+        }
+        Expr::ArrayIndex {
+            name,
+            path,
+            indices,
+        } => subscripts(name, path, indices),
+        Expr::ArrayAssign {
+            name,
+            path,
+            indices,
+            value,
+        } => format!("{} = {}", subscripts(name, path, indices), expr(value)),
+        Expr::InArray {
+            indices,
+            name,
+            path,
+        } => format!("({}) in {}", list(indices), subscripts(name, path, &[])),
+        Expr::Call { target, args } => format!("{}({})", target, list(args)),
+    }
+}
+
+fn column(index: &TypedExpr) -> String {
+    match index.expr {
+        Expr::NumberF64(num) if num >= 0.0 && num.fract() == 0.0 => format!("${}", num),
+        _ => format!("$({})", bare(index)),
+    }
+}
+
+fn subscripts(name: &str, path: &[Vec<TypedExpr>], indices: &[TypedExpr]) -> String {
+    let mut out = name.to_string();
+    for group in path.iter().map(|g| g.as_slice()).chain([indices]) {
+        if !group.is_empty() {
+            out.push_str(&format!("[{}]", list(group)));
+        }
+    }
+    out
+}
+
+// Quoted with the escapes the lexer decodes
+fn string_literal(string: &str) -> String {
+    let mut out = String::from("\"");
+    for c in string.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_ascii_control() => out.push_str(&format!("\\{:03o}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::lex;
+    use crate::parser::{parse, walk_expr_mut, walk_program_mut, AwkT, TypedExpr, VisitorMut};
+
+    // The parser gives a few expressions known types up front, the source doesn't say
+    struct ClearTypes {}
+
+    impl VisitorMut for ClearTypes {
+        fn visit_expr(&mut self, expr: &mut TypedExpr) {
+            walk_expr_mut(self, expr);
+            expr.typ = AwkT::Variable;
+        }
+    }
+
+    fn round_trip(program: &str) {
+        let mut parsed = parse(lex(program).unwrap()).unwrap();
+        let source = parsed.to_source();
+        let mut reparsed = parse(lex(&source).unwrap()).unwrap();
+        walk_program_mut(&mut ClearTypes {}, &mut parsed);
+        walk_program_mut(&mut ClearTypes {}, &mut reparsed);
+        assert_eq!(parsed, reparsed, "source was:\n{}", source);
+        // Canonical, printing it again changes nothing
+        assert_eq!(reparsed.to_source(), source);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        round_trip(
+            "BEGIN { x = 1 + 2 * 3 ^ 2 ^ 2; y = \"a\\\"\\n\\\\\" x; a[1, \"k\"] = !y < 3 }
+            $1 > 2 && NR % 2 || /a\\/b/ { print $1, ($2 > 1) > \"out\"; print; $(NF - 1) = \"z\" }
+            NR == 1, NR == 3 { printf \"%s-%d\\n\", $0, NR }
+            { printf \"%d\\n\", a > b; printf(\"%d\\n\", a > b) | \"sort -n\"; printf \"x\" >> \"f\" }
+            { for (i = 1; i <= NF; i++) { s = s $i } }
+            { for (k in a) { if (k == 1) next; else if (k) { exit 2 } else print k } }
+            { while (1) { if (x++ > 3) break; else continue } do { x-- } while (x) }
+            { for (;;) { break } for (; i < 3; ) { i++ } }
+            { while ((getline line < \"f\") > 0) { n++ } \"cmd\" | getline $2; getline; x -= 3 }
+            { b[1][2] += toupper(sprintf(\"%d\", rand())); x = y ? z = 1 : w ? \"a\" : \"b\" }
+            NR == 9 { }
+            $1 ~ /x/ || $2 !~ \"y\" { print (k in a) + ((1, k) in b[2]), !(k in a) }
+            { NF = 2; NF++; print (NF -= 1) }
+            { delete a[$1, 2]; delete b[1][k]; delete a }
+            END { print s >> \"log\"; exit }",
+        );
+    }
+
+    #[test]
+    fn test_getline_forms_round_trip() {
+        round_trip(
+            "{ r = getline; r = getline x; r = getline < \"f\"; r = getline a[1] < \"f\" }
+            { r = \"cmd\" | getline; r = \"cmd\" | getline $NF; print r }
+            { print r |& \"cmd\"; r = \"cmd\" |& getline; \"cmd\" |& getline a[1] }",
+        );
+    }
+}
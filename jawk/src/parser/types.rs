@@ -1,4 +1,4 @@
-use crate::lexer::{BinOp, LogicalOp, MathOp};
+use crate::lexer::{BinOp, LogicalOp, MathOp, Symbol};
 use std::fmt::{Display, Formatter};
 
 #[derive(PartialEq, Clone, Copy, Debug)]
@@ -8,20 +8,121 @@ pub enum AwkT {
     Variable,
 }
 
+// Where print output goes, eg. print a > "file"
+#[derive(Debug, PartialEq)]
+pub enum Output {
+    File(TypedExpr),
+    Append(TypedExpr),
+    Pipe(TypedExpr),
+    // print a |& cmd, a gawk coprocess whose output cmd |& getline reads
+    Coprocess(TypedExpr),
+}
+
+impl Output {
+    pub fn target(&self) -> &TypedExpr {
+        match self {
+            Output::File(target)
+            | Output::Append(target)
+            | Output::Pipe(target)
+            | Output::Coprocess(target) => target,
+        }
+    }
+    pub fn target_mut(&mut self) -> &mut TypedExpr {
+        match self {
+            Output::File(target)
+            | Output::Append(target)
+            | Output::Pipe(target)
+            | Output::Coprocess(target) => target,
+        }
+    }
+}
+
+impl Display for Output {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Output::File(target) => write!(f, " > {}", target),
+            Output::Append(target) => write!(f, " >> {}", target),
+            Output::Pipe(target) => write!(f, " | {}", target),
+            Output::Coprocess(target) => write!(f, " |& {}", target),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum Stmt {
     Expr(TypedExpr),
-    Print(TypedExpr),
+    Print {
+        args: Vec<TypedExpr>,
+        output: Option<Output>,
+    },
+    Printf {
+        fmt: TypedExpr,
+        args: Vec<TypedExpr>,
+        output: Option<Output>,
+    },
     Group(Vec<Stmt>),
     If(TypedExpr, Box<Stmt>, Option<Box<Stmt>>),
     While(TypedExpr, Box<Stmt>),
+    // do body while (test), the body runs once before the test
+    DoWhile(Box<Stmt>, TypedExpr),
+    // for (init; test; incr) body. Not a While so `continue` can still run incr.
+    For {
+        init: Box<Stmt>,
+        test: TypedExpr,
+        incr: Box<Stmt>,
+        body: Box<Stmt>,
+    },
+    // for (var in name[path...]) body
+    ForIn {
+        var: String,
+        name: String,
+        path: Vec<Vec<TypedExpr>>,
+        body: Box<Stmt>,
+    },
+    // delete name[path...][indices] removes one element. Without any indices, delete name, it
+    // empties the whole array.
+    Delete {
+        name: String,
+        path: Vec<Vec<TypedExpr>>,
+        indices: Vec<TypedExpr>,
+    },
+    // Stop this record's actions and start on the next record
+    Next,
+    // Leave the innermost loop
+    Break,
+    // Go on to the innermost loop's next iteration
+    Continue,
+    // Stop reading records and run the END actions, or stop everything when already in END.
+    // The optional expression is the exit status.
+    Exit(Option<TypedExpr>),
+    // Made by the transformer around everything before END so `exit` knows where END starts.
+    // It isn't part of the program's text so it prints as just its body.
+    Main(Box<Stmt>),
 }
 
 impl Display for Stmt {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Stmt::Expr(expr) => write!(f, "{}", expr)?,
-            Stmt::Print(expr) => write!(f, "print {}", expr)?,
+            Stmt::Print { args, output } => {
+                let args = args
+                    .iter()
+                    .map(|a| format!("{}", a))
+                    .collect::<Vec<String>>();
+                write!(f, "print {}", args.join(", "))?;
+                if let Some(output) = output {
+                    write!(f, "{}", output)?;
+                }
+            }
+            Stmt::Printf { fmt, args, output } => {
+                write!(f, "printf {}", fmt)?;
+                for arg in args {
+                    write!(f, ", {}", arg)?;
+                }
+                if let Some(output) = output {
+                    write!(f, "{}", output)?;
+                }
+            }
             Stmt::Group(group) => {
                 for elem in group {
                     write!(f, "{}", elem)?;
@@ -36,32 +137,103 @@ impl Display for Stmt {
             Stmt::While(test, body) => {
                 write!(f, "while {} {{{}}} ", test, body)?;
             }
+            Stmt::DoWhile(body, test) => {
+                write!(f, "do {{{}}} while {} ", body, test)?;
+            }
+            Stmt::For {
+                init,
+                test,
+                incr,
+                body,
+            } => {
+                // Statements print with a newline after them
+                write!(
+                    f,
+                    "for ({}; {}; {}) {{{}}} ",
+                    init.to_string().trim_end(),
+                    test,
+                    incr.to_string().trim_end(),
+                    body
+                )?;
+            }
+            Stmt::ForIn {
+                var,
+                name,
+                path,
+                body,
+            } => {
+                write!(
+                    f,
+                    "for ({} in {}) {{{}}} ",
+                    var,
+                    subscripts(name, path, &[]),
+                    body
+                )?;
+            }
+            Stmt::Delete {
+                name,
+                path,
+                indices,
+            } => write!(f, "delete {}", subscripts(name, path, indices))?,
+            Stmt::Next => write!(f, "next")?,
+            Stmt::Break => write!(f, "break")?,
+            Stmt::Continue => write!(f, "continue")?,
+            Stmt::Exit(status) => {
+                write!(f, "exit")?;
+                if let Some(status) = status {
+                    write!(f, " {}", status)?;
+                }
+            }
+            Stmt::Main(body) => write!(f, "{}", body)?,
         };
         write!(f, "\n")
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub enum Pattern {
+    Expr(TypedExpr),
+    // start, end { ... } runs from a record matching start through the next one matching end
+    Range(TypedExpr, TypedExpr),
+}
+
 #[derive(Debug, PartialEq)]
 pub struct PatternAction {
-    pub pattern: Option<TypedExpr>,
+    pub pattern: Option<Pattern>,
     pub action: Stmt,
 }
 
 impl PatternAction {
     pub fn new(pattern: Option<TypedExpr>, action: Stmt) -> Self {
-        Self { pattern, action }
+        Self {
+            pattern: pattern.map(Pattern::Expr),
+            action,
+        }
+    }
+    pub fn new_range(start: TypedExpr, end: TypedExpr, action: Stmt) -> Self {
+        Self {
+            pattern: Some(Pattern::Range(start, end)),
+            action,
+        }
     }
     pub fn new_pattern_only(test: TypedExpr) -> PatternAction {
-        PatternAction::new(
-            Some(test),
-            Stmt::Print(TypedExpr::new_str(Expr::Column(Box::new(
-                TypedExpr::new_num(Expr::NumberF64(0.0)),
-            )))),
-        )
+        PatternAction::new(Some(test), PatternAction::print_line())
+    }
+    pub fn new_range_only(start: TypedExpr, end: TypedExpr) -> PatternAction {
+        PatternAction::new_range(start, end, PatternAction::print_line())
     }
     pub fn new_action_only(body: Stmt) -> PatternAction {
         PatternAction::new(None, body)
     }
+    // A pattern without an action prints the record
+    fn print_line() -> Stmt {
+        Stmt::Print {
+            args: vec![TypedExpr::new_str(Expr::Column(Box::new(
+                TypedExpr::new_num(Expr::NumberF64(0.0)),
+            )))],
+            output: None,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -99,16 +271,97 @@ impl Into<TypedExpr> for Expr {
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Expr {
-    Assign(String, Box<TypedExpr>),
+    Assign(Symbol, Box<TypedExpr>),
     NumberF64(f64),
     String(String),
+    // A -v value that looks numeric, with the number it reads as. It prints as the text but
+    // compares as the number.
+    StrNum(String, f64),
+    // /re/. Alone it means $0 ~ /re/, as an argument to match() it's the pattern.
+    Regex(String),
     Concatenation(Vec<TypedExpr>),
     BinOp(Box<TypedExpr>, BinOp, Box<TypedExpr>),
     MathOp(Box<TypedExpr>, MathOp, Box<TypedExpr>),
     LogicalOp(Box<TypedExpr>, LogicalOp, Box<TypedExpr>),
-    Variable(String),
+    Not(Box<TypedExpr>),
+    // test ? if_so : if_not, short-circuiting so only the branch picked is evaluated. Its type
+    // is the merge of both branches.
+    Ternary(Box<TypedExpr>, Box<TypedExpr>, Box<TypedExpr>),
+    Variable(Symbol),
     Column(Box<TypedExpr>),
-    Call,
+    // $index = value, which rebuilds $0 (or re-splits the fields when index is 0)
+    ColumnAssign {
+        index: Box<TypedExpr>,
+        value: Box<TypedExpr>,
+    },
+    // NF = value, which drops fields past value or adds empty ones up to it and rebuilds $0
+    NfAssign(Box<TypedExpr>),
+    // Advances the main loop to the next record, the program's own getline is Getline
+    NextLine,
+    // getline [var], getline [var] < file, cmd | getline [var] and cmd |& getline [var]
+    Getline {
+        into: Option<Lvalue>,
+        source: GetlineSource,
+    },
+    // a[1][2] indexes the sub-array a[1]: path [[1]], indices [2]
+    ArrayIndex {
+        name: String,
+        path: Vec<Vec<TypedExpr>>,
+        indices: Vec<TypedExpr>,
+    },
+    ArrayAssign {
+        name: String,
+        path: Vec<Vec<TypedExpr>>,
+        indices: Vec<TypedExpr>,
+        value: Box<TypedExpr>,
+    },
+    // (indices) in name[path], 1 if the element exists without creating it
+    InArray {
+        indices: Vec<TypedExpr>,
+        name: String,
+        path: Vec<Vec<TypedExpr>>,
+    },
+    Call {
+        target: String,
+        args: Vec<TypedExpr>,
+    },
+}
+
+// What getline can store a line into
+#[derive(Debug, PartialEq, Clone)]
+pub enum Lvalue {
+    Variable(Symbol),
+    ArrayIndex {
+        name: String,
+        path: Vec<Vec<TypedExpr>>,
+        indices: Vec<TypedExpr>,
+    },
+    // $index
+    Column(Box<TypedExpr>),
+}
+
+impl Display for Lvalue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Lvalue::Variable(name) => write!(f, "{}", name),
+            Lvalue::ArrayIndex {
+                name,
+                path,
+                indices,
+            } => write!(f, "{}", subscripts(name, path, indices)),
+            Lvalue::Column(index) => write!(f, "${}", index),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum GetlineSource {
+    // The next record of the files being processed, only for getline var
+    MainInput,
+    File(Box<TypedExpr>),
+    Command(Box<TypedExpr>),
+    // cmd |& getline, reading what a coprocess prints
+    Coprocess(Box<TypedExpr>),
 }
 
 impl Display for TypedExpr {
@@ -125,14 +378,61 @@ impl Display for Expr {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Expr::Assign(var, expr) => write!(f, "{} = {}", var, expr),
-            Expr::Call => write!(f, "check_if_there_is_another_line"),
+            Expr::NextLine => write!(f, "check_if_there_is_another_line"),
+            Expr::Getline { into, source } => {
+                let getline = match into {
+                    Some(var) => format!("getline {}", var),
+                    None => "getline".to_string(),
+                };
+                match source {
+                    GetlineSource::MainInput => write!(f, "{}", getline),
+                    GetlineSource::File(file) => write!(f, "{} < {}", getline, file),
+                    GetlineSource::Command(cmd) => write!(f, "{} | {}", cmd, getline),
+                    GetlineSource::Coprocess(cmd) => write!(f, "{} |& {}", cmd, getline),
+                }
+            }
+            Expr::Call { target, args } => {
+                let args = args
+                    .iter()
+                    .map(|a| format!("{}", a))
+                    .collect::<Vec<String>>();
+                write!(f, "{}({})", target, args.join(", "))
+            }
+            Expr::ArrayIndex {
+                name,
+                path,
+                indices,
+            } => write!(f, "{}", subscripts(name, path, indices)),
+            Expr::ArrayAssign {
+                name,
+                path,
+                indices,
+                value,
+            } => write!(f, "{} = {}", subscripts(name, path, indices), value),
+            Expr::InArray {
+                indices,
+                name,
+                path,
+            } => {
+                let indices = indices
+                    .iter()
+                    .map(|i| format!("{}", i))
+                    .collect::<Vec<String>>();
+                let array = subscripts(name, path, &[]);
+                write!(f, "({}) in {}", indices.join(", "), array)
+            }
             Expr::Variable(n) => write!(f, "{}", n),
-            Expr::String(str) => write!(f, "\"{}\"", str),
+            Expr::String(str) | Expr::StrNum(str, _) => write!(f, "\"{}\"", str),
+            Expr::Regex(regex) => write!(f, "/{}/", regex),
             Expr::NumberF64(n) => write!(f, "{}", n),
             Expr::BinOp(left, op, right) => write!(f, "{}{}{}", left, op, right),
             Expr::MathOp(left, op, right) => write!(f, "{}{}{}", left, op, right),
             Expr::LogicalOp(left, op, right) => write!(f, "{}{}{}", left, op, right),
+            Expr::Not(expr) => write!(f, "!{}", expr),
+            Expr::Ternary(test, if_so, if_not) => write!(f, "{} ? {} : {}", test, if_so, if_not),
             Expr::Column(col) => write!(f, "${}", col),
+            Expr::ColumnAssign { index, value } => write!(f, "${} = {}", index, value),
+            Expr::NfAssign(value) => write!(f, "NF = {}", value),
             Expr::Concatenation(vals) => {
                 let vals = vals
                     .iter()
@@ -145,6 +445,22 @@ impl Display for Expr {
     }
 }
 
+// name[path[0]][path[1]]...[indices]
+fn subscripts(name: &str, path: &[Vec<TypedExpr>], indices: &[TypedExpr]) -> String {
+    let mut out = name.to_string();
+    for group in path.iter().map(|g| g.as_slice()).chain([indices]) {
+        if group.is_empty() {
+            continue;
+        }
+        let group = group
+            .iter()
+            .map(|i| format!("{}", i))
+            .collect::<Vec<String>>();
+        out.push_str(&format!("[{}]", group.join(", ")));
+    }
+    out
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Program {
     pub begins: Vec<Stmt>,
@@ -160,7 +476,7 @@ impl Program {
             pattern_actions,
         }
     }
-    #[allow(dead_code)]
+    #[cfg(test)]
     pub fn new_action_only(stmt: Stmt) -> Program {
         Program {
             begins: vec![],
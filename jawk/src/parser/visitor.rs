@@ -0,0 +1,591 @@
+use crate::lexer::{BinOp, LogicalOp, MathOp, Symbol};
+use crate::parser::types::Output;
+use crate::parser::{Expr, GetlineSource, Lvalue, Pattern, Program, Stmt, TypedExpr};
+
+// Walks the tree for a pass. Each method's default visits the node's children, so a pass only
+// overrides the nodes it cares about and calls the matching walk_ function (or the default
+// body) to keep going below them.
+pub trait Visitor {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt)
+    }
+    fn visit_expr(&mut self, expr: &TypedExpr) {
+        walk_expr(self, expr)
+    }
+    fn visit_lvalue(&mut self, lvalue: &Lvalue) {
+        walk_lvalue(self, lvalue)
+    }
+
+    fn visit_expr_stmt(&mut self, expr: &TypedExpr) {
+        self.visit_expr(expr)
+    }
+    fn visit_print(&mut self, args: &[TypedExpr], output: Option<&Output>) {
+        self.visit_exprs(args);
+        if let Some(output) = output {
+            self.visit_expr(output.target());
+        }
+    }
+    fn visit_printf(&mut self, fmt: &TypedExpr, args: &[TypedExpr], output: Option<&Output>) {
+        self.visit_expr(fmt);
+        self.visit_exprs(args);
+        if let Some(output) = output {
+            self.visit_expr(output.target());
+        }
+    }
+    fn visit_group(&mut self, group: &[Stmt]) {
+        for stmt in group {
+            self.visit_stmt(stmt);
+        }
+    }
+    fn visit_if(&mut self, test: &TypedExpr, if_so: &Stmt, if_not: Option<&Stmt>) {
+        self.visit_expr(test);
+        self.visit_stmt(if_so);
+        if let Some(if_not) = if_not {
+            self.visit_stmt(if_not);
+        }
+    }
+    fn visit_while(&mut self, test: &TypedExpr, body: &Stmt) {
+        self.visit_expr(test);
+        self.visit_stmt(body);
+    }
+    fn visit_do_while(&mut self, body: &Stmt, test: &TypedExpr) {
+        self.visit_stmt(body);
+        self.visit_expr(test);
+    }
+    fn visit_for(&mut self, init: &Stmt, test: &TypedExpr, incr: &Stmt, body: &Stmt) {
+        self.visit_stmt(init);
+        self.visit_expr(test);
+        self.visit_stmt(incr);
+        self.visit_stmt(body);
+    }
+    fn visit_for_in(&mut self, _var: &str, _name: &str, path: &[Vec<TypedExpr>], body: &Stmt) {
+        self.visit_path(path, &[]);
+        self.visit_stmt(body);
+    }
+    fn visit_delete(&mut self, _name: &str, path: &[Vec<TypedExpr>], indices: &[TypedExpr]) {
+        self.visit_path(path, indices)
+    }
+    fn visit_next(&mut self) {}
+    fn visit_break(&mut self) {}
+    fn visit_continue(&mut self) {}
+    fn visit_exit(&mut self, status: Option<&TypedExpr>) {
+        if let Some(status) = status {
+            self.visit_expr(status);
+        }
+    }
+    fn visit_main(&mut self, body: &Stmt) {
+        self.visit_stmt(body);
+    }
+
+    fn visit_assign(&mut self, _var: &str, value: &TypedExpr) {
+        self.visit_expr(value)
+    }
+    fn visit_number(&mut self, _num: f64) {}
+    fn visit_string(&mut self, _str: &str) {}
+    fn visit_regex(&mut self, _regex: &str) {}
+    fn visit_concatenation(&mut self, vals: &[TypedExpr]) {
+        self.visit_exprs(vals)
+    }
+    fn visit_binop(&mut self, left: &TypedExpr, _op: BinOp, right: &TypedExpr) {
+        self.visit_expr(left);
+        self.visit_expr(right);
+    }
+    fn visit_math_op(&mut self, left: &TypedExpr, _op: MathOp, right: &TypedExpr) {
+        self.visit_expr(left);
+        self.visit_expr(right);
+    }
+    fn visit_logical_op(&mut self, left: &TypedExpr, _op: LogicalOp, right: &TypedExpr) {
+        self.visit_expr(left);
+        self.visit_expr(right);
+    }
+    fn visit_not(&mut self, value: &TypedExpr) {
+        self.visit_expr(value)
+    }
+    fn visit_ternary(&mut self, test: &TypedExpr, if_so: &TypedExpr, if_not: &TypedExpr) {
+        self.visit_expr(test);
+        self.visit_expr(if_so);
+        self.visit_expr(if_not);
+    }
+    fn visit_variable(&mut self, _name: &str) {}
+    fn visit_column(&mut self, index: &TypedExpr) {
+        self.visit_expr(index)
+    }
+    fn visit_column_assign(&mut self, index: &TypedExpr, value: &TypedExpr) {
+        self.visit_expr(index);
+        self.visit_expr(value);
+    }
+    fn visit_nf_assign(&mut self, value: &TypedExpr) {
+        self.visit_expr(value)
+    }
+    fn visit_next_line(&mut self) {}
+    fn visit_getline(&mut self, into: Option<&Lvalue>, source: &GetlineSource) {
+        if let Some(into) = into {
+            self.visit_lvalue(into);
+        }
+        match source {
+            GetlineSource::MainInput => {}
+            GetlineSource::File(expr)
+            | GetlineSource::Command(expr)
+            | GetlineSource::Coprocess(expr) => self.visit_expr(expr),
+        }
+    }
+    fn visit_array_index(&mut self, _name: &str, path: &[Vec<TypedExpr>], indices: &[TypedExpr]) {
+        self.visit_path(path, indices)
+    }
+    fn visit_array_assign(
+        &mut self,
+        _name: &str,
+        path: &[Vec<TypedExpr>],
+        indices: &[TypedExpr],
+        value: &TypedExpr,
+    ) {
+        self.visit_path(path, indices);
+        self.visit_expr(value);
+    }
+    fn visit_in_array(&mut self, indices: &[TypedExpr], _name: &str, path: &[Vec<TypedExpr>]) {
+        self.visit_path(path, indices)
+    }
+    fn visit_call(&mut self, _target: &str, args: &[TypedExpr]) {
+        self.visit_exprs(args)
+    }
+
+    fn visit_exprs(&mut self, exprs: &[TypedExpr]) {
+        for expr in exprs {
+            self.visit_expr(expr);
+        }
+    }
+    // The subscripts of a[path...][indices]
+    fn visit_path(&mut self, path: &[Vec<TypedExpr>], indices: &[TypedExpr]) {
+        for index in path.iter().flatten().chain(indices) {
+            self.visit_expr(index);
+        }
+    }
+}
+
+// Begins, then each pattern and its action, then ends
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &Program) {
+    for stmt in &program.begins {
+        visitor.visit_stmt(stmt);
+    }
+    for pattern_action in &program.pattern_actions {
+        match &pattern_action.pattern {
+            Some(Pattern::Expr(test)) => visitor.visit_expr(test),
+            Some(Pattern::Range(start, end)) => {
+                visitor.visit_expr(start);
+                visitor.visit_expr(end);
+            }
+            None => {}
+        }
+        visitor.visit_stmt(&pattern_action.action);
+    }
+    for stmt in &program.ends {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Stmt) {
+    match stmt {
+        Stmt::Expr(expr) => visitor.visit_expr_stmt(expr),
+        Stmt::Print { args, output } => visitor.visit_print(args, output.as_ref()),
+        Stmt::Printf { fmt, args, output } => visitor.visit_printf(fmt, args, output.as_ref()),
+        Stmt::Group(group) => visitor.visit_group(group),
+        Stmt::If(test, if_so, if_not) => visitor.visit_if(test, if_so, if_not.as_deref()),
+        Stmt::While(test, body) => visitor.visit_while(test, body),
+        Stmt::DoWhile(body, test) => visitor.visit_do_while(body, test),
+        Stmt::For {
+            init,
+            test,
+            incr,
+            body,
+        } => visitor.visit_for(init, test, incr, body),
+        Stmt::ForIn {
+            var,
+            name,
+            path,
+            body,
+        } => visitor.visit_for_in(var, name, path, body),
+        Stmt::Delete {
+            name,
+            path,
+            indices,
+        } => visitor.visit_delete(name, path, indices),
+        Stmt::Next => visitor.visit_next(),
+        Stmt::Break => visitor.visit_break(),
+        Stmt::Continue => visitor.visit_continue(),
+        Stmt::Exit(status) => visitor.visit_exit(status.as_ref()),
+        Stmt::Main(body) => visitor.visit_main(body),
+    }
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &TypedExpr) {
+    match &expr.expr {
+        Expr::Assign(var, value) => visitor.visit_assign(var, value),
+        Expr::NumberF64(num) => visitor.visit_number(*num),
+        Expr::String(str) | Expr::StrNum(str, _) => visitor.visit_string(str),
+        Expr::Regex(regex) => visitor.visit_regex(regex),
+        Expr::Concatenation(vals) => visitor.visit_concatenation(vals),
+        Expr::BinOp(left, op, right) => visitor.visit_binop(left, *op, right),
+        Expr::MathOp(left, op, right) => visitor.visit_math_op(left, *op, right),
+        Expr::LogicalOp(left, op, right) => visitor.visit_logical_op(left, *op, right),
+        Expr::Not(value) => visitor.visit_not(value),
+        Expr::Ternary(test, if_so, if_not) => visitor.visit_ternary(test, if_so, if_not),
+        Expr::Variable(name) => visitor.visit_variable(name),
+        Expr::Column(index) => visitor.visit_column(index),
+        Expr::ColumnAssign { index, value } => visitor.visit_column_assign(index, value),
+        Expr::NfAssign(value) => visitor.visit_nf_assign(value),
+        Expr::NextLine => visitor.visit_next_line(),
+        Expr::Getline { into, source } => visitor.visit_getline(into.as_ref(), source),
+        Expr::ArrayIndex {
+            name,
+            path,
+            indices,
+        } => visitor.visit_array_index(name, path, indices),
+        Expr::ArrayAssign {
+            name,
+            path,
+            indices,
+            value,
+        } => visitor.visit_array_assign(name, path, indices, value),
+        Expr::InArray {
+            indices,
+            name,
+            path,
+        } => visitor.visit_in_array(indices, name, path),
+        Expr::Call { target, args } => visitor.visit_call(target, args),
+    }
+}
+
+pub fn walk_lvalue<V: Visitor + ?Sized>(visitor: &mut V, lvalue: &Lvalue) {
+    match lvalue {
+        Lvalue::Variable(_) => {}
+        Lvalue::ArrayIndex { path, indices, .. } => visitor.visit_path(path, indices),
+        Lvalue::Column(index) => visitor.visit_expr(index),
+    }
+}
+
+// Visitor for passes that rewrite the tree in place
+pub trait VisitorMut {
+    fn visit_stmt(&mut self, stmt: &mut Stmt) {
+        walk_stmt_mut(self, stmt)
+    }
+    fn visit_expr(&mut self, expr: &mut TypedExpr) {
+        walk_expr_mut(self, expr)
+    }
+    fn visit_lvalue(&mut self, lvalue: &mut Lvalue) {
+        walk_lvalue_mut(self, lvalue)
+    }
+
+    fn visit_expr_stmt(&mut self, expr: &mut TypedExpr) {
+        self.visit_expr(expr)
+    }
+    fn visit_print(&mut self, args: &mut [TypedExpr], output: Option<&mut Output>) {
+        self.visit_exprs(args);
+        if let Some(output) = output {
+            self.visit_expr(output.target_mut());
+        }
+    }
+    fn visit_printf(
+        &mut self,
+        fmt: &mut TypedExpr,
+        args: &mut [TypedExpr],
+        output: Option<&mut Output>,
+    ) {
+        self.visit_expr(fmt);
+        self.visit_exprs(args);
+        if let Some(output) = output {
+            self.visit_expr(output.target_mut());
+        }
+    }
+    fn visit_group(&mut self, group: &mut [Stmt]) {
+        for stmt in group {
+            self.visit_stmt(stmt);
+        }
+    }
+    fn visit_if(&mut self, test: &mut TypedExpr, if_so: &mut Stmt, if_not: Option<&mut Stmt>) {
+        self.visit_expr(test);
+        self.visit_stmt(if_so);
+        if let Some(if_not) = if_not {
+            self.visit_stmt(if_not);
+        }
+    }
+    fn visit_while(&mut self, test: &mut TypedExpr, body: &mut Stmt) {
+        self.visit_expr(test);
+        self.visit_stmt(body);
+    }
+    fn visit_do_while(&mut self, body: &mut Stmt, test: &mut TypedExpr) {
+        self.visit_stmt(body);
+        self.visit_expr(test);
+    }
+    fn visit_for(
+        &mut self,
+        init: &mut Stmt,
+        test: &mut TypedExpr,
+        incr: &mut Stmt,
+        body: &mut Stmt,
+    ) {
+        self.visit_stmt(init);
+        self.visit_expr(test);
+        self.visit_stmt(incr);
+        self.visit_stmt(body);
+    }
+    fn visit_for_in(
+        &mut self,
+        _var: &mut String,
+        _name: &mut String,
+        path: &mut [Vec<TypedExpr>],
+        body: &mut Stmt,
+    ) {
+        self.visit_path(path, &mut []);
+        self.visit_stmt(body);
+    }
+    fn visit_delete(
+        &mut self,
+        _name: &mut String,
+        path: &mut [Vec<TypedExpr>],
+        indices: &mut [TypedExpr],
+    ) {
+        self.visit_path(path, indices)
+    }
+    fn visit_next(&mut self) {}
+    fn visit_break(&mut self) {}
+    fn visit_continue(&mut self) {}
+    fn visit_exit(&mut self, status: Option<&mut TypedExpr>) {
+        if let Some(status) = status {
+            self.visit_expr(status);
+        }
+    }
+    fn visit_main(&mut self, body: &mut Stmt) {
+        self.visit_stmt(body);
+    }
+
+    fn visit_assign(&mut self, _var: &mut Symbol, value: &mut TypedExpr) {
+        self.visit_expr(value)
+    }
+    fn visit_number(&mut self, _num: &mut f64) {}
+    fn visit_string(&mut self, _str: &mut String) {}
+    fn visit_regex(&mut self, _regex: &mut String) {}
+    fn visit_concatenation(&mut self, vals: &mut [TypedExpr]) {
+        self.visit_exprs(vals)
+    }
+    fn visit_binop(&mut self, left: &mut TypedExpr, _op: &mut BinOp, right: &mut TypedExpr) {
+        self.visit_expr(left);
+        self.visit_expr(right);
+    }
+    fn visit_math_op(&mut self, left: &mut TypedExpr, _op: &mut MathOp, right: &mut TypedExpr) {
+        self.visit_expr(left);
+        self.visit_expr(right);
+    }
+    fn visit_logical_op(
+        &mut self,
+        left: &mut TypedExpr,
+        _op: &mut LogicalOp,
+        right: &mut TypedExpr,
+    ) {
+        self.visit_expr(left);
+        self.visit_expr(right);
+    }
+    fn visit_not(&mut self, value: &mut TypedExpr) {
+        self.visit_expr(value)
+    }
+    fn visit_ternary(
+        &mut self,
+        test: &mut TypedExpr,
+        if_so: &mut TypedExpr,
+        if_not: &mut TypedExpr,
+    ) {
+        self.visit_expr(test);
+        self.visit_expr(if_so);
+        self.visit_expr(if_not);
+    }
+    fn visit_variable(&mut self, _name: &mut Symbol) {}
+    fn visit_column(&mut self, index: &mut TypedExpr) {
+        self.visit_expr(index)
+    }
+    fn visit_column_assign(&mut self, index: &mut TypedExpr, value: &mut TypedExpr) {
+        self.visit_expr(index);
+        self.visit_expr(value);
+    }
+    fn visit_nf_assign(&mut self, value: &mut TypedExpr) {
+        self.visit_expr(value)
+    }
+    fn visit_next_line(&mut self) {}
+    fn visit_getline(&mut self, into: Option<&mut Lvalue>, source: &mut GetlineSource) {
+        if let Some(into) = into {
+            self.visit_lvalue(into);
+        }
+        match source {
+            GetlineSource::MainInput => {}
+            GetlineSource::File(expr)
+            | GetlineSource::Command(expr)
+            | GetlineSource::Coprocess(expr) => self.visit_expr(expr),
+        }
+    }
+    fn visit_array_index(
+        &mut self,
+        _name: &mut String,
+        path: &mut [Vec<TypedExpr>],
+        indices: &mut [TypedExpr],
+    ) {
+        self.visit_path(path, indices)
+    }
+    fn visit_array_assign(
+        &mut self,
+        _name: &mut String,
+        path: &mut [Vec<TypedExpr>],
+        indices: &mut [TypedExpr],
+        value: &mut TypedExpr,
+    ) {
+        self.visit_path(path, indices);
+        self.visit_expr(value);
+    }
+    fn visit_in_array(
+        &mut self,
+        indices: &mut [TypedExpr],
+        _name: &mut String,
+        path: &mut [Vec<TypedExpr>],
+    ) {
+        self.visit_path(path, indices)
+    }
+    fn visit_call(&mut self, _target: &mut String, args: &mut [TypedExpr]) {
+        self.visit_exprs(args)
+    }
+
+    fn visit_exprs(&mut self, exprs: &mut [TypedExpr]) {
+        for expr in exprs {
+            self.visit_expr(expr);
+        }
+    }
+    fn visit_path(&mut self, path: &mut [Vec<TypedExpr>], indices: &mut [TypedExpr]) {
+        for index in path.iter_mut().flatten().chain(indices.iter_mut()) {
+            self.visit_expr(index);
+        }
+    }
+}
+
+pub fn walk_program_mut<V: VisitorMut + ?Sized>(visitor: &mut V, program: &mut Program) {
+    for stmt in program.begins.iter_mut() {
+        visitor.visit_stmt(stmt);
+    }
+    for pattern_action in program.pattern_actions.iter_mut() {
+        match &mut pattern_action.pattern {
+            Some(Pattern::Expr(test)) => visitor.visit_expr(test),
+            Some(Pattern::Range(start, end)) => {
+                visitor.visit_expr(start);
+                visitor.visit_expr(end);
+            }
+            None => {}
+        }
+        visitor.visit_stmt(&mut pattern_action.action);
+    }
+    for stmt in program.ends.iter_mut() {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt_mut<V: VisitorMut + ?Sized>(visitor: &mut V, stmt: &mut Stmt) {
+    match stmt {
+        Stmt::Expr(expr) => visitor.visit_expr_stmt(expr),
+        Stmt::Print { args, output } => visitor.visit_print(args, output.as_mut()),
+        Stmt::Printf { fmt, args, output } => visitor.visit_printf(fmt, args, output.as_mut()),
+        Stmt::Group(group) => visitor.visit_group(group),
+        Stmt::If(test, if_so, if_not) => visitor.visit_if(test, if_so, if_not.as_deref_mut()),
+        Stmt::While(test, body) => visitor.visit_while(test, body),
+        Stmt::DoWhile(body, test) => visitor.visit_do_while(body, test),
+        Stmt::For {
+            init,
+            test,
+            incr,
+            body,
+        } => visitor.visit_for(init, test, incr, body),
+        Stmt::ForIn {
+            var,
+            name,
+            path,
+            body,
+        } => visitor.visit_for_in(var, name, path, body),
+        Stmt::Delete {
+            name,
+            path,
+            indices,
+        } => visitor.visit_delete(name, path, indices),
+        Stmt::Next => visitor.visit_next(),
+        Stmt::Break => visitor.visit_break(),
+        Stmt::Continue => visitor.visit_continue(),
+        Stmt::Exit(status) => visitor.visit_exit(status.as_mut()),
+        Stmt::Main(body) => visitor.visit_main(body),
+    }
+}
+
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut TypedExpr) {
+    match &mut expr.expr {
+        Expr::Assign(var, value) => visitor.visit_assign(var, value),
+        Expr::NumberF64(num) => visitor.visit_number(num),
+        Expr::String(str) | Expr::StrNum(str, _) => visitor.visit_string(str),
+        Expr::Regex(regex) => visitor.visit_regex(regex),
+        Expr::Concatenation(vals) => visitor.visit_concatenation(vals),
+        Expr::BinOp(left, op, right) => visitor.visit_binop(left, op, right),
+        Expr::MathOp(left, op, right) => visitor.visit_math_op(left, op, right),
+        Expr::LogicalOp(left, op, right) => visitor.visit_logical_op(left, op, right),
+        Expr::Not(value) => visitor.visit_not(value),
+        Expr::Ternary(test, if_so, if_not) => visitor.visit_ternary(test, if_so, if_not),
+        Expr::Variable(name) => visitor.visit_variable(name),
+        Expr::Column(index) => visitor.visit_column(index),
+        Expr::ColumnAssign { index, value } => visitor.visit_column_assign(index, value),
+        Expr::NfAssign(value) => visitor.visit_nf_assign(value),
+        Expr::NextLine => visitor.visit_next_line(),
+        Expr::Getline { into, source } => visitor.visit_getline(into.as_mut(), source),
+        Expr::ArrayIndex {
+            name,
+            path,
+            indices,
+        } => visitor.visit_array_index(name, path, indices),
+        Expr::ArrayAssign {
+            name,
+            path,
+            indices,
+            value,
+        } => visitor.visit_array_assign(name, path, indices, value),
+        Expr::InArray {
+            indices,
+            name,
+            path,
+        } => visitor.visit_in_array(indices, name, path),
+        Expr::Call { target, args } => visitor.visit_call(target, args),
+    }
+}
+
+pub fn walk_lvalue_mut<V: VisitorMut + ?Sized>(visitor: &mut V, lvalue: &mut Lvalue) {
+    match lvalue {
+        Lvalue::Variable(_) => {}
+        Lvalue::ArrayIndex { path, indices, .. } => visitor.visit_path(path, indices),
+        Lvalue::Column(index) => visitor.visit_expr(index),
+    }
+}
+
+#[cfg(test)]
+struct ColumnCounter {
+    columns: usize,
+}
+
+#[cfg(test)]
+impl Visitor for ColumnCounter {
+    fn visit_column(&mut self, index: &TypedExpr) {
+        self.columns += 1;
+        self.visit_expr(index);
+    }
+}
+
+#[test]
+fn test_count_columns() {
+    use crate::lexer::lex;
+    use crate::parser::parse;
+
+    let program = parse(
+        lex("BEGIN { getline $2 < \"f\" } $1 > 0 { print $($3), a[$4] } END { x = $NF }").unwrap(),
+    )
+    .unwrap();
+    let mut counter = ColumnCounter { columns: 0 };
+    walk_program(&mut counter, &program);
+    // The getline target is an Lvalue::Column, not an Expr::Column
+    assert_eq!(counter.columns, 5);
+}
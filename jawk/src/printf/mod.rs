@@ -0,0 +1,632 @@
+// C style printf formatting for the printf statement.
+// Output is bytes rather than a String because in byte mode %c can produce bytes that aren't
+// valid UTF-8 on their own (eg. `printf "%c", 200` is the single byte 0xC8).
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatArg {
+    Float(f64),
+    String(String),
+}
+
+// Like gawk we only treat characters as multi-byte when the locale is UTF-8. In the C/POSIX
+// locale (or with no locale set at all) every character is one byte.
+pub fn byte_mode_from_env() -> bool {
+    for var in ["LC_ALL", "LC_CTYPE", "LANG"] {
+        if let Ok(locale) = std::env::var(var) {
+            if locale.is_empty() {
+                continue;
+            }
+            let locale = locale.to_ascii_lowercase();
+            return !(locale.contains("utf-8") || locale.contains("utf8"));
+        }
+    }
+    true
+}
+
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    alt: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+struct Formatter<'a> {
+    args: &'a [FormatArg],
+    next_arg: usize,
+    byte_mode: bool,
+    out: Vec<u8>,
+}
+
+pub fn sprintf(fmt: &str, args: &[FormatArg], byte_mode: bool) -> Vec<u8> {
+    let mut formatter = Formatter {
+        args,
+        next_arg: 0,
+        byte_mode,
+        out: Vec::with_capacity(fmt.len()),
+    };
+    formatter.format(fmt.as_bytes());
+    formatter.out
+}
+
+impl<'a> Formatter<'a> {
+    fn format(&mut self, fmt: &[u8]) {
+        let mut idx = 0;
+        while idx < fmt.len() {
+            if fmt[idx] != b'%' {
+                self.out.push(fmt[idx]);
+                idx += 1;
+                continue;
+            }
+            let start = idx;
+            idx += 1;
+            let mut spec = Spec::default();
+            while idx < fmt.len() {
+                match fmt[idx] {
+                    b'-' => spec.left = true,
+                    b'+' => spec.plus = true,
+                    b' ' => spec.space = true,
+                    b'#' => spec.alt = true,
+                    b'0' => spec.zero = true,
+                    _ => break,
+                }
+                idx += 1;
+            }
+            if idx < fmt.len() && fmt[idx] == b'*' {
+                let width = self.next_number();
+                if width < 0.0 {
+                    spec.left = true;
+                }
+                spec.width = width.abs() as usize;
+                idx += 1;
+            } else {
+                spec.width = read_digits(fmt, &mut idx);
+            }
+            if idx < fmt.len() && fmt[idx] == b'.' {
+                idx += 1;
+                if idx < fmt.len() && fmt[idx] == b'*' {
+                    let precision = self.next_number();
+                    // A negative precision is taken as if the precision were omitted
+                    if precision >= 0.0 {
+                        spec.precision = Some(precision as usize);
+                    }
+                    idx += 1;
+                } else {
+                    spec.precision = Some(read_digits(fmt, &mut idx));
+                }
+            }
+            // Length modifiers mean nothing in awk, skip them
+            while idx < fmt.len() && b"hlLqjzt".contains(&fmt[idx]) {
+                idx += 1;
+            }
+            if idx >= fmt.len() {
+                // Incomplete spec at the end of the format, print it as is
+                self.out.extend_from_slice(&fmt[start..]);
+                break;
+            }
+            let conversion = fmt[idx];
+            idx += 1;
+            match conversion {
+                b'%' => self.out.push(b'%'),
+                b'c' => self.char(&spec),
+                b's' => self.string(&spec),
+                b'd' | b'i' => self.signed(&spec),
+                b'o' | b'x' | b'X' | b'u' => self.unsigned(&spec, conversion),
+                b'e' | b'E' | b'f' | b'F' | b'g' | b'G' => self.float(&spec, conversion),
+                // Unknown conversion, print it as is
+                _ => self.out.extend_from_slice(&fmt[start..idx]),
+            }
+        }
+    }
+
+    fn next(&mut self) -> Option<&'a FormatArg> {
+        let arg = self.args.get(self.next_arg);
+        self.next_arg += 1;
+        arg
+    }
+
+    fn next_number(&mut self) -> f64 {
+        match self.next() {
+            Some(FormatArg::Float(num)) => *num,
+            Some(FormatArg::String(str)) => string_to_number(str),
+            None => 0.0,
+        }
+    }
+
+    fn next_string(&mut self) -> String {
+        match self.next() {
+            Some(FormatArg::Float(num)) => number_to_string(*num, NUMBER_FORMAT),
+            Some(FormatArg::String(str)) => str.clone(),
+            None => String::new(),
+        }
+    }
+
+    // Length of a string in the units the width and precision are measured in
+    fn str_len(&self, str: &[u8]) -> usize {
+        if self.byte_mode {
+            str.len()
+        } else {
+            String::from_utf8_lossy(str).chars().count()
+        }
+    }
+
+    fn char(&mut self, spec: &Spec) {
+        let mut bytes = vec![];
+        match self.next() {
+            Some(FormatArg::Float(num)) => {
+                let code = *num as u32;
+                if self.byte_mode {
+                    bytes.push(code as u8);
+                } else {
+                    let chr = char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER);
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(chr.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+            Some(FormatArg::String(str)) => {
+                if self.byte_mode {
+                    bytes.extend(str.as_bytes().first());
+                } else if let Some(chr) = str.chars().next() {
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(chr.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+            None => {}
+        }
+        let len = self.str_len(&bytes);
+        self.pad(spec, &bytes, len);
+    }
+
+    fn string(&mut self, spec: &Spec) {
+        let str = self.next_string();
+        let bytes = match spec.precision {
+            Some(precision) if self.byte_mode => &str.as_bytes()[..precision.min(str.len())],
+            Some(precision) => match str.char_indices().nth(precision) {
+                Some((end, _)) => &str.as_bytes()[..end],
+                None => str.as_bytes(),
+            },
+            None => str.as_bytes(),
+        };
+        let len = self.str_len(bytes);
+        self.pad(spec, bytes, len);
+    }
+
+    fn signed(&mut self, spec: &Spec) {
+        let num = self.next_number();
+        if !num.is_finite() {
+            return self.special(spec, num, false);
+        }
+        let num = num.trunc();
+        let sign = if num < 0.0 {
+            "-"
+        } else if spec.plus {
+            "+"
+        } else if spec.space {
+            " "
+        } else {
+            ""
+        };
+        let digits = integer_digits(&format!("{:.0}", num.abs()), spec.precision);
+        self.pad_number(spec, sign, "", &digits, spec.precision.is_none());
+    }
+
+    fn unsigned(&mut self, spec: &Spec, conversion: u8) {
+        let num = self.next_number();
+        if !num.is_finite() {
+            return self.special(spec, num, false);
+        }
+        let num = num.trunc();
+        let num = if num < 0.0 {
+            (num as i64) as u64
+        } else {
+            num as u64
+        };
+        let digits = match conversion {
+            b'o' => format!("{:o}", num),
+            b'x' => format!("{:x}", num),
+            b'X' => format!("{:X}", num),
+            _ => format!("{}", num),
+        };
+        let mut digits = integer_digits(&digits, spec.precision);
+        let mut prefix = "";
+        if spec.alt {
+            match conversion {
+                b'o' if !digits.starts_with('0') => digits.insert(0, '0'),
+                b'x' if num != 0 => prefix = "0x",
+                b'X' if num != 0 => prefix = "0X",
+                _ => {}
+            }
+        }
+        self.pad_number(spec, "", prefix, &digits, spec.precision.is_none());
+    }
+
+    fn float(&mut self, spec: &Spec, conversion: u8) {
+        let num = self.next_number();
+        let upper = conversion.is_ascii_uppercase();
+        if !num.is_finite() {
+            return self.special(spec, num, upper);
+        }
+        let precision = spec.precision.unwrap_or(6);
+        let abs = num.abs();
+        let digits = match conversion {
+            b'f' | b'F' => format_f(abs, precision, spec.alt),
+            b'e' | b'E' => format_e(abs, precision, spec.alt, upper),
+            _ => format_g(abs, precision, spec.alt, upper),
+        };
+        let sign = if num.is_sign_negative() {
+            "-"
+        } else if spec.plus {
+            "+"
+        } else if spec.space {
+            " "
+        } else {
+            ""
+        };
+        self.pad_number(spec, sign, "", &digits, true);
+    }
+
+    // inf and nan whatever the conversion, even %d. Like C a nan with its sign bit set is -nan.
+    // They are never zero padded.
+    fn special(&mut self, spec: &Spec, num: f64, upper: bool) {
+        let sign = if num.is_sign_negative() {
+            "-"
+        } else if spec.plus {
+            "+"
+        } else if spec.space {
+            " "
+        } else {
+            ""
+        };
+        let name = match (num.is_nan(), upper) {
+            (true, false) => "nan",
+            (true, true) => "NAN",
+            (false, false) => "inf",
+            (false, true) => "INF",
+        };
+        self.pad_number(spec, sign, "", name, false);
+    }
+
+    fn pad_number(&mut self, spec: &Spec, sign: &str, prefix: &str, digits: &str, zero_ok: bool) {
+        let len = sign.len() + prefix.len() + digits.len();
+        let fill = spec.width.saturating_sub(len);
+        if spec.left {
+            self.out.extend_from_slice(sign.as_bytes());
+            self.out.extend_from_slice(prefix.as_bytes());
+            self.out.extend_from_slice(digits.as_bytes());
+            self.out.extend(std::iter::repeat_n(b' ', fill));
+        } else if spec.zero && zero_ok {
+            self.out.extend_from_slice(sign.as_bytes());
+            self.out.extend_from_slice(prefix.as_bytes());
+            self.out.extend(std::iter::repeat_n(b'0', fill));
+            self.out.extend_from_slice(digits.as_bytes());
+        } else {
+            self.out.extend(std::iter::repeat_n(b' ', fill));
+            self.out.extend_from_slice(sign.as_bytes());
+            self.out.extend_from_slice(prefix.as_bytes());
+            self.out.extend_from_slice(digits.as_bytes());
+        }
+    }
+
+    fn pad(&mut self, spec: &Spec, bytes: &[u8], len: usize) {
+        let fill = spec.width.saturating_sub(len);
+        if spec.left {
+            self.out.extend_from_slice(bytes);
+            self.out.extend(std::iter::repeat_n(b' ', fill));
+        } else {
+            self.out.extend(std::iter::repeat_n(b' ', fill));
+            self.out.extend_from_slice(bytes);
+        }
+    }
+}
+
+fn read_digits(fmt: &[u8], idx: &mut usize) -> usize {
+    let mut num: usize = 0;
+    while *idx < fmt.len() && fmt[*idx].is_ascii_digit() {
+        num = num
+            .saturating_mul(10)
+            .saturating_add((fmt[*idx] - b'0') as usize);
+        *idx += 1;
+    }
+    num
+}
+
+// Precision on an integer conversion is the minimum number of digits. Zero with a precision of
+// zero prints no digits at all.
+fn integer_digits(digits: &str, precision: Option<usize>) -> String {
+    match precision {
+        Some(0) if digits == "0" => String::new(),
+        Some(precision) if precision > digits.len() => {
+            format!("{}{}", "0".repeat(precision - digits.len()), digits)
+        }
+        _ => digits.to_string(),
+    }
+}
+
+// Rust rounds an exact halfway value to even, the same as glibc's printf, so %.0f of 2.5 is 2.
+// %e and %g are built on the same formatting and round the same way.
+fn format_f(num: f64, precision: usize, alt: bool) -> String {
+    let mut str = format!("{:.*}", precision, num);
+    if alt && precision == 0 {
+        str.push('.');
+    }
+    str
+}
+
+// Rust writes exponents like 1.5e2, C wants at least two digits and a sign: 1.5e+02
+fn format_e(num: f64, precision: usize, alt: bool, upper: bool) -> String {
+    let str = format!("{:.*e}", precision, num);
+    let (mantissa, exponent) = str.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let sign = if exponent < 0 { '-' } else { '+' };
+    let e = if upper { 'E' } else { 'e' };
+    let dot = if alt && precision == 0 { "." } else { "" };
+    format!("{}{}{}{}{:02}", mantissa, dot, e, sign, exponent.abs())
+}
+
+fn format_g(num: f64, precision: usize, alt: bool, upper: bool) -> String {
+    let precision = if precision == 0 { 1 } else { precision };
+    // The exponent is the one %e would print after rounding to the precision
+    let exponent: i32 = if num == 0.0 {
+        0
+    } else {
+        let str = format!("{:.*e}", precision - 1, num);
+        str.split_once('e').unwrap().1.parse().unwrap()
+    };
+    let str = if exponent < -4 || exponent >= precision as i32 {
+        format_e(num, precision - 1, alt, upper)
+    } else {
+        format_f(num, (precision as i32 - 1 - exponent) as usize, alt)
+    };
+    if alt {
+        return str;
+    }
+    // Without # trailing zeros in the fraction are removed
+    let (mantissa, exponent) = match str.find(['e', 'E']) {
+        Some(idx) => str.split_at(idx),
+        None => (str.as_str(), ""),
+    };
+    let mantissa = if mantissa.contains('.') {
+        mantissa.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        mantissa
+    };
+    format!("{}{}", mantissa, exponent)
+}
+
+// awk's string to number conversion: use the longest numeric prefix, ignoring leading space
+fn string_to_number(str: &str) -> f64 {
+    let bytes = str.trim_start().as_bytes();
+    let mut end = 0;
+    if end < bytes.len() && (bytes[end] == b'+' || bytes[end] == b'-') {
+        end += 1;
+    }
+    let digits_start = end;
+    while end < bytes.len() && bytes[end].is_ascii_digit() {
+        end += 1;
+    }
+    if end < bytes.len() && bytes[end] == b'.' {
+        end += 1;
+        while end < bytes.len() && bytes[end].is_ascii_digit() {
+            end += 1;
+        }
+    }
+    if end == digits_start || &bytes[digits_start..end] == b"." {
+        return 0.0;
+    }
+    if end < bytes.len() && (bytes[end] == b'e' || bytes[end] == b'E') {
+        let mut exp_end = end + 1;
+        if exp_end < bytes.len() && (bytes[exp_end] == b'+' || bytes[exp_end] == b'-') {
+            exp_end += 1;
+        }
+        if exp_end < bytes.len() && bytes[exp_end].is_ascii_digit() {
+            while exp_end < bytes.len() && bytes[exp_end].is_ascii_digit() {
+                exp_end += 1;
+            }
+            end = exp_end;
+        }
+    }
+    std::str::from_utf8(&bytes[..end])
+        .unwrap()
+        .parse()
+        .unwrap_or(0.0)
+}
+
+// Default value of OFMT and CONVFMT
+pub const NUMBER_FORMAT: &str = "%.6g";
+
+// How a number becomes a string. Integers print as integers, everything else with fmt which is
+// OFMT or CONVFMT. Like awk a user's fmt is used even if it makes little sense (OFMT = "x"
+// prints x). Whatever sprintf can't parse it prints literally so no fmt can panic.
+// -0 is an integer and prints as 0 the way %d would. inf and nan aren't, so they go through fmt
+// where every conversion prints them as inf, -inf, nan or -nan.
+pub fn number_to_string(num: f64, fmt: &str) -> String {
+    if num.is_finite() && num.fract() == 0.0 && num.abs() < 1e16 {
+        format!("{}", num as i64)
+    } else {
+        String::from_utf8_lossy(&sprintf(fmt, &[FormatArg::Float(num)], true)).into_owned()
+    }
+}
+
+#[cfg(test)]
+fn fmt(fmt: &str, args: &[FormatArg]) -> String {
+    String::from_utf8(sprintf(fmt, args, false)).unwrap()
+}
+
+#[test]
+fn test_char_byte_mode() {
+    let out = sprintf("%c", &[FormatArg::Float(200.0)], true);
+    assert_eq!(out, vec![0xC8]);
+}
+
+#[test]
+fn test_char_utf8_mode() {
+    let out = sprintf("%c", &[FormatArg::Float(200.0)], false);
+    assert_eq!(out, "È".as_bytes());
+    assert_eq!(out, vec![0xC3, 0x88]);
+}
+
+#[test]
+fn test_char_of_string() {
+    let args = [FormatArg::String("Èa".to_string())];
+    assert_eq!(sprintf("%c", &args, false), "È".as_bytes());
+    assert_eq!(sprintf("%c", &args, true), vec![0xC3]);
+}
+
+#[test]
+fn test_char_width() {
+    assert_eq!(
+        fmt(
+            "%3c|%-3c|",
+            &[FormatArg::Float(65.0), FormatArg::Float(66.0)]
+        ),
+        "  A|B  |"
+    );
+}
+
+#[test]
+fn test_signed() {
+    let args = [
+        FormatArg::Float(42.9),
+        FormatArg::Float(-42.9),
+        FormatArg::Float(7.0),
+        FormatArg::Float(7.0),
+        FormatArg::String("12abc".to_string()),
+    ];
+    assert_eq!(fmt("%d %i %05d %+.3d %d", &args), "42 -42 00007 +007 12");
+}
+
+#[test]
+fn test_unsigned() {
+    let args = [
+        FormatArg::Float(255.0),
+        FormatArg::Float(255.0),
+        FormatArg::Float(8.0),
+        FormatArg::Float(-1.0),
+    ];
+    assert_eq!(
+        fmt("%x %#X %#o %u", &args),
+        "ff 0XFF 010 18446744073709551615"
+    );
+}
+
+#[test]
+fn test_floats() {
+    let args = [
+        FormatArg::Float(1.23456),
+        FormatArg::Float(1234.5),
+        FormatArg::Float(0.0001),
+        FormatArg::Float(100000.0),
+        FormatArg::Float(1e6),
+        FormatArg::Float(-2.5),
+    ];
+    assert_eq!(
+        fmt("%.2f %e %g %g %G %08.2f", &args),
+        "1.23 1.234500e+03 0.0001 100000 1E+06 -0002.50"
+    );
+}
+
+#[test]
+fn test_halfway_rounding() {
+    let halves = [0.5, 1.5, 2.5, 3.5, -2.5].map(FormatArg::Float);
+    assert_eq!(fmt("%.0f %.0f %.0f %.0f %.0f", &halves), "0 2 2 4 -2");
+    // 0.125 is exact in binary, 2.675 is really 2.67499999...
+    let args = [0.125, 0.375, 2.675].map(FormatArg::Float);
+    assert_eq!(fmt("%.2f %.2f %.2f", &args), "0.12 0.38 2.67");
+    let args = [25.0, 125.0, 0.125, 2.5, 1.125].map(FormatArg::Float);
+    assert_eq!(
+        fmt("%.0e %.1E %.2g %.1g %.3G", &args),
+        "2e+01 1.2E+02 0.12 2 1.12"
+    );
+}
+
+#[test]
+fn test_strings() {
+    let args = [
+        FormatArg::String("abc".to_string()),
+        FormatArg::String("abc".to_string()),
+        FormatArg::Float(1.5),
+        FormatArg::Float(3.0),
+    ];
+    assert_eq!(fmt("%5s|%-5.2s|%s|%s", &args), "  abc|ab   |1.5|3");
+}
+
+#[test]
+fn test_star_width_and_missing_args() {
+    let args = [FormatArg::Float(4.0), FormatArg::Float(1.0)];
+    assert_eq!(fmt("%*d|%s|%d%%", &args), "   1||0%");
+}
+
+#[test]
+fn test_special_floats() {
+    let args = [FormatArg::Float(f64::INFINITY), FormatArg::Float(f64::NAN)];
+    assert_eq!(fmt("%05f %F", &args), "  inf NAN");
+}
+
+#[test]
+fn test_negative_zero() {
+    let args = [-0.0, -0.0, -0.0, -0.0, -0.0, -0.4].map(FormatArg::Float);
+    assert_eq!(
+        fmt("%d %+i %f %g %e %.0f", &args),
+        "0 +0 -0.000000 -0 -0.000000e+00 -0"
+    );
+    let args = [-0.0, -0.0].map(FormatArg::Float);
+    assert_eq!(fmt("%x %5.1f", &args), "0  -0.0");
+}
+
+#[test]
+fn test_infinity() {
+    let inf = [f64::INFINITY; 4].map(FormatArg::Float);
+    assert_eq!(fmt("%f %g %e %d", &inf), "inf inf inf inf");
+    let neg_inf = [f64::NEG_INFINITY; 4].map(FormatArg::Float);
+    assert_eq!(fmt("%f %G %e %5i|", &neg_inf), "-inf -INF -inf  -inf|");
+    let args = [f64::INFINITY; 2].map(FormatArg::Float);
+    assert_eq!(fmt("%+.2f %-6x|", &args), "+inf inf   |");
+}
+
+#[test]
+fn test_nan() {
+    let nan = [f64::NAN; 4].map(FormatArg::Float);
+    assert_eq!(fmt("%f %g %E %d", &nan), "nan nan NAN nan");
+    // Like C the sign bit of a nan is printed
+    let neg_nan = [-f64::NAN; 3].map(FormatArg::Float);
+    assert_eq!(fmt("%f %g %u", &neg_nan), "-nan -nan -nan");
+}
+
+#[test]
+fn test_unknown_conversion() {
+    assert_eq!(fmt("%k|%", &[]), "%k|%");
+}
+
+#[test]
+fn test_number_to_string() {
+    assert_eq!(number_to_string(3.0, NUMBER_FORMAT), "3");
+    assert_eq!(number_to_string(0.1 + 0.2, NUMBER_FORMAT), "0.3");
+    assert_eq!(number_to_string(1.23456, "%.2f"), "1.23");
+    assert_eq!(number_to_string(3.0, "%.2f"), "3");
+}
+
+#[test]
+fn test_number_to_string_special() {
+    assert_eq!(number_to_string(-0.0, NUMBER_FORMAT), "0");
+    assert_eq!(number_to_string(-0.0, "%.2f"), "0");
+    assert_eq!(number_to_string(f64::INFINITY, NUMBER_FORMAT), "inf");
+    assert_eq!(number_to_string(f64::NEG_INFINITY, "%.2f"), "-inf");
+    assert_eq!(number_to_string(f64::NAN, NUMBER_FORMAT), "nan");
+    assert_eq!(number_to_string(-f64::NAN, "%d"), "-nan");
+}
+
+#[test]
+fn test_number_to_string_malformed_format() {
+    assert_eq!(number_to_string(1.5, "x"), "x");
+    assert_eq!(number_to_string(1.5, ""), "");
+    assert_eq!(number_to_string(1.5, "%"), "%");
+    assert_eq!(number_to_string(1.5, "%.2"), "%.2");
+    assert_eq!(number_to_string(1.5, "%k"), "%k");
+    assert_eq!(number_to_string(1.5, "%s"), "1.5");
+    assert_eq!(number_to_string(1.5, "%d%d"), "10");
+    assert_eq!(number_to_string(1.5, "%c"), "\u{1}");
+    assert_eq!(number_to_string(200.5, "%c"), "\u{fffd}");
+}
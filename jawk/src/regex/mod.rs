@@ -0,0 +1,771 @@
+use std::collections::HashMap;
+
+// POSIX extended regular expressions as awk uses them, plus gawk's \s \S \w \W \y \B \< \> \`
+// and \'. Like awk ^ and $ anchor to the start and end of the whole string and . matches a
+// newline. Matches are leftmost-longest.
+//
+// A regex is compiled to a program for a Pike VM (a Thompson NFA simulation) so matching never
+// backtracks and takes time linear in the length of the text.
+#[derive(Debug)]
+pub struct Regex {
+    program: Vec<Inst>,
+}
+
+// More copies than this in a{n,m} is an error, like RE_DUP_MAX
+const MAX_REPEAT: u32 = 255;
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    // Try both, the first has priority
+    Split(usize, usize),
+    Jump(usize),
+    Assert(Assertion),
+    Match,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Assertion {
+    Start,
+    End,
+    WordBoundary,
+    NotWordBoundary,
+    WordStart,
+    WordEnd,
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    negated: bool,
+    items: Vec<ClassItem>,
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Char(char),
+    Range(char, char),
+    Named(fn(char) -> bool),
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        let found = self.items.iter().any(|item| match item {
+            ClassItem::Char(x) => *x == c,
+            ClassItem::Range(low, high) => *low <= c && c <= *high,
+            ClassItem::Named(test) => test(c),
+        });
+        found != self.negated
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Assertion),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+    },
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            current: 0,
+        };
+        let node = parser.alternate()?;
+        if parser.current < parser.chars.len() {
+            // Only an unmatched ) stops the parser early
+            return Err(format!("unmatched ) in regex /{}/", pattern));
+        }
+        let mut program = vec![];
+        compile(&node, &mut program);
+        program.push(Inst::Match);
+        Ok(Regex { program })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find_at(text, 0).is_some()
+    }
+
+    // Byte offsets of the leftmost-longest match starting at or after start
+    pub fn find_at(&self, text: &str, start: usize) -> Option<(usize, usize)> {
+        let chars = text[start..]
+            .char_indices()
+            .map(|(idx, c)| (idx + start, c))
+            .collect::<Vec<(usize, char)>>();
+        let byte_at = |idx: usize| chars.get(idx).map_or(text.len(), |(byte, _)| *byte);
+        let before = text[..start].chars().next_back();
+        self.find_chars(&chars, before)
+            .map(|(start, end)| (byte_at(start), byte_at(end)))
+    }
+
+    // Byte offsets of every match in text, leftmost-longest and not overlapping. Empty matches
+    // are skipped. The text is only split into chars once, unlike calling find_at in a loop.
+    pub fn find_all(&self, text: &str) -> Vec<(usize, usize)> {
+        let chars = text.char_indices().collect::<Vec<(usize, char)>>();
+        let byte_at = |idx: usize| chars.get(idx).map_or(text.len(), |(byte, _)| *byte);
+        let mut found = vec![];
+        let mut from = 0;
+        while from <= chars.len() {
+            let before = from.checked_sub(1).map(|idx| chars[idx].1);
+            match self.find_chars(&chars[from..], before) {
+                None => break,
+                Some((start, end)) if start == end => from += start + 1,
+                Some((start, end)) => {
+                    found.push((byte_at(from + start), byte_at(from + end)));
+                    from += end;
+                }
+            }
+        }
+        found
+    }
+
+    // sub and gsub: text with its first match, or every match when global, replaced. An & in the
+    // replacement is the matched text, \& a literal & and \\ a backslash. Also returns how many
+    // matches were replaced. An empty match right after a match is left alone, like awk does.
+    pub fn substitute(&self, text: &str, replacement: &str, global: bool) -> (String, usize) {
+        let chars = text.char_indices().collect::<Vec<(usize, char)>>();
+        let byte_at = |idx: usize| chars.get(idx).map_or(text.len(), |(byte, _)| *byte);
+        let mut result = String::new();
+        let mut count = 0;
+        let mut from = 0;
+        let mut last_end = None;
+        while from <= chars.len() && (global || count == 0) {
+            let before = from.checked_sub(1).map(|idx| chars[idx].1);
+            let (start, end) = match self.find_chars(&chars[from..], before) {
+                Some((start, end)) => (from + start, from + end),
+                None => break,
+            };
+            result.push_str(&text[byte_at(from)..byte_at(start)]);
+            if start != end || last_end != Some(start) {
+                push_replacement(
+                    &mut result,
+                    replacement,
+                    &text[byte_at(start)..byte_at(end)],
+                );
+                count += 1;
+            }
+            if start == end {
+                // Step over a char so the search moves on
+                if let Some((_, c)) = chars.get(start) {
+                    result.push(*c);
+                }
+                from = start + 1;
+            } else {
+                from = end;
+                last_end = Some(end);
+            }
+        }
+        result.push_str(&text[byte_at(from)..]);
+        (result, count)
+    }
+
+    // Char indices into chars of the leftmost-longest match. chars runs from where the search
+    // starts to the end of the text, before is the char preceding it.
+    fn find_chars(&self, chars: &[(usize, char)], before: Option<char>) -> Option<(usize, usize)> {
+        let vm = Vm {
+            program: &self.program,
+            chars,
+            before,
+        };
+
+        // Threads are kept in order of where their match started, so the leftmost wins when
+        // two reach the same instruction
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        let mut best: Option<(usize, usize)> = None;
+        for pos in 0..=chars.len() {
+            if best.is_none() {
+                vm.add_thread(&mut current, 0, pos, pos);
+            }
+            if current.list.is_empty() && best.is_some() {
+                break;
+            }
+            for idx in 0..current.list.len() {
+                let (pc, started) = current.list[idx];
+                if let Some((best_start, _)) = best {
+                    if started > best_start {
+                        break;
+                    }
+                }
+                let c = chars.get(pos).map(|(_, c)| *c);
+                let advance = match (&self.program[pc], c) {
+                    (Inst::Match, _) => {
+                        // Later matches from the same start are longer
+                        best = Some((started, pos));
+                        false
+                    }
+                    (Inst::Char(expected), Some(c)) => *expected == c,
+                    (Inst::Any, Some(_)) => true,
+                    (Inst::Class(class), Some(c)) => class.matches(c),
+                    _ => false,
+                };
+                if advance {
+                    vm.add_thread(&mut next, pc + 1, started, pos + 1);
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+        best
+    }
+}
+
+struct Threads {
+    // (instruction, char index the match started at)
+    list: Vec<(usize, usize)>,
+    seen: Vec<bool>,
+}
+
+impl Threads {
+    fn new(len: usize) -> Self {
+        Threads {
+            list: vec![],
+            seen: vec![false; len],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        self.seen.iter_mut().for_each(|seen| *seen = false);
+    }
+}
+
+struct Vm<'a> {
+    program: &'a [Inst],
+    chars: &'a [(usize, char)],
+    // The character before the text being searched, for \y and \< at the start
+    before: Option<char>,
+}
+
+impl<'a> Vm<'a> {
+    // Follow jumps, splits and assertions so the list only holds instructions that consume a
+    // character or match
+    fn add_thread(&self, threads: &mut Threads, pc: usize, started: usize, pos: usize) {
+        if threads.seen[pc] {
+            return;
+        }
+        threads.seen[pc] = true;
+        match &self.program[pc] {
+            Inst::Jump(to) => self.add_thread(threads, *to, started, pos),
+            Inst::Split(first, second) => {
+                self.add_thread(threads, *first, started, pos);
+                self.add_thread(threads, *second, started, pos);
+            }
+            Inst::Assert(assertion) => {
+                if self.holds(*assertion, pos) {
+                    self.add_thread(threads, pc + 1, started, pos);
+                }
+            }
+            _ => threads.list.push((pc, started)),
+        }
+    }
+
+    fn holds(&self, assertion: Assertion, pos: usize) -> bool {
+        let before = match pos {
+            0 => self.before.is_some_and(is_word),
+            _ => is_word(self.chars[pos - 1].1),
+        };
+        let after = self.chars.get(pos).is_some_and(|(_, c)| is_word(*c));
+        match assertion {
+            Assertion::Start => pos == 0 && self.before.is_none(),
+            Assertion::End => pos == self.chars.len(),
+            Assertion::WordBoundary => before != after,
+            Assertion::NotWordBoundary => before == after,
+            Assertion::WordStart => !before && after,
+            Assertion::WordEnd => before && !after,
+        }
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn compile(node: &Node, program: &mut Vec<Inst>) {
+    match node {
+        Node::Empty => {}
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class(class) => program.push(Inst::Class(class.clone())),
+        Node::Assert(assertion) => program.push(Inst::Assert(*assertion)),
+        Node::Concat(nodes) => {
+            for node in nodes {
+                compile(node, program);
+            }
+        }
+        Node::Alternate(nodes) => {
+            // split L1, next; L1: a; jump end; next: split L2, next2; L2: b; jump end ...
+            let mut jumps = vec![];
+            for (idx, node) in nodes.iter().enumerate() {
+                if idx == nodes.len() - 1 {
+                    compile(node, program);
+                    break;
+                }
+                let split = program.len();
+                program.push(Inst::Split(split + 1, 0));
+                compile(node, program);
+                jumps.push(program.len());
+                program.push(Inst::Jump(0));
+                program[split] = Inst::Split(split + 1, program.len());
+            }
+            let end = program.len();
+            for jump in jumps {
+                program[jump] = Inst::Jump(end);
+            }
+        }
+        Node::Repeat { node, min, max } => {
+            for _ in 0..*min {
+                compile(node, program);
+            }
+            match max {
+                // loop: split body, end; body: node; jump loop
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(node, program);
+                    program.push(Inst::Jump(split));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+                // Each optional copy can skip to the end
+                Some(max) => {
+                    let mut splits = vec![];
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(program.len() + 1, 0));
+                        compile(node, program);
+                    }
+                    let end = program.len();
+                    for split in splits {
+                        program[split] = Inst::Split(split + 1, end);
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    current: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.current).cloned()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.current += 1;
+        c
+    }
+
+    fn alternate(&mut self) -> Result<Node, String> {
+        let mut nodes = vec![self.concat()?];
+        while self.peek() == Some('|') {
+            self.advance();
+            nodes.push(self.concat()?);
+        }
+        if nodes.len() == 1 {
+            return Ok(nodes.pop().unwrap());
+        }
+        Ok(Node::Alternate(nodes))
+    }
+
+    fn concat(&mut self) -> Result<Node, String> {
+        let mut nodes = vec![];
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            nodes.push(self.repeat()?);
+        }
+        match nodes.len() {
+            0 => Ok(Node::Empty),
+            1 => Ok(nodes.pop().unwrap()),
+            _ => Ok(Node::Concat(nodes)),
+        }
+    }
+
+    fn repeat(&mut self) -> Result<Node, String> {
+        let mut node = self.atom()?;
+        loop {
+            let (min, max) = match self.peek() {
+                Some('{') => match self.interval()? {
+                    Some(interval) => interval,
+                    None => break,
+                },
+                Some('*') => self.quantifier(0, None),
+                Some('+') => self.quantifier(1, None),
+                Some('?') => self.quantifier(0, Some(1)),
+                _ => break,
+            };
+            node = Node::Repeat {
+                node: Box::new(node),
+                min,
+                max,
+            };
+        }
+        Ok(node)
+    }
+
+    fn quantifier(&mut self, min: u32, max: Option<u32>) -> (u32, Option<u32>) {
+        self.advance();
+        (min, max)
+    }
+
+    // {n}, {n,} or {n,m}, consumed. None and nothing consumed if the { doesn't start one, it
+    // is then a literal {.
+    fn interval(&mut self) -> Result<Option<(u32, Option<u32>)>, String> {
+        let start = self.current;
+        self.advance();
+        let min = self.number();
+        let max = if self.peek() == Some(',') {
+            self.advance();
+            self.number()
+        } else {
+            min
+        };
+        let (min, closed) = match (min, self.peek()) {
+            (Some(min), Some('}')) => (min, true),
+            _ => (0, false),
+        };
+        if !closed {
+            self.current = start;
+            return Ok(None);
+        }
+        self.advance();
+        if min > MAX_REPEAT || max.is_some_and(|max| max > MAX_REPEAT || max < min) {
+            return Err(format!(
+                "invalid interval {{{}}} in regex",
+                self.chars[start + 1..self.current - 1]
+                    .iter()
+                    .collect::<String>()
+            ));
+        }
+        Ok(Some((min, max)))
+    }
+
+    fn number(&mut self) -> Option<u32> {
+        let mut num: Option<u32> = None;
+        while let Some(digit) = self.peek().and_then(|c| c.to_digit(10)) {
+            num = Some(num.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+            self.advance();
+        }
+        num
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        let c = self.advance().unwrap();
+        let node = match c {
+            '(' => {
+                let node = self.alternate()?;
+                if self.advance() != Some(')') {
+                    return Err("unmatched ( in regex".to_string());
+                }
+                node
+            }
+            '.' => Node::Any,
+            '^' => Node::Assert(Assertion::Start),
+            '$' => Node::Assert(Assertion::End),
+            '[' => Node::Class(self.class()?),
+            '\\' => self.escape()?,
+            // Nothing to repeat so these are literal, like gawk
+            _ => Node::Char(c),
+        };
+        Ok(node)
+    }
+
+    fn escape(&mut self) -> Result<Node, String> {
+        let c = match self.advance() {
+            None => return Err("regex ends with a \\".to_string()),
+            Some(c) => c,
+        };
+        let class = |negated, test: fn(char) -> bool| {
+            Node::Class(Class {
+                negated,
+                items: vec![ClassItem::Named(test)],
+            })
+        };
+        let node = match c {
+            's' => class(false, char::is_whitespace),
+            'S' => class(true, char::is_whitespace),
+            'w' => class(false, is_word),
+            'W' => class(true, is_word),
+            'y' => Node::Assert(Assertion::WordBoundary),
+            'B' => Node::Assert(Assertion::NotWordBoundary),
+            '<' => Node::Assert(Assertion::WordStart),
+            '>' => Node::Assert(Assertion::WordEnd),
+            '`' => Node::Assert(Assertion::Start),
+            '\'' => Node::Assert(Assertion::End),
+            _ => Node::Char(control_escape(c)),
+        };
+        Ok(node)
+    }
+
+    // After the [
+    fn class(&mut self) -> Result<Class, String> {
+        let mut class = Class {
+            negated: false,
+            items: vec![],
+        };
+        if self.peek() == Some('^') {
+            self.advance();
+            class.negated = true;
+        }
+        // A ] right at the start is part of the class
+        let mut first = true;
+        loop {
+            let c = match self.advance() {
+                None => return Err("unterminated [ in regex".to_string()),
+                Some(c) => c,
+            };
+            let low = match c {
+                ']' if !first => break,
+                '[' if self.peek() == Some(':') => {
+                    class.items.push(ClassItem::Named(self.named_class()?));
+                    first = false;
+                    continue;
+                }
+                '\\' => match self.advance() {
+                    None => return Err("unterminated [ in regex".to_string()),
+                    Some(c) => control_escape(c),
+                },
+                _ => c,
+            };
+            first = false;
+            let is_range = self.peek() == Some('-')
+                && !matches!(self.chars.get(self.current + 1), None | Some(']'));
+            if !is_range {
+                class.items.push(ClassItem::Char(low));
+                continue;
+            }
+            self.advance();
+            let high = match self.advance() {
+                Some('\\') => match self.advance() {
+                    None => return Err("unterminated [ in regex".to_string()),
+                    Some(c) => control_escape(c),
+                },
+                Some(c) => c,
+                None => return Err("unterminated [ in regex".to_string()),
+            };
+            if high < low {
+                return Err(format!("invalid range {}-{} in regex", low, high));
+            }
+            class.items.push(ClassItem::Range(low, high));
+        }
+        Ok(class)
+    }
+
+    // After the [ of [:name:]
+    fn named_class(&mut self) -> Result<fn(char) -> bool, String> {
+        self.advance();
+        let start = self.current;
+        while self.peek().is_some_and(|c| c != ':') {
+            self.advance();
+        }
+        let name = self.chars[start..self.current].iter().collect::<String>();
+        if self.advance() != Some(':') || self.advance() != Some(']') {
+            return Err("unterminated [: in regex".to_string());
+        }
+        let test: fn(char) -> bool = match &name[..] {
+            "alpha" => char::is_alphabetic,
+            "digit" => |c| c.is_ascii_digit(),
+            "alnum" => char::is_alphanumeric,
+            "upper" => char::is_uppercase,
+            "lower" => char::is_lowercase,
+            "space" => char::is_whitespace,
+            "blank" => |c| c == ' ' || c == '\t',
+            "punct" => |c| c.is_ascii_punctuation(),
+            "print" => |c| !c.is_control(),
+            "graph" => |c| !c.is_control() && !c.is_whitespace(),
+            "cntrl" => char::is_control,
+            "xdigit" => |c| c.is_ascii_hexdigit(),
+            _ => return Err(format!("unknown character class [:{}:] in regex", name)),
+        };
+        Ok(test)
+    }
+}
+
+// \n, \t etc. Anything else escaped is itself, so \. is a literal dot.
+fn control_escape(c: char) -> char {
+    match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        'f' => '\x0c',
+        'v' => '\x0b',
+        'a' => '\x07',
+        _ => c,
+    }
+}
+
+// The replacement of sub and gsub with & as matched
+fn push_replacement(result: &mut String, replacement: &str, matched: &str) {
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(&escaped @ ('&' | '\\'))) => {
+                result.push(escaped);
+                chars.next();
+            }
+            ('&', _) => result.push_str(matched),
+            _ => result.push(c),
+        }
+    }
+}
+
+// A regex used in a loop is only compiled once
+pub struct Regexes {
+    compiled: HashMap<String, Regex>,
+}
+
+impl Regexes {
+    pub fn new() -> Self {
+        Regexes {
+            compiled: HashMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, pattern: &str) -> Result<&Regex, String> {
+        if !self.compiled.contains_key(pattern) {
+            let regex = Regex::new(pattern)?;
+            self.compiled.insert(pattern.to_string(), regex);
+        }
+        Ok(&self.compiled[pattern])
+    }
+}
+
+#[cfg(test)]
+fn find(pattern: &str, text: &str) -> Option<String> {
+    let regex = Regex::new(pattern).unwrap();
+    regex
+        .find_at(text, 0)
+        .map(|(start, end)| text[start..end].to_string())
+}
+
+#[test]
+fn test_literals_and_any() {
+    assert_eq!(find("abc", "xxabcxx"), Some("abc".to_string()));
+    assert_eq!(find("a.c", "xxabcxx"), Some("abc".to_string()));
+    assert_eq!(find("a.c", "a\nc"), Some("a\nc".to_string()));
+    assert_eq!(find("abd", "abcabc"), None);
+    assert_eq!(find("", "abc"), Some("".to_string()));
+}
+
+#[test]
+fn test_leftmost_longest() {
+    assert_eq!(find("a|ab", "xab"), Some("ab".to_string()));
+    assert_eq!(find("b+|a", "abbb"), Some("a".to_string()));
+    assert_eq!(find("(a|ab)(c|bcd)", "abcd"), Some("abcd".to_string()));
+    assert_eq!(find("x*", "aaa"), Some("".to_string()));
+}
+
+#[test]
+fn test_repeats() {
+    assert_eq!(find("ab*c", "ac abbbc"), Some("ac".to_string()));
+    assert_eq!(find("ab+c", "ac abbbc"), Some("abbbc".to_string()));
+    assert_eq!(find("ab?c", "abbc abc"), Some("abc".to_string()));
+    assert_eq!(find("a{2}", "a aa"), Some("aa".to_string()));
+    assert_eq!(find("a{2,}", "aaaa"), Some("aaaa".to_string()));
+    assert_eq!(find("a{1,2}", "aaaa"), Some("aa".to_string()));
+    assert_eq!(find("(ab){2}", "ababab"), Some("abab".to_string()));
+    assert_eq!(find("(a*)*b", "aab"), Some("aab".to_string()));
+    // Not an interval so a literal {
+    assert_eq!(find("a{x", "a{x"), Some("a{x".to_string()));
+    assert!(Regex::new("a{3,1}").is_err());
+}
+
+#[test]
+fn test_anchors() {
+    assert_eq!(find("^ab", "abab"), Some("ab".to_string()));
+    assert_eq!(find("^b", "ab"), None);
+    assert_eq!(find("b$", "abab"), Some("b".to_string()));
+    assert_eq!(find("^$", ""), Some("".to_string()));
+    assert_eq!(find("a$", "a\nb"), None);
+    let regex = Regex::new("^a").unwrap();
+    assert_eq!(regex.find_at("aa", 1), None);
+}
+
+#[test]
+fn test_classes() {
+    assert_eq!(find("[a-c]+", "xxbcay"), Some("bca".to_string()));
+    assert_eq!(find("[^a-c]+", "abxyc"), Some("xy".to_string()));
+    assert_eq!(find("[]a]+", "x]a]"), Some("]a]".to_string()));
+    assert_eq!(find("[a-]+", "x-a-"), Some("-a-".to_string()));
+    assert_eq!(find("[[:digit:]]+", "ab123c"), Some("123".to_string()));
+    assert_eq!(find("[[:alpha:]_]+", "1a_b2"), Some("a_b".to_string()));
+    assert_eq!(find("[\\]]", "a]"), Some("]".to_string()));
+    assert!(Regex::new("[a").is_err());
+    assert!(Regex::new("[[:nope:]]").is_err());
+    assert!(Regex::new("[z-a]").is_err());
+}
+
+#[test]
+fn test_escapes() {
+    assert_eq!(find("a\\.b", "axb a.b"), Some("a.b".to_string()));
+    assert_eq!(find("\\/", "a/b"), Some("/".to_string()));
+    assert_eq!(find("\\t", "a\tb"), Some("\t".to_string()));
+    assert_eq!(find("\\w+", "  ab_1 "), Some("ab_1".to_string()));
+    assert_eq!(find("\\S+", "  ab "), Some("ab".to_string()));
+    assert_eq!(find("\\yb\\w*", "ab bc"), Some("bc".to_string()));
+    assert_eq!(find("\\<c", "abc cd"), Some("c".to_string()));
+    assert_eq!(find("b\\>", "abc ab"), Some("b".to_string()));
+    assert!(Regex::new("a\\").is_err());
+}
+
+#[test]
+fn test_groups() {
+    assert_eq!(find("(ab|cd)+", "xabcdab"), Some("abcdab".to_string()));
+    assert_eq!(find("a()b", "ab"), Some("ab".to_string()));
+    assert!(Regex::new("(a").is_err());
+    assert!(Regex::new("a)").is_err());
+}
+
+#[test]
+fn test_unicode_offsets() {
+    let regex = Regex::new("é+").unwrap();
+    assert_eq!(regex.find_at("aéébé", 0), Some((1, 5)));
+    assert_eq!(regex.find_at("aéébé", 5), Some((6, 8)));
+}
+
+#[test]
+fn test_find_all() {
+    let regex = Regex::new("é+|x*").unwrap();
+    assert_eq!(regex.find_all("aéébéx"), vec![(1, 5), (6, 8), (8, 9)]);
+    let regex = Regex::new("^a|b$").unwrap();
+    assert_eq!(regex.find_all("aab"), vec![(0, 1), (2, 3)]);
+    assert!(regex.find_all("").is_empty());
+}
+
+#[test]
+fn test_substitute() {
+    let regex = Regex::new("b*").unwrap();
+    assert_eq!(regex.substitute("abc", "-", true), ("-a-c-".to_string(), 3));
+    assert_eq!(regex.substitute("abc", "-", false), ("-abc".to_string(), 1));
+    let regex = Regex::new("^a").unwrap();
+    assert_eq!(regex.substitute("aaa", "x", true), ("xaa".to_string(), 1));
+    let regex = Regex::new("é").unwrap();
+    let replaced = regex.substitute("aébé", "[&|\\&|\\\\&]", true);
+    assert_eq!(replaced, ("a[é|&|\\é]b[é|&|\\é]".to_string(), 2));
+    assert_eq!(regex.substitute("abc", "x", true), ("abc".to_string(), 0));
+}
+
+#[test]
+fn test_regexes_cache() {
+    let mut regexes = Regexes::new();
+    assert!(regexes.get("a+").unwrap().is_match("baa"));
+    assert!(!regexes.get("a+").unwrap().is_match("b"));
+    assert!(regexes.get("(").is_err());
+}
@@ -0,0 +1,403 @@
+use crate::codgen::{FLOAT_TAG, STRING_TAG, UNINIT_TAG};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// Name of the array the header option exposes fields through. Lowercase like every identifier.
+pub const HEADER_ARRAY: &str = "F";
+
+// Holds the environment jawk was started with
+pub const ENVIRON: &str = "ENVIRON";
+
+// An array read hands its result back through this so the jit can load all three parts.
+// Same layout as a ValueT.
+#[repr(C)]
+pub struct ValueOut {
+    pub tag: u8,
+    pub float: f64,
+    pub pointer: *const String,
+}
+
+impl ValueOut {
+    pub const TAG_OFFSET: usize = std::mem::offset_of!(ValueOut, tag);
+    pub const FLOAT_OFFSET: usize = std::mem::offset_of!(ValueOut, float);
+    pub const POINTER_OFFSET: usize = std::mem::offset_of!(ValueOut, pointer);
+
+    pub fn new() -> Self {
+        ValueOut {
+            tag: UNINIT_TAG,
+            float: 0.0,
+            pointer: std::ptr::null(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ArrayValue {
+    Float(f64),
+    String(Rc<String>),
+    Uninit,
+    // gawk's a[1][2], the element is itself an array, by id
+    Array(i32),
+}
+
+impl ArrayValue {
+    // Takes ownership of the string if there is one
+    pub fn from_parts(tag: u8, float: f64, pointer: *const String) -> Self {
+        match tag {
+            FLOAT_TAG => ArrayValue::Float(float),
+            _ => ArrayValue::String(unsafe { Rc::from_raw(pointer) }),
+        }
+    }
+
+    // The caller owns the returned string. None for a sub-array which has no scalar value.
+    pub fn to_out(&self) -> Option<ValueOut> {
+        let out = match self {
+            ArrayValue::Float(float) => ValueOut {
+                tag: FLOAT_TAG,
+                float: *float,
+                pointer: std::ptr::null(),
+            },
+            ArrayValue::String(string) => ValueOut {
+                tag: STRING_TAG,
+                float: 0.0,
+                pointer: Rc::into_raw(string.clone()),
+            },
+            ArrayValue::Uninit => ValueOut {
+                tag: UNINIT_TAG,
+                float: 0.0,
+                pointer: Rc::into_raw(Rc::new(String::new())),
+            },
+            ArrayValue::Array(_) => return None,
+        };
+        Some(out)
+    }
+}
+
+// Every array in the program. Arrays are referred to by an id handed out at compile time, or
+// when a sub-array is created.
+pub struct Arrays {
+    ids: HashMap<String, i32>,
+    arrays: Vec<HashMap<String, ArrayValue>>,
+    // Keys still to visit for each for (k in a) being run, innermost loop last
+    iterators: Vec<Vec<String>>,
+}
+
+impl Arrays {
+    pub fn new() -> Self {
+        Arrays {
+            ids: HashMap::new(),
+            arrays: vec![],
+            iterators: vec![],
+        }
+    }
+
+    pub fn define(&mut self, name: &str) -> i32 {
+        if let Some(id) = self.ids.get(name) {
+            return *id;
+        }
+        let id = self.arrays.len() as i32;
+        self.arrays.push(HashMap::new());
+        self.ids.insert(name.to_string(), id);
+        // Filled in when a program first refers to it, most don't
+        if name == ENVIRON {
+            for (key, value) in std::env::vars_os() {
+                let value = value.to_string_lossy().into_owned();
+                self.assign(
+                    id,
+                    key.to_string_lossy().into_owned(),
+                    ArrayValue::String(Rc::new(value)),
+                );
+            }
+        }
+        id
+    }
+
+    pub fn id(&self, name: &str) -> Option<i32> {
+        self.ids.get(name).cloned()
+    }
+
+    // Referencing a missing element creates it, like awk
+    pub fn get(&mut self, array: i32, key: String) -> &ArrayValue {
+        self.arrays[array as usize]
+            .entry(key)
+            .or_insert(ArrayValue::Uninit)
+    }
+
+    pub fn assign(&mut self, array: i32, key: String, value: ArrayValue) {
+        self.arrays[array as usize].insert(key, value);
+    }
+
+    // Empties the array, and any sub-arrays in it
+    pub fn clear(&mut self, array: i32) {
+        for (_, value) in std::mem::take(&mut self.arrays[array as usize]) {
+            if let ArrayValue::Array(id) = value {
+                self.clear(id);
+            }
+        }
+    }
+
+    // delete a[key], a sub-array stored there is emptied too. A missing key is fine.
+    pub fn delete(&mut self, array: i32, key: &str) {
+        if let Some(ArrayValue::Array(id)) = self.arrays[array as usize].remove(key) {
+            self.clear(id);
+        }
+    }
+
+    // patsplit's result, both arrays are emptied first. Fields are stored from 1 and seps[i] is
+    // the text after field i. Like gawk seps[0] and the text after the last field are only
+    // stored when there is some.
+    pub fn store_split(
+        &mut self,
+        array: i32,
+        seps_array: Option<i32>,
+        fields: Vec<String>,
+        seps: Vec<String>,
+    ) {
+        self.clear(array);
+        let count = fields.len();
+        for (idx, field) in fields.into_iter().enumerate() {
+            self.assign(
+                array,
+                (idx + 1).to_string(),
+                ArrayValue::String(Rc::new(field)),
+            );
+        }
+        if let Some(seps_array) = seps_array {
+            self.clear(seps_array);
+            for (idx, sep) in seps.into_iter().enumerate() {
+                if (idx == 0 || idx == count) && sep.is_empty() {
+                    continue;
+                }
+                self.assign(
+                    seps_array,
+                    idx.to_string(),
+                    ArrayValue::String(Rc::new(sep)),
+                );
+            }
+        }
+    }
+
+    // The array at a[key], created if the element doesn't exist yet. Err if it holds a scalar.
+    pub fn subarray(&mut self, array: i32, key: String) -> Result<i32, String> {
+        let new_id = self.arrays.len() as i32;
+        let element = self.arrays[array as usize]
+            .entry(key.clone())
+            .or_insert(ArrayValue::Uninit);
+        match element {
+            ArrayValue::Array(id) => Ok(*id),
+            ArrayValue::Uninit => {
+                *element = ArrayValue::Array(new_id);
+                self.arrays.push(HashMap::new());
+                Ok(new_id)
+            }
+            _ => Err(format!("attempt to use scalar `{}` as an array", key)),
+        }
+    }
+
+    // key in a, which unlike a[key] doesn't create the element
+    pub fn contains(&self, array: i32, key: &str) -> bool {
+        self.arrays[array as usize].contains_key(key)
+    }
+
+    // isarray(a[key]), without creating the element
+    pub fn is_array(&self, array: i32, key: &str) -> bool {
+        matches!(
+            self.arrays[array as usize].get(key),
+            Some(ArrayValue::Array(_))
+        )
+    }
+
+    // typeof(a[key]), without creating the element. A string element never came from a field
+    // here so it is never a strnum.
+    pub fn type_of(&self, array: i32, key: &str) -> &'static str {
+        match self.arrays[array as usize].get(key) {
+            None => "untyped",
+            Some(ArrayValue::Uninit) => "unassigned",
+            Some(ArrayValue::Float(_)) => "number",
+            Some(ArrayValue::String(_)) => "string",
+            Some(ArrayValue::Array(_)) => "array",
+        }
+    }
+
+    // Start a for (k in a) loop over the keys a has now. Returns the iterator for next_key.
+    pub fn iterate(&mut self, array: i32) -> i32 {
+        let mut keys = self.arrays[array as usize]
+            .keys()
+            .cloned()
+            .collect::<Vec<String>>();
+        // Sorted so the order is the same every run, popped from the back
+        keys.sort_unstable_by(|a, b| b.cmp(a));
+        self.iterators.push(keys);
+        self.iterators.len() as i32 - 1
+    }
+
+    // None once the loop is done, which also drops the iterator
+    pub fn next_key(&mut self, iterator: i32) -> Option<String> {
+        let key = self.iterators[iterator as usize].pop();
+        if key.is_none() {
+            self.iterators.truncate(iterator as usize);
+        }
+        key
+    }
+
+    // Drops the iterator of a loop left early, and those of any loops inside it
+    pub fn stop_iterating(&mut self, iterator: i32) {
+        self.iterators.truncate(iterator as usize);
+    }
+}
+
+#[test]
+fn test_arrays() {
+    let mut arrays = Arrays::new();
+    let a = arrays.define("a");
+    let b = arrays.define("b");
+    assert_ne!(a, b);
+    assert_eq!(arrays.define("a"), a);
+    assert_eq!(arrays.id("b"), Some(b));
+    assert_eq!(arrays.id("c"), None);
+
+    assert_eq!(arrays.get(a, "x".to_string()), &ArrayValue::Uninit);
+    arrays.assign(a, "x".to_string(), ArrayValue::Float(1.0));
+    assert_eq!(arrays.get(a, "x".to_string()), &ArrayValue::Float(1.0));
+    assert_eq!(arrays.get(b, "x".to_string()), &ArrayValue::Uninit);
+}
+
+#[test]
+fn test_environ() {
+    let mut arrays = Arrays::new();
+    let environ = arrays.define(ENVIRON);
+    let path = std::env::var("PATH").unwrap();
+    assert_eq!(
+        arrays.get(environ, "PATH".to_string()),
+        &ArrayValue::String(Rc::new(path))
+    );
+}
+
+#[test]
+fn test_subarrays() {
+    let mut arrays = Arrays::new();
+    let a = arrays.define("a");
+    let b = arrays.define("b");
+    let x = arrays.subarray(a, "x".to_string()).unwrap();
+    assert_ne!(x, b);
+    assert_eq!(arrays.subarray(a, "x".to_string()), Ok(x));
+    assert!(arrays.is_array(a, "x"));
+    assert!(!arrays.is_array(a, "y"));
+    assert!(arrays.get(a, "x".to_string()).to_out().is_none());
+
+    arrays.assign(x, "y".to_string(), ArrayValue::Float(1.0));
+    assert_eq!(arrays.get(x, "y".to_string()), &ArrayValue::Float(1.0));
+    assert!(!arrays.is_array(x, "y"));
+    assert!(arrays.subarray(x, "y".to_string()).is_err());
+    // Referencing an element creates it so it can become a sub-array later
+    arrays.get(b, "z".to_string());
+    assert!(arrays.subarray(b, "z".to_string()).is_ok());
+}
+
+#[test]
+fn test_delete() {
+    let mut arrays = Arrays::new();
+    let a = arrays.define("a");
+    arrays.assign(a, "x".to_string(), ArrayValue::Float(1.0));
+    arrays.assign(a, "y".to_string(), ArrayValue::Float(2.0));
+    let z = arrays.subarray(a, "z".to_string()).unwrap();
+    arrays.assign(z, "w".to_string(), ArrayValue::Float(3.0));
+
+    arrays.delete(a, "x");
+    arrays.delete(a, "missing");
+    assert!(!arrays.contains(a, "x"));
+    assert!(arrays.contains(a, "y"));
+    arrays.delete(a, "z");
+    assert!(!arrays.contains(a, "z"));
+    assert!(!arrays.contains(z, "w"));
+    // A new a["z"] is a new sub-array, not the deleted one
+    assert_ne!(arrays.subarray(a, "z".to_string()), Ok(z));
+
+    let z = arrays.subarray(a, "z".to_string()).unwrap();
+    arrays.assign(z, "w".to_string(), ArrayValue::Float(3.0));
+    arrays.clear(a);
+    assert!(!arrays.contains(a, "y"));
+    assert!(!arrays.contains(z, "w"));
+    arrays.assign(a, "x".to_string(), ArrayValue::Float(1.0));
+    assert_eq!(arrays.get(a, "x".to_string()), &ArrayValue::Float(1.0));
+}
+
+#[test]
+fn test_type_of() {
+    let mut arrays = Arrays::new();
+    let a = arrays.define("a");
+    arrays.assign(a, "n".to_string(), ArrayValue::Float(1.0));
+    arrays.assign(
+        a,
+        "s".to_string(),
+        ArrayValue::String(Rc::new("x".to_string())),
+    );
+    arrays.get(a, "u".to_string());
+    arrays.subarray(a, "a".to_string()).unwrap();
+    assert_eq!(arrays.type_of(a, "n"), "number");
+    assert_eq!(arrays.type_of(a, "s"), "string");
+    assert_eq!(arrays.type_of(a, "u"), "unassigned");
+    assert_eq!(arrays.type_of(a, "a"), "array");
+    assert_eq!(arrays.type_of(a, "missing"), "untyped");
+    assert_eq!(arrays.type_of(a, "missing"), "untyped");
+    assert!(arrays.contains(a, "u"));
+    assert!(!arrays.contains(a, "missing"));
+}
+
+#[test]
+fn test_iterate() {
+    let mut arrays = Arrays::new();
+    let a = arrays.define("a");
+    let b = arrays.define("b");
+    for key in ["2", "1", "3"] {
+        arrays.assign(a, key.to_string(), ArrayValue::Uninit);
+    }
+    arrays.assign(b, "x".to_string(), ArrayValue::Uninit);
+
+    let outer = arrays.iterate(a);
+    assert_eq!(arrays.next_key(outer), Some("1".to_string()));
+    let inner = arrays.iterate(b);
+    assert_ne!(outer, inner);
+    // Changes during the loop don't change what's visited
+    arrays.assign(b, "y".to_string(), ArrayValue::Uninit);
+    assert_eq!(arrays.next_key(inner), Some("x".to_string()));
+    assert_eq!(arrays.next_key(inner), None);
+    assert_eq!(arrays.next_key(outer), Some("2".to_string()));
+    assert_eq!(arrays.next_key(outer), Some("3".to_string()));
+    assert_eq!(arrays.next_key(outer), None);
+    assert_eq!(arrays.iterate(b), outer);
+    // Breaking out of both loops from the inner one
+    let inner = arrays.iterate(a);
+    assert_eq!(arrays.next_key(inner), Some("1".to_string()));
+    arrays.stop_iterating(outer);
+    assert_eq!(arrays.iterate(a), outer);
+}
+
+#[test]
+fn test_store_split() {
+    let mut arrays = Arrays::new();
+    let a = arrays.define("a");
+    let seps = arrays.define("seps");
+    arrays.assign(a, "old".to_string(), ArrayValue::Float(1.0));
+    let strings = |strs: &[&str]| strs.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+    arrays.store_split(
+        a,
+        Some(seps),
+        strings(&["x", "y"]),
+        strings(&["", "-", "."]),
+    );
+    assert_eq!(arrays.type_of(a, "old"), "untyped");
+    assert_eq!(
+        arrays.get(a, "2".to_string()),
+        &ArrayValue::String(Rc::new("y".to_string()))
+    );
+    assert_eq!(arrays.type_of(seps, "0"), "untyped");
+    assert_eq!(
+        arrays.get(seps, "1".to_string()),
+        &ArrayValue::String(Rc::new("-".to_string()))
+    );
+    assert_eq!(
+        arrays.get(seps, "2".to_string()),
+        &ArrayValue::String(Rc::new(".".to_string()))
+    );
+}
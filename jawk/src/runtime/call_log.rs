@@ -12,7 +12,38 @@ pub enum Call {
     PrintString,
     EmptyString,
     PrintFloat,
+    PrintOutput,
     BinOp,
+    TypeOf,
+    PushFormatArg,
+    Printf,
+    Sprintf,
+    ArrayGet,
+    ArrayAssign,
+    Subarray,
+    ArrayTypeOf,
+    ArrayContains,
+    ArrayIsArray,
+    ArrayIterate,
+    ArrayNextKey,
+    ArrayStopIterating,
+    ArrayDelete,
+    ArrayClear,
+    Getline,
+    RecordVar,
+    SetRecordVar,
+    RecordTerminator,
+    SetColumn,
+    Close,
+    System,
+    ChangeCase,
+    MatchIndex,
+    Substitute,
+    Patsplit,
+    SetNumberFormat,
+    SetSeparator,
+    Rand,
+    Srand,
     Malloc,
     Realloc,
     Free,
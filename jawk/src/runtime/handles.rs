@@ -0,0 +1,499 @@
+use crate::runtime::{OUTPUT_APPEND, OUTPUT_COPROCESS, OUTPUT_PIPE};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Error, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+// What a name in Handles is open as, by how it was first used
+enum Handle {
+    // getline < file
+    ReadFile(BufReader<File>),
+    // cmd | getline
+    ReadCommand(Child, BufReader<ChildStdout>),
+    // print > file truncates it when opening and print >> file appends, after that they are the
+    // same
+    WriteFile(BufWriter<File>),
+    // print | cmd
+    WriteCommand(Child, BufWriter<ChildStdin>),
+    // print |& cmd and cmd |& getline, both ends of one command. Each end is None once
+    // close(cmd, "to") or close(cmd, "from") shut it.
+    Coprocess {
+        child: Child,
+        to: Option<BufWriter<ChildStdin>>,
+        from: Option<BufReader<ChildStdout>>,
+    },
+    // getline < "/dev/stdin" or "-". Both names read through stdin's one shared buffer.
+    ReadStdin,
+    // print > "/dev/stderr", written straight away so it interleaves with stdout
+    WriteStderr,
+}
+
+// Names that are the process's own streams rather than files, like in gawk. They work whether
+// or not the system has them as files.
+const STDERR: &str = "/dev/stderr";
+
+pub fn is_stdin(name: &str) -> bool {
+    name == "/dev/stdin" || name == "-"
+}
+
+// print > "/dev/stdout" is the same as a plain print, the runtime sends it to its own output
+// instead of here so it stays in order with the rest
+pub fn is_stdout(name: &str) -> bool {
+    name == "/dev/stdout"
+}
+
+// How many files and commands can be open at once before close() has to be used
+const MAX_OPEN: usize = 256;
+
+// Files opened by getline < file and print > file or >> file, and commands started by
+// cmd | getline and print | cmd. They share one table keyed by the exact string used in the
+// program and stay open so each use continues from where the last one stopped. close(name)
+// removes the entry so the next use opens it again, which truncates again for print > file.
+pub struct Handles {
+    open: HashMap<String, Handle>,
+}
+
+impl Handles {
+    pub fn new() -> Self {
+        Handles {
+            open: HashMap::new(),
+        }
+    }
+
+    // The entry for name, opened with open if there isn't one yet
+    fn get_or_open(
+        &mut self,
+        name: &str,
+        open: impl FnOnce() -> Result<Handle, Error>,
+    ) -> Result<&mut Handle, Error> {
+        if !self.open.contains_key(name) {
+            if self.open.len() >= MAX_OPEN {
+                return Err(Error::other(format!(
+                    "{} files and commands are already open, close() some of them first",
+                    MAX_OPEN
+                )));
+            }
+            let handle = open()?;
+            self.open.insert(name.to_string(), handle);
+        }
+        Ok(self.open.get_mut(name).unwrap())
+    }
+
+    // kind is one of the OUTPUT_ consts and only matters when name isn't open yet. Err if it
+    // can't be opened or written to, or is open for reading.
+    pub fn write(&mut self, name: &str, kind: u8, bytes: &[u8]) -> Result<(), Error> {
+        let handle = self.get_or_open(name, || {
+            if name == STDERR {
+                return Ok(Handle::WriteStderr);
+            }
+            Ok(match kind {
+                OUTPUT_COPROCESS => coprocess(name)?,
+                OUTPUT_PIPE => {
+                    let mut child = spawn(name, Stdio::piped(), Stdio::inherit())?;
+                    let stdin = BufWriter::new(child.stdin.take().unwrap());
+                    Handle::WriteCommand(child, stdin)
+                }
+                OUTPUT_APPEND => {
+                    let file = OpenOptions::new().append(true).create(true).open(name)?;
+                    Handle::WriteFile(BufWriter::new(file))
+                }
+                _ => Handle::WriteFile(BufWriter::new(File::create(name)?)),
+            })
+        })?;
+        let writer: &mut dyn Write = match handle {
+            Handle::WriteFile(writer) => writer,
+            Handle::WriteCommand(_, writer) => writer,
+            Handle::WriteStderr => {
+                let _ = std::io::stdout().flush();
+                return std::io::stderr().write_all(bytes);
+            }
+            Handle::Coprocess { to: Some(to), .. } => {
+                // Sent right away, the command may answer before we print anything else
+                to.write_all(bytes)?;
+                return to.flush();
+            }
+            Handle::Coprocess { to: None, .. } => return Err(already_closed(name, "to")),
+            Handle::ReadFile(_) | Handle::ReadCommand(..) | Handle::ReadStdin => {
+                return Err(already_open(name, "reading"))
+            }
+        };
+        writer.write_all(bytes)
+    }
+
+    // Called once the program is done, normally or by exit, so everything printed to files and
+    // commands is written and every command has finished before we exit. Stdout goes first
+    // since it was printed to before anything the commands print now.
+    pub fn close_all(&mut self) {
+        let _ = std::io::stdout().flush();
+        for (_name, handle) in self.open.drain() {
+            close_handle(handle);
+        }
+    }
+
+    // Ok(None) at the end of the input. Err if it can't be opened or read, or is open for
+    // writing.
+    pub fn read_line(&mut self, name: &str) -> Result<Option<String>, Error> {
+        let handle = self.get_or_open(name, || {
+            if is_stdin(name) {
+                return Ok(Handle::ReadStdin);
+            }
+            Ok(Handle::ReadFile(BufReader::new(File::open(name)?)))
+        })?;
+        read_open(name, handle)
+    }
+
+    // Same as read_line but reads what the coprocess command prints
+    pub fn read_coprocess_line(&mut self, command: &str) -> Result<Option<String>, Error> {
+        let handle = self.get_or_open(command, || coprocess(command))?;
+        read_open(command, handle)
+    }
+
+    // Same as read_line but name is a shell command whose output is read
+    pub fn read_command_line(&mut self, command: &str) -> Result<Option<String>, Error> {
+        let handle = self.get_or_open(command, || {
+            let mut child = spawn(command, Stdio::inherit(), Stdio::piped())?;
+            let stdout = BufReader::new(child.stdout.take().unwrap());
+            Ok(Handle::ReadCommand(child, stdout))
+        })?;
+        read_open(command, handle)
+    }
+
+    // 0 for a closed file, a command's exit status, -1 if nothing by that name was open
+    pub fn close(&mut self, name: &str) -> f64 {
+        match self.open.remove(name) {
+            Some(handle) => close_handle(handle),
+            None => -1.0,
+        }
+    }
+
+    // close(name) when how is empty, otherwise close(name, how) where how is "to" or "from"
+    pub fn close_how(&mut self, name: &str, how: &str) -> f64 {
+        match how {
+            "" => self.close(name),
+            "to" => self.close_coprocess(name, true),
+            "from" => self.close_coprocess(name, false),
+            _ => -1.0,
+        }
+    }
+
+    // close(name, "to") when to otherwise close(name, "from"), for a coprocess. Closing "to"
+    // lets the command see the end of its input. Once both ends are closed it is waited for
+    // and the result is its exit status, until then 0. -1 if name isn't an open coprocess.
+    pub fn close_coprocess(&mut self, name: &str, to: bool) -> f64 {
+        let (to_end, from_end) = match self.open.get_mut(name) {
+            Some(Handle::Coprocess { to, from, .. }) => (to, from),
+            _ => return -1.0,
+        };
+        if to {
+            if let Some(mut to_end) = to_end.take() {
+                let _ = to_end.flush();
+            }
+        } else {
+            from_end.take();
+        }
+        if to_end.is_none() && from_end.is_none() {
+            return self.close(name);
+        }
+        0.0
+    }
+}
+
+// Commands run with sh like system's. Output printed so far is flushed first so it comes
+// before anything the command prints.
+fn spawn(command: &str, stdin: Stdio, stdout: Stdio) -> Result<Child, Error> {
+    let _ = std::io::stdout().flush();
+    Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(stdin)
+        .stdout(stdout)
+        .spawn()
+}
+
+// Both ends of command piped to us
+fn coprocess(command: &str) -> Result<Handle, Error> {
+    let mut child = spawn(command, Stdio::piped(), Stdio::piped())?;
+    let to = BufWriter::new(child.stdin.take().unwrap());
+    let from = BufReader::new(child.stdout.take().unwrap());
+    Ok(Handle::Coprocess {
+        child,
+        to: Some(to),
+        from: Some(from),
+    })
+}
+
+fn already_closed(name: &str, end: &str) -> Error {
+    Error::other(format!("`{}` was closed with close(\"{}\")", name, end))
+}
+
+fn already_open(name: &str, how: &str) -> Error {
+    Error::other(format!("`{}` is open for {}, close() it first", name, how))
+}
+
+fn read_open(name: &str, handle: &mut Handle) -> Result<Option<String>, Error> {
+    let mut line = String::new();
+    let read = match handle {
+        Handle::ReadFile(reader) => reader.read_line(&mut line),
+        Handle::ReadCommand(_, reader) => reader.read_line(&mut line),
+        Handle::ReadStdin => std::io::stdin().read_line(&mut line),
+        Handle::Coprocess {
+            from: Some(from), ..
+        } => from.read_line(&mut line),
+        Handle::Coprocess { from: None, .. } => return Err(already_closed(name, "from")),
+        Handle::WriteFile(_) | Handle::WriteCommand(..) | Handle::WriteStderr => {
+            return Err(already_open(name, "writing"))
+        }
+    };
+    if read? == 0 {
+        return Ok(None);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+// Same results as Handles::close. This is the only place a command is waited for and the
+// handle is gone after it, so each command is reaped exactly once.
+fn close_handle(handle: Handle) -> f64 {
+    match handle {
+        Handle::ReadFile(_) | Handle::ReadStdin | Handle::WriteStderr => 0.0,
+        Handle::WriteFile(mut writer) => match writer.flush() {
+            Ok(()) => 0.0,
+            Err(_) => -1.0,
+        },
+        Handle::ReadCommand(child, stdout) => {
+            // Closing our end first lets a command still writing finish instead of blocking
+            drop(stdout);
+            wait(child)
+        }
+        Handle::WriteCommand(child, mut stdin) => {
+            // The command only sees the end of its input once stdin is dropped
+            let _ = stdin.flush();
+            drop(stdin);
+            wait(child)
+        }
+        Handle::Coprocess { child, to, from } => {
+            if let Some(mut to) = to {
+                let _ = to.flush();
+            }
+            drop(from);
+            wait(child)
+        }
+    }
+}
+
+fn wait(mut child: Child) -> f64 {
+    match child.wait() {
+        Ok(status) => status.code().unwrap_or(-1) as f64,
+        Err(_) => -1.0,
+    }
+}
+
+// system(command). Output printed so far is flushed first so it comes before the command's.
+// Returns the exit status, 256 + the signal if the command was killed, -1 if it couldn't run.
+pub fn system(command: &str) -> f64 {
+    use std::io::Write;
+    use std::os::unix::process::ExitStatusExt;
+
+    let _ = std::io::stdout().flush();
+    match Command::new("sh").arg("-c").arg(command).status() {
+        Ok(status) => match (status.code(), status.signal()) {
+            (Some(code), _) => code as f64,
+            (None, Some(signal)) => (256 + signal) as f64,
+            (None, None) => -1.0,
+        },
+        Err(_) => -1.0,
+    }
+}
+
+// 1 a line was read, 0 end of input, -1 error. This is getline's return value.
+pub fn getline_result(line: &Result<Option<String>, Error>) -> f64 {
+    match line {
+        Ok(Some(_)) => 1.0,
+        Ok(None) => 0.0,
+        Err(_) => -1.0,
+    }
+}
+
+#[test]
+fn test_read_and_close() {
+    use tempfile::tempdir;
+
+    let temp_dir = tempdir().unwrap();
+    let file_path = temp_dir.path().join("lookup.txt");
+    std::fs::write(file_path.clone(), "a b\nc\n").unwrap();
+    let name = file_path.to_str().unwrap();
+
+    let mut handles = Handles::new();
+    assert_eq!(handles.read_line(name).unwrap(), Some("a b".to_string()));
+    assert_eq!(handles.read_line(name).unwrap(), Some("c".to_string()));
+    assert_eq!(handles.read_line(name).unwrap(), None);
+    assert_eq!(handles.close(name), 0.0);
+    assert_eq!(handles.close(name), -1.0);
+    assert_eq!(handles.read_line(name).unwrap(), Some("a b".to_string()));
+
+    let missing = temp_dir.path().join("missing.txt");
+    let missing = handles.read_line(missing.to_str().unwrap());
+    assert_eq!(getline_result(&missing), -1.0);
+}
+
+#[test]
+fn test_read_command_and_close() {
+    let mut handles = Handles::new();
+    let cmd = "printf 'a\\nb\\n'; exit 3";
+    assert_eq!(
+        handles.read_command_line(cmd).unwrap(),
+        Some("a".to_string())
+    );
+    assert_eq!(
+        handles.read_command_line(cmd).unwrap(),
+        Some("b".to_string())
+    );
+    assert_eq!(handles.read_command_line(cmd).unwrap(), None);
+    assert_eq!(handles.close(cmd), 3.0);
+    assert_eq!(handles.close(cmd), -1.0);
+    assert_eq!(
+        handles.read_command_line(cmd).unwrap(),
+        Some("a".to_string())
+    );
+    assert_eq!(handles.close(cmd), 3.0);
+}
+
+#[test]
+fn test_write_and_close() {
+    use crate::runtime::OUTPUT_FILE;
+    use tempfile::tempdir;
+
+    let temp_dir = tempdir().unwrap();
+    let file_path = temp_dir.path().join("out.txt");
+    let name = file_path.to_str().unwrap();
+
+    let mut handles = Handles::new();
+    handles.write(name, OUTPUT_FILE, b"a\n").unwrap();
+    handles.write(name, OUTPUT_FILE, b"b\n").unwrap();
+    assert_eq!(handles.close(name), 0.0);
+    assert_eq!(std::fs::read_to_string(name).unwrap(), "a\nb\n");
+    handles.write(name, OUTPUT_APPEND, b"c\n").unwrap();
+    handles.close_all();
+    assert_eq!(std::fs::read_to_string(name).unwrap(), "a\nb\nc\n");
+    assert_eq!(handles.close(name), -1.0);
+
+    let cmd = format!("cat > {}; exit 4", name);
+    handles.write(&cmd, OUTPUT_PIPE, b"piped\n").unwrap();
+    assert_eq!(handles.close(&cmd), 4.0);
+    assert_eq!(std::fs::read_to_string(name).unwrap(), "piped\n");
+}
+
+#[test]
+fn test_truncate_after_close() {
+    use crate::runtime::OUTPUT_FILE;
+    use tempfile::tempdir;
+
+    let temp_dir = tempdir().unwrap();
+    let file_path = temp_dir.path().join("out.txt");
+    let name = file_path.to_str().unwrap();
+
+    let mut handles = Handles::new();
+    handles.write(name, OUTPUT_FILE, b"a\n").unwrap();
+    // Already open, so this appends even as print > name
+    handles.write(name, OUTPUT_FILE, b"b\n").unwrap();
+    assert_eq!(handles.close(name), 0.0);
+    handles.write(name, OUTPUT_FILE, b"c\n").unwrap();
+    handles.close_all();
+    assert_eq!(std::fs::read_to_string(name).unwrap(), "c\n");
+}
+
+#[test]
+fn test_commands_reaped_once() {
+    use tempfile::tempdir;
+
+    let temp_dir = tempdir().unwrap();
+    let log_path = temp_dir.path().join("log.txt");
+    let log = log_path.to_str().unwrap();
+
+    let mut handles = Handles::new();
+    let cmd = format!("cat > /dev/null; echo done >> {}; exit 5", log);
+    handles.write(&cmd, OUTPUT_PIPE, b"x\n").unwrap();
+    assert_eq!(handles.close(&cmd), 5.0);
+    assert_eq!(handles.close(&cmd), -1.0);
+    handles.close_all();
+    assert_eq!(std::fs::read_to_string(log).unwrap(), "done\n");
+
+    // close_all waits for a command being read from even if it is still running
+    let reading = format!("echo a; sleep 0.2; echo read >> {}", log);
+    assert_eq!(
+        handles.read_command_line(&reading).unwrap(),
+        Some("a".to_string())
+    );
+    handles.close_all();
+    assert_eq!(std::fs::read_to_string(log).unwrap(), "done\nread\n");
+    assert_eq!(handles.close(&reading), -1.0);
+}
+
+#[test]
+fn test_coprocess() {
+    let mut handles = Handles::new();
+    let cmd = "cat; exit 3";
+    handles.write(cmd, OUTPUT_COPROCESS, b"a\n").unwrap();
+    assert_eq!(
+        handles.read_coprocess_line(cmd).unwrap(),
+        Some("a".to_string())
+    );
+    assert_eq!(handles.close_how(cmd, "to"), 0.0);
+    assert!(handles.write(cmd, OUTPUT_COPROCESS, b"b\n").is_err());
+    assert_eq!(handles.read_coprocess_line(cmd).unwrap(), None);
+    assert_eq!(handles.close_how(cmd, "from"), 3.0);
+    assert_eq!(handles.close_how(cmd, "to"), -1.0);
+    assert_eq!(handles.close_how("cat", "sideways"), -1.0);
+}
+
+#[test]
+fn test_open_one_way_only() {
+    use crate::runtime::OUTPUT_FILE;
+    use tempfile::tempdir;
+
+    let temp_dir = tempdir().unwrap();
+    let file_path = temp_dir.path().join("out.txt");
+    let name = file_path.to_str().unwrap();
+
+    let mut handles = Handles::new();
+    handles.write(name, OUTPUT_FILE, b"a\n").unwrap();
+    let read = handles.read_line(name);
+    assert_eq!(getline_result(&read), -1.0);
+    assert_eq!(handles.close(name), 0.0);
+    assert_eq!(handles.read_line(name).unwrap(), Some("a".to_string()));
+    assert!(handles.write(name, OUTPUT_FILE, b"b\n").is_err());
+}
+
+#[test]
+fn test_too_many_open() {
+    use crate::runtime::OUTPUT_FILE;
+    use tempfile::tempdir;
+
+    let temp_dir = tempdir().unwrap();
+    let mut handles = Handles::new();
+    let names: Vec<String> = (0..=MAX_OPEN)
+        .map(|idx| {
+            let path = temp_dir.path().join(format!("{}.txt", idx));
+            path.to_str().unwrap().to_string()
+        })
+        .collect();
+    for name in &names[..MAX_OPEN] {
+        handles.write(name, OUTPUT_FILE, b"x").unwrap();
+    }
+    let err = handles
+        .write(&names[MAX_OPEN], OUTPUT_FILE, b"x")
+        .unwrap_err();
+    assert!(err.to_string().contains("close()"), "{}", err);
+    assert_eq!(handles.close(&names[0]), 0.0);
+    handles.write(&names[MAX_OPEN], OUTPUT_FILE, b"x").unwrap();
+    handles.close_all();
+}
+
+#[test]
+fn test_system() {
+    assert_eq!(system("true"), 0.0);
+    assert_eq!(system("exit 3"), 3.0);
+    assert_eq!(system("kill -9 $$"), 265.0);
+}
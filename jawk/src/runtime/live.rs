@@ -1,27 +1,53 @@
-use crate::codgen::FLOAT_TAG;
-use crate::columns::Columns;
+use crate::codgen::{FLOAT_TAG, STRING_TAG, STRNUM_TAG, UNINIT_TAG};
+use crate::columns::{split_by_pattern, Columns};
 use crate::lexer::BinOp;
-use crate::runtime::Runtime;
+use crate::printf::{self, byte_mode_from_env, FormatArg, NUMBER_FORMAT};
+use crate::regex::Regexes;
+use crate::runtime::arrays::{ArrayValue, Arrays, ValueOut, HEADER_ARRAY};
+use crate::runtime::handles::{self, getline_result, is_stdout, Handles};
+use crate::runtime::random::Random;
+use crate::runtime::{
+    looks_numeric, mkbool, Runtime, FNR_VAR, FS_SEP, GETLINE_COMMAND, GETLINE_COPROCESS,
+    GETLINE_MAIN_INPUT, NR_VAR, RS_SEP,
+};
 use gnu_libjit::{Context, Function, Value};
 use std::ffi::c_void;
+use std::io::{ErrorKind, Write};
 use std::rc::Rc;
 
 // Live runtime used by most programs.
 // A pointer to the runtime data is provided for all calls but only used for some.
 // Its mainly here for the test runtime.
 
-pub extern "C" fn print_string(_data: *mut c_void, value: *mut String) {
+pub extern "C" fn print_string(data: *mut c_void, value: *mut String) {
+    let data = cast_to_runtime_data(data);
     let str = unsafe { Rc::from_raw(value) };
-    if str.ends_with("\n") {
-        print!("{}", str);
-    } else {
-        println!("{}", str);
-    }
+    data.output.write(str.as_bytes());
     Rc::into_raw(str);
 }
 
-pub extern "C" fn print_float(_data: *mut c_void, value: f64) {
-    println!("{}", value);
+pub extern "C" fn print_float(data: *mut c_void, value: f64) {
+    let data = cast_to_runtime_data(data);
+    let string = printf::number_to_string(value, &data.ofmt);
+    data.output.write(string.as_bytes());
+}
+
+// Consumes target and text
+extern "C" fn print_output(
+    data: *mut c_void,
+    kind: u8,
+    target: *const String,
+    text: *const String,
+) {
+    let data = cast_to_runtime_data(data);
+    let target = unsafe { Rc::from_raw(target) };
+    let text = unsafe { Rc::from_raw(text) };
+    if is_stdout(&target) {
+        data.output.write(text.as_bytes());
+    } else if let Err(err) = data.handles.write(&target, kind, text.as_bytes()) {
+        eprintln!("can't redirect to `{}`: {}", target, err);
+        std::process::exit(-1);
+    }
 }
 
 extern "C" fn next_line(data: *mut c_void) -> f64 {
@@ -75,11 +101,12 @@ extern "C" fn empty_string(_data_ptr: *mut c_void) -> *const String {
 }
 
 extern "C" fn binop(
-    _dat: *mut c_void,
+    data: *mut c_void,
     l_ptr: *const String,
     r_ptr: *const String,
     binop: BinOp,
 ) -> std::os::raw::c_double {
+    let data = cast_to_runtime_data(data);
     let left = unsafe { Rc::from_raw(l_ptr) };
     let right = unsafe { Rc::from_raw(r_ptr) };
 
@@ -90,15 +117,25 @@ extern "C" fn binop(
         BinOp::LessEq => left <= right,
         BinOp::BangEq => left != right,
         BinOp::EqEq => left == right,
-        BinOp::MatchedBy => todo!("regex"),
-        BinOp::NotMatchedBy => todo!("regex"),
+        BinOp::MatchedBy => matches(data, &left, &right),
+        BinOp::NotMatchedBy => !matches(data, &left, &right),
     };
-    let res = if res { 1.0 } else { 0.0 };
+    let res = mkbool(res);
     Rc::into_raw(left);
     Rc::into_raw(right);
     res
 }
 
+fn matches(data: &mut RuntimeData, text: &str, pattern: &str) -> bool {
+    match data.regexes.get(pattern) {
+        Ok(regex) => regex.is_match(text),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(-1);
+        }
+    }
+}
+
 extern "C" fn string_to_number(_data: *mut c_void, ptr: *const String) -> f64 {
     let string = unsafe { Rc::from_raw(ptr) };
     let res = if string.len() == 0 {
@@ -113,13 +150,10 @@ extern "C" fn string_to_number(_data: *mut c_void, ptr: *const String) -> f64 {
     res
 }
 
-extern "C" fn number_to_string(_data: *mut c_void, value: f64) -> *const String {
-    let value = if value.fract() == 0.0 {
-        value.floor()
-    } else {
-        value
-    };
-    Rc::into_raw(Rc::new(value.to_string()))
+extern "C" fn number_to_string(data: *mut c_void, value: f64) -> *const String {
+    let data = cast_to_runtime_data(data);
+    let string = printf::number_to_string(value, &data.convfmt);
+    Rc::into_raw(Rc::new(string))
 }
 
 extern "C" fn copy_string(_data: *mut c_void, ptr: *mut String) -> *const String {
@@ -129,6 +163,398 @@ extern "C" fn copy_string(_data: *mut c_void, ptr: *mut String) -> *const String
     Rc::into_raw(copy)
 }
 
+extern "C" fn type_of(
+    _data_ptr: *mut c_void,
+    tag: u8,
+    pointer: *const String,
+    is_field: u8,
+) -> *const String {
+    let typ = match tag {
+        FLOAT_TAG => "number",
+        UNINIT_TAG => "unassigned",
+        STRNUM_TAG => "strnum",
+        _ => {
+            let string = unsafe { &*pointer };
+            if is_field != 0 && looks_numeric(string) {
+                "strnum"
+            } else {
+                "string"
+            }
+        }
+    };
+    Rc::into_raw(Rc::new(typ.to_string()))
+}
+
+// The runtime takes ownership of string arguments
+extern "C" fn push_format_arg(data_ptr: *mut c_void, tag: u8, value: f64, pointer: *const String) {
+    let data = cast_to_runtime_data(data_ptr);
+    let arg = if tag == FLOAT_TAG {
+        FormatArg::Float(value)
+    } else {
+        let string = unsafe { Rc::from_raw(pointer) };
+        match Rc::try_unwrap(string) {
+            Ok(str) => FormatArg::String(str),
+            Err(rc) => FormatArg::String((*rc).clone()),
+        }
+    };
+    data.format_args.push(arg);
+}
+
+extern "C" fn printf(data_ptr: *mut c_void, fmt: *const String) {
+    let data = cast_to_runtime_data(data_ptr);
+    let fmt = unsafe { &*fmt };
+    let args = std::mem::take(&mut data.format_args);
+    let bytes = printf::sprintf(fmt, &args, data.byte_mode);
+    data.output.write(&bytes);
+}
+
+// Strings are UTF-8 so any invalid bytes from %c in byte mode are replaced
+extern "C" fn sprintf(data_ptr: *mut c_void, fmt: *const String) -> *const String {
+    let data = cast_to_runtime_data(data_ptr);
+    let fmt = unsafe { &*fmt };
+    let args = std::mem::take(&mut data.format_args);
+    let bytes = printf::sprintf(fmt, &args, data.byte_mode);
+    let string = String::from_utf8_lossy(&bytes).into_owned();
+    Rc::into_raw(Rc::new(string))
+}
+
+// Consumes the key
+extern "C" fn array_get(data_ptr: *mut c_void, array: i32, key: *const String) -> *const ValueOut {
+    let data = cast_to_runtime_data(data_ptr);
+    let key = unsafe { Rc::from_raw(key) };
+    let key = Rc::try_unwrap(key).unwrap_or_else(|rc| (*rc).clone());
+    let out = match data.header_field(array, &key) {
+        Some(field) => ArrayValue::String(Rc::new(field)).to_out(),
+        None => data.arrays.get(array, key.clone()).to_out(),
+    };
+    data.value_out = match out {
+        Some(out) => out,
+        None => {
+            eprintln!("attempt to use array `{}` in a scalar context", key);
+            std::process::exit(-1);
+        }
+    };
+    &data.value_out
+}
+
+// Consumes the key. The id of the array at array[key], which is created if missing.
+extern "C" fn subarray(data_ptr: *mut c_void, array: i32, key: *const String) -> i32 {
+    let data = cast_to_runtime_data(data_ptr);
+    let key = unsafe { Rc::from_raw(key) };
+    let key = Rc::try_unwrap(key).unwrap_or_else(|rc| (*rc).clone());
+    match data.arrays.subarray(array, key) {
+        Ok(id) => id,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(-1);
+        }
+    }
+}
+
+// Consumes the key
+extern "C" fn array_type_of(
+    data_ptr: *mut c_void,
+    array: i32,
+    key: *const String,
+) -> *const String {
+    let data = cast_to_runtime_data(data_ptr);
+    let key = unsafe { Rc::from_raw(key) };
+    let typ = data.arrays.type_of(array, &key);
+    Rc::into_raw(Rc::new(typ.to_string()))
+}
+
+// Consumes the key
+extern "C" fn array_contains(data_ptr: *mut c_void, array: i32, key: *const String) -> f64 {
+    let data = cast_to_runtime_data(data_ptr);
+    let key = unsafe { Rc::from_raw(key) };
+    mkbool(data.arrays.contains(array, &key))
+}
+
+extern "C" fn array_is_array(data_ptr: *mut c_void, array: i32, key: *const String) -> f64 {
+    let data = cast_to_runtime_data(data_ptr);
+    let key = unsafe { Rc::from_raw(key) };
+    mkbool(data.arrays.is_array(array, &key))
+}
+
+extern "C" fn array_iterate(data_ptr: *mut c_void, array: i32) -> i32 {
+    let data = cast_to_runtime_data(data_ptr);
+    data.arrays.iterate(array)
+}
+
+// float is 1 and pointer the next key (owned by the caller) until the loop is done
+extern "C" fn array_next_key(data_ptr: *mut c_void, iterator: i32) -> *const ValueOut {
+    let data = cast_to_runtime_data(data_ptr);
+    data.value_out = match data.arrays.next_key(iterator) {
+        Some(key) => ValueOut {
+            tag: STRING_TAG,
+            float: 1.0,
+            pointer: Rc::into_raw(Rc::new(key)),
+        },
+        None => ValueOut {
+            tag: STRING_TAG,
+            float: 0.0,
+            pointer: std::ptr::null(),
+        },
+    };
+    &data.value_out
+}
+
+extern "C" fn array_stop_iterating(data_ptr: *mut c_void, iterator: i32) {
+    let data = cast_to_runtime_data(data_ptr);
+    data.arrays.stop_iterating(iterator)
+}
+
+// Consumes the key
+extern "C" fn array_delete(data_ptr: *mut c_void, array: i32, key: *const String) {
+    let data = cast_to_runtime_data(data_ptr);
+    let key = unsafe { Rc::from_raw(key) };
+    data.arrays.delete(array, &key)
+}
+
+extern "C" fn array_clear(data_ptr: *mut c_void, array: i32) {
+    let data = cast_to_runtime_data(data_ptr);
+    data.arrays.clear(array)
+}
+
+// Consumes the key and the value
+extern "C" fn array_assign(
+    data_ptr: *mut c_void,
+    array: i32,
+    key: *const String,
+    tag: u8,
+    float: f64,
+    pointer: *const String,
+) {
+    let data = cast_to_runtime_data(data_ptr);
+    let key = unsafe { Rc::from_raw(key) };
+    let key = Rc::try_unwrap(key).unwrap_or_else(|rc| (*rc).clone());
+    let value = ArrayValue::from_parts(tag, float, pointer);
+    data.arrays.assign(array, key, value);
+}
+
+// Consumes the file name or command. With a var the line is handed back for the jit to store,
+// otherwise it replaces $0.
+extern "C" fn getline(
+    data_ptr: *mut c_void,
+    source: *const String,
+    source_kind: u8,
+    into_var: u8,
+) -> *const ValueOut {
+    let data = cast_to_runtime_data(data_ptr);
+    let line = match source_kind {
+        GETLINE_MAIN_INPUT => Ok(data.columns.next_line_for_var()),
+        GETLINE_COMMAND | GETLINE_COPROCESS => {
+            let source = unsafe { Rc::from_raw(source) };
+            let line = if source_kind == GETLINE_COPROCESS {
+                data.handles.read_coprocess_line(&source)
+            } else {
+                data.handles.read_command_line(&source)
+            };
+            if let Ok(Some(_)) = line {
+                data.columns.count_record();
+            }
+            line
+        }
+        _ => {
+            let source = unsafe { Rc::from_raw(source) };
+            data.handles.read_line(&source)
+        }
+    };
+    let result = getline_result(&line);
+    let mut pointer = std::ptr::null();
+    if let Ok(Some(line)) = line {
+        if into_var != 0 {
+            pointer = Rc::into_raw(Rc::new(line));
+        } else {
+            data.columns.set(0, line);
+        }
+    }
+    data.value_out = ValueOut {
+        tag: STRING_TAG,
+        float: result,
+        pointer,
+    };
+    &data.value_out
+}
+
+extern "C" fn record_var(data_ptr: *mut c_void, var: u8) -> f64 {
+    let data = cast_to_runtime_data(data_ptr);
+    let value = match var {
+        NR_VAR => data.columns.nr(),
+        FNR_VAR => data.columns.fnr(),
+        _ => data.columns.nf(),
+    };
+    value as f64
+}
+
+extern "C" fn set_record_var(data_ptr: *mut c_void, var: u8, value: f64) {
+    let data = cast_to_runtime_data(data_ptr);
+    // Negative counts aren't meaningful, they saturate to 0
+    match var {
+        NR_VAR => data.columns.set_nr(value as usize),
+        FNR_VAR => data.columns.set_fnr(value as usize),
+        _ => data.columns.set_nf(value as usize),
+    }
+}
+
+// RT as a new string owned by the caller
+extern "C" fn record_terminator(data_ptr: *mut c_void) -> *const String {
+    let data = cast_to_runtime_data(data_ptr);
+    Rc::into_raw(Rc::new(data.columns.rt().to_string()))
+}
+
+// $idx = value. Consumes the value.
+extern "C" fn set_column(data_ptr: *mut c_void, idx: f64, value: *const String) {
+    let data = cast_to_runtime_data(data_ptr);
+    let value = unsafe { Rc::from_raw(value) };
+    let value = Rc::try_unwrap(value).unwrap_or_else(|rc| (*rc).clone());
+    let idx = idx.round();
+    if idx < 0.0 {
+        eprintln!("attempt to access field {}", idx);
+        std::process::exit(-1);
+    }
+    data.columns.set(idx as usize, value);
+}
+
+// Consumes the name and how
+extern "C" fn close(data_ptr: *mut c_void, name: *const String, how: *const String) -> f64 {
+    let data = cast_to_runtime_data(data_ptr);
+    let name = unsafe { Rc::from_raw(name) };
+    let how = unsafe { Rc::from_raw(how) };
+    data.handles.close_how(&name, &how)
+}
+
+// Consumes the command
+extern "C" fn system(_data: *mut c_void, command: *const String) -> f64 {
+    let command = unsafe { Rc::from_raw(command) };
+    handles::system(&command)
+}
+
+// toupper(s) when upper != 0 else tolower(s). Consumes the string.
+extern "C" fn change_case(_data: *mut c_void, ptr: *const String, upper: u8) -> *const String {
+    let string = unsafe { Rc::from_raw(ptr) };
+    let result = if upper != 0 {
+        string.to_uppercase()
+    } else {
+        string.to_lowercase()
+    };
+    Rc::into_raw(Rc::new(result))
+}
+
+// match(string, pattern). Consumes both.
+extern "C" fn match_index(
+    data_ptr: *mut c_void,
+    string: *const String,
+    pattern: *const String,
+) -> f64 {
+    let data = cast_to_runtime_data(data_ptr);
+    let string = unsafe { Rc::from_raw(string) };
+    let pattern = unsafe { Rc::from_raw(pattern) };
+    let found = match data.regexes.get(&pattern) {
+        Ok(regex) => regex.find_at(&string, 0),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(-1);
+        }
+    };
+    match found {
+        Some((start, _)) => (string[..start].chars().count() + 1) as f64,
+        None => 0.0,
+    }
+}
+
+// sub or gsub. Consumes the strings, the new text is only handed back if something was replaced.
+extern "C" fn substitute(
+    data_ptr: *mut c_void,
+    pattern: *const String,
+    replacement: *const String,
+    text: *const String,
+    global: u8,
+) -> *const ValueOut {
+    let data = cast_to_runtime_data(data_ptr);
+    let pattern = unsafe { Rc::from_raw(pattern) };
+    let replacement = unsafe { Rc::from_raw(replacement) };
+    let text = unsafe { Rc::from_raw(text) };
+    let (result, count) = match data.regexes.get(&pattern) {
+        Ok(regex) => regex.substitute(&text, &replacement, global != 0),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(-1);
+        }
+    };
+    let mut pointer = std::ptr::null();
+    if count > 0 {
+        pointer = Rc::into_raw(Rc::new(result));
+    }
+    data.value_out = ValueOut {
+        tag: STRING_TAG,
+        float: count as f64,
+        pointer,
+    };
+    &data.value_out
+}
+
+// patsplit(string, array, fpat[, seps]), seps is -1 when not given. Consumes the string and the
+// pattern.
+extern "C" fn patsplit(
+    data_ptr: *mut c_void,
+    string: *const String,
+    array: i32,
+    fpat: *const String,
+    seps: i32,
+) -> f64 {
+    let data = cast_to_runtime_data(data_ptr);
+    let string = unsafe { Rc::from_raw(string) };
+    let fpat = unsafe { Rc::from_raw(fpat) };
+    let (fields, found_seps) = match data.regexes.get(&fpat) {
+        Ok(regex) => split_by_pattern(regex, &string),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(-1);
+        }
+    };
+    let count = fields.len();
+    let seps = if seps >= 0 { Some(seps) } else { None };
+    data.arrays.store_split(array, seps, fields, found_seps);
+    count as f64
+}
+
+// Called whenever OFMT or CONVFMT are assigned. Consumes the string.
+extern "C" fn set_number_format(data_ptr: *mut c_void, is_ofmt: u8, fmt: *const String) {
+    let data = cast_to_runtime_data(data_ptr);
+    let fmt = unsafe { Rc::from_raw(fmt) };
+    if is_ofmt != 0 {
+        data.ofmt = (*fmt).clone();
+    } else {
+        data.convfmt = (*fmt).clone();
+    }
+}
+
+// Consumes value
+extern "C" fn set_separator(data_ptr: *mut c_void, sep: u8, value: *const String) {
+    let data = cast_to_runtime_data(data_ptr);
+    let value = unsafe { Rc::from_raw(value) };
+    match sep {
+        FS_SEP => data.columns.set_field_sep((*value).clone()),
+        RS_SEP => data.columns.set_record_sep((*value).clone()),
+        _ => data.columns.set_output_field_sep((*value).clone()),
+    }
+}
+
+extern "C" fn rand(data_ptr: *mut c_void) -> f64 {
+    let data = cast_to_runtime_data(data_ptr);
+    data.random.rand()
+}
+
+extern "C" fn srand(data_ptr: *mut c_void, seed: f64, has_seed: u8) -> f64 {
+    let data = cast_to_runtime_data(data_ptr);
+    if has_seed != 0 {
+        data.random.srand(seed)
+    } else {
+        data.random.srand_from_time()
+    }
+}
+
 extern "C" fn malloc(_data: *mut std::os::raw::c_void, num_bytes: usize) -> *mut c_void {
     unsafe { libc::malloc(num_bytes) as *mut c_void }
 }
@@ -159,41 +585,139 @@ pub struct LiveRuntime {
     pub number_to_string: *mut c_void,
     pub print_string: *mut c_void,
     pub print_float: *mut c_void,
+    pub print_output: *mut c_void,
     pub copy_string: *mut c_void,
     pub concat: *mut c_void,
     pub binop: *mut c_void,
+    pub type_of: *mut c_void,
+    pub push_format_arg: *mut c_void,
+    pub printf: *mut c_void,
+    pub sprintf: *mut c_void,
+    pub array_get: *mut c_void,
+    pub array_assign: *mut c_void,
+    pub subarray: *mut c_void,
+    pub array_type_of: *mut c_void,
+    pub array_contains: *mut c_void,
+    pub array_is_array: *mut c_void,
+    pub array_iterate: *mut c_void,
+    pub array_next_key: *mut c_void,
+    pub array_stop_iterating: *mut c_void,
+    pub array_delete: *mut c_void,
+    pub array_clear: *mut c_void,
+    pub getline: *mut c_void,
+    pub record_var: *mut c_void,
+    pub set_record_var: *mut c_void,
+    pub record_terminator: *mut c_void,
+    pub set_column: *mut c_void,
+    pub close: *mut c_void,
+    pub system: *mut c_void,
+    pub change_case: *mut c_void,
+    pub match_index: *mut c_void,
+    pub substitute: *mut c_void,
+    pub patsplit: *mut c_void,
+    pub set_number_format: *mut c_void,
+    pub set_separator: *mut c_void,
+    pub rand: *mut c_void,
+    pub srand: *mut c_void,
     pub empty_string: *mut c_void,
 }
 
+// Where print and printf write to
+enum Output {
+    Stdout,
+    // Collected for the caller, see LiveRuntime::capturing
+    Memory(Vec<u8>),
+}
+
+impl Output {
+    fn write(&mut self, bytes: &[u8]) {
+        match self {
+            Output::Stdout => match write_to(&mut std::io::stdout(), bytes) {
+                Ok(true) => {}
+                // Nothing more can be printed, stop quietly like awk does
+                Ok(false) => std::process::exit(0),
+                Err(err) => {
+                    eprintln!("jawk: write error: {}", err);
+                    std::process::exit(-1);
+                }
+            },
+            Output::Memory(buffer) => buffer.extend_from_slice(bytes),
+        }
+    }
+}
+
+// False if whoever was reading has gone away, eg. jawk ... | head. Any other error is returned.
+fn write_to(sink: &mut impl Write, bytes: &[u8]) -> std::io::Result<bool> {
+    match sink.write_all(bytes) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == ErrorKind::BrokenPipe => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
 // Pointer to this is passed in with every call. The reason we require it for every call instead of making it
 // a rust global is so we can easily run tests fully independently of each other.
 pub struct RuntimeData {
     columns: Columns,
+    // Arguments pushed for the next printf call
+    format_args: Vec<FormatArg>,
+    byte_mode: bool,
+    arrays: Arrays,
+    handles: Handles,
+    // Current values of OFMT and CONVFMT
+    ofmt: String,
+    convfmt: String,
+    random: Random,
+    regexes: Regexes,
+    // Result of the last array read
+    value_out: ValueOut,
+    output: Output,
 }
 
 impl RuntimeData {
-    pub fn new(files: Vec<String>) -> RuntimeData {
+    pub fn new(files: Vec<String>, header: bool) -> RuntimeData {
+        RuntimeData::with_columns(Columns::new(files), header)
+    }
+
+    fn with_columns(mut columns: Columns, header: bool) -> RuntimeData {
+        if header {
+            columns.use_header();
+        }
         RuntimeData {
-            columns: Columns::new(files),
+            columns,
+            format_args: vec![],
+            byte_mode: byte_mode_from_env(),
+            arrays: Arrays::new(),
+            handles: Handles::new(),
+            ofmt: NUMBER_FORMAT.to_string(),
+            convfmt: NUMBER_FORMAT.to_string(),
+            random: Random::new(),
+            regexes: Regexes::new(),
+            value_out: ValueOut::new(),
+            output: Output::Stdout,
         }
     }
-}
 
-impl LiveRuntime {
-    fn data_ptr(&mut self, func: &mut Function) -> Value {
-        if let Some(val) = &self.runtime_data_constant {
-            val.clone()
-        } else {
-            let val = func.create_void_ptr_constant(self.runtime_data as *mut c_void);
-            self.runtime_data_constant.insert(val.clone());
-            val
+    // With the header option F["name"] reads the field under that name
+    fn header_field(&mut self, array: i32, key: &str) -> Option<String> {
+        if self.arrays.id(HEADER_ARRAY) != Some(array) {
+            return None;
         }
+        let idx = self.columns.header_index(key)?;
+        Some(self.columns.get(idx))
     }
 }
 
-impl Runtime for LiveRuntime {
-    fn new(files: Vec<String>) -> LiveRuntime {
-        let data = Box::new(RuntimeData::new(files));
+impl LiveRuntime {
+    // Reads its records from input and keeps the output for take_output instead of printing it
+    pub fn capturing(input: String) -> LiveRuntime {
+        let mut data = RuntimeData::with_columns(Columns::from_text(input), false);
+        data.output = Output::Memory(vec![]);
+        LiveRuntime::with_data(data)
+    }
+
+    fn with_data(data: RuntimeData) -> LiveRuntime {
+        let data = Box::new(data);
         let ptr = Box::leak(data);
         LiveRuntime {
             runtime_data_constant: None,
@@ -207,11 +731,74 @@ impl Runtime for LiveRuntime {
             print_string: print_string as *mut c_void,
             concat: concat as *mut c_void,
             print_float: print_float as *mut c_void,
+            print_output: print_output as *mut c_void,
             empty_string: empty_string as *mut c_void,
             binop: binop as *mut c_void,
+            type_of: type_of as *mut c_void,
+            push_format_arg: push_format_arg as *mut c_void,
+            printf: printf as *mut c_void,
+            sprintf: sprintf as *mut c_void,
+            array_get: array_get as *mut c_void,
+            array_assign: array_assign as *mut c_void,
+            subarray: subarray as *mut c_void,
+            array_type_of: array_type_of as *mut c_void,
+            array_contains: array_contains as *mut c_void,
+            array_is_array: array_is_array as *mut c_void,
+            array_iterate: array_iterate as *mut c_void,
+            array_next_key: array_next_key as *mut c_void,
+            array_stop_iterating: array_stop_iterating as *mut c_void,
+            array_delete: array_delete as *mut c_void,
+            array_clear: array_clear as *mut c_void,
+            getline: getline as *mut c_void,
+            record_var: record_var as *mut c_void,
+            set_record_var: set_record_var as *mut c_void,
+            record_terminator: record_terminator as *mut c_void,
+            set_column: set_column as *mut c_void,
+            close: close as *mut c_void,
+            system: system as *mut c_void,
+            change_case: change_case as *mut c_void,
+            match_index: match_index as *mut c_void,
+            substitute: substitute as *mut c_void,
+            patsplit: patsplit as *mut c_void,
+            set_number_format: set_number_format as *mut c_void,
+            set_separator: set_separator as *mut c_void,
+            rand: rand as *mut c_void,
+            srand: srand as *mut c_void,
+        }
+    }
+
+    // Finishes writing to the files and commands print was redirected to and waits for every
+    // command still open
+    pub fn close_handles(&mut self) {
+        let data = unsafe { &mut *self.runtime_data };
+        data.handles.close_all();
+    }
+
+    // Everything printed so far when capturing
+    pub fn take_output(&mut self) -> Vec<u8> {
+        let data = unsafe { &mut *self.runtime_data };
+        match &mut data.output {
+            Output::Stdout => vec![],
+            Output::Memory(buffer) => std::mem::take(buffer),
         }
     }
 
+    fn data_ptr(&mut self, func: &mut Function) -> Value {
+        if let Some(val) = &self.runtime_data_constant {
+            val.clone()
+        } else {
+            let val = func.create_void_ptr_constant(self.runtime_data as *mut c_void);
+            self.runtime_data_constant.insert(val.clone());
+            val
+        }
+    }
+}
+
+impl Runtime for LiveRuntime {
+    fn new(files: Vec<String>, header: bool) -> LiveRuntime {
+        LiveRuntime::with_data(RuntimeData::new(files, header))
+    }
+
     fn call_next_line(&mut self, func: &mut Function) -> Value {
         let data_ptr = self.data_ptr(func);
         func.insn_call_native(
@@ -272,6 +859,21 @@ impl Runtime for LiveRuntime {
         func.insn_call_native(self.print_float, vec![data_ptr, number], None);
     }
 
+    fn print_output(
+        &mut self,
+        func: &mut Function,
+        output_kind: Value,
+        target: Value,
+        text: Value,
+    ) {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.print_output,
+            vec![data_ptr, output_kind, target, text],
+            None,
+        );
+    }
+
     fn concat(&mut self, func: &mut Function, ptr1: Value, ptr2: Value) -> Value {
         let data_ptr = self.data_ptr(func);
         func.insn_call_native(
@@ -299,6 +901,270 @@ impl Runtime for LiveRuntime {
             Some(Context::float64_type()),
         )
     }
+
+    fn type_of(&mut self, func: &mut Function, tag: Value, ptr: Value, is_field: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.type_of,
+            vec![data_ptr, tag, ptr, is_field],
+            Some(Context::void_ptr_type()),
+        )
+    }
+
+    fn push_format_arg(&mut self, func: &mut Function, tag: Value, float: Value, ptr: Value) {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(self.push_format_arg, vec![data_ptr, tag, float, ptr], None);
+    }
+
+    fn printf(&mut self, func: &mut Function, fmt: Value) {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(self.printf, vec![data_ptr, fmt], None);
+    }
+
+    fn sprintf(&mut self, func: &mut Function, fmt: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.sprintf,
+            vec![data_ptr, fmt],
+            Some(Context::void_ptr_type()),
+        )
+    }
+
+    fn define_array(&mut self, name: &str) -> i32 {
+        cast_to_runtime_data(self.runtime_data as *mut c_void)
+            .arrays
+            .define(name)
+    }
+
+    fn array_get(&mut self, func: &mut Function, array: Value, key: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.array_get,
+            vec![data_ptr, array, key],
+            Some(Context::void_ptr_type()),
+        )
+    }
+
+    fn subarray(&mut self, func: &mut Function, array: Value, key: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.subarray,
+            vec![data_ptr, array, key],
+            Some(Context::int_type()),
+        )
+    }
+
+    fn array_type_of(&mut self, func: &mut Function, array: Value, key: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.array_type_of,
+            vec![data_ptr, array, key],
+            Some(Context::void_ptr_type()),
+        )
+    }
+
+    fn array_contains(&mut self, func: &mut Function, array: Value, key: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.array_contains,
+            vec![data_ptr, array, key],
+            Some(Context::float64_type()),
+        )
+    }
+
+    fn array_is_array(&mut self, func: &mut Function, array: Value, key: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.array_is_array,
+            vec![data_ptr, array, key],
+            Some(Context::float64_type()),
+        )
+    }
+
+    fn array_iterate(&mut self, func: &mut Function, array: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.array_iterate,
+            vec![data_ptr, array],
+            Some(Context::int_type()),
+        )
+    }
+
+    fn array_next_key(&mut self, func: &mut Function, iterator: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.array_next_key,
+            vec![data_ptr, iterator],
+            Some(Context::void_ptr_type()),
+        )
+    }
+
+    fn array_stop_iterating(&mut self, func: &mut Function, iterator: Value) {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(self.array_stop_iterating, vec![data_ptr, iterator], None);
+    }
+
+    fn array_delete(&mut self, func: &mut Function, array: Value, key: Value) {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(self.array_delete, vec![data_ptr, array, key], None);
+    }
+
+    fn array_clear(&mut self, func: &mut Function, array: Value) {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(self.array_clear, vec![data_ptr, array], None);
+    }
+
+    fn getline(
+        &mut self,
+        func: &mut Function,
+        source: Value,
+        source_kind: Value,
+        into_var: Value,
+    ) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.getline,
+            vec![data_ptr, source, source_kind, into_var],
+            Some(Context::void_ptr_type()),
+        )
+    }
+
+    fn record_var(&mut self, func: &mut Function, var: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.record_var,
+            vec![data_ptr, var],
+            Some(Context::float64_type()),
+        )
+    }
+
+    fn set_record_var(&mut self, func: &mut Function, var: Value, value: Value) {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(self.set_record_var, vec![data_ptr, var, value], None);
+    }
+
+    fn record_terminator(&mut self, func: &mut Function) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.record_terminator,
+            vec![data_ptr],
+            Some(Context::void_ptr_type()),
+        )
+    }
+
+    fn set_column(&mut self, func: &mut Function, idx: Value, value: Value) {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(self.set_column, vec![data_ptr, idx, value], None);
+    }
+
+    fn close(&mut self, func: &mut Function, name: Value, how: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.close,
+            vec![data_ptr, name, how],
+            Some(Context::float64_type()),
+        )
+    }
+
+    fn system(&mut self, func: &mut Function, command: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.system,
+            vec![data_ptr, command],
+            Some(Context::float64_type()),
+        )
+    }
+
+    fn change_case(&mut self, func: &mut Function, ptr: Value, upper: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.change_case,
+            vec![data_ptr, ptr, upper],
+            Some(Context::void_ptr_type()),
+        )
+    }
+
+    fn match_index(&mut self, func: &mut Function, string: Value, pattern: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.match_index,
+            vec![data_ptr, string, pattern],
+            Some(Context::float64_type()),
+        )
+    }
+
+    fn substitute(
+        &mut self,
+        func: &mut Function,
+        pattern: Value,
+        replacement: Value,
+        text: Value,
+        global: Value,
+    ) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.substitute,
+            vec![data_ptr, pattern, replacement, text, global],
+            Some(Context::void_ptr_type()),
+        )
+    }
+
+    fn patsplit(
+        &mut self,
+        func: &mut Function,
+        string: Value,
+        array: Value,
+        fpat: Value,
+        seps: Value,
+    ) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.patsplit,
+            vec![data_ptr, string, array, fpat, seps],
+            Some(Context::float64_type()),
+        )
+    }
+
+    fn set_number_format(&mut self, func: &mut Function, is_ofmt: Value, fmt: Value) {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(self.set_number_format, vec![data_ptr, is_ofmt, fmt], None);
+    }
+
+    fn set_separator(&mut self, func: &mut Function, sep: Value, value: Value) {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(self.set_separator, vec![data_ptr, sep, value], None);
+    }
+
+    fn rand(&mut self, func: &mut Function) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(self.rand, vec![data_ptr], Some(Context::float64_type()))
+    }
+
+    fn srand(&mut self, func: &mut Function, seed: Value, has_seed: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.srand,
+            vec![data_ptr, seed, has_seed],
+            Some(Context::float64_type()),
+        )
+    }
+
+    fn array_assign(
+        &mut self,
+        func: &mut Function,
+        array: Value,
+        key: Value,
+        tag: Value,
+        float: Value,
+        ptr: Value,
+    ) {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.array_assign,
+            vec![data_ptr, array, key, tag, float, ptr],
+            None,
+        );
+    }
 }
 
 pub fn cast_to_runtime_data(data: *mut c_void) -> &'static mut RuntimeData {
@@ -308,3 +1174,32 @@ pub fn cast_to_runtime_data(data: *mut c_void) -> &'static mut RuntimeData {
         d
     }
 }
+
+#[cfg(test)]
+struct ClosedPipe {}
+
+#[cfg(test)]
+impl Write for ClosedPipe {
+    fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+        Err(std::io::Error::from(ErrorKind::BrokenPipe))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_write_to() {
+    let mut sink = vec![];
+    assert!(write_to(&mut sink, b"abc").unwrap());
+    assert_eq!(sink, b"abc");
+    assert!(!write_to(&mut ClosedPipe {}, b"abc").unwrap());
+}
+
+#[test]
+fn test_write_to_other_error() {
+    let mut full: &mut [u8] = &mut [0; 2];
+    let err = write_to(&mut full, b"abc").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WriteZero);
+}
@@ -1,15 +1,99 @@
+mod arrays;
 mod call_log;
+mod handles;
 mod live;
+mod random;
 mod testing;
 
 use crate::lexer::BinOp;
+pub use arrays::ValueOut;
 use gnu_libjit::{Context, Function, Value};
+pub use handles::is_stdin;
 pub use live::LiveRuntime;
 use std::ffi::c_void;
 pub use testing::TestRuntime;
 
+// What Runtime::getline reads from
+pub const GETLINE_FILE: u8 = 0;
+pub const GETLINE_COMMAND: u8 = 1;
+pub const GETLINE_MAIN_INPUT: u8 = 2;
+pub const GETLINE_COPROCESS: u8 = 3;
+
+// Where Runtime::print_output writes to
+pub const OUTPUT_FILE: u8 = 0;
+pub const OUTPUT_APPEND: u8 = 1;
+pub const OUTPUT_PIPE: u8 = 2;
+pub const OUTPUT_COPROCESS: u8 = 3;
+
+// The variables Runtime::record_var can read, the runtime updates them as records are read
+pub const NR_VAR: u8 = 0;
+pub const FNR_VAR: u8 = 1;
+pub const NF_VAR: u8 = 2;
+
+// The separators Runtime::set_separator sets, the runtime keeps its own copy for the records it
+// splits and rebuilds
+pub const OFS_SEP: u8 = 0;
+pub const FS_SEP: u8 = 1;
+pub const RS_SEP: u8 = 2;
+
+// Every boolean awk produces is exactly 1 or 0
+pub fn mkbool(value: bool) -> f64 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+// The number a field or -v value reads as when it looks numeric to awk, which makes it a
+// strnum: blanks around an optional sign, digits with an optional fraction and an optional
+// exponent. Rust's float syntax is wider, inf, nan and 1e5000 are all just strings to awk.
+pub fn strnum(string: &str) -> Option<f64> {
+    let text = string.trim_matches(|c| c == ' ' || c == '\t' || c == '\n');
+    let bytes = text.as_bytes();
+    let digits = |mut idx: usize| {
+        let start = idx;
+        while idx < bytes.len() && bytes[idx].is_ascii_digit() {
+            idx += 1;
+        }
+        (idx, idx - start)
+    };
+    let mut idx = 0;
+    if matches!(bytes.first(), Some(b'+' | b'-')) {
+        idx += 1;
+    }
+    let (end, whole) = digits(idx);
+    idx = end;
+    let mut fraction = 0;
+    if bytes.get(idx) == Some(&b'.') {
+        (idx, fraction) = digits(idx + 1);
+    }
+    if whole + fraction == 0 {
+        return None;
+    }
+    if matches!(bytes.get(idx), Some(b'e' | b'E')) {
+        let mut exponent = idx + 1;
+        if matches!(bytes.get(exponent), Some(b'+' | b'-')) {
+            exponent += 1;
+        }
+        let (end, count) = digits(exponent);
+        if count == 0 {
+            return None;
+        }
+        idx = end;
+    }
+    if idx != bytes.len() {
+        return None;
+    }
+    text.parse::<f64>().ok().filter(|num| num.is_finite())
+}
+
+pub fn looks_numeric(string: &str) -> bool {
+    strnum(string).is_some()
+}
+
 pub trait Runtime {
-    fn new(files: Vec<String>) -> Self;
+    fn new(files: Vec<String>, header: bool) -> Self;
     fn call_next_line(&mut self, func: &mut Function) -> Value;
     fn column(&mut self, func: &mut Function, tag: Value, float: Value, ptr: Value) -> Value;
     fn free_string(&mut self, func: &mut Function, ptr: Value) -> Value;
@@ -18,7 +102,123 @@ pub trait Runtime {
     fn number_to_string(&mut self, func: &mut Function, number: Value) -> Value;
     fn print_string(&mut self, func: &mut Function, ptr: Value);
     fn print_float(&mut self, func: &mut Function, number: Value);
+    // print > target, >> target, | target or |& target by output_kind, one of the OUTPUT_
+    // consts. Takes ownership of target and text.
+    fn print_output(&mut self, func: &mut Function, output_kind: Value, target: Value, text: Value);
     fn concat(&mut self, func: &mut Function, ptr1: Value, ptr2: Value) -> Value;
     fn empty_string(&mut self, func: &mut Function) -> Value;
     fn binop(&mut self, func: &mut Function, ptr1: Value, ptr2: Value, binop: BinOp) -> Value;
+    fn type_of(&mut self, func: &mut Function, tag: Value, ptr: Value, is_field: Value) -> Value;
+    fn push_format_arg(&mut self, func: &mut Function, tag: Value, float: Value, ptr: Value);
+    fn printf(&mut self, func: &mut Function, fmt: Value);
+    fn sprintf(&mut self, func: &mut Function, fmt: Value) -> Value;
+    // Called while compiling, returns the id the other array calls take
+    fn define_array(&mut self, name: &str) -> i32;
+    // Returns a pointer to a ValueOut, the string in it is owned by the caller
+    fn array_get(&mut self, func: &mut Function, array: Value, key: Value) -> Value;
+    // The id of the sub-array at array[key], created if missing. Takes ownership of key.
+    fn subarray(&mut self, func: &mut Function, array: Value, key: Value) -> Value;
+    // typeof(array[key]), which unlike a read doesn't create the element. Takes ownership of key.
+    fn array_type_of(&mut self, func: &mut Function, array: Value, key: Value) -> Value;
+    // key in array, 1 or 0. Takes ownership of key.
+    fn array_contains(&mut self, func: &mut Function, array: Value, key: Value) -> Value;
+    // isarray(array[key]). Takes ownership of key.
+    fn array_is_array(&mut self, func: &mut Function, array: Value, key: Value) -> Value;
+    // Starts a for (k in array) loop, returns the iterator array_next_key takes
+    fn array_iterate(&mut self, func: &mut Function, array: Value) -> Value;
+    // Returns a pointer to a ValueOut, float is 0 once the loop is done otherwise 1 and the
+    // pointer is the key (owned by the caller)
+    fn array_next_key(&mut self, func: &mut Function, iterator: Value) -> Value;
+    // Drops the iterator when leaving the loop before array_next_key is done with it
+    fn array_stop_iterating(&mut self, func: &mut Function, iterator: Value);
+    // delete array[key]. Takes ownership of key.
+    fn array_delete(&mut self, func: &mut Function, array: Value, key: Value);
+    // delete array, which empties it
+    fn array_clear(&mut self, func: &mut Function, array: Value);
+    // getline [var] < file, cmd | getline [var], cmd |& getline [var] or getline var depending
+    // on source_kind, one of the GETLINE_ consts. source is ignored (and may be null) for the
+    // main input. Returns a pointer to a ValueOut, float is getline's result and with a var the
+    // pointer is the line read (owned by the caller) or null.
+    fn getline(
+        &mut self,
+        func: &mut Function,
+        source: Value,
+        source_kind: Value,
+        into_var: Value,
+    ) -> Value;
+    // NR, FNR or NF by one of the _VAR consts
+    fn record_var(&mut self, func: &mut Function, var: Value) -> Value;
+    // Assign NR or FNR, later records count up from value. Assigning NF rebuilds $0 with that
+    // many fields.
+    fn set_record_var(&mut self, func: &mut Function, var: Value, value: Value);
+    // RT, the separator that ended the last record read from the main input. Owned by the caller.
+    fn record_terminator(&mut self, func: &mut Function) -> Value;
+    // $idx = value, rebuilding $0 or re-splitting the fields. Takes ownership of value.
+    fn set_column(&mut self, func: &mut Function, idx: Value, value: Value);
+    // close(name, how), how is "to" or "from" to close one direction of a coprocess and empty
+    // to close all of it. Takes ownership of name and how.
+    fn close(&mut self, func: &mut Function, name: Value, how: Value) -> Value;
+    // Runs command with sh and returns its exit status. Takes ownership of command.
+    fn system(&mut self, func: &mut Function, command: Value) -> Value;
+    // toupper when upper != 0 otherwise tolower. Takes ownership of ptr.
+    fn change_case(&mut self, func: &mut Function, ptr: Value, upper: Value) -> Value;
+    // match(string, pattern), where pattern first matches string counting chars from 1 or 0.
+    // Takes ownership of string and pattern.
+    fn match_index(&mut self, func: &mut Function, string: Value, pattern: Value) -> Value;
+    // sub or gsub when global != 0. Returns a pointer to a ValueOut with the number of matches
+    // replaced and, when there were any, the new text owned by the caller. Takes ownership of
+    // pattern, replacement and text.
+    fn substitute(
+        &mut self,
+        func: &mut Function,
+        pattern: Value,
+        replacement: Value,
+        text: Value,
+        global: Value,
+    ) -> Value;
+    // patsplit(string, array, fpat, seps), seps is an array id or -1 without one. Returns the
+    // number of fields. Takes ownership of string and fpat.
+    fn patsplit(
+        &mut self,
+        func: &mut Function,
+        string: Value,
+        array: Value,
+        fpat: Value,
+        seps: Value,
+    ) -> Value;
+    // Tell the runtime OFMT (is_ofmt != 0) or CONVFMT changed. Takes ownership of fmt.
+    fn set_number_format(&mut self, func: &mut Function, is_ofmt: Value, fmt: Value);
+    // Tell the runtime a separator changed, sep is one of the _SEP consts. Takes ownership of
+    // value.
+    fn set_separator(&mut self, func: &mut Function, sep: Value, value: Value);
+    fn rand(&mut self, func: &mut Function) -> Value;
+    // Returns the previous seed. Without has_seed seed is ignored and the time of day is used.
+    fn srand(&mut self, func: &mut Function, seed: Value, has_seed: Value) -> Value;
+    fn array_assign(
+        &mut self,
+        func: &mut Function,
+        array: Value,
+        key: Value,
+        tag: Value,
+        float: Value,
+        ptr: Value,
+    );
+}
+
+#[test]
+fn test_strnum() {
+    for (string, num) in [
+        ("1", 1.0),
+        (" -2.5 ", -2.5),
+        ("+.5", 0.5),
+        ("3.", 3.0),
+        ("1e3", 1000.0),
+        ("\t2E-1\n", 0.2),
+    ] {
+        assert_eq!(strnum(string), Some(num), "{:?}", string);
+    }
+    let rejected = "inf -infinity nan NaN 1e 1e+ 1e5000 0x10 1a e5 . - +";
+    for string in rejected.split(' ').chain(["", " ", "1 2"]) {
+        assert_eq!(strnum(string), None, "{:?}", string);
+    }
 }
@@ -0,0 +1,79 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// State behind rand() and srand(). Each runtime has its own so tests don't share a sequence.
+// Like other awks the seed starts at 0, so a program that never calls srand sees the same
+// numbers on every run.
+pub struct Random {
+    seed: f64,
+    state: u64,
+}
+
+impl Random {
+    pub fn new() -> Self {
+        let mut random = Random {
+            seed: 0.0,
+            state: 0,
+        };
+        random.srand(0.0);
+        random
+    }
+
+    // Returns the previous seed
+    pub fn srand(&mut self, seed: f64) -> f64 {
+        let previous = self.seed;
+        self.seed = seed;
+        // splitmix64 so that nearby seeds still give unrelated sequences
+        let mut z = (seed as i64 as u64).wrapping_add(0x9E3779B97F4A7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        // xorshift never leaves 0 so avoid it
+        self.state = (z ^ (z >> 31)) | 1;
+        previous
+    }
+
+    // srand() without an argument seeds from the time of day
+    pub fn srand_from_time(&mut self) -> f64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.srand(now as f64)
+    }
+
+    // 0 <= rand() < 1
+    pub fn rand(&mut self) -> f64 {
+        // xorshift64*
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        let value = self.state.wrapping_mul(0x2545F4914F6CDD1D);
+        (value >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[test]
+fn test_random() {
+    let mut random = Random::new();
+    let first = (0..100).map(|_| random.rand()).collect::<Vec<f64>>();
+    assert!(first.iter().all(|x| (0.0..1.0).contains(x)));
+    assert_eq!(random.srand(0.0), 0.0);
+    let again = (0..100).map(|_| random.rand()).collect::<Vec<f64>>();
+    assert_eq!(first, again);
+    assert_eq!(random.srand(42.0), 0.0);
+    assert_ne!(random.rand(), first[0]);
+    assert_eq!(random.srand(1.0), 42.0);
+}
+
+#[test]
+fn test_srand_sequence() {
+    let mut random = Random::new();
+    random.srand(1.0);
+    assert_eq!(random.srand(2.0), 1.0);
+    assert_eq!(random.srand(7.0), 2.0);
+    let first = (0..10).map(|_| random.rand()).collect::<Vec<f64>>();
+    // Seeding from the time of day still hands back the seed it replaced
+    assert_eq!(random.srand_from_time(), 7.0);
+    random.srand(7.0);
+    let again = (0..10).map(|_| random.rand()).collect::<Vec<f64>>();
+    assert_eq!(first, again);
+}
@@ -1,10 +1,19 @@
-use crate::codgen::FLOAT_TAG;
-use crate::columns::Columns;
+use crate::codgen::{FLOAT_TAG, STRING_TAG, STRNUM_TAG, UNINIT_TAG};
+use crate::columns::{split_by_pattern, Columns};
 use crate::lexer::BinOp;
+use crate::printf::{self, byte_mode_from_env, FormatArg, NUMBER_FORMAT};
+use crate::regex::Regexes;
+use crate::runtime::arrays::{ArrayValue, Arrays, ValueOut, HEADER_ARRAY};
 use crate::runtime::call_log::{Call, CallLog};
-use crate::runtime::Runtime;
+use crate::runtime::handles::{self, getline_result, is_stdout, Handles};
+use crate::runtime::random::Random;
+use crate::runtime::{
+    looks_numeric, mkbool, Runtime, FNR_VAR, FS_SEP, GETLINE_COMMAND, GETLINE_COPROCESS,
+    GETLINE_MAIN_INPUT, NR_VAR, RS_SEP,
+};
 use gnu_libjit::{Context, Function, Value};
 use std::ffi::c_void;
+use std::io::Write;
 use std::rc::Rc;
 
 pub const CANARY: &str = "this is the canary!";
@@ -13,13 +22,7 @@ pub extern "C" fn print_string(data: *mut c_void, value: *mut String) {
     let data = cast_to_runtime_data(data);
     data.calls.log(Call::PrintString);
     let str = unsafe { Rc::from_raw(value) };
-
-    let res = if str.ends_with("\n") {
-        format!("{}", str)
-    } else {
-        format!("{}\n", str)
-    };
-    data.output.push_str(&res);
+    data.output.push_str(&str);
     println!("{}", str);
     Rc::into_raw(str);
 }
@@ -27,9 +30,30 @@ pub extern "C" fn print_string(data: *mut c_void, value: *mut String) {
 pub extern "C" fn print_float(data: *mut c_void, value: f64) {
     let data = cast_to_runtime_data(data);
     data.calls.log(Call::PrintFloat);
-    let res = format!("{}\n", value);
+    let res = printf::number_to_string(value, &data.ofmt);
     data.output.push_str(&res);
-    println!("{}", value);
+    println!("{}", res);
+}
+
+// Consumes target and text
+extern "C" fn print_output(
+    data: *mut c_void,
+    kind: u8,
+    target: *const String,
+    text: *const String,
+) {
+    let data = cast_to_runtime_data(data);
+    data.calls.log(Call::PrintOutput);
+    data.string_in("print_output target");
+    data.string_in("print_output text");
+    let target = unsafe { Rc::from_raw(target) };
+    let text = unsafe { Rc::from_raw(text) };
+    println!("\tprint {:?} to {:?} ({})", text, target, kind);
+    if is_stdout(&target) {
+        data.output.push_str(&text);
+    } else {
+        data.handles.write(&target, kind, text.as_bytes()).unwrap();
+    }
 }
 
 extern "C" fn next_line(data: *mut c_void) -> f64 {
@@ -138,13 +162,8 @@ extern "C" fn number_to_string(data_ptr: *mut c_void, value: f64) -> *const Stri
     data.calls.log(Call::NumberToString);
     data.string_out("number_to_string");
     println!("\tnum: {}", value);
-    let value = if value.fract() == 0.0 {
-        value.floor()
-    } else {
-        value
-    };
 
-    let heap_alloc_string = Rc::new(value.to_string());
+    let heap_alloc_string = Rc::new(printf::number_to_string(value, &data.convfmt));
 
     let str = (*heap_alloc_string).clone();
     let ptr = Rc::into_raw(heap_alloc_string);
@@ -191,10 +210,10 @@ extern "C" fn binop(
         BinOp::LessEq => left <= right,
         BinOp::BangEq => left != right,
         BinOp::EqEq => left == right,
-        BinOp::MatchedBy => todo!("regex"),
-        BinOp::NotMatchedBy => todo!("regex"),
+        BinOp::MatchedBy => data.regexes.get(&right).unwrap().is_match(&left),
+        BinOp::NotMatchedBy => !data.regexes.get(&right).unwrap().is_match(&left),
     };
-    let res = if res { 1.0 } else { 0.0 };
+    let res = mkbool(res);
     println!(
         "\tBinop called: '{}' {:?} '{}' == {}",
         left, binop, right, res
@@ -204,6 +223,446 @@ extern "C" fn binop(
     res
 }
 
+extern "C" fn type_of(
+    data_ptr: *mut c_void,
+    tag: u8,
+    pointer: *const String,
+    is_field: u8,
+) -> *const String {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::TypeOf);
+    data.string_out("type_of");
+    let typ = match tag {
+        FLOAT_TAG => "number",
+        UNINIT_TAG => "unassigned",
+        STRNUM_TAG => "strnum",
+        _ => {
+            let string = unsafe { &*pointer };
+            if is_field != 0 && looks_numeric(string) {
+                "strnum"
+            } else {
+                "string"
+            }
+        }
+    };
+    Rc::into_raw(Rc::new(typ.to_string()))
+}
+
+extern "C" fn push_format_arg(data_ptr: *mut c_void, tag: u8, value: f64, pointer: *const String) {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::PushFormatArg);
+    let arg = if tag == FLOAT_TAG {
+        FormatArg::Float(value)
+    } else {
+        data.string_in("push_format_arg");
+        let string = unsafe { Rc::from_raw(pointer) };
+        match Rc::try_unwrap(string) {
+            Ok(str) => FormatArg::String(str),
+            Err(rc) => FormatArg::String((*rc).clone()),
+        }
+    };
+    println!("\tformat arg {:?}", arg);
+    data.format_args.push(arg);
+}
+
+extern "C" fn printf(data_ptr: *mut c_void, fmt: *const String) {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::Printf);
+    let fmt = unsafe { &*fmt };
+    let args = std::mem::take(&mut data.format_args);
+    let bytes = printf::sprintf(fmt, &args, data.byte_mode);
+    data.output.push_str(&String::from_utf8_lossy(&bytes));
+    std::io::stdout()
+        .write_all(&bytes)
+        .expect("failed to write to stdout");
+}
+
+extern "C" fn sprintf(data_ptr: *mut c_void, fmt: *const String) -> *const String {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::Sprintf);
+    data.string_out("sprintf");
+    let fmt = unsafe { &*fmt };
+    let args = std::mem::take(&mut data.format_args);
+    let bytes = printf::sprintf(fmt, &args, data.byte_mode);
+    let string = String::from_utf8_lossy(&bytes).into_owned();
+    println!("\tsprintf result '{}'", string);
+    Rc::into_raw(Rc::new(string))
+}
+
+extern "C" fn array_get(data_ptr: *mut c_void, array: i32, key: *const String) -> *const ValueOut {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::ArrayGet);
+    data.string_in("array_get key");
+    let key = unsafe { Rc::from_raw(key) };
+    let key = Rc::try_unwrap(key).unwrap_or_else(|rc| (*rc).clone());
+    let value = match data.header_field(array, &key) {
+        Some(field) => ArrayValue::String(Rc::new(field)),
+        None => data.arrays.get(array, key.clone()).clone(),
+    };
+    println!("\tarray {} [{}] is {:?}", array, key, value);
+    if !matches!(value, ArrayValue::Float(_)) {
+        data.string_out("array_get");
+    }
+    data.value_out = value
+        .to_out()
+        .expect("attempt to use array in a scalar context");
+    &data.value_out
+}
+
+extern "C" fn subarray(data_ptr: *mut c_void, array: i32, key: *const String) -> i32 {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::Subarray);
+    data.string_in("subarray key");
+    let key = unsafe { Rc::from_raw(key) };
+    let key = Rc::try_unwrap(key).unwrap_or_else(|rc| (*rc).clone());
+    let id = data.arrays.subarray(array, key.clone()).unwrap();
+    println!("	array {} [{}] is array {}", array, key, id);
+    id
+}
+
+extern "C" fn array_type_of(
+    data_ptr: *mut c_void,
+    array: i32,
+    key: *const String,
+) -> *const String {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::ArrayTypeOf);
+    data.string_in("array_type_of key");
+    data.string_out("array_type_of");
+    let key = unsafe { Rc::from_raw(key) };
+    let typ = data.arrays.type_of(array, &key);
+    println!("\ttypeof array {} [{}] is {}", array, key, typ);
+    Rc::into_raw(Rc::new(typ.to_string()))
+}
+
+extern "C" fn array_contains(data_ptr: *mut c_void, array: i32, key: *const String) -> f64 {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::ArrayContains);
+    data.string_in("array_contains key");
+    let key = unsafe { Rc::from_raw(key) };
+    mkbool(data.arrays.contains(array, &key))
+}
+
+extern "C" fn array_is_array(data_ptr: *mut c_void, array: i32, key: *const String) -> f64 {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::ArrayIsArray);
+    data.string_in("array_is_array key");
+    let key = unsafe { Rc::from_raw(key) };
+    mkbool(data.arrays.is_array(array, &key))
+}
+
+extern "C" fn array_iterate(data_ptr: *mut c_void, array: i32) -> i32 {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::ArrayIterate);
+    data.arrays.iterate(array)
+}
+
+extern "C" fn array_stop_iterating(data_ptr: *mut c_void, iterator: i32) {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::ArrayStopIterating);
+    data.arrays.stop_iterating(iterator)
+}
+
+extern "C" fn array_delete(data_ptr: *mut c_void, array: i32, key: *const String) {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::ArrayDelete);
+    data.string_in("array_delete key");
+    let key = unsafe { Rc::from_raw(key) };
+    data.arrays.delete(array, &key)
+}
+
+extern "C" fn array_clear(data_ptr: *mut c_void, array: i32) {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::ArrayClear);
+    data.arrays.clear(array)
+}
+
+extern "C" fn array_next_key(data_ptr: *mut c_void, iterator: i32) -> *const ValueOut {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::ArrayNextKey);
+    data.value_out = match data.arrays.next_key(iterator) {
+        Some(key) => {
+            data.string_out("array_next_key");
+            ValueOut {
+                tag: STRING_TAG,
+                float: 1.0,
+                pointer: Rc::into_raw(Rc::new(key)),
+            }
+        }
+        None => ValueOut {
+            tag: STRING_TAG,
+            float: 0.0,
+            pointer: std::ptr::null(),
+        },
+    };
+    &data.value_out
+}
+
+extern "C" fn array_assign(
+    data_ptr: *mut c_void,
+    array: i32,
+    key: *const String,
+    tag: u8,
+    float: f64,
+    pointer: *const String,
+) {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::ArrayAssign);
+    data.string_in("array_assign key");
+    let key = unsafe { Rc::from_raw(key) };
+    let key = Rc::try_unwrap(key).unwrap_or_else(|rc| (*rc).clone());
+    let value = ArrayValue::from_parts(tag, float, pointer);
+    if !matches!(value, ArrayValue::Float(_)) {
+        data.string_in("array_assign value");
+    }
+    println!("\tarray {} [{}] = {:?}", array, key, value);
+    data.arrays.assign(array, key, value);
+}
+
+extern "C" fn getline(
+    data_ptr: *mut c_void,
+    source: *const String,
+    source_kind: u8,
+    into_var: u8,
+) -> *const ValueOut {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::Getline);
+    let line = match source_kind {
+        GETLINE_MAIN_INPUT => Ok(data.columns.next_line_for_var()),
+        GETLINE_COMMAND | GETLINE_COPROCESS => {
+            data.string_in("getline source");
+            let source = unsafe { Rc::from_raw(source) };
+            let line = if source_kind == GETLINE_COPROCESS {
+                data.handles.read_coprocess_line(&source)
+            } else {
+                data.handles.read_command_line(&source)
+            };
+            if let Ok(Some(_)) = line {
+                data.columns.count_record();
+            }
+            line
+        }
+        _ => {
+            data.string_in("getline source");
+            let source = unsafe { Rc::from_raw(source) };
+            data.handles.read_line(&source)
+        }
+    };
+    let result = getline_result(&line);
+    println!("\tgetline {} is {:?}", source_kind, line);
+    let mut pointer = std::ptr::null();
+    if let Ok(Some(line)) = line {
+        if into_var != 0 {
+            data.string_out("getline line");
+            pointer = Rc::into_raw(Rc::new(line));
+        } else {
+            data.columns.set(0, line);
+        }
+    }
+    data.value_out = ValueOut {
+        tag: STRING_TAG,
+        float: result,
+        pointer,
+    };
+    &data.value_out
+}
+
+extern "C" fn record_var(data_ptr: *mut c_void, var: u8) -> f64 {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::RecordVar);
+    let value = match var {
+        NR_VAR => data.columns.nr(),
+        FNR_VAR => data.columns.fnr(),
+        _ => data.columns.nf(),
+    };
+    value as f64
+}
+
+extern "C" fn set_record_var(data_ptr: *mut c_void, var: u8, value: f64) {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::SetRecordVar);
+    // Negative counts aren't meaningful, they saturate to 0
+    match var {
+        NR_VAR => data.columns.set_nr(value as usize),
+        FNR_VAR => data.columns.set_fnr(value as usize),
+        _ => data.columns.set_nf(value as usize),
+    }
+}
+
+extern "C" fn record_terminator(data_ptr: *mut c_void) -> *const String {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::RecordTerminator);
+    data.string_out("record_terminator");
+    Rc::into_raw(Rc::new(data.columns.rt().to_string()))
+}
+
+extern "C" fn set_column(data_ptr: *mut c_void, idx: f64, value: *const String) {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::SetColumn);
+    data.string_in("set_column value");
+    let value = unsafe { Rc::from_raw(value) };
+    let value = Rc::try_unwrap(value).unwrap_or_else(|rc| (*rc).clone());
+    let idx = idx.round();
+    assert!(idx >= 0.0, "attempt to access field {}", idx);
+    println!("\t${} = {:?}", idx, value);
+    data.columns.set(idx as usize, value);
+}
+
+extern "C" fn close(data_ptr: *mut c_void, name: *const String, how: *const String) -> f64 {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::Close);
+    data.string_in("close name");
+    data.string_in("close how");
+    let name = unsafe { Rc::from_raw(name) };
+    let how = unsafe { Rc::from_raw(how) };
+    data.handles.close_how(&name, &how)
+}
+
+extern "C" fn system(data_ptr: *mut c_void, command: *const String) -> f64 {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::System);
+    data.string_in("system command");
+    let command = unsafe { Rc::from_raw(command) };
+    handles::system(&command)
+}
+
+extern "C" fn change_case(data_ptr: *mut c_void, ptr: *const String, upper: u8) -> *const String {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::ChangeCase);
+    data.string_in("change_case string");
+    let string = unsafe { Rc::from_raw(ptr) };
+    let result = if upper != 0 {
+        string.to_uppercase()
+    } else {
+        string.to_lowercase()
+    };
+    data.string_out("change_case result");
+    Rc::into_raw(Rc::new(result))
+}
+
+extern "C" fn match_index(
+    data_ptr: *mut c_void,
+    string: *const String,
+    pattern: *const String,
+) -> f64 {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::MatchIndex);
+    data.string_in("match_index string");
+    data.string_in("match_index pattern");
+    let string = unsafe { Rc::from_raw(string) };
+    let pattern = unsafe { Rc::from_raw(pattern) };
+    let found = match data.regexes.get(&pattern) {
+        Ok(regex) => regex.find_at(&string, 0),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(-1);
+        }
+    };
+    match found {
+        Some((start, _)) => (string[..start].chars().count() + 1) as f64,
+        None => 0.0,
+    }
+}
+
+extern "C" fn substitute(
+    data_ptr: *mut c_void,
+    pattern: *const String,
+    replacement: *const String,
+    text: *const String,
+    global: u8,
+) -> *const ValueOut {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::Substitute);
+    data.string_in("substitute pattern");
+    data.string_in("substitute replacement");
+    data.string_in("substitute text");
+    let pattern = unsafe { Rc::from_raw(pattern) };
+    let replacement = unsafe { Rc::from_raw(replacement) };
+    let text = unsafe { Rc::from_raw(text) };
+    let (result, count) = match data.regexes.get(&pattern) {
+        Ok(regex) => regex.substitute(&text, &replacement, global != 0),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(-1);
+        }
+    };
+    let mut pointer = std::ptr::null();
+    if count > 0 {
+        data.string_out("substitute result");
+        pointer = Rc::into_raw(Rc::new(result));
+    }
+    data.value_out = ValueOut {
+        tag: STRING_TAG,
+        float: count as f64,
+        pointer,
+    };
+    &data.value_out
+}
+
+extern "C" fn patsplit(
+    data_ptr: *mut c_void,
+    string: *const String,
+    array: i32,
+    fpat: *const String,
+    seps: i32,
+) -> f64 {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::Patsplit);
+    data.string_in("patsplit string");
+    data.string_in("patsplit fpat");
+    let string = unsafe { Rc::from_raw(string) };
+    let fpat = unsafe { Rc::from_raw(fpat) };
+    let regex = data.regexes.get(&fpat).unwrap();
+    let (fields, found_seps) = split_by_pattern(regex, &string);
+    println!("\tpatsplit {:?} by {:?} is {:?}", string, fpat, fields);
+    let count = fields.len();
+    let seps = if seps >= 0 { Some(seps) } else { None };
+    data.arrays.store_split(array, seps, fields, found_seps);
+    count as f64
+}
+
+extern "C" fn set_number_format(data_ptr: *mut c_void, is_ofmt: u8, fmt: *const String) {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::SetNumberFormat);
+    data.string_in("set_number_format");
+    let fmt = unsafe { Rc::from_raw(fmt) };
+    println!("\tofmt: {} format: '{}'", is_ofmt, fmt);
+    if is_ofmt != 0 {
+        data.ofmt = (*fmt).clone();
+    } else {
+        data.convfmt = (*fmt).clone();
+    }
+}
+
+extern "C" fn set_separator(data_ptr: *mut c_void, sep: u8, value: *const String) {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::SetSeparator);
+    data.string_in("set_separator");
+    let value = unsafe { Rc::from_raw(value) };
+    println!("\tseparator: {} value: '{}'", sep, value);
+    match sep {
+        FS_SEP => data.columns.set_field_sep((*value).clone()),
+        RS_SEP => data.columns.set_record_sep((*value).clone()),
+        _ => data.columns.set_output_field_sep((*value).clone()),
+    }
+}
+
+extern "C" fn rand(data_ptr: *mut c_void) -> f64 {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::Rand);
+    data.random.rand()
+}
+
+extern "C" fn srand(data_ptr: *mut c_void, seed: f64, has_seed: u8) -> f64 {
+    let data = cast_to_runtime_data(data_ptr);
+    data.calls.log(Call::Srand);
+    if has_seed != 0 {
+        data.random.srand(seed)
+    } else {
+        data.random.srand_from_time()
+    }
+}
+
 extern "C" fn malloc(data: *mut std::os::raw::c_void, num_bytes: usize) -> *mut c_void {
     let data = cast_to_runtime_data(data);
     data.string_out("malloc");
@@ -242,9 +701,40 @@ pub struct TestRuntime {
     number_to_string: *mut c_void,
     print_string: *mut c_void,
     print_float: *mut c_void,
+    print_output: *mut c_void,
     concat: *mut c_void,
     copy_string: *mut c_void,
     binop: *mut c_void,
+    type_of: *mut c_void,
+    push_format_arg: *mut c_void,
+    printf: *mut c_void,
+    sprintf: *mut c_void,
+    array_get: *mut c_void,
+    array_assign: *mut c_void,
+    subarray: *mut c_void,
+    array_type_of: *mut c_void,
+    array_contains: *mut c_void,
+    array_is_array: *mut c_void,
+    array_iterate: *mut c_void,
+    array_next_key: *mut c_void,
+    array_stop_iterating: *mut c_void,
+    array_delete: *mut c_void,
+    array_clear: *mut c_void,
+    getline: *mut c_void,
+    record_var: *mut c_void,
+    set_record_var: *mut c_void,
+    record_terminator: *mut c_void,
+    set_column: *mut c_void,
+    close: *mut c_void,
+    system: *mut c_void,
+    change_case: *mut c_void,
+    match_index: *mut c_void,
+    substitute: *mut c_void,
+    patsplit: *mut c_void,
+    set_number_format: *mut c_void,
+    set_separator: *mut c_void,
+    rand: *mut c_void,
+    srand: *mut c_void,
     empty_string: *mut c_void,
     malloc: *mut c_void,
     realloc: *mut c_void,
@@ -257,6 +747,16 @@ pub struct RuntimeData {
     canary: String,
     output: String,
     calls: CallLog,
+    format_args: Vec<FormatArg>,
+    byte_mode: bool,
+    arrays: Arrays,
+    handles: Handles,
+    // Current values of OFMT and CONVFMT
+    ofmt: String,
+    convfmt: String,
+    random: Random,
+    regexes: Regexes,
+    value_out: ValueOut,
     string_out: usize,
     strings_in: usize,
 }
@@ -270,19 +770,42 @@ impl RuntimeData {
         println!("\t<=== {} (string in)", src);
         self.strings_in += 1;
     }
-    pub fn new(files: Vec<String>) -> RuntimeData {
+    pub fn new(files: Vec<String>, header: bool) -> RuntimeData {
+        let mut columns = Columns::new(files);
+        if header {
+            columns.use_header();
+        }
         RuntimeData {
             canary: String::from(CANARY),
-            columns: Columns::new(files),
+            columns,
             output: String::new(),
             calls: CallLog::new(),
+            format_args: vec![],
+            byte_mode: byte_mode_from_env(),
+            arrays: Arrays::new(),
+            handles: Handles::new(),
+            ofmt: NUMBER_FORMAT.to_string(),
+            convfmt: NUMBER_FORMAT.to_string(),
+            random: Random::new(),
+            regexes: Regexes::new(),
+            value_out: ValueOut::new(),
             string_out: 0,
             strings_in: 0,
         }
     }
+    fn header_field(&mut self, array: i32, key: &str) -> Option<String> {
+        if self.arrays.id(HEADER_ARRAY) != Some(array) {
+            return None;
+        }
+        let idx = self.columns.header_index(key)?;
+        Some(self.columns.get(idx))
+    }
 }
 
 impl TestRuntime {
+    pub fn close_handles(&mut self) {
+        cast_to_runtime_data(self.runtime_data).handles.close_all();
+    }
     #[allow(dead_code)]
     pub fn output(&self) -> String {
         cast_to_runtime_data(self.runtime_data).output.clone()
@@ -309,8 +832,8 @@ impl TestRuntime {
 }
 
 impl Runtime for TestRuntime {
-    fn new(files: Vec<String>) -> TestRuntime {
-        let data = Box::new(RuntimeData::new(files));
+    fn new(files: Vec<String>, header: bool) -> TestRuntime {
+        let data = Box::new(RuntimeData::new(files, header));
         let runtime_data = (Box::leak(data) as *mut RuntimeData) as *mut c_void;
         let rt = TestRuntime {
             runtime_data,
@@ -323,9 +846,40 @@ impl Runtime for TestRuntime {
             number_to_string: number_to_string as *mut c_void,
             print_string: print_string as *mut c_void,
             print_float: print_float as *mut c_void,
+            print_output: print_output as *mut c_void,
             concat: concat as *mut c_void,
             empty_string: empty_string as *mut c_void,
             binop: binop as *mut c_void,
+            type_of: type_of as *mut c_void,
+            push_format_arg: push_format_arg as *mut c_void,
+            printf: printf as *mut c_void,
+            sprintf: sprintf as *mut c_void,
+            array_get: array_get as *mut c_void,
+            array_assign: array_assign as *mut c_void,
+            subarray: subarray as *mut c_void,
+            array_type_of: array_type_of as *mut c_void,
+            array_contains: array_contains as *mut c_void,
+            array_is_array: array_is_array as *mut c_void,
+            array_iterate: array_iterate as *mut c_void,
+            array_next_key: array_next_key as *mut c_void,
+            array_stop_iterating: array_stop_iterating as *mut c_void,
+            array_delete: array_delete as *mut c_void,
+            array_clear: array_clear as *mut c_void,
+            getline: getline as *mut c_void,
+            record_var: record_var as *mut c_void,
+            set_record_var: set_record_var as *mut c_void,
+            record_terminator: record_terminator as *mut c_void,
+            set_column: set_column as *mut c_void,
+            close: close as *mut c_void,
+            system: system as *mut c_void,
+            change_case: change_case as *mut c_void,
+            match_index: match_index as *mut c_void,
+            substitute: substitute as *mut c_void,
+            patsplit: patsplit as *mut c_void,
+            set_number_format: set_number_format as *mut c_void,
+            set_separator: set_separator as *mut c_void,
+            rand: rand as *mut c_void,
+            srand: srand as *mut c_void,
             malloc: malloc as *mut c_void,
             realloc: realloc as *mut c_void,
             free: free as *mut c_void,
@@ -395,6 +949,21 @@ impl Runtime for TestRuntime {
         func.insn_call_native(self.print_float, vec![data_ptr, number], None);
     }
 
+    fn print_output(
+        &mut self,
+        func: &mut Function,
+        output_kind: Value,
+        target: Value,
+        text: Value,
+    ) {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.print_output,
+            vec![data_ptr, output_kind, target, text],
+            None,
+        );
+    }
+
     fn concat(&mut self, func: &mut Function, ptr1: Value, ptr2: Value) -> Value {
         let data_ptr = self.data_ptr(func);
         func.insn_call_native(
@@ -422,6 +991,270 @@ impl Runtime for TestRuntime {
             Some(Context::float64_type()),
         )
     }
+
+    fn type_of(&mut self, func: &mut Function, tag: Value, ptr: Value, is_field: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.type_of,
+            vec![data_ptr, tag, ptr, is_field],
+            Some(Context::void_ptr_type()),
+        )
+    }
+
+    fn push_format_arg(&mut self, func: &mut Function, tag: Value, float: Value, ptr: Value) {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(self.push_format_arg, vec![data_ptr, tag, float, ptr], None);
+    }
+
+    fn printf(&mut self, func: &mut Function, fmt: Value) {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(self.printf, vec![data_ptr, fmt], None);
+    }
+
+    fn sprintf(&mut self, func: &mut Function, fmt: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.sprintf,
+            vec![data_ptr, fmt],
+            Some(Context::void_ptr_type()),
+        )
+    }
+
+    fn define_array(&mut self, name: &str) -> i32 {
+        let id = cast_to_runtime_data(self.runtime_data).arrays.define(name);
+        println!("array {} has id {}", name, id);
+        id
+    }
+
+    fn array_get(&mut self, func: &mut Function, array: Value, key: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.array_get,
+            vec![data_ptr, array, key],
+            Some(Context::void_ptr_type()),
+        )
+    }
+
+    fn subarray(&mut self, func: &mut Function, array: Value, key: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.subarray,
+            vec![data_ptr, array, key],
+            Some(Context::int_type()),
+        )
+    }
+
+    fn array_type_of(&mut self, func: &mut Function, array: Value, key: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.array_type_of,
+            vec![data_ptr, array, key],
+            Some(Context::void_ptr_type()),
+        )
+    }
+
+    fn array_contains(&mut self, func: &mut Function, array: Value, key: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.array_contains,
+            vec![data_ptr, array, key],
+            Some(Context::float64_type()),
+        )
+    }
+
+    fn array_is_array(&mut self, func: &mut Function, array: Value, key: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.array_is_array,
+            vec![data_ptr, array, key],
+            Some(Context::float64_type()),
+        )
+    }
+
+    fn array_iterate(&mut self, func: &mut Function, array: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.array_iterate,
+            vec![data_ptr, array],
+            Some(Context::int_type()),
+        )
+    }
+
+    fn array_next_key(&mut self, func: &mut Function, iterator: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.array_next_key,
+            vec![data_ptr, iterator],
+            Some(Context::void_ptr_type()),
+        )
+    }
+
+    fn array_stop_iterating(&mut self, func: &mut Function, iterator: Value) {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(self.array_stop_iterating, vec![data_ptr, iterator], None);
+    }
+
+    fn array_delete(&mut self, func: &mut Function, array: Value, key: Value) {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(self.array_delete, vec![data_ptr, array, key], None);
+    }
+
+    fn array_clear(&mut self, func: &mut Function, array: Value) {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(self.array_clear, vec![data_ptr, array], None);
+    }
+
+    fn getline(
+        &mut self,
+        func: &mut Function,
+        source: Value,
+        source_kind: Value,
+        into_var: Value,
+    ) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.getline,
+            vec![data_ptr, source, source_kind, into_var],
+            Some(Context::void_ptr_type()),
+        )
+    }
+
+    fn record_var(&mut self, func: &mut Function, var: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.record_var,
+            vec![data_ptr, var],
+            Some(Context::float64_type()),
+        )
+    }
+
+    fn set_record_var(&mut self, func: &mut Function, var: Value, value: Value) {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(self.set_record_var, vec![data_ptr, var, value], None);
+    }
+
+    fn record_terminator(&mut self, func: &mut Function) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.record_terminator,
+            vec![data_ptr],
+            Some(Context::void_ptr_type()),
+        )
+    }
+
+    fn set_column(&mut self, func: &mut Function, idx: Value, value: Value) {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(self.set_column, vec![data_ptr, idx, value], None);
+    }
+
+    fn close(&mut self, func: &mut Function, name: Value, how: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.close,
+            vec![data_ptr, name, how],
+            Some(Context::float64_type()),
+        )
+    }
+
+    fn system(&mut self, func: &mut Function, command: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.system,
+            vec![data_ptr, command],
+            Some(Context::float64_type()),
+        )
+    }
+
+    fn change_case(&mut self, func: &mut Function, ptr: Value, upper: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.change_case,
+            vec![data_ptr, ptr, upper],
+            Some(Context::void_ptr_type()),
+        )
+    }
+
+    fn match_index(&mut self, func: &mut Function, string: Value, pattern: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.match_index,
+            vec![data_ptr, string, pattern],
+            Some(Context::float64_type()),
+        )
+    }
+
+    fn substitute(
+        &mut self,
+        func: &mut Function,
+        pattern: Value,
+        replacement: Value,
+        text: Value,
+        global: Value,
+    ) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.substitute,
+            vec![data_ptr, pattern, replacement, text, global],
+            Some(Context::void_ptr_type()),
+        )
+    }
+
+    fn patsplit(
+        &mut self,
+        func: &mut Function,
+        string: Value,
+        array: Value,
+        fpat: Value,
+        seps: Value,
+    ) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.patsplit,
+            vec![data_ptr, string, array, fpat, seps],
+            Some(Context::float64_type()),
+        )
+    }
+
+    fn set_number_format(&mut self, func: &mut Function, is_ofmt: Value, fmt: Value) {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(self.set_number_format, vec![data_ptr, is_ofmt, fmt], None);
+    }
+
+    fn set_separator(&mut self, func: &mut Function, sep: Value, value: Value) {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(self.set_separator, vec![data_ptr, sep, value], None);
+    }
+
+    fn rand(&mut self, func: &mut Function) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(self.rand, vec![data_ptr], Some(Context::float64_type()))
+    }
+
+    fn srand(&mut self, func: &mut Function, seed: Value, has_seed: Value) -> Value {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.srand,
+            vec![data_ptr, seed, has_seed],
+            Some(Context::float64_type()),
+        )
+    }
+
+    fn array_assign(
+        &mut self,
+        func: &mut Function,
+        array: Value,
+        key: Value,
+        tag: Value,
+        float: Value,
+        ptr: Value,
+    ) {
+        let data_ptr = self.data_ptr(func);
+        func.insn_call_native(
+            self.array_assign,
+            vec![data_ptr, array, key, tag, float, ptr],
+            None,
+        );
+    }
 }
 
 pub fn cast_to_runtime_data(data: *mut c_void) -> &'static mut RuntimeData {
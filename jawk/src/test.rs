@@ -1,6 +1,8 @@
 use crate::codgen::compile_and_capture;
-use crate::{analyze, lex, parse, transform};
-use std::path::PathBuf;
+use crate::parser::parse;
+use crate::runtime::TestRuntime;
+use crate::{analyze, lex, transform};
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 use tempfile::tempdir;
 
@@ -11,8 +13,8 @@ const NUMBERS2: &'static str = "1 2 3 4\n4 5 6 5 \n7 8 9 4";
 const FLOAT_NUMBERS: &'static str = "1.1 2.2 3.3\n4.4 5.5 6.6\n7.7 8.8 9.9";
 const NUMERIC_STRING: &'static str = "1 2 3\n04 005 6\n07 8 9";
 
-fn test_once(interpreter: &str, prog: &str, file: &PathBuf) -> (String, Duration) {
-    // Run a single awk once and capture the output
+fn test_once(interpreter: &str, prog: &str, file: &PathBuf) -> (String, Duration, i32) {
+    // Run a single awk once and capture the output and exit status
     let start = Instant::now();
     let output = std::process::Command::new(interpreter)
         .args(vec![prog, file.to_str().unwrap()])
@@ -22,6 +24,7 @@ fn test_once(interpreter: &str, prog: &str, file: &PathBuf) -> (String, Duration
     (
         String::from_utf8(output.stdout).expect("cannot convert output to utf8"),
         dir,
+        output.status.code().expect("awk was killed by a signal"),
     )
 }
 
@@ -43,13 +46,18 @@ const PERF_RUNS: u128 = 3;
 fn test_against(interpreter: &str, prog: &str, file: &PathBuf) {
     // Run jawk against some other awk 10 times (interleaved) and comapre timing
 
-    let ours = test_once("./target/release/jawk", prog, file).0;
-    let output = test_once(interpreter, prog, file).0;
+    let (ours, _, our_status) = test_once("./target/release/jawk", prog, file);
+    let (output, _, status) = test_once(interpreter, prog, file);
     assert_eq!(
         ours, output,
         "LEFT jawk, RIGHT {} stdout didnt match for {}",
         interpreter, interpreter
     );
+    assert_eq!(
+        our_status, status,
+        "LEFT jawk, RIGHT {} exit status didnt match for {}",
+        interpreter, interpreter
+    );
 
     // let mut ours_us = 0;
     // let mut theirs_us = 0;
@@ -79,18 +87,14 @@ fn test_against(interpreter: &str, prog: &str, file: &PathBuf) {
     // );
 }
 
-fn test_it<S: AsRef<str>>(prog: &str, file: S, _expected_output: &str, _status: i32) {
+fn run_captured(prog: &str, file_path: &Path, header: bool) -> TestRuntime {
     println!("Program:\n{}", prog);
-    let mut ast = transform(parse(lex(&prog).unwrap()));
+    let mut ast = transform(parse(lex(&prog).unwrap()).unwrap());
     analyze(&mut ast);
     println!("Ast:\n{}", ast);
 
-    let temp_dir = tempdir().unwrap();
-    let file_path = temp_dir.path().join("tmp");
-    std::fs::write(file_path.clone(), file.as_ref()).unwrap();
-
     let file_path_string = file_path.to_str().unwrap().to_string();
-    let res = compile_and_capture(ast, &[file_path_string]).unwrap();
+    let res = compile_and_capture(ast, &[file_path_string], header).unwrap();
     let string_in = res.strings_in();
     let string_out = res.strings_out();
     assert_eq!(
@@ -98,6 +102,15 @@ fn test_it<S: AsRef<str>>(prog: &str, file: S, _expected_output: &str, _status:
         "runtime strings_in didn't match string_out. Possible mem leak {} vs {}",
         string_in, string_out
     );
+    res
+}
+
+fn test_it<S: AsRef<str>>(prog: &str, file: S, _expected_output: &str, _status: i32) {
+    let temp_dir = tempdir().unwrap();
+    let file_path = temp_dir.path().join("tmp");
+    std::fs::write(file_path.clone(), file.as_ref()).unwrap();
+
+    run_captured(prog, &file_path, false);
 
     test_against("awk", prog, &file_path);
     // test_against("mawk", prog, &file_path);
@@ -117,6 +130,13 @@ macro_rules! test {
 test!(test_print_int, "{print 1;}", ONE_LINE, "1\n", 0);
 test!(test_print_str, "{print \"abc\";}", ONE_LINE, "abc\n", 0);
 test!(test_just_begin, "BEGIN { print 1; }", ONE_LINE, "1\n", 0);
+test!(
+    test_hash_comments,
+    "# the first line\nBEGIN { x = \"#\" # not the string\n# another\nprint x, 1 } # done",
+    ONE_LINE,
+    "# 1\n",
+    0
+);
 test!(
     test_assign_to_undef,
     "BEGIN { print (x = x + 1); }",
@@ -145,6 +165,20 @@ test!(
     "12\n",
     0
 );
+test!(
+    test_exponential_unary_power,
+    "BEGIN { print 2^-1, 2^-1 == 0.5, -2^2, -2^2 == -4, 2^!x, 2^-1^2 }",
+    ONE_LINE,
+    "0.5 1 -4 1 2 0.5\n",
+    0
+);
+test!(
+    test_exponential_right_associative,
+    "BEGIN { print 2 ^ 3 ^ 2, 2 ^ 2 ^ 3 ^ 0, !2 ^ 2 }",
+    ONE_LINE,
+    "512 4 0\n",
+    0
+);
 test!(
     test_e2e_begin_end,
     "BEGIN { print 1; } END { print 3; } END { print 4; }",
@@ -159,6 +193,13 @@ test!(
     "1\n2\n3\n",
     0
 );
+test!(
+    test_begin_end_order_interleaved,
+    "END { print \"end1\", x } { x = x \"r\" $1 } BEGIN { x = \"b1\" } $1 == 4 { x = x \"!\" } BEGIN { x = x \"b2\" } END { print \"end2\" }",
+    NUMBERS,
+    "end1 b1b2r1r4!r7\nend2\n",
+    0
+);
 test!(test_4_assgn, "{x = 4; print x }", ONE_LINE, "4\n", 0);
 test!(test_cmpop2, "BEGIN { print (3 < 5) }", ONE_LINE, "1\n", 1);
 test!(test_cmpop1, "BEGIN { print (5 < 3) }", ONE_LINE, "0\n", 0);
@@ -764,6 +805,132 @@ test!(
     0
 );
 test!(test_assign_ops_2, "BEGIN { a = 1; b = 3; c = 5; d = 7; a += b +=c -= d ^= 3; print a; print b; print c; print d  }", ONE_LINE, "-334\n-335\n-338\n343\n", 0);
+test!(
+    test_chained_assign,
+    "{ a = b = c = NR; x[1] = $2 = c \"s\"; print a, b, c, x[1], $0 }",
+    NUMBERS,
+    "1 1 1 1s 1 1s 3\n2 2 2 2s 4 2s 6\n3 3 3 3s 7 3s 9\n",
+    0
+);
+test!(
+    test_chained_assign_value,
+    "BEGIN { print (a = b = c = 5) + 1, a, b, c }",
+    ONE_LINE,
+    "6 5 5 5\n",
+    0
+);
+test!(
+    test_assign_in_conditions,
+    "BEGIN { n = 3; while ((n = n - 1) > 0) print n; if (a = 1) print \"a\", a; if (b = 0) print \"b\"; print toupper(s = \"abc\"), s }",
+    ONE_LINE,
+    "2\n1\na 1\nABC abc\n",
+    0
+);
+test!(
+    test_pre_increment,
+    "BEGIN { x = 5; print ++x, x; print --x, x }",
+    ONE_LINE,
+    "6 6\n5 5\n",
+    0
+);
+test!(
+    test_pre_increment_lvalues,
+    "{ ++$2; print; print --n, n; ++a[$1 % 2]; print a[1] }",
+    NUMBERS,
+    "1 3 3\n-1 -1\n1\n4 6 6\n-2 -2\n1\n7 9 9\n-3 -3\n2\n",
+    0
+);
+test!(
+    test_increment_spacing,
+    "BEGIN { a = 1; b = 5; print a++ + b, a; print a+ ++b, b; print a + +b, a - -b, a; print -a, - -a, -2 ^ 2, +\"3x\" }",
+    ONE_LINE,
+    "6 2\n8 6\n8 8 2\n-2 2 -4 3\n",
+    0
+);
+test!(
+    test_post_increment_field,
+    "{ i = 2; x = $i++; print x, $0; $3--; print }",
+    ONE_LINE,
+    "2 1 3 3\n1 3 2\n",
+    0
+);
+test!(
+    test_ofs_rebuilds_record,
+    "BEGIN { OFS = \"-\" } { $1 = $1; print; OFS = OFS \"+\"; $2 = \"x\"; print }",
+    NUMBERS,
+    "1-2-3\n1-+x-+3\n4-+5-+6\n4-++x-++6\n7-++8-++9\n7-+++x-+++9\n",
+    0
+);
+test!(
+    test_assign_fs,
+    "BEGIN { FS = \",\" } { print $2, NF } NR == 1 { FS = \":\" }",
+    "a,b c,d\ne:f,g\n",
+    "b c 3\nf,g 2\n",
+    0
+);
+test!(
+    test_regex_fs,
+    "BEGIN { FS = \", *\" } { print $2, NF }",
+    "a, b,c\nx\n",
+    "b 3\n 1\n",
+    0
+);
+test!(
+    test_empty_loop_body,
+    "BEGIN { for (i = 0; i < 3; i++) ; if (i) ; else print \"no\"; print i }",
+    ONE_LINE,
+    "3\n",
+    0
+);
+test!(
+    test_rs_change_mid_input,
+    "NR == 1 { RS = \";\" } { print NR \": \" $0 }",
+    "a;b\nc;d;e\n",
+    "1: a;b\n2: c\n3: d\n4: e\n\n",
+    0
+);
+test!(
+    test_printf_pipe,
+    "{ printf(\"%d\\n\", $1 * 2) | \"sort -nr\" }",
+    NUMBERS,
+    "14\n8\n2\n",
+    0
+);
+test!(
+    test_nf_assign,
+    "{ NF = 2; print; print NF } NR == 2 { NF += 2; $NF = \"x\"; print } END { OFS = \"-\"; NF = 3; print }",
+    NUMBERS,
+    "1 2\n2\n4 5\n2\n4 5  x\n7 8\n2\n7-8-\n",
+    0
+);
+test!(
+    test_post_increment_at_line_end,
+    "BEGIN { x++\nprint x }",
+    ONE_LINE,
+    "1\n",
+    0
+);
+test!(
+    test_assign_ops_x,
+    "BEGIN { x = 3; x ^= 2; x %= 5; print x }",
+    ONE_LINE,
+    "4\n",
+    0
+);
+test!(
+    test_assign_ops_array,
+    "{ a[$1] += $2; a[$1] *= 2; a[$1] -= 1; a[$1] /= 3; a[$1] %= 2; a[$1] ^= 3; print a[$1] }",
+    NUMBERS,
+    "1\n1\n1\n",
+    0
+);
+test!(
+    test_assign_ops_field,
+    "{ $2 *= 3; $3 ^= 2; $1 -= 1; $1 += 10; $3 /= 2; $2 %= 4; print }",
+    NUMBERS,
+    "10 2 4.5\n13 3 18\n16 0 40.5\n",
+    0
+);
 test!(test_looping_concat, "BEGIN { a = \"\"; b = \"\"; x = 0; while (x < 50) {a = a \"a\"; b = b \"b\"; x += 1; } print a; print b; print x; }", ONE_LINE, "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\nbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb\n50\n", 0);
 test!(
     test_concat_undef,
@@ -840,3 +1007,1174 @@ test!(
     ".",
     1
 );
+test!(
+    test_typeof_unassigned,
+    "BEGIN { y = x; print typeof(x) }",
+    ONE_LINE,
+    "unassigned\n",
+    0
+);
+test!(
+    test_typeof_untyped,
+    "BEGIN { print typeof(x), isarray(x) }",
+    ONE_LINE,
+    "untyped 0\n",
+    0
+);
+test!(
+    test_typeof_string,
+    "BEGIN { x = \"a\"; print typeof(x); x = 1; print typeof(x); print typeof(NR) }",
+    ONE_LINE,
+    "string\nnumber\nnumber\n",
+    0
+);
+test!(
+    test_typeof_array,
+    "BEGIN { a[1] = 1; a[2] = \"s\"; a[3]; print typeof(a), isarray(a), typeof(a[1]), typeof(a[2]), typeof(a[3]), typeof(a[4]) }",
+    ONE_LINE,
+    "array 1 number string unassigned untyped\n",
+    0
+);
+test!(
+    test_typeof_number,
+    "BEGIN { x = 1; print typeof(x); print typeof(1) }",
+    ONE_LINE,
+    "number\nnumber\n",
+    0
+);
+test!(
+    test_typeof_field,
+    "{ print typeof($1); print typeof(\"a\") }",
+    ONE_LINE,
+    "strnum\nstring\n",
+    0
+);
+test!(
+    test_isarray_scalar,
+    "BEGIN { x = 1; print isarray(x) }",
+    ONE_LINE,
+    "0\n",
+    0
+);
+test!(
+    test_printf_conversions,
+    "BEGIN { printf \"%d-%s-%5.2f|%c\", 3, \"x\", 2.5, 65; print \"\" }",
+    ONE_LINE,
+    "3-x- 2.50|A\n",
+    0
+);
+test!(
+    test_printf_parens_fields,
+    "{ printf(\"%s:%s\", $1, $3); print \"\" }",
+    ONE_LINE,
+    "1:3\n",
+    0
+);
+test!(
+    test_sprintf,
+    "BEGIN { n = 7; s = \"abc\"; x = sprintf(\"%d-%s\", n, s); print x; print sprintf(\"%05.1f\", 2.25) }",
+    ONE_LINE,
+    "7-abc\n002.2\n",
+    0
+);
+test!(
+    test_getline_skips_records,
+    "{ getline; print $0 }",
+    NUMBERS,
+    "4 5 6\n7 8 9\n",
+    0
+);
+test!(
+    test_getline_reads_each_record_once,
+    "{ print \"rule\", $0; if ((getline) > 0) print \"got\", $0 } END { print NR }",
+    "a\nb\n\nd\n",
+    "rule a\ngot b\nrule \ngot d\n4\n",
+    0
+);
+test!(
+    test_assign_field,
+    "{ $2 = \"x\"; print; print NF, $2 }",
+    NUMBERS,
+    "1 x 3\n3 x\n4 x 6\n3 x\n7 x 9\n3 x\n",
+    0
+);
+test!(
+    test_assign_field_past_nf,
+    "{ $(NF + 2) = \"y\"; print NF, $0 \"|\"; $0 = \"a b\"; print NF, $2 }",
+    NUMBERS,
+    "5 1 2 3  y|\n2 b\n5 4 5 6  y|\n2 b\n5 7 8 9  y|\n2 b\n",
+    0
+);
+test!(
+    test_for_each_field,
+    "{ for (i=1; i<=NF; i++) print $i }",
+    NUMBERS2,
+    "1\n2\n3\n4\n4\n5\n6\n5\n7\n8\n9\n4\n",
+    0
+);
+test!(
+    test_for_each_field_uneven,
+    "{ for (i = 1; i <= NF; i++) print NR, i, $i; print \"after\", i }",
+    "a\nb c d\n\n  e   f\n",
+    "1 1 a\nafter 2\n2 1 b\n2 2 c\n2 3 d\nafter 4\nafter 1\n4 1 e\n4 2 f\nafter 3\n",
+    0
+);
+test!(
+    test_continue_in_for,
+    "{ for (i = 1; i <= NF; i++) { if (i == 2) continue; print $i } }",
+    NUMBERS,
+    "1\n3\n4\n6\n7\n9\n",
+    0
+);
+test!(
+    test_continue_in_while,
+    "{ i = 0; while (i < NF) { i++; if ($i % 2) continue; s = s $i } } END { print s }",
+    NUMBERS,
+    "2468\n",
+    0
+);
+test!(
+    test_do_while_runs_once,
+    "BEGIN { x = 0; do { print \"once\"; x++ } while (x < 0); print x }",
+    ONE_LINE,
+    "once\n1\n",
+    0
+);
+test!(
+    test_do_while_single_stmt,
+    "BEGIN { i = 0; do i++; while (i < 5); print i }",
+    ONE_LINE,
+    "5\n",
+    0
+);
+test!(
+    test_do_while_break_continue,
+    "BEGIN { do { n++; if (n % 2) continue; if (n > 6) break; print n } while (n < 10); print \"n\", n }",
+    ONE_LINE,
+    "2\n4\n6\nn 8\n",
+    0
+);
+test!(
+    test_break_in_while,
+    "BEGIN { while (1) { n++; if (n >= 3) break } print n }",
+    ONE_LINE,
+    "3\n",
+    0
+);
+test!(
+    test_break_inner_loop_only,
+    "BEGIN { for (i = 0; i < 3; i++) for (j = 0; j < 3; j++) { if (j > i) break; print i, j } }",
+    ONE_LINE,
+    "0 0\n1 0\n1 1\n2 0\n2 1\n2 2\n",
+    0
+);
+test!(
+    test_break_continue_for_in,
+    "{ a[$1]; for (k in a) { c++; break } for (k in a) { if (k == \"4\") continue; m++ } } END { print c, m }",
+    NUMBERS,
+    "3 4\n",
+    0
+);
+test!(
+    test_next_in_for_in,
+    "{ a[NR]; for (k in a) next } END { for (k in a) n++; print n }",
+    NUMBERS,
+    "3\n",
+    0
+);
+test!(
+    test_print_negative_zero_and_inf,
+    "BEGIN { inf = 2 ^ 2000; z = 0 * (0 - 1); print z, inf, 0 - inf; x = z \"\"; y = inf \"\"; print x, y }",
+    ONE_LINE,
+    "0 inf -inf\n0 inf\n",
+    0
+);
+test!(
+    test_printf_negative_zero_and_inf,
+    "BEGIN { inf = 2 ^ 2000; z = 0 * (0 - 1); printf \"%d %f %g %e|%f %g %e|%.1f\\n\", z, z, z, z, inf, inf, inf, 0 - inf; OFMT = \"%.2f\"; print z, inf }",
+    ONE_LINE,
+    "0 -0.000000 -0 -0.000000e+00|inf inf inf|-inf\n0 inf\n",
+    0
+);
+test!(
+    test_print_empty_string,
+    "{ print \"\" }",
+    "a\n\nb\n",
+    "\n\n\n",
+    0
+);
+test!(
+    test_empty_records,
+    "{ print \"\"; print; print NF, \"[\" $1 \"]\" \"[\" $0 \"]\" }",
+    "a b\n\n  \n c \n",
+    "\na b\n2 [a][a b]\n\n\n0 [][]\n\n  \n0 [][  ]\n\n c \n1 [c][ c ]\n",
+    0
+);
+test!(
+    test_assign_field_number,
+    "{ x = $1 = $1 * 10; print x + 1, $0 }",
+    NUMBERS,
+    "11 10 2 3\n41 40 5 6\n71 70 8 9\n",
+    0
+);
+test!(
+    test_getline_into_field,
+    "{ \"echo z\" | getline $2; print }",
+    NUMBERS,
+    "1 z 3\n4 5 6\n7 8 9\n",
+    0
+);
+test!(
+    test_getline_return_value,
+    "{ r = getline; print r }",
+    NUMBERS,
+    "1\n0\n",
+    0
+);
+test!(
+    test_print_multiple_args,
+    "{ print $1, $3; print ($2, \"x\") }",
+    ONE_LINE,
+    "1 3\n2 x\n",
+    0
+);
+test!(
+    test_print_ofs_ors,
+    "BEGIN { OFS = \"-\"; ORS = \"|\" } { print $1, $2 }",
+    NUMBERS,
+    "1-2|4-5|7-8|",
+    0
+);
+test!(
+    test_print_empty_ors,
+    "BEGIN { ORS = \"\" } { print $1; print $2, $3 } END { print \"\\n\" }",
+    NUMBERS,
+    "12 34 5 67 8 9\n",
+    0
+);
+test!(
+    test_print_records_empty_ors,
+    "BEGIN{ORS=\"\"} {print $0}",
+    NUMBERS,
+    "1 2 34 5 67 8 9",
+    0
+);
+test!(
+    test_printf_ignores_ors,
+    "BEGIN { ORS = \"|\" } { printf \"%s-\", $1 } END { printf \"\\n\" }",
+    NUMBERS,
+    "1-4-7-\n",
+    0
+);
+test!(
+    test_printf_ignores_empty_ors,
+    "BEGIN { ORS = \"\" } { printf \"%s\\n\", $2; print $1 }",
+    NUMBERS,
+    "2\n15\n48\n7",
+    0
+);
+test!(
+    test_assign_nr,
+    "{ if (NR==1) NR=10 } { print NR }",
+    NUMBERS,
+    "10\n11\n12\n",
+    0
+);
+test!(
+    test_assign_fnr,
+    "FNR == 2 { FNR = 0 } { print NR, FNR } END { NR = \"5\"; print NR }",
+    NUMBERS,
+    "1 1\n2 0\n3 1\n5\n",
+    0
+);
+test!(
+    test_folded_constants,
+    "BEGIN { x = 60 * 60 * 24; print x, \"a\" 1 + 2, 7 % 3, 2 ^ 3, !\"0\", 1 / 4 \"\" }",
+    ONE_LINE,
+    "86400 a3 1 8 0 0.25\n",
+    0
+);
+test!(
+    test_next_skips_later_actions,
+    "$1 == 4 { print \"skip\"; next; print \"never\" } { print $1 } END { print NR }",
+    NUMBERS,
+    "1\nskip\n7\n3\n",
+    0
+);
+test!(
+    test_next_inside_loop,
+    "{ i = 0; while (i < 5) { i = i + 1; if (i == $2) { next } } print \"no next\", $2 } { print \"after\" }",
+    NUMBERS,
+    "no next 8\nafter\n",
+    0
+);
+test!(
+    test_next_from_for_in,
+    "BEGIN { a[\"x\"] = 1 } { for (k in a) { if ($1 == 4) next; print k, $1 } print \"done\" } END { print \"end\" }",
+    NUMBERS,
+    "x 1\ndone\nx 7\ndone\nend\n",
+    0
+);
+test!(
+    test_exit_runs_end,
+    "{ if (NR == 2) exit 3; print } END { print \"end\", NR }",
+    NUMBERS,
+    "1 2 3\nend 2\n",
+    3
+);
+test!(
+    test_exit_in_begin,
+    "BEGIN { print \"begin\"; exit; print \"never\" } { print } END { print \"end\" }",
+    NUMBERS,
+    "begin\nend\n",
+    0
+);
+test!(
+    test_exit_in_begin_status,
+    "BEGIN { exit 1 } END { print \"cleanup\" }",
+    NUMBERS,
+    "cleanup\n",
+    1
+);
+test!(
+    test_exit_inside_loop,
+    "{ i = 0; while (i < 5) { i = i + 1; if (i == $2) { exit i + 10 } } print $2 } { print \"after\" } END { print \"end\", NR }",
+    NUMBERS,
+    "end 1\n",
+    12
+);
+test!(
+    test_exit_in_end_keeps_status,
+    "{ exit 2 } END { print \"end\"; exit; print \"never\" }",
+    NUMBERS,
+    "end\n",
+    2
+);
+test!(
+    test_exit_in_end_skips_rest,
+    "END { exit 4; print \"never\" } END { print \"never\" }",
+    NUMBERS,
+    "",
+    4
+);
+test!(
+    test_exit_string_status,
+    "{ exit \"7x\" } END { print $1 }",
+    NUMBERS,
+    "1\n",
+    7
+);
+test!(
+    test_array_assign_and_read,
+    "BEGIN { a[\"x\"] = 1; a[1] = \"b\"; a[1, 2] = 3; print a[\"x\"], a[1], a[1, 2], a[\"missing\"] \"|\" }",
+    ONE_LINE,
+    "1 b 3 |\n",
+    0
+);
+
+test!(
+    test_environ,
+    "BEGIN { print (ENVIRON[\"PATH\"] != \"\"), ENVIRON[\"JAWK_UNSET_VAR\"] \"|\" }",
+    ONE_LINE,
+    "1 |\n",
+    0
+);
+
+// --header is our own option so there is no other awk to compare against
+#[test]
+fn test_header_fields_by_name() {
+    let temp_dir = tempdir().unwrap();
+    let file_path = temp_dir.path().join("people");
+    std::fs::write(file_path.clone(), "name age\nbob 30\namy 41\n").unwrap();
+
+    let res = run_captured(
+        "{ print F[\"age\"], F[\"name\"], F[\"height\"] \"|\" }",
+        &file_path,
+        true,
+    );
+    assert_eq!(res.output(), "30 bob |\n41 amy |\n");
+}
+
+test!(
+    test_bare_print,
+    "{ print }",
+    NUMBERS,
+    "1 2 3\n4 5 6\n7 8 9\n",
+    0
+);
+test!(
+    test_empty_actions,
+    "NR == 2 { }\nNR != 2 { ; }\nNR == 3",
+    NUMBERS,
+    "7 8 9\n",
+    0
+);
+test!(test_empty_regex_action, "/a/ {}", "a\nb\nab\n", "", 0);
+test!(
+    test_regex_escaped_slash,
+    "/a\\/b/ { print \"escaped\", $0 } /^[^/]+[/]c$/ { print \"class\", $0 }",
+    "a/b\nab\nx/c\nx/y/c\n",
+    "escaped a/b\nclass x/c\n",
+    0
+);
+test!(
+    test_line_continuation,
+    "$1 > 1 \\\n&& $1 < 4 { print $1, \\\n$2 }",
+    "1 a\n2 b\n3 c\n4 d\n",
+    "2 b\n3 c\n",
+    0
+);
+test!(
+    test_empty_begin_end,
+    "BEGIN { } { } END { print NR }",
+    NUMBERS,
+    "3\n",
+    0
+);
+test!(
+    test_assign_concatenation,
+    "{ a = $1 $2 $3; b = $3 - $1 $2; print a; print b }",
+    ONE_LINE,
+    "123\n22\n",
+    0
+);
+
+#[test]
+fn test_getline_from_file() {
+    let temp_dir = tempdir().unwrap();
+    let lookup = temp_dir.path().join("lookup");
+    std::fs::write(lookup.clone(), NUMBERS).unwrap();
+    let prog = format!(
+        "BEGIN {{ \
+            while ((getline line < \"{0}\") > 0) {{ print \"got \" line }}; \
+            close(\"{0}\"); \
+            getline < \"{0}\"; \
+            print $0, $2; \
+            print (getline x < \"{0}/missing\") \
+        }}",
+        lookup.to_str().unwrap()
+    );
+    test_it(
+        &prog,
+        ONE_LINE,
+        "got 1 2 3\ngot 4 5 6\ngot 7 8 9\n1 2 3 2\n-1\n",
+        0,
+    );
+}
+test!(
+    test_custom_ofmt_convfmt,
+    "BEGIN { OFMT = \"%.2f\"; x = 1.23456; print x; CONVFMT = \"%.3f\"; y = x \"\"; print y; print 2 }",
+    ONE_LINE,
+    "1.23\n1.235\n2\n",
+    0
+);
+test!(
+    test_ofmt_without_conversion,
+    "BEGIN { OFMT = \"x\"; print 1.5; print 2 }",
+    ONE_LINE,
+    "x\n2\n",
+    0
+);
+test!(
+    test_rand_srand,
+    "BEGIN { srand(1); x = rand(); srand(1); print (x == rand()), srand(5), (rand() < 1) }",
+    ONE_LINE,
+    "1 1 1\n",
+    0
+);
+test!(
+    test_srand_returns_previous_seed,
+    "BEGIN { x = srand(1); y = srand(2); z = srand(); print y, z }",
+    ONE_LINE,
+    "1 2\n",
+    0
+);
+test!(
+    test_getline_from_command,
+    "BEGIN { cmd = \"echo a; echo b\"; cmd | getline; print $0; cmd | getline x; print x; print (cmd | getline), close(cmd) }",
+    ONE_LINE,
+    "a\nb\n0 0\n",
+    0
+);
+test!(
+    test_print_to_pipe,
+    "BEGIN { OFS = \"-\"; ORS = \"|\\n\"; print \"a\", \"b\" | \"cat\"; print \"c\", 1 + 1 | \"cat\" }",
+    ONE_LINE,
+    "a-b|\nc-2|\n",
+    0
+);
+test!(
+    test_print_to_pipe_and_close,
+    "{ print $3, $1 | \"sort -r\" } END { print close(\"sort -r\"), close(\"sort -r\") }",
+    NUMBERS,
+    "9 7\n6 4\n3 1\n0 -1\n",
+    0
+);
+test!(
+    test_print_comparison_results,
+    "BEGIN { print (1<2); print !0; print (3<2); print !\"a\", !\"\", (1<2) + 0.5 }",
+    ONE_LINE,
+    "1\n1\n0\n0 1 1.5\n",
+    0
+);
+test!(
+    test_range_pattern,
+    "$1 == 4, $1 == 7 { print $1 } $1 == 4, $1 == 4 { print \"one line\" } $1 == 7, 0",
+    NUMBERS,
+    "4\none line\n7\n7 8 9\n",
+    0
+);
+test!(
+    test_boolean_operators_give_one_or_zero,
+    "{ a[$1]; print (5 < 7), (5 > 7), ($1 <= 2), ($1 >= 2), ($1 == 1), ($1 != 1), (\"b\" < \"a\"), (\"b\" > \"a\"), (5 && \"x\"), (5 && \"\"), (0 || 3), (0 || 0), !7, !\"\", ($1 in a), ($1 in b), ($0 ~ /2/), ($0 ~ /x/), ($0 !~ /x/) }",
+    ONE_LINE,
+    "1 0 1 0 1 0 0 1 1 0 1 0 0 1 1 0 1 0 1\n",
+    0
+);
+test!(
+    test_in_array_doesnt_create,
+    "BEGIN { a[1, 2]; print ((1, 2) in a), ((2, 1) in a), !(3 in a), (3 in a) }",
+    ONE_LINE,
+    "1 0 1 0\n",
+    0
+);
+test!(
+    test_toupper_tolower,
+    "{ print toupper($1 \"abc\"), tolower(\"MiXeD\"), toupper(1.5) }",
+    ONE_LINE,
+    "1ABC mixed 1.5\n",
+    0
+);
+test!(
+    test_match,
+    "{ print match($0, /b+/), match($2, \"[0-9]\"), match($1, \"z\") }",
+    "abbc x9\nz\n",
+    "2 2 0\n0 0 1\n",
+    0
+);
+test!(
+    test_gsub_record_resplits_fields,
+    "{ n = gsub(/b/, \"x y\"); print n, NF, $1, $2, $0 }",
+    "ab b\nc\n",
+    "2 4 ax y ax y x y\n0 1 c  c\n",
+    0
+);
+test!(
+    test_gsub_field_rebuilds_record,
+    "BEGIN { OFS = \"-\" } { n = gsub(/a/, \"z\", $1); print n, $0, NF }",
+    "aba c\nd\n",
+    "2-zbz-c-2\n0-d-1\n",
+    0
+);
+test!(
+    test_sub_targets,
+    "{ x = $0; sub(/[0-9]+/, \"<&>\", x); a[1] = $1; gsub(/o/, \"\\\\&\", a[1]); print x, a[1], sub(/q/, \"r\"), $0 }",
+    "foo 42 bar\n",
+    "foo <42> bar f&& 0 foo 42 bar\n",
+    0
+);
+test!(
+    test_gsub_number_and_empty_matches,
+    "BEGIN { x = 1231; print gsub(/1/, \"\", x), x + 1; y = \"aaa\"; print gsub(/x*/, \"-\", y), y }",
+    ONE_LINE,
+    "2 24\n4 -a-a-a-\n",
+    0
+);
+test!(
+    test_newline_terminated_stmts,
+    "BEGIN {
+    x = 1
+    y = x + 2
+    if (y == 3 &&
+        x == 1)
+    {
+        print x,
+            y
+    }
+}
+{ print $2 }
+",
+    ONE_LINE,
+    "1 3\n2\n",
+    0
+);
+test!(
+    test_for_in,
+    "BEGIN { a[1] = 5; a[2] = 6; for (k in a) { s = s + k * a[k]; n = n + 1 } print s, n; for (k in b) { print \"never\" } }",
+    ONE_LINE,
+    "17 2\n",
+    0
+);
+test!(
+    test_delete_element,
+    "BEGIN { for (i = 0; i < 6; i++) a[i] = i; for (k in a) if (k % 2) delete a[k]; for (k in a) s += a[k]; print s, (1 in a), (2 in a); a[\"x\", 1] = 1; a[\"x\", 2] = 2; delete a[\"x\", 1]; print ((\"x\", 1) in a), ((\"x\", 2) in a) }",
+    ONE_LINE,
+    "6 0 1\n0 1\n",
+    0
+);
+test!(
+    test_delete_array,
+    "{ a[$1] = $2 } END { delete a; for (k in a) n++; print n + 0, (1 in a); a[1] = \"new\"; print a[1]; delete b; delete b[1]; print (1 in b) }",
+    NUMBERS,
+    "0 0\nnew\n0\n",
+    0
+);
+test!(
+    test_string_escapes,
+    "BEGIN { print \"a\\tb\\\\c\\\"d\\101\\q\" }",
+    ONE_LINE,
+    "a\tb\\c\"dA\\q\n",
+    0
+);
+test!(
+    test_printf_halfway_rounding,
+    "BEGIN { printf \"%.0f %.0f %.0f %.0f %.2f %.2f %.0e %.1e %.2g %.1g\\n\", 0.5, 1.5, 2.5, 3.5, 0.125, 2.675, 25, 125, 0.125, 2.5 }",
+    ONE_LINE,
+    "0 2 2 4 0.12 2.67 2e+01 1.2e+02 0.12 2\n",
+    0
+);
+test!(
+    test_system,
+    "BEGIN { print \"before\"; r = system(\"echo from sh; exit 3\"); print r, system(\"true\") }",
+    ONE_LINE,
+    "before\nfrom sh\n3 0\n",
+    0
+);
+test!(
+    test_number_literals,
+    "BEGIN { print 0x1F, 1e3, 1.5E-3, .5, 2e+2, 0x10 + 1 }",
+    ONE_LINE,
+    "31 1000 0.0015 0.5 200 17\n",
+    0
+);
+
+test!(
+    test_getline_into_array,
+    "{ getline a[$1]; print a[$1] \"|\" NR } END { \"echo c1\" | getline b[\"k\"]; \"echo c2\" | getline b[\"k\", 2]; print b[\"k\"], b[\"k\", 2] }",
+    NUMBERS,
+    "4 5 6|2\n|3\nc1 c2\n",
+    0
+);
+
+// getline in a pattern reads the side file one line per record until it runs out
+#[test]
+fn test_getline_in_pattern() {
+    let temp_dir = tempdir().unwrap();
+    let file_path = temp_dir.path().join("tmp");
+    std::fs::write(file_path.clone(), NUMBERS).unwrap();
+    let side_path = temp_dir.path().join("side");
+    std::fs::write(side_path.clone(), "a\nb\n").unwrap();
+
+    let prog = format!(
+        "(getline line < \"{}\") > 0 {{ print $1, line }}",
+        side_path.to_str().unwrap()
+    );
+    run_captured(&prog, &file_path, false);
+    let ours = test_once("./target/release/jawk", &prog, &file_path).0;
+    assert_eq!(ours, "1 a\n4 b\n");
+    test_against("awk", &prog, &file_path);
+}
+
+// gawk's a[1][2] arrays of arrays. Other awks reject the syntax so expected output is
+// spelled out and only gawk is compared against.
+const NESTED_ARRAYS: &[(&str, &str)] = &[
+    (
+        "BEGIN { a[\"x\"][\"y\"] = 1; a[\"x\"][\"z\"] = 2; a[\"w\"] = 3; print a[\"x\"][\"y\"], a[\"x\"][\"z\"], a[\"w\"] }",
+        "1 2 3\n",
+    ),
+    (
+        "BEGIN { a[\"x\"][1] = 1; a[\"w\"] = 3; print isarray(a), isarray(a[\"x\"]), isarray(a[\"w\"]), isarray(a[\"x\"][1]), isarray(b) }",
+        "1 1 0 0 0\n",
+    ),
+    (
+        "BEGIN { a[\"x\"][1] = 1; print typeof(a), typeof(a[\"x\"]), typeof(a[\"x\"][1]), typeof(a[\"x\"][2]) }",
+        "array array number untyped\n",
+    ),
+    (
+        "BEGIN { a[1][\"p\"] = 2; a[1][\"q\"] = 3; a[2] = 10; for (k in a[1]) { n = n + 1; s = s + a[1][k] } print n, s }",
+        "2 5\n",
+    ),
+    (
+        "{ a[$1][$2][$3] = $1 + $2 + $3 } END { print a[1][2][3], a[4][5][6], a[1, 2][3] }",
+        "6 15 \n",
+    ),
+    (
+        "BEGIN { a[1][1] = 1; a[1][2] = 2; a[2][1] = 3; delete a[1][1]; print (1 in a[1]), (2 in a[1]); delete a[1]; print (1 in a), (2 in a), isarray(a[2]) }",
+        "0 1\n0 1 1\n",
+    ),
+];
+
+#[test]
+fn test_nested_arrays() {
+    let temp_dir = tempdir().unwrap();
+    let file_path = temp_dir.path().join("tmp");
+    std::fs::write(file_path.clone(), NUMBERS).unwrap();
+
+    for (prog, expected) in NESTED_ARRAYS {
+        run_captured(prog, &file_path, false);
+        let ours = test_once("./target/release/jawk", prog, &file_path).0;
+        assert_eq!(&ours, expected, "wrong output for {}", prog);
+        test_against("gawk", prog, &file_path);
+    }
+}
+
+// gawk's patsplit, which other awks don't have
+const PATSPLIT: &[(&str, &str)] = &[
+    (
+        "BEGIN { n = patsplit(\"a1b2c\", x, /[a-z]/); print n, x[1], x[2], x[3] }",
+        "3 a b c\n",
+    ),
+    (
+        "BEGIN { n = patsplit(\"  ab, cd \", x, \"[a-z]+\", s); for (k in s) { c = c + 1 } print n, x[2], \"[\" s[0] \"]\", \"[\" s[1] \"]\", \"[\" s[2] \"]\", c }",
+        "2 cd [  ] [, ] [ ] 3\n",
+    ),
+    ("{ n = patsplit($0, f); print n, f[n] }", "3 3\n3 6\n3 9\n"),
+    (
+        "BEGIN { x[9] = 1; patsplit(\"ab\", x, /./); for (k in x) { c = c + 1 } print c, x[2] }",
+        "2 b\n",
+    ),
+];
+
+#[test]
+fn test_patsplit() {
+    let temp_dir = tempdir().unwrap();
+    let file_path = temp_dir.path().join("tmp");
+    std::fs::write(file_path.clone(), NUMBERS).unwrap();
+
+    for (prog, expected) in PATSPLIT {
+        run_captured(prog, &file_path, false);
+        let ours = test_once("./target/release/jawk", prog, &file_path).0;
+        assert_eq!(&ours, expected, "wrong output for {}", prog);
+        test_against("gawk", prog, &file_path);
+    }
+}
+
+// ** and **= are gawk's, mawk rejects them
+#[test]
+fn test_star_star() {
+    let temp_dir = tempdir().unwrap();
+    let file_path = temp_dir.path().join("tmp");
+    std::fs::write(file_path.clone(), ONE_LINE).unwrap();
+
+    let prog = "{ print 2 ** 10, 2 ** 3 ** 2; x = 3; x **= 2; print x }";
+    run_captured(prog, &file_path, false);
+    let ours = test_once("./target/release/jawk", prog, &file_path).0;
+    assert_eq!(ours, "1024 512\n9\n");
+    test_against("gawk", prog, &file_path);
+}
+
+// mawk doesn't treat newlines as field separators in paragraph mode, gawk and POSIX do
+#[test]
+fn test_paragraph_mode() {
+    let temp_dir = tempdir().unwrap();
+    let file_path = temp_dir.path().join("tmp");
+    std::fs::write(file_path.clone(), "\na:b\nc:d\n\n\ne f:g\n").unwrap();
+
+    let prog = "BEGIN { RS = \"\"; FS = \":\" } { print NR \": \" NF, $2, $3 }";
+    run_captured(prog, &file_path, false);
+    let ours = test_once("./target/release/jawk", prog, &file_path).0;
+    assert_eq!(ours, "1: 4 b c\n2: 2 g \n");
+    test_against("gawk", prog, &file_path);
+}
+
+#[test]
+fn test_regex_record_sep() {
+    let temp_dir = tempdir().unwrap();
+    let file_path = temp_dir.path().join("tmp");
+    std::fs::write(file_path.clone(), "a 12b\nc;;d").unwrap();
+
+    let prog = "BEGIN { RS = \"[0-9]+|;;\" } { print NR \": \" $0 \"|\" RT \"|\" }";
+    run_captured(prog, &file_path, false);
+    let ours = test_once("./target/release/jawk", prog, &file_path).0;
+    assert_eq!(ours, "1: a |12|\n2: b\nc|;;|\n3: d||\n");
+    test_against("gawk", prog, &file_path);
+}
+
+#[test]
+fn test_record_terminator() {
+    let temp_dir = tempdir().unwrap();
+    let prog = "{ print $0, RT == \"\\n\" ? \"newline\" : \"eof\" }";
+    for (contents, expected) in [
+        ("a\nb\n", "a newline\nb newline\n"),
+        ("a\nb", "a newline\nb eof\n"),
+    ] {
+        let file_path = temp_dir.path().join("tmp");
+        std::fs::write(file_path.clone(), contents).unwrap();
+        run_captured(prog, &file_path, false);
+        let ours = test_once("./target/release/jawk", prog, &file_path).0;
+        assert_eq!(ours, expected);
+        test_against("gawk", prog, &file_path);
+    }
+}
+
+#[test]
+fn test_coprocess() {
+    let temp_dir = tempdir().unwrap();
+    let file_path = temp_dir.path().join("tmp");
+    std::fs::write(file_path.clone(), ONE_LINE).unwrap();
+
+    let prog = "BEGIN { print \"x\" |& \"cat\"; close(\"cat\", \"to\"); \"cat\" |& getline y; print y; print close(\"cat\") }";
+    run_captured(prog, &file_path, false);
+    let ours = test_once("./target/release/jawk", prog, &file_path).0;
+    assert_eq!(ours, "x\n0\n");
+    test_against("gawk", prog, &file_path);
+}
+
+// What each form of getline changes. The first record runs the form and then reports
+// NR, FNR, NF, $0 and x, END reports the final NR and FNR. SIDE is a file holding
+// "s1 s2 s3 s4". This is the POSIX table, which gawk follows and extends to cmd |& getline.
+// mawk differs by not counting cmd | getline records in NR.
+const GETLINE_SIDE_EFFECTS: &[(&str, &str)] = &[
+    ("getline", "2 2 3 4 5 6 \n3 3\n"),
+    ("getline x", "2 2 3 1 2 3 4 5 6\n3 3\n"),
+    ("getline < \"SIDE\"", "1 1 4 s1 s2 s3 s4 \n3 3\n"),
+    ("getline x < \"SIDE\"", "1 1 3 1 2 3 s1 s2 s3 s4\n3 3\n"),
+    ("\"echo c1 c2\" | getline", "2 1 2 c1 c2 \n4 3\n"),
+    ("\"echo c1 c2\" | getline x", "2 1 3 1 2 3 c1 c2\n4 3\n"),
+    ("\"echo c1 c2\" |& getline", "2 1 2 c1 c2 \n4 3\n"),
+    ("\"echo c1 c2\" |& getline x", "2 1 3 1 2 3 c1 c2\n4 3\n"),
+];
+
+#[test]
+fn test_getline_side_effects() {
+    let temp_dir = tempdir().unwrap();
+    let file_path = temp_dir.path().join("tmp");
+    std::fs::write(file_path.clone(), NUMBERS).unwrap();
+    let side_path = temp_dir.path().join("side");
+    std::fs::write(side_path.clone(), "s1 s2 s3 s4\n").unwrap();
+
+    for (form, expected) in GETLINE_SIDE_EFFECTS {
+        let form = form.replace("SIDE", side_path.to_str().unwrap());
+        let prog = format!(
+            "NR == 1 {{ {}; print NR, FNR, NF, $0, x }} END {{ print NR, FNR }}",
+            form
+        );
+        run_captured(&prog, &file_path, false);
+        let ours = test_once("./target/release/jawk", &prog, &file_path).0;
+        assert_eq!(&ours, expected, "wrong side effects for {}", form);
+        test_against("gawk", &prog, &file_path);
+    }
+}
+
+test!(
+    test_ternary,
+    "{ print (NR == 1 ? \"item\" : \"items\") }",
+    NUMBERS,
+    "item\nitems\nitems\n",
+    0
+);
+test!(
+    test_ternary_only_runs_one_branch,
+    "BEGIN { a = 1; b = 1; r = 1 ? a++ : b++; print r, a, b }",
+    ONE_LINE,
+    "1 2 1\n",
+    0
+);
+test!(
+    test_ternary_default_idiom,
+    "{ x = $2; x = x ? x : \"none\"; print x }",
+    "a b\nc\n",
+    "b\nnone\n",
+    0
+);
+test!(
+    test_nested_ternary,
+    "BEGIN { for (i = 1; i <= 3; i++) print (i == 1 ? \"one\" : i == 2 ? \"two\" : \"many\") }",
+    ONE_LINE,
+    "one\ntwo\nmany\n",
+    0
+);
+test!(
+    test_ternary_mixed_types,
+    "{ x = $1 > 1 ? $1 * 2 : \"small\"; print x }",
+    NUMBERS,
+    "small\n8\n14\n",
+    0
+);
+
+test!(
+    test_double_not,
+    "{ print !!$1, !$1, !!\"\", !!\"0\", !!0, !!x, !x == 1, !!($1 * 0.5) }",
+    NUMBERS,
+    "1 0 0 1 0 0 1 1\n1 0 0 1 0 0 1 1\n1 0 0 1 0 0 1 1\n",
+    0
+);
+test!(
+    test_not_field_strnum,
+    "{ print !$1, !$2, !$3, !!$4, !!$3 }",
+    "0 0.0 a\n",
+    "1 1 0 0 1\n",
+    0
+);
+test!(
+    test_concatenation_before_comparison,
+    "$1 $2 == \"ab\" { print \"match\", NR } \"x\" $1 != \"xa\" { print \"differs\", NR }",
+    "a b\nab c\n",
+    "match 1\ndiffers 2\n",
+    0
+);
+test!(test_negated_regex_pattern, "!/b/", "a\nb\nc\n", "a\nc\n", 0);
+test!(
+    test_match_operators,
+    "$1 ~ /^b/ { print \"b\", $2 } $1 !~ \"a\" { print \"no a\", $1 } { print $2 ~ \"[0-9]\" }",
+    "ab 1\nbc x\n",
+    "1\nb x\nno a bc\n0\n",
+    0
+);
+test!(test_regex_pattern, "/b/", "a\nb\nc\n", "b\n", 0);
+test!(
+    test_empty_statements,
+    "BEGIN { ; ; print 1 ; ;; }",
+    ONE_LINE,
+    "1\n",
+    0
+);
+test!(
+    test_for_without_clauses,
+    "BEGIN { for (;;) { i = i + 1; if (i > 3) break; print i } for (j = 0; ; j++) { if (j == 2) break } print j }",
+    ONE_LINE,
+    "1\n2\n3\n2\n",
+    0
+);
+test!(
+    test_for_without_incr,
+    "BEGIN { n = 3; for (i = 0; i < n;) i = i + 1; print i }",
+    ONE_LINE,
+    "3\n",
+    0
+);
+
+// -v assignments are made before BEGIN, "10" is a number so x < 9 is false, y has its \t decoded
+#[test]
+fn test_command_line_assignment() {
+    let prog = "BEGIN { print x < 9, x + 1, y }";
+    let args = ["-v", "x=10", "-vy=a\\tb", prog];
+    let run = |interpreter: &str| {
+        let output = std::process::Command::new(interpreter)
+            .args(args)
+            .output()
+            .unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let ours = run("./target/release/jawk");
+    assert_eq!(ours, "0 11 a\tb\n");
+    assert_eq!(ours, run("awk"));
+}
+
+// A -v value that looks numeric is a strnum, it compares as a number but prints as given
+#[test]
+fn test_command_line_strnum() {
+    let prog =
+        "BEGIN { print x, y, x == 3, y == 3, y < 10, x == \"3.0\", x y; print NF, \"[\" $0 \"]\" }";
+    let vars = ["x=3.0", "y=03", "NF=2"];
+    let output = crate::run_capture_with_vars(prog, &vars, "").unwrap();
+    let ours = String::from_utf8(output).unwrap();
+    assert_eq!(ours, "3.0 03 1 1 1 1 3.003\n2 [ ]\n");
+    let args = vars
+        .iter()
+        .flat_map(|var| ["-v", var])
+        .chain([prog])
+        .collect::<Vec<&str>>();
+    let output = std::process::Command::new("awk")
+        .args(args)
+        .output()
+        .unwrap();
+    assert_eq!(ours, String::from_utf8(output.stdout).unwrap());
+}
+
+// Rust reads these as floats but awk doesn't, they stay strings
+#[test]
+fn test_command_line_not_strnum() {
+    let prog = "BEGIN { print typeof(x), typeof(y), typeof(z), typeof(w), w + 1 }";
+    let vars = ["x=inf", "y=nan", "z=1e5000", "w=-1.5e2"];
+    let output = crate::run_capture_with_vars(prog, &vars, "").unwrap();
+    assert_eq!(output, b"string string string strnum -149\n");
+}
+
+// A keyword that is turned off is just a name
+#[test]
+fn test_run_capture_with_keywords() {
+    let keywords = crate::Keywords::default().without("print");
+    let prog = "BEGIN { print = 5; printf \"%d %s\\n\", print, print + 1 }";
+    let output = crate::run_capture_with_keywords(prog, keywords, "").unwrap();
+    assert_eq!(output, b"5 6\n");
+}
+
+// A syntax error comes back with every message in it rather than panicking
+#[test]
+fn test_syntax_error_is_returned() {
+    let err = crate::run_capture("BEGIN { x = }\nEND { y = ( }", "").unwrap_err();
+    assert!(matches!(err, crate::RunError::Compile(_)));
+    assert_eq!(err.to_string().lines().count(), 2, "{}", err);
+    assert_eq!(
+        crate::run_cli(vec!["jawk".into(), "BEGIN { x = }".into()]),
+        2
+    );
+}
+
+#[test]
+fn test_diagnostics() {
+    let errors = crate::diagnostics("BEGIN { x = ; print 1 }\n{ print (1 }\nEND { break }");
+    let messages: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
+    assert_eq!(messages.len(), 3, "{:?}", messages);
+    assert!(messages[1].contains("didn't find a RightParen"));
+    assert_eq!(messages[2], "break must be inside a loop");
+    let errors = crate::diagnostics("{ print \"open }");
+    assert!(matches!(errors.as_slice(), [crate::RunError::Lex(_, _)]));
+    assert!(crate::diagnostics("{ print $1 }").is_empty());
+}
+
+// -F sets FS before BEGIN, with escapes decoded
+#[test]
+fn test_field_sep_option() {
+    let temp_dir = tempdir().unwrap();
+    let file_path = temp_dir.path().join("tmp");
+    std::fs::write(file_path.clone(), "a,b c,d\ne\tf\n").unwrap();
+    let prog = "{ print $2, NF }";
+    for (option, expected) in [
+        (vec!["-F,"], "b c 3\n 1\n"),
+        (vec!["-F", "\\t"], " 1\nf 2\n"),
+    ] {
+        let run = |interpreter: &str| {
+            let output = std::process::Command::new(interpreter)
+                .args(&option)
+                .args([prog, file_path.to_str().unwrap()])
+                .output()
+                .unwrap();
+            String::from_utf8(output.stdout).unwrap()
+        };
+        let ours = run("./target/release/jawk");
+        assert_eq!(ours, expected, "wrong output for {:?}", option);
+        assert_eq!(ours, run("awk"));
+    }
+}
+
+#[test]
+fn test_program_files() {
+    let temp_dir = tempdir().unwrap();
+    let first = temp_dir.path().join("first.awk");
+    let second = temp_dir.path().join("second.awk");
+    let file_path = temp_dir.path().join("tmp");
+    std::fs::write(&first, "BEGIN { sep = \"-\" }\n{ total += $1 }").unwrap();
+    std::fs::write(&second, "END { print total, sep, NR }\n").unwrap();
+    std::fs::write(&file_path, NUMBERS).unwrap();
+
+    let run = |interpreter: &str| {
+        let output = std::process::Command::new(interpreter)
+            .args(["-f", first.to_str().unwrap()])
+            .args(["-f", second.to_str().unwrap()])
+            .arg(file_path.to_str().unwrap())
+            .output()
+            .unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let ours = run("./target/release/jawk");
+    assert_eq!(ours, "12 - 3\n");
+    assert_eq!(ours, run("awk"));
+}
+
+#[test]
+fn test_program_file_errors() {
+    let temp_dir = tempdir().unwrap();
+    let first = temp_dir.path().join("first.awk");
+    let second = temp_dir.path().join("second.awk");
+    std::fs::write(&first, "BEGIN { x = 1 }\n\n").unwrap();
+    std::fs::write(&second, "{ print x }\n{ print @ }").unwrap();
+
+    let err = crate::run_capture_files(&[&first, &second], "").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!("{}: line 1: Unexpected token: `@`", second.display())
+    );
+    let missing = temp_dir.path().join("missing.awk");
+    let err = crate::run_capture_files(&[&first, &missing], "").unwrap_err();
+    assert!(matches!(err, crate::RunError::Load(file, _) if file == missing.display().to_string()));
+}
+
+// Runs prog with stdin as its input and returns what it printed to stdout and to stderr
+fn run_with_stdin(
+    interpreter: &str,
+    prog: &str,
+    operands: &[&str],
+    stdin: &str,
+) -> (String, String) {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = std::process::Command::new(interpreter)
+        .arg(prog)
+        .args(operands)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+// /dev/stderr and /dev/stdout are the process's own streams, "-" and /dev/stdin read stdin
+#[test]
+fn test_special_files() {
+    let cases: &[(&str, &[&str], (&str, &str))] = &[
+        (
+            "{ print \"bad record \" NR > \"/dev/stderr\"; print $2 > \"/dev/stdout\" }",
+            &["-"],
+            ("b\nd\n", "bad record 1\nbad record 2\n"),
+        ),
+        (
+            "BEGIN { while ((getline line < \"-\") > 0) print \"got\", line; close(\"-\") }",
+            &[],
+            ("got a b\ngot c d\n", ""),
+        ),
+        (
+            "BEGIN { getline line < \"/dev/stdin\"; print line > \"/dev/stderr\" }",
+            &[],
+            ("", "a b\n"),
+        ),
+    ];
+    for (prog, operands, expected) in cases {
+        let ours = run_with_stdin("./target/release/jawk", prog, operands, "a b\nc d\n");
+        assert_eq!(
+            (ours.0.as_str(), ours.1.as_str()),
+            *expected,
+            "wrong output for {}",
+            prog
+        );
+        assert_eq!(ours, run_with_stdin("awk", prog, operands, "a b\nc d\n"));
+    }
+}
+
+// Any write error other than the reader going away stops jawk with a message
+#[test]
+fn test_write_error() {
+    let full = std::fs::OpenOptions::new()
+        .write(true)
+        .open("/dev/full")
+        .unwrap();
+    let output = std::process::Command::new("./target/release/jawk")
+        .arg("BEGIN { print \"a\" }")
+        .stdout(full)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("jawk: write error: "), "{}", stderr);
+}
+
+#[test]
+fn test_print_record_from_stdin() {
+    let prog = "{ print }\nNF > 1 { print; print; }";
+    let ours = run_with_stdin("./target/release/jawk", prog, &[], "a b\nc\n");
+    assert_eq!(ours, ("a b\na b\na b\nc\n".to_string(), "".to_string()));
+    assert_eq!(ours, run_with_stdin("awk", prog, &[], "a b\nc\n"));
+}
@@ -1,31 +1,60 @@
-use crate::parser::{Stmt, TypedExpr};
+use crate::lexer::{LogicalOp, Symbol};
+use crate::parser::{Pattern, Stmt, TypedExpr};
 use crate::{parser, Expr};
 
-// Turn a program into just a single Stmt
+// Turn a program into just a single Stmt. Everything before END is wrapped in Stmt::Main so
+// `exit` can jump to the END actions.
 // Also convert any concatenations
 //  like a = a b into  a c= b (an append Expr aka concat equals)
 pub fn transform(program: parser::Program) -> Stmt {
     let mut prog = program.begins;
 
     let mut every_line_stms = vec![];
+    let mut ranges = 0;
     for pattern in program.pattern_actions {
-        let stmt = if let Some(test) = pattern.pattern {
-            Stmt::If(test, Box::new(pattern.action), None)
-        } else {
-            pattern.action
+        let stmt = match pattern.pattern {
+            Some(Pattern::Expr(test)) => Stmt::If(test, Box::new(pattern.action), None),
+            Some(Pattern::Range(start, end)) => {
+                let active = range_flag(ranges);
+                ranges += 1;
+                prog.push(Stmt::Expr(TypedExpr::new_num(Expr::Assign(
+                    active.clone(),
+                    Box::new(TypedExpr::new_num(Expr::NumberF64(0.0))),
+                ))));
+                range(active, start, end, pattern.action)
+            }
+            None => pattern.action,
         };
         every_line_stms.push(stmt)
     }
     if every_line_stms.len() > 0 {
         let line_loop = Stmt::While(
-            TypedExpr::new_num(Expr::Call),
+            TypedExpr::new_num(Expr::NextLine),
             Box::new(Stmt::Group(every_line_stms)),
         );
         prog.push(line_loop);
     }
 
+    let mut prog = vec![Stmt::Main(Box::new(Stmt::Group(prog)))];
     for end in program.ends {
         prog.push(end);
     }
     Stmt::Group(prog)
 }
+
+// The variable remembering whether a range is between its start and end records. Identifiers
+// are lowercased by the lexer so an uppercase name can't collide with one from the program.
+fn range_flag(idx: usize) -> Symbol {
+    Symbol::from(format!("RANGE{}", idx))
+}
+
+// start, end { action } becomes
+//   if (active || start) { active = !end; action }
+// so start is only tested outside the range and end is tested on the start record too.
+fn range(active: Symbol, start: TypedExpr, end: TypedExpr, action: Stmt) -> Stmt {
+    let flag = || Box::new(TypedExpr::new_num(Expr::Variable(active.clone())));
+    let test = TypedExpr::new_num(Expr::LogicalOp(flag(), LogicalOp::Or, Box::new(start)));
+    let not_end = TypedExpr::new_num(Expr::Not(Box::new(end)));
+    let update = Stmt::Expr(TypedExpr::new_num(Expr::Assign(active, Box::new(not_end))));
+    Stmt::If(test, Box::new(Stmt::Group(vec![update, action])), None)
+}
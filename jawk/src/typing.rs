@@ -1,28 +1,110 @@
-use crate::codgen::variable_extract;
-use crate::parser::{AwkT, Stmt, TypedExpr};
+use crate::codgen::{self, variable_extract};
+use crate::lexer::Symbol;
+use crate::parser::{
+    walk_expr_mut, walk_program_mut, AwkT, GetlineSource, Lvalue, Program, Stmt, TypedExpr,
+    VisitorMut,
+};
 use crate::Expr;
 use immutable_chunkmap::map::Map;
 
-pub type MapT = Map<String, AwkT, 1000>;
+pub type MapT = Map<Symbol, AwkT, 1000>;
 
 pub fn analyze(stmt: &mut Stmt) {
     let mut map = MapT::new();
-    let (vars, _) = variable_extract::extract(stmt);
+    let (vars, _, _) = variable_extract::extract(stmt);
     for var in vars {
-        map = map.insert(var, AwkT::String).0;
+        map = map.insert(var.into(), AwkT::String).0;
+    }
+    TypeAnalysis {
+        map,
+        next_maps: vec![],
+        exit_maps: vec![],
+        break_maps: vec![],
+        continue_maps: vec![],
+    }
+    .analyze_stmt(stmt)
+}
+
+// Types that follow from an expression alone, without following the program's control flow:
+// literals, arithmetic, comparisons, concatenation and builtins with a fixed result type. Anything
+// depending on what ran before, like a variable or an array element, stays AwkT::Variable.
+// analyze does the full job on the transformed program.
+pub fn infer_types(program: &mut Program) {
+    walk_program_mut(&mut Inference {}, program);
+}
+
+struct Inference {}
+
+impl VisitorMut for Inference {
+    fn visit_expr(&mut self, expr: &mut TypedExpr) {
+        walk_expr_mut(self, expr);
+        expr.typ = match &expr.expr {
+            Expr::NumberF64(_)
+            | Expr::Regex(_)
+            | Expr::BinOp(..)
+            | Expr::MathOp(..)
+            | Expr::LogicalOp(..)
+            | Expr::Not(_)
+            | Expr::NfAssign(_)
+            | Expr::NextLine
+            | Expr::InArray { .. }
+            | Expr::Getline { .. } => AwkT::Float,
+            Expr::StrNum(..) => AwkT::Variable,
+            Expr::String(_) | Expr::Concatenation(_) | Expr::Column(_) => AwkT::String,
+            Expr::Assign(_, value)
+            | Expr::ArrayAssign { value, .. }
+            | Expr::ColumnAssign { value, .. } => value.typ,
+            Expr::Ternary(_, if_so, if_not) => TypeAnalysis::merge_types(&if_so.typ, &if_not.typ),
+            Expr::Variable(var) if codgen::record_var(var).is_some() => AwkT::Float,
+            Expr::Variable(var) if *var == codgen::RT => AwkT::String,
+            Expr::Call { target, .. } => call_type(target),
+            Expr::Variable(_) | Expr::ArrayIndex { .. } => AwkT::Variable,
+        };
+    }
+}
+
+// What a builtin returns, AwkT::Variable when it depends on the arguments
+fn call_type(target: &str) -> AwkT {
+    match target {
+        "typeof" | "sprintf" | "toupper" | "tolower" => AwkT::String,
+        "isarray" | "close" | "system" | "rand" | "srand" | "match" | "patsplit" | "sub"
+        | "gsub" => AwkT::Float,
+        _ => AwkT::Variable,
     }
-    TypeAnalysis { map }.analyze_stmt(stmt)
 }
 
 struct TypeAnalysis {
     map: MapT,
+    // Types at each `next`, which jumps back to the top of the record loop
+    next_maps: Vec<MapT>,
+    // Types at each `exit` before END, which jumps to the END actions
+    exit_maps: Vec<MapT>,
+    // Types at each `break` and `continue` of the innermost loop being analyzed
+    break_maps: Vec<MapT>,
+    continue_maps: Vec<MapT>,
 }
 
 impl TypeAnalysis {
     pub fn analyze_stmt(&mut self, stmt: &mut Stmt) {
         match stmt {
             Stmt::Expr(expr) => self.analyze_expr(expr),
-            Stmt::Print(expr) => self.analyze_expr(expr),
+            Stmt::Print { args, output } => {
+                for arg in args {
+                    self.analyze_expr(arg);
+                }
+                if let Some(output) = output {
+                    self.analyze_expr(output.target_mut());
+                }
+            }
+            Stmt::Printf { fmt, args, output } => {
+                self.analyze_expr(fmt);
+                for arg in args {
+                    self.analyze_expr(arg);
+                }
+                if let Some(output) = output {
+                    self.analyze_expr(output.target_mut());
+                }
+            }
             Stmt::Group(grouping) => {
                 for stmt in grouping {
                     self.analyze_stmt(stmt);
@@ -43,25 +125,121 @@ impl TypeAnalysis {
                 std::mem::swap(&mut if_not_map, &mut self.map);
                 self.map = TypeAnalysis::merge_maps(&[&if_so_map, &if_not_map]);
             }
-            Stmt::While(test, body) => {
-                self.analyze_expr(test);
-
-                let after_test_map = self.map.clone();
-
+            Stmt::While(test, body) => self.analyze_loop(test, body, None),
+            Stmt::DoWhile(body, test) => {
+                // The first time round the body starts with the types from before the loop
+                let before_map = self.map.clone();
+                let outer_breaks = std::mem::take(&mut self.break_maps);
+                for _ in 0..2 {
+                    self.analyze_loop_body(body);
+                    self.map = self.loop_end_map();
+                    self.analyze_expr(test);
+                    self.map = TypeAnalysis::merge_maps(&[&before_map, &self.map]);
+                }
+                self.merge_breaks(outer_breaks);
+            }
+            Stmt::For {
+                init,
+                test,
+                incr,
+                body,
+            } => {
+                self.analyze_stmt(init);
+                self.analyze_loop(test, body, Some(incr));
+            }
+            Stmt::ForIn {
+                var,
+                name: _,
+                path,
+                body,
+            } => {
+                for index in path.iter_mut().flatten() {
+                    self.analyze_expr(index);
+                }
+                // The body may run zero times in which case var keeps its old value. Twice like
+                // while so types from the end of the body reach its start.
+                let before_map = self.map.clone();
+                let outer_breaks = std::mem::take(&mut self.break_maps);
+                for _ in 0..2 {
+                    self.map = self.map.insert(var.as_str().into(), AwkT::String).0;
+                    self.analyze_loop_body(body);
+                    self.map = TypeAnalysis::merge_maps(&[&before_map, &self.map]);
+                }
+                self.merge_breaks(outer_breaks);
+            }
+            Stmt::Delete { path, indices, .. } => {
+                for index in path.iter_mut().flatten().chain(indices.iter_mut()) {
+                    self.analyze_expr(index);
+                }
+            }
+            Stmt::Next => self.next_maps.push(self.map.clone()),
+            Stmt::Break => self.break_maps.push(self.map.clone()),
+            Stmt::Continue => self.continue_maps.push(self.map.clone()),
+            Stmt::Exit(status) => {
+                if let Some(status) = status {
+                    self.analyze_expr(status);
+                }
+                self.exit_maps.push(self.map.clone());
+            }
+            Stmt::Main(body) => {
                 self.analyze_stmt(body);
+                let mut maps = vec![&self.map];
+                maps.extend(self.exit_maps.iter());
+                self.map = TypeAnalysis::merge_maps(&maps);
+                self.exit_maps.clear();
+            }
+        }
+    }
 
-                let after_body_map = self.map.clone();
+    // while (test) body, or the test, body and incr of a for loop. Twice so types from the end of
+    // the body reach its start.
+    fn analyze_loop(
+        &mut self,
+        test: &mut TypedExpr,
+        body: &mut Stmt,
+        mut incr: Option<&mut Box<Stmt>>,
+    ) {
+        let outer_breaks = std::mem::take(&mut self.break_maps);
+        for _ in 0..2 {
+            self.analyze_expr(test);
+            let after_test_map = self.map.clone();
+            self.analyze_loop_body(body);
+            if let Some(incr) = incr.as_mut() {
+                self.analyze_stmt(incr);
+            }
+            let after_body_map = self.loop_end_map();
+            self.map = TypeAnalysis::merge_maps(&[&after_test_map, &after_body_map]);
+        }
+        self.merge_breaks(outer_breaks);
+    }
 
-                self.map = TypeAnalysis::merge_maps(&[&after_test_map, &after_body_map]);
+    // A `continue` skips the rest of the body so its types join those at the end of the body
+    fn analyze_loop_body(&mut self, body: &mut Stmt) {
+        let outer_continues = std::mem::take(&mut self.continue_maps);
+        self.analyze_stmt(body);
+        let mut maps = vec![&self.map];
+        maps.extend(self.continue_maps.iter());
+        self.map = TypeAnalysis::merge_maps(&maps);
+        self.continue_maps = outer_continues;
+    }
 
-                self.analyze_expr(test);
+    // A `break` leaves the loop so its types join those after the loop. Then the enclosing loop's
+    // breaks are put back.
+    fn merge_breaks(&mut self, outer_breaks: Vec<MapT>) {
+        let mut maps = vec![&self.map];
+        maps.extend(self.break_maps.iter());
+        self.map = TypeAnalysis::merge_maps(&maps);
+        self.break_maps = outer_breaks;
+    }
 
-                let after_test_map = self.map.clone();
-                self.analyze_stmt(body);
-                let after_body_map = self.map.clone();
-                self.map = TypeAnalysis::merge_maps(&[&after_test_map, &after_body_map]);
-            }
-        }
+    // The types at the end of a loop body, including any `next` inside it. Merging a `next` into
+    // an inner loop only widens types so it is fine that `next` really leaves the record loop.
+    fn loop_end_map(&mut self) -> MapT {
+        let mut maps = vec![&self.map];
+        maps.extend(self.next_maps.iter());
+        let merged = TypeAnalysis::merge_maps(&maps);
+        self.next_maps.clear();
+        merged
     }
 
     pub fn analyze_expr(&mut self, expr: &mut TypedExpr) {
@@ -72,6 +250,14 @@ impl TypeAnalysis {
             Expr::String(_) => {
                 expr.typ = AwkT::String;
             }
+            // Neither a string nor a number, its tag sorts it out at runtime
+            Expr::StrNum(..) => {
+                expr.typ = AwkT::Variable;
+            }
+            // Alone it's $0 ~ /re/
+            Expr::Regex(_) => {
+                expr.typ = AwkT::Float;
+            }
             Expr::BinOp(left, _op, right) => {
                 self.analyze_expr(left);
                 self.analyze_expr(right);
@@ -87,6 +273,20 @@ impl TypeAnalysis {
                 self.analyze_expr(right);
                 expr.typ = AwkT::Float;
             }
+            Expr::Not(value) => {
+                self.analyze_expr(value);
+                expr.typ = AwkT::Float;
+            }
+            // Like an if, either branch may have run afterwards
+            Expr::Ternary(test, if_so, if_not) => {
+                self.analyze_expr(test);
+                let before_map = self.map.clone();
+                self.analyze_expr(if_so);
+                let if_so_map = std::mem::replace(&mut self.map, before_map);
+                self.analyze_expr(if_not);
+                self.map = TypeAnalysis::merge_maps(&[&if_so_map, &self.map]);
+                expr.typ = TypeAnalysis::merge_types(&if_so.typ, &if_not.typ);
+            }
             Expr::Assign(var, value) => {
                 self.analyze_expr(value);
                 self.map = self.map.insert(var.clone(), value.typ).0;
@@ -94,7 +294,11 @@ impl TypeAnalysis {
             }
 
             Expr::Variable(var) => {
-                if let Some(typ) = self.map.get(var) {
+                if codgen::record_var(var).is_some() {
+                    expr.typ = AwkT::Float;
+                } else if *var == codgen::RT {
+                    expr.typ = AwkT::String;
+                } else if let Some(typ) = self.map.get(var) {
                     expr.typ = *typ;
                 } else {
                     expr.typ = AwkT::String;
@@ -104,7 +308,95 @@ impl TypeAnalysis {
                 expr.typ = AwkT::String;
                 self.analyze_expr(col);
             }
-            Expr::Call => expr.typ = AwkT::Float,
+            Expr::ColumnAssign { index, value } => {
+                self.analyze_expr(index);
+                self.analyze_expr(value);
+                expr.typ = value.typ;
+            }
+            Expr::NfAssign(value) => {
+                self.analyze_expr(value);
+                expr.typ = AwkT::Float;
+            }
+            Expr::NextLine => expr.typ = AwkT::Float,
+            Expr::Getline { into, source } => {
+                match source {
+                    GetlineSource::MainInput => {}
+                    GetlineSource::File(expr)
+                    | GetlineSource::Command(expr)
+                    | GetlineSource::Coprocess(expr) => self.analyze_expr(expr),
+                }
+                match into {
+                    Some(Lvalue::Variable(var)) => {
+                        // Only assigned when a line is read so the old type may survive
+                        let typ = match self.map.get(var) {
+                            Some(AwkT::String) => AwkT::String,
+                            _ => AwkT::Variable,
+                        };
+                        self.map = self.map.insert(var.clone(), typ).0;
+                    }
+                    Some(Lvalue::ArrayIndex { path, indices, .. }) => {
+                        for index in path.iter_mut().flatten().chain(indices.iter_mut()) {
+                            self.analyze_expr(index);
+                        }
+                    }
+                    Some(Lvalue::Column(index)) => self.analyze_expr(index),
+                    None => {}
+                }
+                expr.typ = AwkT::Float;
+            }
+            Expr::ArrayIndex {
+                name: _,
+                path,
+                indices,
+            } => {
+                for index in path.iter_mut().flatten().chain(indices.iter_mut()) {
+                    self.analyze_expr(index);
+                }
+                expr.typ = AwkT::Variable;
+            }
+            Expr::InArray {
+                indices,
+                name: _,
+                path,
+            } => {
+                for index in path.iter_mut().flatten().chain(indices.iter_mut()) {
+                    self.analyze_expr(index);
+                }
+                expr.typ = AwkT::Float;
+            }
+            Expr::ArrayAssign {
+                name: _,
+                path,
+                indices,
+                value,
+            } => {
+                for index in path.iter_mut().flatten().chain(indices.iter_mut()) {
+                    self.analyze_expr(index);
+                }
+                self.analyze_expr(value);
+                expr.typ = value.typ;
+            }
+            Expr::Call { target, args } => {
+                for arg in args.iter_mut() {
+                    self.analyze_expr(arg);
+                }
+                // sub and gsub store the result in their target, but only when something was
+                // replaced so the old type may survive
+                let substituted = match args.get(2).map(|arg| &arg.expr) {
+                    Some(Expr::Variable(var)) if target == "sub" || target == "gsub" => {
+                        Some(var.clone())
+                    }
+                    _ => None,
+                };
+                if let Some(var) = substituted {
+                    let typ = match self.map.get(&var) {
+                        Some(AwkT::String) => AwkT::String,
+                        _ => AwkT::Variable,
+                    };
+                    self.map = self.map.insert(var, typ).0;
+                }
+                expr.typ = call_type(target);
+            }
             Expr::Concatenation(vals) => {
                 expr.typ = AwkT::String;
                 for val in vals {
@@ -150,8 +442,9 @@ fn test_it(program: &str, expected: &str) {
             .replace(";", "")
     }
 
-    use crate::{lex, parse, transform};
-    let mut ast = transform(parse(lex(program).unwrap()));
+    use crate::parser::parse;
+    use crate::{lex, transform};
+    let mut ast = transform(parse(lex(program).unwrap()).unwrap());
     analyze(&mut ast);
     println!("prog: {:?}", ast);
     let result_clean = strip(&format!("{}", ast));
@@ -221,6 +514,56 @@ fn test_if_else_polluting() {
             "(f a = (f 1)); print (f a); if (s $(f 1)) { (s a = (s \"a\"); ) } else { (s a = (s \"a\")); } print (s a)");
 }
 
+#[test]
+fn test_ternary_types() {
+    test_it(
+        "BEGIN { a = 1; b = $1 ? 2 : \"x\"; c = $1 ? a = \"s\" : 3; print a }",
+        "(f a = (f 1)); (v b = (v (s $(f 1)) ? (f 2) : (s \"x\"))); \
+         (v c = (v (s $(f 1)) ? (s a = (s \"s\")) : (f 3))); print (v a)",
+    );
+}
+
+#[test]
+fn test_ternary_merges_branch_types() {
+    test_it(
+        "BEGIN { a = x ? 1 : 2; b = x ? \"a\" : 1; c = x ? \"a\" : \"b\" }",
+        "(f a = (f (s x) ? (f 1) : (f 2))); (v b = (v (s x) ? (s \"a\") : (f 1))); \
+         (s c = (s (s x) ? (s \"a\") : (s \"b\")))",
+    );
+}
+
+#[test]
+fn test_next_reaches_loop_start() {
+    test_it(
+        "BEGIN { a = \"s\" } { print a; a = 1; if ($1) { next } a = \"s\" } END { print a }",
+        "(s a = (s \"s\")); while (f check_if_there_is_another_line) { print (v a); (f a = (f 1)); if (s $(f 1)) { next } (s a = (s \"s\")) }; print (v a);",
+    );
+}
+
+#[test]
+fn test_exit_reaches_end() {
+    test_it(
+        "BEGIN { a = \"s\"; if (x) { a = 1; exit } a = \"t\" } END { print a }",
+        "(s a = (s \"s\")); if (s x) { (f a = (f 1)); exit } (s a = (s \"t\")); print (v a);",
+    );
+}
+
+#[test]
+fn test_break_reaches_loop_end() {
+    test_it(
+        "BEGIN { a = \"s\"; while (x) { a = 1; if (y) { break } a = \"s\" } print a }",
+        "(s a = (s \"s\")); while (s x) { (f a = (f 1)); if (s y) { break } (s a = (s \"s\")) }; print (v a);",
+    );
+}
+
+#[test]
+fn test_continue_reaches_incr() {
+    test_it(
+        "BEGIN { for (a = \"s\"; x; b = a) { a = 1; if (y) continue; a = \"t\" } }",
+        "for ((s a = (s \"s\")); (s x); (v b = (v a))) { (f a = (f 1)); if (s y) { continue } (s a = (s \"t\")) }",
+    );
+}
+
 #[test]
 fn test_concat_loop() {
     test_it(
@@ -249,3 +592,64 @@ fn test_assignment_col() {
         "while(fcheck_if_there_is_another_line){ (s x = (s$(f 0) ))}; print (s x);",
     );
 }
+
+#[test]
+fn test_typeof_isarray() {
+    test_it(
+        "BEGIN { print typeof(x); print isarray(x) }",
+        "print (s typeof((s x))); print (f isarray((s x)))",
+    );
+}
+
+#[test]
+fn test_sprintf_is_string() {
+    test_it(
+        "BEGIN { x = sprintf(\"%d\", 1); print x }",
+        "(s x = (s sprintf((s \"%d\"), (f 1)))); print (s x)",
+    );
+}
+
+#[test]
+fn test_array_types() {
+    test_it(
+        "BEGIN { a[1] = \"x\"; print a[1] }",
+        "(s a[(f 1)] = (s \"x\")); print (v a[(f 1)])",
+    );
+}
+
+#[test]
+fn test_getline_var_types() {
+    test_it(
+        "BEGIN { x = 1; getline x < \"f\"; getline y < \"f\"; print x; print y }",
+        "(f x = (f 1)); (f getline x < (s \"f\")); (f getline y < (s \"f\")); print (v x); print (s y)",
+    );
+}
+
+#[test]
+fn test_infer_types() {
+    use crate::lexer::lex;
+    use crate::parser::parse;
+    let mut program = parse(
+        lex("BEGIN { x = 1 + 2; y = \"a\" x; z = (1 < y); w = v; print $1, a[1], toupper(y), NR }")
+            .unwrap(),
+    )
+    .unwrap();
+    infer_types(&mut program);
+    // Variables and array elements stay unknown, everything else is decided by its own shape
+    let expected = [
+        "(f x = (f (f 1)+(f 2)))",
+        "(s y = (s (s \"a\") (v x)))",
+        "(f z = (f (f 1)<(v y)))",
+        "(v w = (v v))",
+        "print (s $(f 1)), (v a[(f 1)]), (s toupper((v y))), (f NR)",
+    ];
+    let begin = match &program.begins[0] {
+        Stmt::Group(begin) => begin,
+        _ => panic!("BEGIN is a group"),
+    };
+    let actual = begin
+        .iter()
+        .map(|stmt| stmt.to_string().trim_end().to_string())
+        .collect::<Vec<String>>();
+    assert_eq!(actual, expected);
+}
@@ -0,0 +1,110 @@
+// Runs every script in tests/compat over tests/compat/input.txt with jawk and with a reference
+// awk and compares what they print. Only built with `cargo test --features compat`, and skipped
+// when the reference can't be run. The reference is `awk` unless JAWK_REFERENCE_AWK names another.
+#![cfg(feature = "compat")]
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn reference_awk() -> String {
+    std::env::var("JAWK_REFERENCE_AWK").unwrap_or_else(|_| "awk".to_string())
+}
+
+fn corpus_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("compat")
+}
+
+fn scripts() -> Vec<PathBuf> {
+    let mut scripts: Vec<PathBuf> = std::fs::read_dir(corpus_dir())
+        .expect("cannot read tests/compat")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "awk"))
+        .collect();
+    scripts.sort();
+    scripts
+}
+
+fn run_reference(awk: &str, script: &Path, input: &Path) -> Option<String> {
+    let output = Command::new(awk)
+        .arg("-f")
+        .arg(script)
+        .arg(input)
+        .output()
+        .ok()?;
+    Some(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// Lines only in the reference start with -, lines only in jawk with +
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    // Longest common subsequence, lcs[i][j] is for expected[i..] and actual[j..]
+    let mut lcs = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            out.push_str(&format!("  {}\n", expected[i]));
+            i += 1;
+            j += 1;
+        } else if j < actual.len() && (i == expected.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            out.push_str(&format!("+ {}\n", actual[j]));
+            j += 1;
+        } else {
+            out.push_str(&format!("- {}\n", expected[i]));
+            i += 1;
+        }
+    }
+    out
+}
+
+#[test]
+fn test_compat_corpus() {
+    let awk = reference_awk();
+    let input_path = corpus_dir().join("input.txt");
+    if run_reference(&awk, &corpus_dir().join("fields.awk"), &input_path).is_none() {
+        eprintln!("skipping the compatibility corpus, `{}` can't be run", awk);
+        return;
+    }
+    let input = std::fs::read_to_string(&input_path).unwrap();
+
+    let mut failures = vec![];
+    for script in scripts() {
+        let name = script.file_name().unwrap().to_string_lossy().into_owned();
+        let program = std::fs::read_to_string(&script).unwrap();
+        let expected = run_reference(&awk, &script, &input_path).unwrap();
+        let actual = match jawk::run_capture(&program, &input) {
+            Ok(output) => String::from_utf8_lossy(&output).into_owned(),
+            Err(err) => {
+                failures.push(format!("{}: jawk failed: {}", name, err));
+                continue;
+            }
+        };
+        if expected != actual {
+            failures.push(format!(
+                "{}: {} (-) and jawk (+) differ\n{}",
+                name,
+                awk,
+                diff(&expected, &actual)
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn test_diff() {
+    assert_eq!(diff("a\nb\nc\n", "a\nc\nd\n"), "  a\n- b\n  c\n+ d\n");
+    assert_eq!(diff("", "x\n"), "+ x\n");
+}
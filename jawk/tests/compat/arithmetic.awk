@@ -0,0 +1,2 @@
+BEGIN { print 7 % 3, 2 ^ 10, 10 / 4, 3 * (2 + 1), 1 - 0.75 }
+BEGIN { print 10 - 4 - 3, 10 - 4 + 3, 1 - 2 - 3 - 4 }
@@ -0,0 +1 @@
+{ print toupper($1), tolower("MiXeD"), sprintf("%03d", NR) }
@@ -0,0 +1,2 @@
+{ if ($2 > 30) print $1, "senior"; else print $1, "junior" }
+$1 < "c" { print "early", $1 }
@@ -0,0 +1,2 @@
+{ count[$3] += 1 }
+END { print count["engineering"], count["sales"], count["marketing"], count["hr"] + 0 }
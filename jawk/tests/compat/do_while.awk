@@ -0,0 +1 @@
+BEGIN { do { n++; if (n == 3) continue; if (n > 5) break; printf "%d ", n } while (1); print "" }
@@ -0,0 +1,2 @@
+{ seen[$3] = 1; total[$3] += $4 }
+END { n = 0; sum = 0; for (k in seen) { n++; sum += total[k] } print n, sum }
@@ -0,0 +1,2 @@
+NR == 1 { getline; print "after getline", $1, NR }
+END { print NR }
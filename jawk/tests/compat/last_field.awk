@@ -0,0 +1 @@
+{ print NF, $NF }
@@ -0,0 +1,3 @@
+$3 ~ /^eng/ { print "engineer", $1 }
+$1 !~ "e" { print "no e", $1 }
+{ print $1 ~ /a/, $2 !~ /5$/ }
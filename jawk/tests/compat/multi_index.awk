@@ -0,0 +1,2 @@
+{ a[$3, NR] = $1 }
+END { print a["sales", 2], a["engineering", 3], a["sales", 5] }
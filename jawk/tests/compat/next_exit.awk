@@ -0,0 +1,4 @@
+NR == 2 { next }
+{ print $1 }
+NR == 4 { exit }
+END { print "end", NR }
@@ -0,0 +1 @@
+{ x = $2 $2; y = x + 1; print x, y, $2 / 3 }
@@ -0,0 +1 @@
+{ printf "%-8s|%5d|%10.2f|%x\n", $1, $2, $4, $2 }
@@ -0,0 +1 @@
+BEGIN { printf "%g %e %.3g %05.1f %c%c %%\n", 1234567, 0.000123, 3.14159, 2.5, 65, "hello" }
@@ -0,0 +1 @@
+$1 == "bob", NR == 4 { print NR ": " $1 }
@@ -0,0 +1,2 @@
+/^[a-c]/ { print $1 }
+/[0-9]\.[0-9]/ { print "decimal", $4 }
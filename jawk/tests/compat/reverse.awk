@@ -0,0 +1 @@
+{ s = ""; i = NF; while (i > 0) { s = s $i " "; i-- } print s }
@@ -0,0 +1,2 @@
+BEGIN { OFS = "-"; ORS = "|\n" }
+{ $1 = $1; print; print $1, $2 }
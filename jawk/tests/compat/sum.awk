@@ -0,0 +1,2 @@
+{ total += $4 }
+END { print total, total / NR }
@@ -0,0 +1 @@
+BEGIN { print x + 0, "[" x "]"; if (!y) print "unset" }